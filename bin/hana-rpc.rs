@@ -1,11 +1,14 @@
 use hana::{
     hana_tracing::{self, Component},
     binutil::HanaDataDir,
-    kv::{mdbx::*, MdbxWithDirHandle},
+    execution::analysis_cache::AnalysisCache,
+    kv::{freezer::Freezer, mdbx::*, MdbxWithDirHandle},
+    metrics,
     rpc::{
-        debug::DebugApiServerImpl, erigon::ErigonApiServerImpl, eth::EthApiServerImpl,
-        net::NetApiServerImpl, otterscan::OtterscanApiServerImpl, parity::ParityApiServerImpl,
-        trace::TraceApiServerImpl, web3::Web3ApiServerImpl,
+        debug::DebugApiServerImpl, debug_session::DebugSessionApiServerImpl,
+        erigon::ErigonApiServerImpl, eth::EthApiServerImpl, net::NetApiServerImpl,
+        otterscan::OtterscanApiServerImpl, parity::ParityApiServerImpl, trace::TraceApiServerImpl,
+        web3::Web3ApiServerImpl,
     },
 };
 use anyhow::format_err;
@@ -34,6 +37,10 @@ pub struct Opt {
     /// Enable API options
     #[clap(long)]
     pub enable_api: Option<String>,
+
+    /// Enable Prometheus metrics at this IP address and port.
+    #[clap(long)]
+    pub metrics_listen_address: Option<SocketAddr>,
 }
 
 #[tokio::main]
@@ -43,23 +50,33 @@ async fn main() -> anyhow::Result<()> {
     hana_tracing::build_subscriber(Component::RPCDaemon).init();
 
     let hana_chain_data_dir = opt.datadir.chain_data_dir();
-    let db: Arc<MdbxWithDirHandle<NoWriteMap>> = Arc::new(
-        MdbxEnvironment::<NoWriteMap>::open_ro(
-            mdbx::Environment::new(),
-            &hana_chain_data_dir,
-            &hana::kv::tables::CHAINDATA_TABLES,
-        )?
-        .into(),
-    );
+    let mut env = MdbxEnvironment::<NoWriteMap>::open_ro(
+        mdbx::Environment::new(),
+        &hana_chain_data_dir,
+        &hana::kv::tables::CHAINDATA_TABLES,
+    )?;
+    // Opened even if the node has not frozen anything yet, lookups pick up new segments.
+    env.set_freezer(Arc::new(Freezer::open(opt.datadir.ancient_dir())?));
+    let db: Arc<MdbxWithDirHandle<NoWriteMap>> = Arc::new(env.into());
+
+    if let Some(metrics_listen_address) = opt.metrics_listen_address {
+        tokio::spawn(async move {
+            if let Err(e) = metrics::serve(metrics_listen_address).await {
+                error!("Metrics server failed: {e:?}");
+            }
+        });
+        tokio::spawn(metrics::run_db_stats_loop(
+            db.clone(),
+            std::time::Duration::from_secs(30),
+        ));
+    }
 
     let network_id = hana::accessors::chain::chain_config::read(&db.begin()?)?
         .ok_or_else(|| format_err!("no chainspec found"))?
         .params
         .network_id;
 
-    let jsonrpc_server = ServerBuilder::default()
-        .build(&opt.rpc_listen_address)
-        .await?;
+    let analysis_cache = AnalysisCache::default();
 
     let mut api = Methods::new();
 
@@ -78,6 +95,7 @@ async fn main() -> anyhow::Result<()> {
             EthApiServerImpl {
                 db: db.clone(),
                 call_gas_limit: 100_000_000,
+                analysis_cache: analysis_cache.clone(),
             }
             .into_rpc(),
         )
@@ -95,8 +113,14 @@ async fn main() -> anyhow::Result<()> {
     }
 
     if api_options.is_empty() || api_options.contains("otterscan") {
-        api.merge(OtterscanApiServerImpl { db: db.clone() }.into_rpc())
-            .unwrap();
+        api.merge(
+            OtterscanApiServerImpl {
+                db: db.clone(),
+                analysis_cache: analysis_cache.clone(),
+            }
+            .into_rpc(),
+        )
+        .unwrap();
     }
 
     if api_options.is_empty() || api_options.contains("parity") {
@@ -109,16 +133,29 @@ async fn main() -> anyhow::Result<()> {
             TraceApiServerImpl {
                 db: db.clone(),
                 call_gas_limit: 100_000_000,
+                analysis_cache: analysis_cache.clone(),
             }
             .into_rpc(),
         )
         .unwrap();
     }
 
+    if api_options.is_empty() || api_options.contains("debug") {
+        api.merge(
+            DebugSessionApiServerImpl::new(db.clone(), 100_000_000, analysis_cache.clone())
+                .into_rpc(),
+        )
+        .unwrap();
+    }
+
     if api_options.is_empty() || api_options.contains("web3") {
         api.merge(Web3ApiServerImpl.into_rpc()).unwrap();
     }
 
+    let jsonrpc_server = ServerBuilder::default()
+        .set_logger(metrics::RpcMetrics::new(&api))
+        .build(&opt.rpc_listen_address)
+        .await?;
     let _jsonrpc_server_handle = jsonrpc_server.start(api.clone())?;
     info!("JSONRPC server listening on {}", opt.rpc_listen_address);
 
@@ -146,6 +183,7 @@ async fn main() -> anyhow::Result<()> {
                         TraceApiServerImpl {
                             db,
                             call_gas_limit: 100_000_000,
+                            analysis_cache,
                         },
                     ),
                 )
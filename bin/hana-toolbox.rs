@@ -2,27 +2,32 @@
 use hana::{
     binutil::HanaDataDir,
     consensus::{engine_factory, Consensus, ForkChoiceMode},
+    execution::analysis_cache::AnalysisCache,
     hex_to_bytes,
     kv::{
+        freezer::Freezer,
         tables::{self, BitmapKey, CHAINDATA_TABLES},
         traits::*,
     },
     models::*,
     p2p::node::NodeBuilder,
-    stagedsync,
-    stages::*,
+    stagedsync::{self, stage::*},
+    stages::{stage_util::IndexParams, *},
 };
-use anyhow::{ensure, format_err, Context};
+use anyhow::{bail, ensure, format_err, Context};
 use bytes::Bytes;
 use clap::Parser;
 use expanded_pathbuf::ExpandedPathBuf;
 use jsonrpsee::{core::client::ClientT, http_client::HttpClientBuilder, rpc_params};
-use std::{borrow::Cow, collections::BTreeMap, sync::Arc};
+use std::{borrow::Cow, collections::BTreeMap, path::Path, sync::Arc, time::Instant};
 use tokio::pin;
 use tracing::*;
 use tracing_subscriber::{prelude::*, EnvFilter};
 use url::Url;
 
+#[global_allocator]
+static ALLOCATOR: hana::replay::CountingAllocator = hana::replay::CountingAllocator;
+
 #[derive(Parser)]
 #[clap(name = "Hana Toolbox", about = "Utilities for Hana Ethereum client")]
 struct Opt {
@@ -84,6 +89,20 @@ pub enum OptCommand {
         table: String,
     },
 
+    /// Write a consistent copy of the database into a new data directory, safe while the node runs
+    Backup {
+        /// Data directory to create
+        #[clap(long)]
+        to: ExpandedPathBuf,
+    },
+
+    /// Validate a backup and copy it into the data directory
+    Restore {
+        /// Data directory of the backup
+        #[clap(long)]
+        from: ExpandedPathBuf,
+    },
+
     /// Check table equality in two databases
     CheckEqual {
         #[clap(long)]
@@ -154,6 +173,112 @@ pub enum OptCommand {
         finalized: Option<H256>,
     },
 
+    /// Export canonical pre-merge history into era1 files
+    ExportEra1 {
+        /// Directory to write era1 files to
+        #[clap(long)]
+        dir: ExpandedPathBuf,
+        /// Network name used in file names, defaults to chain name
+        #[clap(long)]
+        network: Option<String>,
+        #[clap(long, default_value = "0")]
+        from_epoch: u64,
+        /// Last epoch to export, defaults to the last epoch before the merge
+        #[clap(long)]
+        to_epoch: Option<u64>,
+    },
+
+    /// Import pre-merge history from era1 files
+    ImportEra1 {
+        /// Directory holding era1 files
+        #[clap(long)]
+        dir: ExpandedPathBuf,
+        /// File with trusted accumulator roots, one per epoch and line
+        #[clap(long)]
+        accumulators: Option<ExpandedPathBuf>,
+        /// Import without checking files against trusted accumulator roots
+        #[clap(long)]
+        insecure: bool,
+    },
+
+    /// Export canonical blocks into a chain file of concatenated RLP blocks
+    ExportChain {
+        file: ExpandedPathBuf,
+        #[clap(long, default_value = "0")]
+        from: BlockNumber,
+        /// Last block to export, defaults to the last block with a body
+        #[clap(long)]
+        to: Option<BlockNumber>,
+    },
+
+    /// Import and execute blocks from a chain file of concatenated RLP blocks
+    ImportChain {
+        file: ExpandedPathBuf,
+        /// Name of the network, used when the database is not initialized yet
+        #[clap(long)]
+        chain: Option<String>,
+        /// Chain specification file, used when the database is not initialized yet
+        #[clap(long)]
+        chain_spec_file: Option<ExpandedPathBuf>,
+    },
+
+    /// Train zstd dictionaries for compressed tables on their most recent values
+    TrainDictionaries {
+        /// Directory to write dictionaries to, `src/kv/dictionaries` embeds them into the build
+        #[clap(long)]
+        dir: ExpandedPathBuf,
+        /// Number of values to train on per table
+        #[clap(long, default_value = "100000")]
+        samples: usize,
+        /// Maximum dictionary size in bytes
+        #[clap(long, default_value = "112640")]
+        max_size: usize,
+    },
+
+    /// Verify hashed state and trie tables against plain state and the header state root
+    VerifyState {
+        /// Rebuild hashed state and trie tables if verification fails
+        #[clap(long)]
+        repair: bool,
+    },
+
+    /// Verify changesets against history indexes and re-execution, printing a JSON report
+    VerifyHistory {
+        #[clap(long, default_value = "0")]
+        from: BlockNumber,
+        /// Last block to check, defaults to the last block covered by history indexes
+        #[clap(long)]
+        to: Option<BlockNumber>,
+        /// Check only every N-th block
+        #[clap(long, default_value = "1")]
+        sample_every: u64,
+        /// Re-execute checked blocks and compare changes to changesets
+        #[clap(long)]
+        execute: bool,
+    },
+
+    /// Replay a single stage over a block range in a copy of the database and report its performance
+    BenchStage {
+        #[clap(long)]
+        stage: String,
+        #[clap(long)]
+        from: BlockNumber,
+        #[clap(long)]
+        to: BlockNumber,
+        /// Directory to create the database copy in, defaults to the data directory
+        #[clap(long)]
+        work_dir: Option<ExpandedPathBuf>,
+        /// Report of an earlier run to compare with
+        #[clap(long)]
+        baseline: Option<ExpandedPathBuf>,
+        /// Allowed regression relative to baseline
+        #[clap(long, default_value = "0.1")]
+        tolerance: f64,
+        /// File to write the report to instead of printing it
+        #[clap(long)]
+        output: Option<ExpandedPathBuf>,
+    },
+
     SetStageProgress {
         #[clap(long)]
         stage: String,
@@ -180,8 +305,13 @@ async fn download_headers(
     let _ = std::fs::remove_dir_all(&etl_temp_path);
     std::fs::create_dir_all(&etl_temp_path)?;
     let env = Arc::new(hana::kv::new_database(&CHAINDATA_TABLES, &chain_data_dir)?);
-    let consensus: Arc<dyn Consensus> =
-        engine_factory(Some(env.clone()), chain_config.chain_spec.clone(), None)?.into();
+    let consensus: Arc<dyn Consensus> = engine_factory(
+        Some((env.clone(), AnalysisCache::default())),
+        chain_config.chain_spec.clone(),
+        None,
+        None,
+    )?
+    .into();
     let txn = env.begin_mutable()?;
     hana::genesis::initialize_genesis(
         &txn,
@@ -195,6 +325,7 @@ async fn download_headers(
     let node = Arc::new(
         NodeBuilder::new(chain_config)
             .set_stash(env.clone())
+            .set_consensus(consensus.clone())
             .add_sentry(uri)
             .build()?,
     );
@@ -249,21 +380,32 @@ async fn blockhashes(data_dir: HanaDataDir) -> anyhow::Result<()> {
 fn open_db(
     data_dir: HanaDataDir,
 ) -> anyhow::Result<hana::kv::mdbx::MdbxEnvironment<mdbx::NoWriteMap>> {
-    hana::kv::mdbx::MdbxEnvironment::<mdbx::NoWriteMap>::open_ro(
+    let mut env = hana::kv::mdbx::MdbxEnvironment::<mdbx::NoWriteMap>::open_ro(
         mdbx::Environment::new(),
         &data_dir.chain_data_dir(),
         &CHAINDATA_TABLES,
-    )
+    )?;
+    let ancient_dir = data_dir.ancient_dir();
+    if ancient_dir.exists() {
+        env.set_freezer(Arc::new(Freezer::open(ancient_dir)?));
+    }
+    Ok(env)
 }
 
 fn open_db_rw(
     data_dir: HanaDataDir,
 ) -> anyhow::Result<hana::kv::mdbx::MdbxEnvironment<mdbx::NoWriteMap>> {
-    hana::kv::mdbx::MdbxEnvironment::<mdbx::NoWriteMap>::open_rw(
+    let mut env = hana::kv::mdbx::MdbxEnvironment::<mdbx::NoWriteMap>::open_rw(
         mdbx::Environment::new(),
         &data_dir.chain_data_dir(),
         &CHAINDATA_TABLES,
-    )
+    )?;
+    let ancient_dir = data_dir.ancient_dir();
+    if ancient_dir.exists() {
+        env.set_freezer(Arc::new(Freezer::open(ancient_dir)?));
+        recover_freezer(&env.begin()?)?;
+    }
+    Ok(env)
 }
 
 fn table_sizes(data_dir: HanaDataDir, csv: bool) -> anyhow::Result<()> {
@@ -355,6 +497,9 @@ fn select_db_decode(table: &str, key: &[u8], value: &[u8]) -> anyhow::Result<(St
             CallFromIndex,
             CallToIndex,
             BlockTransactionLookup,
+            ContractCreationSet,
+            ContractCreator,
+            SenderNonceIndex,
             Config,
             TxSender,
             Issuance,
@@ -462,6 +607,29 @@ fn db_drop(data_dir: HanaDataDir, table: String) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn backup(data_dir: HanaDataDir, to: ExpandedPathBuf) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let info = hana::backup::backup(&env, &HanaDataDir(to))?;
+
+    info!(
+        "Backed up {} database version {} at block #{}",
+        info.chain, info.version, info.head
+    );
+
+    Ok(())
+}
+
+fn restore(data_dir: HanaDataDir, from: ExpandedPathBuf) -> anyhow::Result<()> {
+    let info = hana::backup::restore(&HanaDataDir(from), &data_dir)?;
+
+    info!(
+        "Restored {} database version {} at block #{} into {}",
+        info.chain, info.version, info.head, data_dir
+    );
+
+    Ok(())
+}
+
 fn check_table_eq(
     db1_path: ExpandedPathBuf,
     db2_path: ExpandedPathBuf,
@@ -713,6 +881,440 @@ async fn send_chain_tip(endpoint: Url, head: H256, finalized: Option<H256>) -> a
     Ok(())
 }
 
+fn export_era1(
+    data_dir: HanaDataDir,
+    dir: ExpandedPathBuf,
+    network: Option<String>,
+    from_epoch: u64,
+    to_epoch: Option<u64>,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let tx = env.begin()?;
+
+    let network = match network {
+        Some(network) => network,
+        None => hana::accessors::chain::chain_config::read(&tx)?
+            .ok_or_else(|| format_err!("chain specification not found"))?
+            .name
+            .to_lowercase(),
+    };
+
+    std::fs::create_dir_all(&dir)?;
+
+    let mut analysis_cache = AnalysisCache::default();
+    for epoch in from_epoch..=to_epoch.unwrap_or(u64::MAX) {
+        if hana::era1::export_epoch_to_dir(&tx, &mut analysis_cache, epoch, &network, &dir)?
+            .is_none()
+        {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+async fn import_era1(
+    data_dir: HanaDataDir,
+    dir: ExpandedPathBuf,
+    accumulators: Option<ExpandedPathBuf>,
+    insecure: bool,
+) -> anyhow::Result<()> {
+    let trusted_accumulators = accumulators
+        .map(hana::era1::read_accumulators)
+        .transpose()?;
+    if trusted_accumulators.is_none() {
+        if !insecure {
+            bail!("--accumulators is required, or pass --insecure");
+        }
+        warn!("Importing era1 files without trusted accumulator roots");
+    }
+
+    let env = open_db_rw(data_dir)?;
+
+    let chain_spec = hana::accessors::chain::chain_config::read(&env.begin()?)?
+        .ok_or_else(|| format_err!("chain specification not found"))?;
+    let consensus: Arc<dyn Consensus> = engine_factory(None, chain_spec, None, None)?.into();
+
+    let mut stage = Era1Import {
+        dir: dir.to_path_buf(),
+        trusted_accumulators,
+        consensus,
+    };
+    loop {
+        let mut tx = env.begin_mutable()?;
+        let stage_progress = ERA1_IMPORT.get_progress(&tx)?;
+        let input = StageInput {
+            restarted: false,
+            first_started_at: (Instant::now(), stage_progress),
+            previous_stage: None,
+            stage_progress,
+        };
+
+        match stage.execute(&mut tx, input).await {
+            Ok(ExecOutput::Progress {
+                stage_progress,
+                done,
+                ..
+            }) => {
+                ERA1_IMPORT.save_progress(&tx, stage_progress)?;
+                tx.commit()?;
+
+                info!("Imported history up to block {stage_progress}");
+
+                if done {
+                    break;
+                }
+            }
+            Ok(ExecOutput::Unwind { unwind_to }) => {
+                bail!("unexpected unwind to block {unwind_to}")
+            }
+            Err(StageError::Validation { block, error }) => {
+                bail!("block #{block} failed validation: {error:?}")
+            }
+            Err(StageError::Internal(e)) => return Err(e),
+        }
+    }
+
+    Ok(())
+}
+
+fn export_chain(
+    data_dir: HanaDataDir,
+    file: ExpandedPathBuf,
+    from: BlockNumber,
+    to: Option<BlockNumber>,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let tx = env.begin()?;
+
+    let to = match to {
+        Some(to) => to,
+        None => hana::accessors::chain::storage_body::last(&tx)?
+            .map(|(block_number, _)| block_number)
+            .ok_or_else(|| format_err!("no blocks in database"))?,
+    };
+
+    let mut out = std::io::BufWriter::new(std::fs::File::create(&file)?);
+    let written = hana::blockfile::write(&tx, from, to, &mut out)?;
+    std::io::Write::flush(&mut out)?;
+
+    info!("Exported {written} blocks to {}", file.display());
+
+    Ok(())
+}
+
+async fn import_chain(
+    data_dir: HanaDataDir,
+    file: ExpandedPathBuf,
+    chain: Option<String>,
+    chain_spec_file: Option<ExpandedPathBuf>,
+) -> anyhow::Result<()> {
+    let mut bundled_chain_spec = false;
+    let chain_spec = if let Some(chain) = chain {
+        bundled_chain_spec = true;
+        Some(ChainSpec::load_builtin(chain)?)
+    } else if let Some(path) = chain_spec_file {
+        Some(ChainSpec::load_from_file(path)?)
+    } else {
+        None
+    };
+
+    std::fs::create_dir_all(&data_dir.0)?;
+    let etl_temp_path = data_dir.etl_temp_dir();
+    let _ = std::fs::remove_dir_all(&etl_temp_path);
+    std::fs::create_dir_all(&etl_temp_path)?;
+    let etl_temp_dir =
+        Arc::new(tempfile::tempdir_in(&etl_temp_path).context("failed to create ETL temp dir")?);
+
+    let env = Arc::new(
+        hana::kv::new_database(&CHAINDATA_TABLES, &data_dir.chain_data_dir())?
+            .with_freezer(Arc::new(Freezer::open(data_dir.ancient_dir())?)),
+    );
+    recover_freezer(&env.begin()?)?;
+    let txn = env.begin_mutable()?;
+    let (chain_spec, _) =
+        hana::genesis::initialize_genesis(&txn, &etl_temp_dir, bundled_chain_spec, chain_spec)?;
+    txn.commit()?;
+
+    let blocks = hana::blockfile::read(&file)?;
+    info!("Read {} blocks from {}", blocks.len(), file.display());
+
+    let analysis_cache = AnalysisCache::default();
+    let consensus: Arc<dyn Consensus> = engine_factory(
+        Some((env.clone(), analysis_cache.clone())),
+        chain_spec,
+        None,
+        None,
+    )?
+    .into();
+    let import = BlockImport::new(blocks, consensus);
+    let max_block = match import.last_block() {
+        Some(max_block) => max_block,
+        None => return Ok(()),
+    };
+
+    let mut staged_sync = stagedsync::StagedSync::new();
+    staged_sync.set_max_block(Some(max_block));
+    staged_sync.push(import, false);
+    staged_sync.push(TotalGasIndex, false);
+    staged_sync.push(
+        BlockHashes {
+            temp_dir: etl_temp_dir.clone(),
+        },
+        false,
+    );
+    staged_sync.push(TotalTxIndex, false);
+    staged_sync.push(IssuanceIndex, false);
+    staged_sync.push(
+        SenderRecovery {
+            batch_size: 500_000,
+        },
+        false,
+    );
+    staged_sync.push(
+        Execution {
+            max_block: Some(max_block),
+            batch_size: 5_000_000_000_000,
+            history_batch_size: 250_000_000_000,
+            exit_after_batch: false,
+            batch_until: None,
+            commit_every: None,
+            parallel: false,
+            analysis_cache,
+        },
+        false,
+    );
+    staged_sync.push(HashState::new(etl_temp_dir.clone(), None), false);
+    staged_sync.push_with_unwind_priority(Interhashes::new(etl_temp_dir.clone(), None), false, 1);
+    let index_params = IndexParams {
+        temp_dir: etl_temp_dir.clone(),
+        flush_interval: 50_000,
+    };
+    staged_sync.push(AccountHistoryIndex(index_params.clone()), false);
+    staged_sync.push(StorageHistoryIndex(index_params.clone()), false);
+    staged_sync.push(LogTopicIndex(index_params.clone()), false);
+    staged_sync.push(LogAddressIndex(index_params), false);
+    staged_sync.push(
+        TxLookup {
+            temp_dir: etl_temp_dir.clone(),
+        },
+        false,
+    );
+    staged_sync.push(
+        CallTraceIndex {
+            temp_dir: etl_temp_dir.clone(),
+            flush_interval: 50_000,
+        },
+        false,
+    );
+    staged_sync.push(ContractCreatorIndex, false);
+    staged_sync.push(
+        SenderNonceIndex {
+            temp_dir: etl_temp_dir,
+        },
+        false,
+    );
+    staged_sync.push(Finish, false);
+    staged_sync.run(&env).await?;
+
+    Ok(())
+}
+
+fn train_dictionary<T, K, E>(
+    tx: &hana::kv::mdbx::MdbxTransaction<'_, K, E>,
+    table: T,
+    dir: &Path,
+    samples: usize,
+    max_size: usize,
+) -> anyhow::Result<()>
+where
+    T: Table,
+    T::Key: TableDecode,
+    K: mdbx::TransactionKind,
+    E: mdbx::EnvironmentKind,
+{
+    let name = table.db_name();
+    let values = tx
+        .cursor(table)?
+        .walk_back(None)
+        .take(samples)
+        .map(|res| res.map(|(_, v)| v.encode().as_ref().to_vec()))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    if values.is_empty() {
+        warn!("Table {name} is empty, skipping");
+        return Ok(());
+    }
+
+    let dictionary = hana::kv::compression::train_dictionary(&values, max_size)?;
+    let path = dir.join(format!("{name}.dict"));
+    std::fs::write(&path, &dictionary)?;
+
+    info!(
+        "Trained {} byte dictionary for {name} on {} values, written to {}",
+        dictionary.len(),
+        values.len(),
+        path.display()
+    );
+
+    Ok(())
+}
+
+fn verify_state(data_dir: HanaDataDir, repair: bool) -> anyhow::Result<()> {
+    let etl_temp_path = data_dir.etl_temp_dir();
+    let _ = std::fs::remove_dir_all(&etl_temp_path);
+    std::fs::create_dir_all(&etl_temp_path)?;
+    let etl_temp_dir =
+        tempfile::tempdir_in(&etl_temp_path).context("failed to create ETL temp dir")?;
+
+    let env = open_db_rw(data_dir)?;
+    let report = hana::integrity::verify_state(env.begin_mutable()?, &etl_temp_dir)?;
+
+    for mismatch in &report.mismatches {
+        warn!("{mismatch}");
+    }
+    let omitted = report.total_mismatches - report.mismatches.len() as u64;
+    if omitted > 0 {
+        warn!("... and {omitted} more");
+    }
+
+    info!(
+        "Verified {} accounts and {} storage slots at block #{}, state root {:?}, {} mismatches",
+        report.accounts,
+        report.storage_slots,
+        report.block,
+        report.state_root,
+        report.total_mismatches
+    );
+
+    if !report.is_ok() {
+        if !repair {
+            bail!("state is inconsistent, run with --repair to rebuild hashed state and trie");
+        }
+
+        let txn = env.begin_mutable()?;
+        let block = hana::integrity::repair_state(&txn, &etl_temp_dir)?;
+        txn.commit()?;
+
+        info!("Repaired state at block #{block}");
+    }
+
+    Ok(())
+}
+
+fn verify_history(
+    data_dir: HanaDataDir,
+    from: BlockNumber,
+    to: Option<BlockNumber>,
+    sample_every: u64,
+    execute: bool,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let txn = env.begin()?;
+
+    let to = match to {
+        Some(to) => to,
+        None => [EXECUTION, ACCOUNT_HISTORY_INDEX, STORAGE_HISTORY_INDEX]
+            .into_iter()
+            .map(|stage| Ok(stage.get_progress(&txn)?.unwrap_or_default()))
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .min()
+            .unwrap(),
+    };
+
+    let report = hana::integrity::verify_history(&txn, from..=to, sample_every, execute)?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+
+    ensure!(report.is_ok(), "found {} mismatches", report.total_mismatches);
+
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn bench_stage(
+    data_dir: HanaDataDir,
+    stage: String,
+    from: BlockNumber,
+    to: BlockNumber,
+    work_dir: Option<ExpandedPathBuf>,
+    baseline: Option<ExpandedPathBuf>,
+    tolerance: f64,
+    output: Option<ExpandedPathBuf>,
+) -> anyhow::Result<()> {
+    let baseline = baseline
+        .map(|path| -> anyhow::Result<hana::replay::ReplayReport> {
+            Ok(serde_json::from_slice(&std::fs::read(&path)?)?)
+        })
+        .transpose()
+        .context("failed to read baseline")?;
+
+    let work_dir = work_dir.unwrap_or_else(|| data_dir.0.clone()).0;
+    std::fs::create_dir_all(&work_dir)?;
+    let copy_dir = tempfile::tempdir_in(&work_dir).context("failed to create work dir")?;
+    let copy_data_dir = HanaDataDir(ExpandedPathBuf(copy_dir.path().join("datadir")));
+
+    info!("Copying database into {copy_data_dir}");
+    hana::backup::backup(&open_db(data_dir)?, &copy_data_dir)?;
+
+    let etl_temp_path = copy_data_dir.etl_temp_dir();
+    std::fs::create_dir_all(&etl_temp_path)?;
+    let etl_temp_dir =
+        Arc::new(tempfile::tempdir_in(&etl_temp_path).context("failed to create ETL temp dir")?);
+
+    let env = open_db_rw(copy_data_dir)?;
+    let report = hana::replay::replay_stage(&env, etl_temp_dir, &stage, from, to).await?;
+
+    let json = serde_json::to_string_pretty(&report)?;
+    match output {
+        Some(path) => std::fs::write(path, json)?,
+        None => println!("{json}"),
+    }
+
+    info!(
+        "Replayed {} blocks of {stage} in {:.2}s, {:.2} blocks/s, {:.2} Mgas/s",
+        to.0 + 1 - from.0,
+        report.seconds,
+        report.blocks_per_second,
+        report.mgas_per_second
+    );
+
+    if let Some(baseline) = baseline {
+        let regressions = report.regressions(&baseline, tolerance)?;
+        for regression in &regressions {
+            warn!("Regression: {regression}");
+        }
+        ensure!(
+            regressions.is_empty(),
+            "{} regressions against baseline",
+            regressions.len()
+        );
+    }
+
+    Ok(())
+}
+
+fn train_dictionaries(
+    data_dir: HanaDataDir,
+    dir: ExpandedPathBuf,
+    samples: usize,
+    max_size: usize,
+) -> anyhow::Result<()> {
+    let env = open_db(data_dir)?;
+    let tx = env.begin()?;
+
+    std::fs::create_dir_all(&dir)?;
+
+    train_dictionary(&tx, tables::Code, &dir, samples, max_size)?;
+    train_dictionary(&tx, tables::BlockTransaction, &dir, samples, max_size)?;
+
+    warn!(
+        "Embedding new dictionaries requires a database version bump and a migration \
+         recompressing the tables, existing databases refuse to open otherwise"
+    );
+
+    Ok(())
+}
+
 fn set_stage_progress(
     data_dir: HanaDataDir,
     stage: String,
@@ -758,6 +1360,8 @@ async fn main() -> anyhow::Result<()> {
         OptCommand::DbSet { table, key, value } => db_set(opt.data_dir, table, key, Some(value))?,
         OptCommand::DbUnset { table, key } => db_set(opt.data_dir, table, key, None)?,
         OptCommand::DbDrop { table } => db_drop(opt.data_dir, table)?,
+        OptCommand::Backup { to } => backup(opt.data_dir, to)?,
+        OptCommand::Restore { from } => restore(opt.data_dir, from)?,
         OptCommand::CheckEqual { db1, db2, table } => check_table_eq(db1, db2, table)?,
         OptCommand::ReadBlock { block_number } => read_block(opt.data_dir, block_number)?,
         OptCommand::ReadAccount {
@@ -778,6 +1382,56 @@ async fn main() -> anyhow::Result<()> {
             head,
             finalized,
         } => send_chain_tip(endpoint, head, finalized).await?,
+        OptCommand::ExportEra1 {
+            dir,
+            network,
+            from_epoch,
+            to_epoch,
+        } => export_era1(opt.data_dir, dir, network, from_epoch, to_epoch)?,
+        OptCommand::ImportEra1 {
+            dir,
+            accumulators,
+            insecure,
+        } => import_era1(opt.data_dir, dir, accumulators, insecure).await?,
+        OptCommand::ExportChain { file, from, to } => export_chain(opt.data_dir, file, from, to)?,
+        OptCommand::ImportChain {
+            file,
+            chain,
+            chain_spec_file,
+        } => import_chain(opt.data_dir, file, chain, chain_spec_file).await?,
+        OptCommand::TrainDictionaries {
+            dir,
+            samples,
+            max_size,
+        } => train_dictionaries(opt.data_dir, dir, samples, max_size)?,
+        OptCommand::VerifyState { repair } => verify_state(opt.data_dir, repair)?,
+        OptCommand::VerifyHistory {
+            from,
+            to,
+            sample_every,
+            execute,
+        } => verify_history(opt.data_dir, from, to, sample_every, execute)?,
+        OptCommand::BenchStage {
+            stage,
+            from,
+            to,
+            work_dir,
+            baseline,
+            tolerance,
+            output,
+        } => {
+            bench_stage(
+                opt.data_dir,
+                stage,
+                from,
+                to,
+                work_dir,
+                baseline,
+                tolerance,
+                output,
+            )
+            .await?
+        }
         OptCommand::SetStageProgress { stage, progress } => {
             set_stage_progress(opt.data_dir, stage, Some(progress))?
         }
@@ -1,14 +1,30 @@
 use hana::{
     hana_tracing::{self, Component},
     binutil::HanaDataDir,
-    consensus::{engine_factory, Consensus, ForkChoiceMode},
-    kv::tables::CHAINDATA_TABLES,
+    consensus::{
+        engine_factory,
+        light_client::{source_from_str, LightClientOptions},
+        Consensus, ForkChoiceMode,
+    },
+    execution::analysis_cache::AnalysisCache,
+    health,
+    kv::{freezer::Freezer, tables::CHAINDATA_TABLES},
+    metrics,
     models::*,
     p2p::node::NodeBuilder,
     rpc::{
-        debug::DebugApiServerImpl, erigon::ErigonApiServerImpl, eth::EthApiServerImpl,
-        net::NetApiServerImpl, otterscan::OtterscanApiServerImpl, parity::ParityApiServerImpl,
-        trace::TraceApiServerImpl, web3::Web3ApiServerImpl,
+        admin::{AdminApiServerImpl, BackupApiServerImpl},
+        debug::DebugApiServerImpl,
+        debug_session::DebugSessionApiServerImpl,
+        erigon::ErigonApiServerImpl,
+        eth::EthApiServerImpl,
+        issuance::IssuanceApiServerImpl,
+        les::{self, LesApiServerImpl},
+        net::NetApiServerImpl,
+        otterscan::OtterscanApiServerImpl,
+        parity::ParityApiServerImpl,
+        trace::TraceApiServerImpl,
+        web3::Web3ApiServerImpl,
     },
     stagedsync,
     stages::{stage_util::IndexParams, *},
@@ -53,6 +69,22 @@ pub struct Opt {
     #[clap(flatten)]
     pub sentry_opts: hana::sentry::Opts,
 
+    /// Import pre-merge history from era1 files in this directory before downloading.
+    #[clap(long)]
+    pub era1_dir: Option<ExpandedPathBuf>,
+
+    /// File with trusted era1 accumulator roots, one per epoch and line.
+    #[clap(long)]
+    pub era1_accumulators: Option<ExpandedPathBuf>,
+
+    /// Import era1 files without checking them against trusted accumulator roots.
+    #[clap(long)]
+    pub era1_insecure: bool,
+
+    /// Move headers, bodies and transactions older than this many blocks into ancient storage.
+    #[clap(long)]
+    pub freezer_threshold: Option<u64>,
+
     /// Last block where to sync to.
     #[clap(long)]
     pub max_block: Option<BlockNumber>,
@@ -81,6 +113,14 @@ pub struct Opt {
     #[clap(long)]
     pub execution_exit_after_batch: bool,
 
+    /// Execute transactions within a block in parallel.
+    #[clap(long)]
+    pub execution_parallel: bool,
+
+    /// Size of code analysis cache shared by execution and RPC (MiB).
+    #[clap(long, default_value = "256")]
+    pub analysis_cache_size: usize,
+
     /// Skip commitment (state root) verification.
     #[clap(long)]
     pub skip_commitment: bool,
@@ -97,7 +137,8 @@ pub struct Opt {
     #[clap(long)]
     pub no_rpc: bool,
 
-    /// Enable API options
+    /// Enable API options. The admin API is never enabled by default, admin peer management
+    /// requires the built-in sentry.
     #[clap(long)]
     pub enable_api: Option<String>,
 
@@ -116,6 +157,27 @@ pub struct Opt {
     /// Path to JWT secret file.
     #[clap(long)]
     pub jwt_secret_path: Option<ExpandedPathBuf>,
+
+    /// Follow the beacon chain with the built-in light client instead of a CL. Takes a beacon API
+    /// URL as 'http://host:port' or a directory of recorded beacon API responses.
+    #[clap(long, requires = "beacon_checkpoint")]
+    pub beacon_light_client: Option<String>,
+
+    /// Trusted beacon block root for the light client to bootstrap from.
+    #[clap(long)]
+    pub beacon_checkpoint: Option<H256>,
+
+    /// Enable Prometheus metrics at this IP address and port.
+    #[clap(long)]
+    pub metrics_listen_address: Option<SocketAddr>,
+
+    /// Enable health and readiness probes at this IP address and port.
+    #[clap(long)]
+    pub health_listen_address: Option<SocketAddr>,
+
+    /// Serve light clients (les API) at this IP address and port.
+    #[clap(long)]
+    pub les_listen_address: Option<SocketAddr>,
 }
 
 #[allow(unreachable_code)]
@@ -155,12 +217,13 @@ fn main() -> anyhow::Result<()> {
                     tempfile::tempdir_in(&etl_temp_path)
                         .context("failed to create ETL temp dir")?,
                 );
-                let db = Arc::new(hana::kv::new_database(
-                    &CHAINDATA_TABLES,
-                    &hana_chain_data_dir,
-                )?);
+                let db = Arc::new(
+                    hana::kv::new_database(&CHAINDATA_TABLES, &hana_chain_data_dir)?
+                        .with_freezer(Arc::new(Freezer::open(opt.datadir.ancient_dir())?)),
+                );
 
                 hana::database_version::migrate_database(&db)?;
+                recover_freezer(&db.begin()?)?;
 
                 let chainspec = {
                     let span = span!(Level::INFO, "", " Genesis initialization ");
@@ -181,6 +244,32 @@ fn main() -> anyhow::Result<()> {
 
                 info!("Current network: {}", chainspec.name);
 
+                if let Some(metrics_listen_address) = opt.metrics_listen_address {
+                    tokio::spawn(async move {
+                        if let Err(e) = metrics::serve(metrics_listen_address).await {
+                            error!("Metrics server failed: {e:?}");
+                        }
+                    });
+                    tokio::spawn(metrics::run_db_stats_loop(
+                        db.clone(),
+                        Duration::from_secs(30),
+                    ));
+                }
+
+                let analysis_cache =
+                    AnalysisCache::new(opt.analysis_cache_size.saturating_mul(1024 * 1024));
+                let warmed_up = analysis_cache.warm_up(&db.begin()?)?;
+                info!("Loaded {} contracts into analysis cache", warmed_up);
+
+                if let Some(les_listen_address) = opt.les_listen_address {
+                    let api = LesApiServerImpl::new(db.clone(), analysis_cache.clone());
+                    tokio::spawn(async move {
+                        if let Err(e) = les::serve(les_listen_address, api).await {
+                            error!("LES server failed: {e:?}");
+                        }
+                    });
+                }
+
                 let jwt_secret_path = opt
                     .jwt_secret_path
                     .map(|v| v.0)
@@ -201,10 +290,22 @@ fn main() -> anyhow::Result<()> {
                     file.flush()?;
                 }
 
+                let light_client = opt
+                    .beacon_light_client
+                    .as_deref()
+                    .map(|source| {
+                        anyhow::Ok(LightClientOptions {
+                            source: source_from_str(source)?,
+                            checkpoint: opt.beacon_checkpoint.unwrap(),
+                        })
+                    })
+                    .transpose()?;
+
                 let consensus: Arc<dyn Consensus> = engine_factory(
-                    Some(db.clone()),
+                    Some((db.clone(), analysis_cache.clone())),
                     chainspec.clone(),
                     Some(opt.engine_listen_address),
+                    light_client,
                 )?
                 .into();
 
@@ -212,15 +313,81 @@ fn main() -> anyhow::Result<()> {
 
                 let chain_config = ChainConfig::from(chainspec);
 
+                // staged sync setup
+                let mut staged_sync = stagedsync::StagedSync::new();
+                staged_sync.set_min_progress_to_commit_after_stage(1024);
+                staged_sync.set_max_block(opt.max_block);
+                staged_sync.start_with_unwind(opt.start_with_unwind);
+                staged_sync.set_exit_after_sync(opt.exit_after_sync);
+
+                if opt.delay_after_sync > 0 {
+                    staged_sync
+                        .set_delay_after_sync(Some(Duration::from_millis(opt.delay_after_sync)));
+                }
+
+                if let Some(health_listen_address) = opt.health_listen_address {
+                    let reached_tip = staged_sync.reached_tip();
+                    tokio::spawn(async move {
+                        if let Err(e) = health::serve(health_listen_address, reached_tip).await {
+                            error!("Health server failed: {e:?}");
+                        }
+                    });
+                }
+
+                let (sentries, swarm) = if let Some(raw_str) = opt.sentry_api_addr {
+                    (
+                        raw_str
+                            .split(',')
+                            .filter_map(|s| s.parse::<Uri>().ok())
+                            .collect::<Vec<_>>(),
+                        None,
+                    )
+                } else {
+                    let max_peers = opt.sentry_opts.max_peers;
+                    let sentry_api_addr = opt.sentry_opts.sentry_addr;
+                    let listen_addr =
+                        SocketAddr::new(opt.sentry_opts.listen_addr, opt.sentry_opts.listen_port);
+                    let swarm = hana::sentry::run(
+                        opt.sentry_opts,
+                        opt.datadir,
+                        chain_config.chain_spec.p2p.clone(),
+                    )
+                    .await?;
+
+                    let current_stage = staged_sync.current_stage();
+
+                    tokio::spawn({
+                        let swarm = swarm.clone();
+                        async move {
+                            loop {
+                                if let Some(stage) = *current_stage.borrow() {
+                                    if stage == HEADERS || stage == BODIES {
+                                        info!(
+                                            "P2P node peer info: {} active (+{} dialing) / {} max.",
+                                            swarm.connected_peers(),
+                                            swarm.dialing(),
+                                            max_peers
+                                        );
+                                    }
+                                }
+
+                                sleep(Duration::from_secs(5)).await;
+                            }
+                        }
+                    });
+
+                    (
+                        vec![format!("http://{sentry_api_addr}").parse()?],
+                        Some((swarm, listen_addr)),
+                    )
+                };
+
                 if !opt.no_rpc {
                     tokio::spawn({
                         let db = db.clone();
+                        let analysis_cache = analysis_cache.clone();
+                        let swarm = swarm.clone();
                         async move {
-                            let jsonrpc_server = ServerBuilder::default()
-                                .build(&opt.rpc_listen_address)
-                                .await
-                                .unwrap();
-
                             let mut api = Methods::new();
 
                             let api_options = opt
@@ -238,6 +405,7 @@ fn main() -> anyhow::Result<()> {
                                     EthApiServerImpl {
                                         db: db.clone(),
                                         call_gas_limit: 100_000_000,
+                                        analysis_cache: analysis_cache.clone(),
                                     }
                                     .into_rpc(),
                                 )
@@ -254,11 +422,22 @@ fn main() -> anyhow::Result<()> {
                                     .unwrap();
                             }
 
-                            if api_options.is_empty() || api_options.contains("otterscan") {
-                                api.merge(OtterscanApiServerImpl { db: db.clone() }.into_rpc())
+                            if api_options.is_empty() || api_options.contains("issuance") {
+                                api.merge(IssuanceApiServerImpl { db: db.clone() }.into_rpc())
                                     .unwrap();
                             }
 
+                            if api_options.is_empty() || api_options.contains("otterscan") {
+                                api.merge(
+                                    OtterscanApiServerImpl {
+                                        db: db.clone(),
+                                        analysis_cache: analysis_cache.clone(),
+                                    }
+                                    .into_rpc(),
+                                )
+                                .unwrap();
+                            }
+
                             if api_options.is_empty() || api_options.contains("parity") {
                                 api.merge(ParityApiServerImpl { db: db.clone() }.into_rpc())
                                     .unwrap();
@@ -269,16 +448,45 @@ fn main() -> anyhow::Result<()> {
                                     TraceApiServerImpl {
                                         db: db.clone(),
                                         call_gas_limit: 100_000_000,
+                                        analysis_cache: analysis_cache.clone(),
                                     }
                                     .into_rpc(),
                                 )
                                 .unwrap();
                             }
 
+                            if api_options.is_empty() || api_options.contains("debug") {
+                                api.merge(
+                                    DebugSessionApiServerImpl::new(
+                                        db.clone(),
+                                        100_000_000,
+                                        analysis_cache.clone(),
+                                    )
+                                    .into_rpc(),
+                                )
+                                .unwrap();
+                            }
+
+                            if api_options.contains("admin") {
+                                api.merge(BackupApiServerImpl::new(db.clone()).into_rpc())
+                                    .unwrap();
+                                if let Some((swarm, listen_addr)) = swarm {
+                                    api.merge(AdminApiServerImpl { swarm, listen_addr }.into_rpc())
+                                        .unwrap();
+                                } else {
+                                    warn!("Admin API is only available with built-in sentry");
+                                }
+                            }
+
                             if api_options.is_empty() || api_options.contains("web3") {
                                 api.merge(Web3ApiServerImpl.into_rpc()).unwrap();
                             }
 
+                            let jsonrpc_server = ServerBuilder::default()
+                                .set_logger(metrics::RpcMetrics::new(&api))
+                                .build(&opt.rpc_listen_address)
+                                .await
+                                .unwrap();
                             let _jsonrpc_server_handle = jsonrpc_server.start(api.clone()).unwrap();
                             info!("JSONRPC server listening on {}", opt.rpc_listen_address);
 
@@ -288,6 +496,7 @@ fn main() -> anyhow::Result<()> {
 
                     tokio::spawn({
                         let db = db.clone();
+                        let analysis_cache = analysis_cache.clone();
                         async move {
                             info!("Starting gRPC server on {}", opt.grpc_listen_address);
                             let mut builder = tonic::transport::Server::builder();
@@ -313,6 +522,7 @@ fn main() -> anyhow::Result<()> {
                                     TraceApiServerImpl {
                                         db,
                                         call_gas_limit: 100_000_000,
+                                        analysis_cache,
                                     },
                                 ),
                             )
@@ -323,61 +533,27 @@ fn main() -> anyhow::Result<()> {
                     });
                 }
 
-                // staged sync setup
-                let mut staged_sync = stagedsync::StagedSync::new();
-                staged_sync.set_min_progress_to_commit_after_stage(1024);
-                staged_sync.set_max_block(opt.max_block);
-                staged_sync.start_with_unwind(opt.start_with_unwind);
-                staged_sync.set_exit_after_sync(opt.exit_after_sync);
-
-                if opt.delay_after_sync > 0 {
-                    staged_sync
-                        .set_delay_after_sync(Some(Duration::from_millis(opt.delay_after_sync)));
+                let mut builder = NodeBuilder::new(chain_config.clone())
+                    .set_stash(db.clone())
+                    .set_consensus(consensus.clone());
+                for sentry_api_addr in sentries {
+                    builder = builder.add_sentry(sentry_api_addr);
                 }
 
-                let sentries = if let Some(raw_str) = opt.sentry_api_addr {
-                    raw_str
-                        .split(',')
-                        .filter_map(|s| s.parse::<Uri>().ok())
-                        .collect::<Vec<_>>()
-                } else {
-                    let max_peers = opt.sentry_opts.max_peers;
-                    let sentry_api_addr = opt.sentry_opts.sentry_addr;
-                    let swarm = hana::sentry::run(
-                        opt.sentry_opts,
-                        opt.datadir,
-                        chain_config.chain_spec.p2p.clone(),
-                    )
-                    .await?;
-
-                    let current_stage = staged_sync.current_stage();
+                let node = Arc::new(builder.build()?);
 
-                    tokio::spawn(async move {
-                        loop {
-                            if let Some(stage) = *current_stage.borrow() {
-                                if stage == HEADERS || stage == BODIES {
-                                    info!(
-                                        "P2P node peer info: {} active (+{} dialing) / {} max.",
-                                        swarm.connected_peers(),
-                                        swarm.dialing(),
-                                        max_peers
-                                    );
-                                }
+                if opt.metrics_listen_address.is_some() {
+                    tokio::spawn({
+                        let node = node.clone();
+                        async move {
+                            loop {
+                                metrics::P2P_PEERS.set(node.total_peers().await as i64);
+                                sleep(Duration::from_secs(5)).await;
                             }
-
-                            sleep(Duration::from_secs(5)).await;
                         }
                     });
-
-                    vec![format!("http://{sentry_api_addr}").parse()?]
-                };
-
-                let mut builder = NodeBuilder::new(chain_config.clone()).set_stash(db.clone());
-                for sentry_api_addr in sentries {
-                    builder = builder.add_sentry(sentry_api_addr);
                 }
 
-                let node = Arc::new(builder.build()?);
                 let tip_discovery =
                     !matches!(consensus.fork_choice_mode(), ForkChoiceMode::External(_));
 
@@ -388,6 +564,28 @@ fn main() -> anyhow::Result<()> {
                     }
                 });
 
+                if let Some(era1_dir) = opt.era1_dir {
+                    let trusted_accumulators = opt
+                        .era1_accumulators
+                        .map(hana::era1::read_accumulators)
+                        .transpose()?;
+                    if trusted_accumulators.is_none() {
+                        if !opt.era1_insecure {
+                            anyhow::bail!(
+                                "--era1-accumulators is required, or pass --era1-insecure"
+                            );
+                        }
+                        warn!("Importing era1 files without trusted accumulator roots");
+                    }
+                    staged_sync.push(
+                        Era1Import {
+                            dir: era1_dir.to_path_buf(),
+                            trusted_accumulators,
+                            consensus: consensus.clone(),
+                        },
+                        false,
+                    );
+                }
                 staged_sync.push(
                     HeaderDownload {
                         node: node.clone(),
@@ -406,6 +604,7 @@ fn main() -> anyhow::Result<()> {
                 );
                 staged_sync.push(BodyDownload { node, consensus }, false);
                 staged_sync.push(TotalTxIndex, false);
+                staged_sync.push(IssuanceIndex, false);
                 staged_sync.push(
                     SenderRecovery {
                         batch_size: opt.sender_recovery_batch_size.try_into().unwrap(),
@@ -422,6 +621,8 @@ fn main() -> anyhow::Result<()> {
                         exit_after_batch: opt.execution_exit_after_batch,
                         batch_until: None,
                         commit_every: None,
+                        parallel: opt.execution_parallel,
+                        analysis_cache,
                     },
                     false,
                 );
@@ -454,6 +655,16 @@ fn main() -> anyhow::Result<()> {
                     },
                     true,
                 );
+                staged_sync.push(ContractCreatorIndex, true);
+                staged_sync.push(
+                    SenderNonceIndex {
+                        temp_dir: etl_temp_dir.clone(),
+                    },
+                    true,
+                );
+                if let Some(threshold) = opt.freezer_threshold {
+                    staged_sync.push(Freeze { threshold }, true);
+                }
                 staged_sync.push(Finish, true);
 
                 info!("Running staged sync");
@@ -1,9 +1,26 @@
 use crate::{
-    kv::{mdbx::*, tables},
+    kv::{freezer::Freezer, mdbx::*, tables, traits::TryGenIter},
     models::*,
 };
+use anyhow::format_err;
 use tracing::*;
 
+/// Rescans the freezer after `key` was not found in MDBX, since another process sharing the
+/// freezer may have frozen and pruned it. Returns the freezer if `key` is frozen now.
+fn refreshed_freezer<'env, K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'env, K, E>,
+    key: u64,
+    frozen: impl Fn(&Freezer) -> u64,
+) -> anyhow::Result<Option<&'env Freezer>> {
+    if let Some(freezer) = tx.freezer() {
+        if freezer.refresh()? > 0 && key < frozen(freezer) {
+            return Ok(Some(freezer));
+        }
+    }
+
+    Ok(None)
+}
+
 pub mod canonical_hash {
     use super::*;
 
@@ -53,7 +70,59 @@ pub mod header {
         let number = number.into();
         trace!("Reading header for block number {}", number);
 
-        tx.get(tables::Header, number)
+        if let Some(freezer) = tx.freezer() {
+            if number.0 < freezer.frozen_blocks() {
+                return freezer.header(number);
+            }
+        }
+
+        if let Some(header) = tx.get(tables::Header, number)? {
+            return Ok(Some(header));
+        }
+
+        if let Some(freezer) = refreshed_freezer(tx, number.0, Freezer::frozen_blocks)? {
+            return freezer.header(number);
+        }
+
+        Ok(None)
+    }
+
+    /// Walks headers starting from `from`, frozen ones first.
+    pub fn walk<'db: 'tx, 'tx, K: TransactionKind, E: EnvironmentKind>(
+        tx: &'tx MdbxTransaction<'db, K, E>,
+        from: BlockNumber,
+    ) -> impl Iterator<Item = anyhow::Result<(BlockNumber, BlockHeader)>> + 'tx {
+        TryGenIter::from(move || {
+            let mut number = from;
+            loop {
+                if let Some(freezer) = tx.freezer() {
+                    while number.0 < freezer.frozen_blocks() {
+                        let header = freezer
+                            .header(number)?
+                            .ok_or_else(|| format_err!("frozen header #{number} not found"))?;
+                        yield (number, header);
+                        number.0 += 1;
+                    }
+                }
+
+                let mut walker = tx.cursor(tables::Header)?.walk(Some(number));
+                let first = walker.next().transpose()?;
+                if first.as_ref().map_or(true, |(first, _)| *first != number)
+                    && refreshed_freezer(tx, number.0, Freezer::frozen_blocks)?.is_some()
+                {
+                    continue;
+                }
+
+                if let Some(entry) = first {
+                    yield entry;
+                    while let Some(entry) = walker.next().transpose()? {
+                        yield entry;
+                    }
+                }
+
+                return Ok(());
+            }
+        })
     }
 }
 
@@ -72,15 +141,29 @@ pub mod tx {
             base_tx_id
         );
 
-        if amount > 0 {
-            tx.cursor(tables::BlockTransaction)?
-                .walk(Some(base_tx_id))
-                .take(amount)
-                .map(|res| res.map(|(_, v)| v))
-                .collect()
-        } else {
-            Ok(vec![])
+        if let Some(freezer) = tx.freezer() {
+            if *base_tx_id < freezer.frozen_transactions() {
+                return freezer.transactions(base_tx_id, amount);
+            }
         }
+
+        if amount == 0 {
+            return Ok(vec![]);
+        }
+
+        let txs = tx
+            .cursor(tables::BlockTransaction)?
+            .walk(Some(base_tx_id))
+            .take(amount)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        if txs.first().map_or(true, |(id, _)| *id != base_tx_id) {
+            if let Some(freezer) = refreshed_freezer(tx, *base_tx_id, Freezer::frozen_transactions)?
+            {
+                return freezer.transactions(base_tx_id, amount);
+            }
+        }
+
+        Ok(txs.into_iter().map(|(_, v)| v).collect())
     }
 
     pub fn write<'db, E: EnvironmentKind>(
@@ -151,7 +234,85 @@ pub mod storage_body {
         let number = number.into();
         trace!("Reading storage body for block {number}");
 
-        tx.get(tables::BlockBody, number)
+        if let Some(freezer) = tx.freezer() {
+            if number.0 < freezer.frozen_blocks() {
+                return freezer.body(number);
+            }
+        }
+
+        if let Some(body) = tx.get(tables::BlockBody, number)? {
+            return Ok(Some(body));
+        }
+
+        if let Some(freezer) = refreshed_freezer(tx, number.0, Freezer::frozen_blocks)? {
+            return freezer.body(number);
+        }
+
+        Ok(None)
+    }
+
+    /// Latest stored body, frozen or not.
+    pub fn last<K, E>(
+        tx: &MdbxTransaction<'_, K, E>,
+    ) -> anyhow::Result<Option<(BlockNumber, BodyForStorage)>>
+    where
+        K: TransactionKind,
+        E: EnvironmentKind,
+    {
+        if let Some(entry) = tx.cursor(tables::BlockBody)?.last()? {
+            return Ok(Some(entry));
+        }
+
+        if let Some(freezer) = tx.freezer() {
+            if let Some(number) = freezer.frozen_blocks().checked_sub(1) {
+                let number = BlockNumber(number);
+                return Ok(freezer.body(number)?.map(|body| (number, body)));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Walks bodies starting from `from`, frozen ones first.
+    pub fn walk<'db: 'tx, 'tx, K, E>(
+        tx: &'tx MdbxTransaction<'db, K, E>,
+        from: BlockNumber,
+    ) -> impl Iterator<Item = anyhow::Result<(BlockNumber, BodyForStorage)>> + 'tx
+    where
+        K: TransactionKind,
+        E: EnvironmentKind,
+    {
+        TryGenIter::from(move || {
+            let mut number = from;
+            loop {
+                if let Some(freezer) = tx.freezer() {
+                    while number.0 < freezer.frozen_blocks() {
+                        let body = freezer
+                            .body(number)?
+                            .ok_or_else(|| format_err!("frozen body #{number} not found"))?;
+                        yield (number, body);
+                        number.0 += 1;
+                    }
+                }
+
+                let mut walker = tx.cursor(tables::BlockBody)?.walk(Some(number));
+                let first = walker.next().transpose()?;
+                if first.as_ref().map_or(true, |(first, _)| *first != number)
+                    && refreshed_freezer(tx, number.0, Freezer::frozen_blocks)?.is_some()
+                {
+                    continue;
+                }
+
+                if let Some(entry) = first {
+                    yield entry;
+                    while let Some(entry) = walker.next().transpose()? {
+                        yield entry;
+                    }
+                }
+
+                return Ok(());
+            }
+        })
     }
 
     pub fn write<E>(
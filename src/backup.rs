@@ -0,0 +1,247 @@
+//! Consistent copies of a live database and validation of restored ones.
+
+use crate::{
+    accessors::chain,
+    binutil::HanaDataDir,
+    database_version::{get_database_version, DATABASE_VERSION},
+    kv::{mdbx::*, tables::CHAINDATA_TABLES, CustomTable},
+    models::*,
+    stages::{BODIES, EXECUTION, FINISH, HEADERS},
+};
+use anyhow::{ensure, format_err};
+use serde::Serialize;
+use std::path::Path;
+use tracing::*;
+
+/// Entries copied per write transaction of the backup.
+const COMMIT_EVERY: usize = 1_000_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotInfo {
+    pub version: u64,
+    pub chain: String,
+    pub headers: BlockNumber,
+    pub head: BlockNumber,
+}
+
+/// Checks that the database is one this build can open: known version, chain specification and
+/// stage progress consistent with the data in it.
+pub fn validate<K, E>(txn: &MdbxTransaction<'_, K, E>) -> anyhow::Result<SnapshotInfo>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    let version = get_database_version(txn)?;
+    ensure!(version > 0, "database version is not set");
+    ensure!(
+        version <= DATABASE_VERSION,
+        "database version {version} is newer than supported version {DATABASE_VERSION}"
+    );
+
+    let chain_spec = chain::chain_config::read(txn)?
+        .ok_or_else(|| format_err!("no chain specification in database"))?;
+
+    let headers = HEADERS
+        .get_progress(txn)?
+        .ok_or_else(|| format_err!("no {HEADERS} stage progress in database"))?;
+    let mut prev = (HEADERS, headers);
+    for stage in [BODIES, EXECUTION, FINISH] {
+        let progress = stage.get_progress(txn)?.unwrap_or_default();
+        ensure!(
+            progress <= prev.1,
+            "{stage} progress {progress} is ahead of {} progress {}",
+            prev.0,
+            prev.1
+        );
+        prev = (stage, progress);
+    }
+
+    ensure!(
+        chain::canonical_hash::read(txn, headers)?.is_some()
+            && chain::header::read(txn, headers)?.is_some(),
+        "no canonical header for block #{headers}"
+    );
+
+    Ok(SnapshotInfo {
+        version,
+        chain: chain_spec.name,
+        headers,
+        head: prev.1,
+    })
+}
+
+fn copy_table<K, E>(
+    txn: &MdbxTransaction<'_, K, E>,
+    dest: &MdbxEnvironment<WriteMap>,
+    table: &'static str,
+    dup_sort: bool,
+) -> anyhow::Result<u64>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    let mut src = txn.cursor(CustomTable::from(table.to_string()))?;
+    let mut entry = src.first()?;
+    let mut copied = 0;
+    while entry.is_some() {
+        let dest_txn = dest.begin_mutable()?;
+        {
+            let mut cursor = dest_txn.cursor(CustomTable::from(table.to_string()))?;
+            let mut batch = 0;
+            while let Some((key, value)) = entry.take() {
+                if dup_sort {
+                    cursor.put(key, value)?;
+                } else {
+                    cursor.append(key, value)?;
+                }
+                entry = src.next()?;
+
+                batch += 1;
+                if batch == COMMIT_EVERY {
+                    break;
+                }
+            }
+            copied += batch as u64;
+        }
+        dest_txn.commit()?;
+    }
+
+    Ok(copied)
+}
+
+/// Copies the database and its frozen segments into a new data directory as of a single read
+/// transaction, so the node can keep running meanwhile. The copy is compacted as a side effect.
+pub fn backup<E>(env: &MdbxEnvironment<E>, dest: &HanaDataDir) -> anyhow::Result<SnapshotInfo>
+where
+    E: EnvironmentKind,
+{
+    ensure!(
+        !dest.exists() || dest.read_dir()?.next().is_none(),
+        "backup directory {} is not empty",
+        dest.display()
+    );
+
+    let txn = env.begin()?;
+    let info = validate(&txn)?;
+    let frozen = env.freezer().map(|freezer| freezer.frozen_blocks());
+
+    info!(
+        "Backing up {} database at block #{} into {}",
+        info.chain,
+        info.head,
+        dest.display()
+    );
+
+    let dest_env = crate::kv::new_database(&CHAINDATA_TABLES, &dest.chain_data_dir())?;
+    for (table, table_info) in CHAINDATA_TABLES.iter() {
+        let copied = copy_table(&txn, &dest_env, table, table_info.dup_sort)?;
+        debug!("Copied {copied} entries of {table}");
+    }
+
+    // Segments frozen after the transaction began are copied too, their rows still in the copy
+    // are pruned by the next run of the freeze stage.
+    if let (Some(freezer), Some(frozen)) = (env.freezer(), frozen) {
+        let copied = freezer.copy_to(&dest.ancient_dir())?;
+        debug!("Copied {copied} frozen blocks, {frozen} were frozen at backup start");
+    }
+
+    info!("Backup complete");
+
+    Ok(info)
+}
+
+/// Validates a backup and copies it into an empty data directory.
+pub fn restore(backup: &HanaDataDir, dest: &HanaDataDir) -> anyhow::Result<SnapshotInfo> {
+    ensure!(
+        !dest.chain_data_dir().exists(),
+        "database already exists at {}",
+        dest.chain_data_dir().display()
+    );
+
+    let mut env = MdbxEnvironment::<NoWriteMap>::open_ro(
+        ::mdbx::Environment::new(),
+        &backup.chain_data_dir(),
+        &CHAINDATA_TABLES,
+    )?;
+    let ancient_dir = backup.ancient_dir();
+    if ancient_dir.exists() {
+        env.set_freezer(std::sync::Arc::new(crate::kv::freezer::Freezer::open(
+            &ancient_dir,
+        )?));
+    }
+    let info = validate(&env.begin()?)
+        .map_err(|e| format_err!("invalid backup at {}: {e}", backup.display()))?;
+    drop(env);
+
+    for (from, to) in [
+        (backup.chain_data_dir(), dest.chain_data_dir()),
+        (ancient_dir, dest.ancient_dir()),
+    ] {
+        if from.exists() {
+            copy_dir(&from, &to)?;
+        }
+    }
+
+    Ok(info)
+}
+
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(to)?;
+    for entry in std::fs::read_dir(from)? {
+        let path = entry?.path();
+        // Lock file belongs to the environment that created it.
+        if path.extension() == Some("lck".as_ref()) {
+            continue;
+        }
+        std::fs::copy(&path, to.join(path.file_name().unwrap()))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_chaindata;
+    use expanded_pathbuf::ExpandedPathBuf;
+
+    #[test]
+    fn backup_and_restore() {
+        let db = new_mem_chaindata().unwrap();
+        crate::database_version::migrate_database(&db).unwrap();
+        {
+            let txn = db.begin_mutable().unwrap();
+            crate::genesis::initialize_genesis(
+                &txn,
+                &tempfile::tempdir().unwrap(),
+                false,
+                Some(crate::res::chainspec::MAINNET.clone()),
+            )
+            .unwrap();
+            HEADERS.save_progress(&txn, BlockNumber(0)).unwrap();
+            txn.commit().unwrap();
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        let backup_dir = HanaDataDir(ExpandedPathBuf(dir.path().join("backup")));
+        let restored_dir = HanaDataDir(ExpandedPathBuf(dir.path().join("restored")));
+
+        let info = backup(&db, &backup_dir).unwrap();
+        assert_eq!(info.version, DATABASE_VERSION);
+        assert_eq!(info.head, BlockNumber(0));
+        assert!(backup(&db, &backup_dir).is_err());
+
+        assert_eq!(restore(&backup_dir, &restored_dir).unwrap(), info);
+        assert!(restore(&backup_dir, &restored_dir).is_err());
+
+        let restored =
+            crate::kv::new_database(&CHAINDATA_TABLES, &restored_dir.chain_data_dir()).unwrap();
+        let txn = restored.begin().unwrap();
+        assert_eq!(validate(&txn).unwrap(), info);
+        assert_eq!(
+            chain::canonical_hash::read(&txn, 0).unwrap(),
+            chain::canonical_hash::read(&db.begin().unwrap(), 0).unwrap()
+        );
+    }
+}
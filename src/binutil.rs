@@ -18,6 +18,10 @@ impl HanaDataDir {
         self.0.join("chaindata")
     }
 
+    pub fn ancient_dir(&self) -> PathBuf {
+        self.0.join("ancient")
+    }
+
     pub fn etl_temp_dir(&self) -> PathBuf {
         self.0.join("etl-temp")
     }
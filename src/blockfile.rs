@@ -0,0 +1,158 @@
+//! Chain files of concatenated RLP-encoded blocks, as read by `geth import` and written by
+//! `geth export`.
+
+use crate::{accessors::chain, kv::mdbx::*, models::*};
+use anyhow::format_err;
+use bytes::BytesMut;
+use fastrlp::{Decodable, Encodable};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Read, Write},
+    path::Path,
+};
+
+/// Streams blocks out of a chain file, decoding them one at a time.
+pub struct Reader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    index: usize,
+}
+
+impl<R: BufRead> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            buf: Vec::new(),
+            index: 0,
+        }
+    }
+
+    /// Reads the next RLP item into `self.buf`, returning `false` on a clean end of file.
+    fn read_item(&mut self) -> std::io::Result<bool> {
+        if self.inner.fill_buf()?.is_empty() {
+            return Ok(false);
+        }
+
+        let mut prefix = [0; 1];
+        self.inner.read_exact(&mut prefix)?;
+        self.buf.clear();
+        self.buf.push(prefix[0]);
+
+        let payload_len = match prefix[0] {
+            b @ 0xc0..=0xf7 => (b - 0xc0) as usize,
+            b @ 0xf8..=0xff => {
+                let len_of_len = (b - 0xf7) as usize;
+                let mut be = [0; 8];
+                self.inner.read_exact(&mut be[8 - len_of_len..])?;
+                self.buf.extend_from_slice(&be[8 - len_of_len..]);
+                usize::try_from(u64::from_be_bytes(be))
+                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?
+            }
+            b => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("expected RLP list, got prefix {b:#04x}"),
+                ))
+            }
+        };
+
+        let header_len = self.buf.len();
+        self.buf.resize(header_len + payload_len, 0);
+        self.inner.read_exact(&mut self.buf[header_len..])?;
+
+        Ok(true)
+    }
+}
+
+impl<R: BufRead> Iterator for Reader<R> {
+    type Item = anyhow::Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.index;
+        let res = match self.read_item() {
+            Ok(false) => return None,
+            Ok(true) => Block::decode(&mut &self.buf[..]).map_err(|e| format_err!("{e}")),
+            Err(e) => Err(e.into()),
+        };
+        self.index += 1;
+
+        Some(res.map_err(|e| format_err!("failed to decode block at index {index}: {e}")))
+    }
+}
+
+pub fn read(path: impl AsRef<Path>) -> anyhow::Result<Vec<Block>> {
+    Reader::new(BufReader::new(File::open(path)?)).collect()
+}
+
+/// Writes canonical blocks `from..=to`, returning the number of blocks written.
+pub fn write<K: TransactionKind, E: EnvironmentKind>(
+    txn: &MdbxTransaction<'_, K, E>,
+    from: BlockNumber,
+    to: BlockNumber,
+    out: &mut impl Write,
+) -> anyhow::Result<u64> {
+    let mut written = 0;
+    let mut buf = BytesMut::new();
+    for block_number in from..=to {
+        let header = chain::header::read(txn, block_number)?
+            .ok_or_else(|| format_err!("header not found for block #{block_number}"))?;
+        let body = chain::block_body::read_without_senders(txn, block_number)?
+            .ok_or_else(|| format_err!("body not found for block #{block_number}"))?;
+
+        buf.clear();
+        Block {
+            header,
+            transactions: body.transactions,
+            ommers: body.ommers,
+        }
+        .encode(&mut buf);
+        out.write_all(&buf)?;
+
+        written += 1;
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_chaindata;
+
+    #[test]
+    fn roundtrip() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        crate::genesis::initialize_genesis(
+            &txn,
+            &tempfile::tempdir().unwrap(),
+            false,
+            Some(crate::res::chainspec::MAINNET.clone()),
+        )
+        .unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(
+            write(&txn, BlockNumber(0), BlockNumber(0), &mut out).unwrap(),
+            1
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chain.rlp");
+        std::fs::write(&path, out).unwrap();
+
+        let blocks = read(&path).unwrap();
+        assert_eq!(blocks.len(), 1);
+        assert_eq!(
+            Some(blocks[0].header.hash()),
+            chain::canonical_hash::read(&txn, 0).unwrap()
+        );
+
+        let mut out = std::fs::read(&path).unwrap();
+        out.pop();
+        let err = Reader::new(&out[..])
+            .collect::<anyhow::Result<Vec<_>>>()
+            .unwrap_err();
+        assert!(err.to_string().contains("at index 0"), "{err}");
+    }
+}
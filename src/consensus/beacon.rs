@@ -1,5 +1,6 @@
-use super::*;
+use super::{light_client::LightClient, *};
 use crate::{
+    execution::analysis_cache::AnalysisCache,
     kv::{mdbx::*, MdbxWithDirHandle},
     models::*,
     rpc::{eth::EthApiServerImpl, net::NetApiServerImpl, web3::Web3ApiServerImpl},
@@ -100,11 +101,12 @@ pub struct BeaconConsensus {
     since: Option<BlockNumber>,
     receiver: watch::Receiver<ExternalForkChoice>,
     server_task: Option<TaskGuard<!>>,
+    light_client_task: Option<TaskGuard<!>>,
 }
 
 impl BeaconConsensus {
     pub fn new(
-        db: Option<Arc<MdbxWithDirHandle<WriteMap>>>,
+        db: Option<(Arc<MdbxWithDirHandle<WriteMap>>, AnalysisCache)>,
         engine_addr: SocketAddr,
         chain_id: ChainId,
         network_id: NetworkId,
@@ -115,18 +117,32 @@ impl BeaconConsensus {
         terminal_block_hash: Option<H256>,
         terminal_block_number: Option<BlockNumber>,
         since: Option<BlockNumber>,
+        light_client: Option<LightClientOptions>,
     ) -> Self {
         let (chain_tip_sender, receiver) = tokio::sync::watch::channel(ExternalForkChoice {
             head_block: H256::zero(),
             finalized_block: H256::zero(),
         });
+
+        // With the light client in charge of the fork choice, the engine API still serves the CL
+        // if there is one, but its fork choice updates go nowhere.
+        let (chain_tip_sender, light_client_task) = match light_client {
+            Some(light_client) => (
+                watch::channel(*receiver.borrow()).0,
+                Some(TaskGuard(tokio::spawn(
+                    LightClient::new(light_client).run(chain_tip_sender),
+                ))),
+            ),
+            None => (chain_tip_sender, None),
+        };
+
         Self {
             base: ConsensusEngineBase::new(chain_id, eip1559_block, Some((since, 32))),
             block_reward,
             beneficiary_schedule,
             since,
             receiver,
-            server_task: db.map(move |db| {
+            server_task: db.map(move |(db, analysis_cache)| {
                 TaskGuard(tokio::spawn(async move {
                     #[derive(Clone)]
                     struct M;
@@ -204,6 +220,7 @@ impl BeaconConsensus {
                         EthApiServerImpl {
                             db,
                             call_gas_limit: 0,
+                            analysis_cache,
                         }
                         .into_rpc(),
                     )
@@ -219,6 +236,7 @@ impl BeaconConsensus {
                     pending().await
                 }))
             }),
+            light_client_task,
         }
     }
 }
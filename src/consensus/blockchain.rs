@@ -24,7 +24,7 @@ impl<'state> Blockchain<'state> {
     ) -> anyhow::Result<Blockchain<'state>> {
         Self::new_with_consensus(
             state,
-            engine_factory(None, config.clone(), None)?,
+            engine_factory(None, config.clone(), None, None)?,
             config,
             genesis_block,
         )
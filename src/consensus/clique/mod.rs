@@ -2,15 +2,13 @@ pub mod state;
 pub use state::CliqueState;
 
 use crate::{
+    accessors,
     consensus::{
         fork_choice_graph::ForkChoiceGraph, state::CliqueBlock, CliqueError, Consensus,
         ConsensusEngineBase, ConsensusState, DuoError, FinalizationChange, ForkChoiceMode,
         ValidationError,
     },
-    kv::{
-        mdbx::{MdbxCursor, MdbxTransaction},
-        tables,
-    },
+    kv::mdbx::MdbxTransaction,
     models::{
         Block, BlockHeader, BlockNumber, ChainConfig, ChainId, ChainSpec, Seal, EMPTY_LIST_HASH,
     },
@@ -71,13 +69,13 @@ fn parse_checkpoint(extra_data: &[u8]) -> Result<Vec<Address>, DuoError> {
     Ok(addresses)
 }
 
-fn get_header<K: TransactionKind>(
-    cursor: &mut MdbxCursor<'_, K, tables::Header>,
+fn get_header<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
     height: BlockNumber,
 ) -> anyhow::Result<BlockHeader> {
-    Ok(match cursor.seek(height)? {
-        Some((found_height, header)) if found_height == height => header,
-        _ => bail!("Header for block {} missing from database.", height),
+    Ok(match accessors::chain::header::read(tx, height)? {
+        Some(header) => header,
+        None => bail!("Header for block {} missing from database.", height),
     })
 }
 
@@ -85,8 +83,7 @@ pub fn recover_signers_from_epoch_block<T: TransactionKind, E: EnvironmentKind>(
     tx: &MdbxTransaction<'_, T, E>,
     current_epoch: BlockNumber,
 ) -> anyhow::Result<Vec<Address>> {
-    let mut cursor = tx.cursor(tables::Header)?;
-    let epoch_header = get_header(&mut cursor, current_epoch)?;
+    let epoch_header = get_header(tx, current_epoch)?;
     Ok(parse_checkpoint(epoch_header.extra_data.as_ref())?)
 }
 
@@ -96,10 +93,8 @@ pub fn fast_forward_within_epoch<T: TransactionKind, E: EnvironmentKind>(
     latest_epoch: BlockNumber,
     starting_block: BlockNumber,
 ) -> anyhow::Result<()> {
-    let mut cursor = tx.cursor(tables::Header)?;
-
     for height in latest_epoch + 1..starting_block {
-        state.finalize(CliqueBlock::from_header(&get_header(&mut cursor, height)?)?);
+        state.finalize(CliqueBlock::from_header(&get_header(tx, height)?)?);
     }
 
     Ok(())
@@ -138,8 +133,7 @@ pub fn recover_clique_state<T: TransactionKind, E: EnvironmentKind>(
     }
 
     if starting_block > 1 {
-        let mut cursor = tx.cursor(tables::Header)?;
-        let header = get_header(&mut cursor, starting_block - BlockNumber(1))?;
+        let header = get_header(tx, starting_block - BlockNumber(1))?;
         state.set_block_hash(header.hash());
     } else {
         let config = ChainConfig::from(chain_spec.clone());
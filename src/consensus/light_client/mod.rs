@@ -0,0 +1,706 @@
+//! Beacon chain light client following sync committee updates.
+//!
+//! Lets a node track the canonical chain tip without an external consensus client. Starting from a
+//! trusted beacon block root, the client fetches the sync committee of its period, verifies the
+//! aggregate signatures of every subsequent update against it and derives the execution head and
+//! finalized block from the attested and finalized beacon headers. Updates are accepted only with a
+//! supermajority of the sync committee participating.
+
+mod source;
+pub mod types;
+
+pub use self::source::*;
+use self::types::*;
+use super::ExternalForkChoice;
+use crate::models::*;
+use anyhow::{bail, ensure, format_err};
+use blst::{
+    min_pk::{PublicKey, Signature},
+    BLST_ERROR,
+};
+use ethereum_types::H32;
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::watch;
+use tracing::*;
+
+pub const SECONDS_PER_SLOT: u64 = 12;
+pub const SLOTS_PER_EPOCH: u64 = 32;
+pub const EPOCHS_PER_SYNC_COMMITTEE_PERIOD: u64 = 256;
+
+const DOMAIN_SYNC_COMMITTEE: [u8; 4] = [7, 0, 0, 0];
+const BLS_DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+const MAX_REQUEST_UPDATES: u64 = 128;
+
+pub fn sync_committee_period(slot: u64) -> u64 {
+    slot / SLOTS_PER_EPOCH / EPOCHS_PER_SYNC_COMMITTEE_PERIOD
+}
+
+pub fn compute_domain(fork_version: H32, genesis_validators_root: H256) -> H256 {
+    let mut version = [0; 32];
+    version[..4].copy_from_slice(fork_version.as_bytes());
+    let fork_data_root = sha256_pair(&version, genesis_validators_root.as_bytes());
+
+    let mut domain = H256::zero();
+    domain.0[..4].copy_from_slice(&DOMAIN_SYNC_COMMITTEE);
+    domain.0[4..].copy_from_slice(&fork_data_root.as_bytes()[..28]);
+    domain
+}
+
+#[derive(Clone, Debug)]
+pub struct LightClientOptions {
+    pub source: Arc<dyn LightClientSource>,
+    /// Trusted beacon block root to bootstrap from.
+    pub checkpoint: H256,
+}
+
+#[derive(Debug)]
+struct Store {
+    finalized_header: LightClientHeader,
+    optimistic_header: LightClientHeader,
+    current_sync_committee: SyncCommittee,
+    next_sync_committee: Option<SyncCommittee>,
+}
+
+#[derive(Debug)]
+pub struct LightClient {
+    source: Arc<dyn LightClientSource>,
+    checkpoint: H256,
+    genesis: Option<Genesis>,
+    forks: Vec<Fork>,
+    store: Option<Store>,
+}
+
+impl LightClient {
+    pub fn new(options: LightClientOptions) -> Self {
+        Self {
+            source: options.source,
+            checkpoint: options.checkpoint,
+            genesis: None,
+            forks: Vec::new(),
+            store: None,
+        }
+    }
+
+    /// Execution layer blocks at the verified beacon head and finalized checkpoint.
+    pub fn fork_choice(&self) -> Option<ExternalForkChoice> {
+        self.store.as_ref().map(|store| ExternalForkChoice {
+            head_block: store.optimistic_header.execution.block_hash,
+            finalized_block: store.finalized_header.execution.block_hash,
+        })
+    }
+
+    async fn bootstrap(&mut self) -> anyhow::Result<()> {
+        let genesis = self.source.genesis().await?;
+        let mut forks = self.source.fork_schedule().await?;
+        forks.sort_by_key(|fork| fork.epoch);
+
+        let bootstrap = self.source.bootstrap(self.checkpoint).await?;
+        let header = bootstrap.header;
+        ensure!(
+            header.beacon.hash_tree_root() == self.checkpoint,
+            "bootstrap header does not match checkpoint {:?}",
+            self.checkpoint
+        );
+        ensure!(
+            header.is_valid(),
+            "invalid execution payload proof in bootstrap header"
+        );
+        let committee = bootstrap.current_sync_committee;
+        ensure!(
+            committee.pubkeys.len() == SYNC_COMMITTEE_SIZE,
+            "sync committee has {} members",
+            committee.pubkeys.len()
+        );
+        ensure!(
+            is_valid_state_branch(
+                committee.hash_tree_root(),
+                &bootstrap.current_sync_committee_branch,
+                CURRENT_SYNC_COMMITTEE_GINDICES,
+                header.beacon.state_root,
+            ),
+            "invalid current sync committee proof in bootstrap"
+        );
+
+        info!(
+            "Beacon light client bootstrapped at slot {}, block {}",
+            header.beacon.slot, header.execution.block_number
+        );
+
+        self.genesis = Some(genesis);
+        self.forks = forks;
+        self.store = Some(Store {
+            finalized_header: header.clone(),
+            optimistic_header: header,
+            current_sync_committee: committee,
+            next_sync_committee: None,
+        });
+
+        Ok(())
+    }
+
+    /// Fetches and applies everything new from the source, bootstrapping first if needed.
+    pub async fn sync(&mut self) -> anyhow::Result<Option<ExternalForkChoice>> {
+        if self.store.is_none() {
+            self.bootstrap().await?;
+        }
+
+        loop {
+            let period = self.finalized_period();
+            for update in self.source.updates(period, MAX_REQUEST_UPDATES).await? {
+                self.process_update(update)?;
+            }
+            if self.finalized_period() == period {
+                break;
+            }
+        }
+
+        if let Some(update) = self.source.finality_update().await? {
+            self.process_update(update)?;
+        }
+        if let Some(update) = self.source.optimistic_update().await? {
+            self.process_update(update)?;
+        }
+
+        Ok(self.fork_choice())
+    }
+
+    /// Keeps syncing from the source and publishes new heads until the task is dropped.
+    pub async fn run(mut self, sender: watch::Sender<ExternalForkChoice>) -> ! {
+        loop {
+            match self.sync().await {
+                Ok(Some(fork_choice)) => {
+                    if *sender.borrow() != fork_choice {
+                        debug!("Beacon light client fork choice: {fork_choice:?}");
+                        let _ = sender.send(fork_choice);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Beacon light client sync failed: {e:?}"),
+            }
+
+            tokio::time::sleep(Duration::from_secs(SECONDS_PER_SLOT)).await;
+        }
+    }
+
+    fn store(&self) -> anyhow::Result<&Store> {
+        self.store
+            .as_ref()
+            .ok_or_else(|| format_err!("light client is not bootstrapped"))
+    }
+
+    fn finalized_period(&self) -> u64 {
+        self.store
+            .as_ref()
+            .map(|store| sync_committee_period(store.finalized_header.beacon.slot))
+            .unwrap_or_default()
+    }
+
+    fn current_slot(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let genesis_time = self.genesis.as_ref().map(|g| g.genesis_time).unwrap_or(0);
+        now.saturating_sub(genesis_time) / SECONDS_PER_SLOT
+    }
+
+    fn fork_version(&self, epoch: u64) -> H32 {
+        self.forks
+            .iter()
+            .rev()
+            .find(|fork| fork.epoch <= epoch)
+            .map(|fork| fork.current_version)
+            .or_else(|| self.genesis.as_ref().map(|g| g.genesis_fork_version))
+            .unwrap_or_default()
+    }
+
+    /// Verifies the update and applies it to the store. Returns false if the update does not tell
+    /// anything new, e. g. because it was already applied.
+    pub fn process_update(&mut self, update: LightClientUpdate) -> anyhow::Result<bool> {
+        let store = self.store()?;
+        let store_period = sync_committee_period(store.finalized_header.beacon.slot);
+        let attested_slot = update.attested_header.beacon.slot;
+        let attested_period = sync_committee_period(attested_slot);
+        let finalized_header = update
+            .finalized_header
+            .as_ref()
+            .filter(|_| update.is_finality_update());
+        let next_sync_committee = update
+            .next_sync_committee
+            .as_ref()
+            .filter(|_| update.is_sync_committee_update());
+
+        let learns_next_committee = next_sync_committee.is_some()
+            && store.next_sync_committee.is_none()
+            && attested_period == store_period;
+        let advances_finality = finalized_header
+            .map(|header| header.beacon.slot > store.finalized_header.beacon.slot)
+            .unwrap_or(false);
+        if attested_slot <= store.optimistic_header.beacon.slot
+            && !learns_next_committee
+            && !advances_finality
+        {
+            return Ok(false);
+        }
+
+        self.validate_update(store, &update, finalized_header, next_sync_committee)?;
+
+        let store = self.store.as_mut().unwrap();
+        if learns_next_committee {
+            store.next_sync_committee = next_sync_committee.cloned();
+        }
+        if let Some(finalized_header) = finalized_header.filter(|_| advances_finality) {
+            let finalized_period = sync_committee_period(finalized_header.beacon.slot);
+            if finalized_period == store_period + 1 {
+                store.current_sync_committee = store
+                    .next_sync_committee
+                    .take()
+                    .ok_or_else(|| format_err!("cannot rotate to unknown sync committee"))?;
+                store.next_sync_committee = next_sync_committee
+                    .filter(|_| attested_period == finalized_period)
+                    .cloned();
+            }
+            store.finalized_header = finalized_header.clone();
+            if store.finalized_header.beacon.slot > store.optimistic_header.beacon.slot {
+                store.optimistic_header = store.finalized_header.clone();
+            }
+        }
+        if attested_slot > store.optimistic_header.beacon.slot {
+            store.optimistic_header = update.attested_header;
+        }
+
+        Ok(true)
+    }
+
+    fn validate_update(
+        &self,
+        store: &Store,
+        update: &LightClientUpdate,
+        finalized_header: Option<&LightClientHeader>,
+        next_sync_committee: Option<&SyncCommittee>,
+    ) -> anyhow::Result<()> {
+        let bits = &update.sync_aggregate.sync_committee_bits;
+        ensure!(
+            bits.len() == SYNC_COMMITTEE_SIZE / 8,
+            "sync committee bits have wrong length {}",
+            bits.len()
+        );
+        let participation = (0..SYNC_COMMITTEE_SIZE)
+            .map(|i| (bits[i / 8] >> (i % 8)) & 1 == 1)
+            .collect::<Vec<_>>();
+        let participants = participation.iter().filter(|p| **p).count();
+        ensure!(
+            participants * 3 >= SYNC_COMMITTEE_SIZE * 2,
+            "insufficient sync committee participation: {participants}/{SYNC_COMMITTEE_SIZE}"
+        );
+
+        let attested = &update.attested_header;
+        ensure!(
+            update.signature_slot > attested.beacon.slot
+                && update.signature_slot <= self.current_slot(),
+            "invalid signature slot {}",
+            update.signature_slot
+        );
+        ensure!(
+            attested.is_valid(),
+            "invalid execution payload proof in attested header"
+        );
+
+        let store_period = sync_committee_period(store.finalized_header.beacon.slot);
+        let signature_period = sync_committee_period(update.signature_slot);
+        let sync_committee = if signature_period == store_period {
+            &store.current_sync_committee
+        } else if signature_period == store_period + 1 {
+            store.next_sync_committee.as_ref().ok_or_else(|| {
+                format_err!("update signed in period {signature_period} by unknown committee")
+            })?
+        } else {
+            bail!("update signed in period {signature_period}, store is in period {store_period}");
+        };
+
+        if let Some(finalized_header) = finalized_header {
+            ensure!(
+                finalized_header.beacon.slot <= attested.beacon.slot,
+                "finalized header is newer than attested header"
+            );
+            ensure!(
+                finalized_header.is_valid(),
+                "invalid execution payload proof in finalized header"
+            );
+            ensure!(
+                is_valid_state_branch(
+                    finalized_header.beacon.hash_tree_root(),
+                    &update.finality_branch,
+                    FINALIZED_ROOT_GINDICES,
+                    attested.beacon.state_root,
+                ),
+                "invalid finality proof"
+            );
+        }
+
+        if let Some(next_sync_committee) = next_sync_committee {
+            ensure!(
+                next_sync_committee.pubkeys.len() == SYNC_COMMITTEE_SIZE,
+                "sync committee has {} members",
+                next_sync_committee.pubkeys.len()
+            );
+            if let Some(known) = store.next_sync_committee.as_ref() {
+                if sync_committee_period(attested.beacon.slot) == store_period {
+                    ensure!(
+                        known == next_sync_committee,
+                        "next sync committee does not match the known one"
+                    );
+                }
+            }
+            ensure!(
+                is_valid_state_branch(
+                    next_sync_committee.hash_tree_root(),
+                    &update.next_sync_committee_branch,
+                    NEXT_SYNC_COMMITTEE_GINDICES,
+                    attested.beacon.state_root,
+                ),
+                "invalid next sync committee proof"
+            );
+        }
+
+        let pubkeys = sync_committee
+            .pubkeys
+            .iter()
+            .zip(participation)
+            .filter(|(_, participated)| *participated)
+            .map(|(pubkey, _)| PublicKey::from_bytes(&pubkey.0))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format_err!("invalid sync committee public key: {e:?}"))?;
+        let signature = Signature::from_bytes(&update.sync_aggregate.sync_committee_signature)
+            .map_err(|e| format_err!("invalid sync committee signature encoding: {e:?}"))?;
+
+        let fork_version =
+            self.fork_version(update.signature_slot.max(1).saturating_sub(1) / SLOTS_PER_EPOCH);
+        let genesis_validators_root = self
+            .genesis
+            .as_ref()
+            .map(|g| g.genesis_validators_root)
+            .unwrap_or_default();
+        let signing_root = sha256_pair(
+            attested.beacon.hash_tree_root().as_bytes(),
+            compute_domain(fork_version, genesis_validators_root).as_bytes(),
+        );
+
+        let result = signature.fast_aggregate_verify(
+            true,
+            signing_root.as_bytes(),
+            BLS_DST,
+            &pubkeys.iter().collect::<Vec<_>>(),
+        );
+        ensure!(
+            result == BLST_ERROR::BLST_SUCCESS,
+            "invalid sync committee signature: {result:?}"
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use blst::min_pk::{AggregatePublicKey, AggregateSignature, SecretKey};
+    use bytes::Bytes;
+    use std::collections::HashMap;
+
+    const GENESIS_VALIDATORS_ROOT: H256 = H256([0x42; 32]);
+    const CAPELLA_FORK_VERSION: H32 = H32([3, 0, 0, 0]);
+
+    struct Committee {
+        keys: Vec<SecretKey>,
+        committee: SyncCommittee,
+    }
+
+    /// Committee of a few distinct keys, each repeated to fill all seats.
+    fn committee(seed: u8) -> Committee {
+        let keys = (0..8)
+            .map(|i| SecretKey::key_gen(&[seed, i, 0, 0, 0, 0, 0, 0].repeat(4), &[]).unwrap())
+            .collect::<Vec<_>>();
+        let pubkeys = (0..SYNC_COMMITTEE_SIZE)
+            .map(|i| keys[i % keys.len()].sk_to_pk())
+            .collect::<Vec<_>>();
+        let aggregate_pubkey =
+            AggregatePublicKey::aggregate(&pubkeys.iter().collect::<Vec<_>>(), false)
+                .unwrap()
+                .to_public_key();
+
+        Committee {
+            committee: SyncCommittee {
+                pubkeys: pubkeys
+                    .iter()
+                    .map(|pk| BlsPublicKey(Bytes::copy_from_slice(&pk.to_bytes())))
+                    .collect(),
+                aggregate_pubkey: BlsPublicKey(Bytes::copy_from_slice(
+                    &aggregate_pubkey.to_bytes(),
+                )),
+            },
+            keys,
+        }
+    }
+
+    impl Committee {
+        fn sign(&self, header: &BeaconBlockHeader, bits: &[u8]) -> SyncAggregate {
+            let domain = compute_domain(CAPELLA_FORK_VERSION, GENESIS_VALIDATORS_ROOT);
+            let signing_root = sha256_pair(header.hash_tree_root().as_bytes(), domain.as_bytes());
+
+            let signatures = self
+                .keys
+                .iter()
+                .map(|key| key.sign(signing_root.as_bytes(), BLS_DST, &[]))
+                .collect::<Vec<_>>();
+            let participants = (0..SYNC_COMMITTEE_SIZE)
+                .filter(|i| (bits[i / 8] >> (i % 8)) & 1 == 1)
+                .map(|i| &signatures[i % signatures.len()])
+                .collect::<Vec<_>>();
+            let signature = AggregateSignature::aggregate(&participants, false)
+                .unwrap()
+                .to_signature();
+
+            SyncAggregate {
+                sync_committee_bits: Bytes::copy_from_slice(bits),
+                sync_committee_signature: Bytes::copy_from_slice(&signature.to_bytes()),
+            }
+        }
+    }
+
+    /// Node of a sparse Merkle tree of the given depth with `leaves` at generalized indices and
+    /// zero chunks everywhere else.
+    fn tree_node(leaves: &HashMap<u64, H256>, gindex: u64, depth: usize) -> H256 {
+        if let Some(leaf) = leaves.get(&gindex) {
+            return *leaf;
+        }
+        if gindex_depth(gindex) >= depth {
+            return H256::zero();
+        }
+        sha256_pair(
+            tree_node(leaves, gindex * 2, depth).as_bytes(),
+            tree_node(leaves, gindex * 2 + 1, depth).as_bytes(),
+        )
+    }
+
+    fn tree_branch(leaves: &HashMap<u64, H256>, mut gindex: u64, depth: usize) -> Vec<H256> {
+        let mut branch = Vec::new();
+        while gindex > 1 {
+            branch.push(tree_node(leaves, gindex ^ 1, depth));
+            gindex /= 2;
+        }
+        branch
+    }
+
+    fn header(slot: u64, block_number: u64) -> LightClientHeader {
+        let execution = ExecutionPayloadHeader {
+            parent_hash: H256::from_low_u64_be(block_number - 1),
+            fee_recipient: Address::repeat_byte(0xfe),
+            state_root: H256::repeat_byte(0x01),
+            receipts_root: H256::repeat_byte(0x02),
+            logs_bloom: Bloom::zero(),
+            prev_randao: H256::repeat_byte(0x03),
+            block_number,
+            gas_limit: 30_000_000,
+            gas_used: 21_000,
+            timestamp: slot * SECONDS_PER_SLOT,
+            extra_data: Bytes::from_static(b"hana"),
+            base_fee_per_gas: U256::from(7_u64),
+            block_hash: H256::from_low_u64_be(block_number),
+            transactions_root: H256::repeat_byte(0x04),
+            withdrawals_root: H256::repeat_byte(0x05),
+            blob_gas_used: None,
+            excess_blob_gas: None,
+        };
+        let body = HashMap::from([(EXECUTION_PAYLOAD_GINDEX, execution.hash_tree_root())]);
+
+        LightClientHeader {
+            beacon: BeaconBlockHeader {
+                slot,
+                proposer_index: 0,
+                parent_root: H256::zero(),
+                state_root: H256::zero(),
+                body_root: tree_node(&body, 1, 4),
+            },
+            execution,
+            execution_branch: tree_branch(&body, EXECUTION_PAYLOAD_GINDEX, 4),
+        }
+    }
+
+    struct Fixture {
+        dir: tempfile::TempDir,
+        checkpoint: H256,
+        update: LightClientUpdate,
+        current: Committee,
+        next: Committee,
+    }
+
+    fn write(dir: &tempfile::TempDir, name: &str, value: &impl serde::Serialize) {
+        std::fs::write(dir.path().join(name), serde_json::to_vec(value).unwrap()).unwrap();
+    }
+
+    /// Records a bootstrap at slot 64 and a period 0 update attesting slot 96 and finalizing slot
+    /// 80, in the same layout as the beacon API would serve them.
+    fn fixture() -> Fixture {
+        let current = committee(1);
+        let next = committee(2);
+
+        let mut checkpoint_header = header(64, 100);
+        let state = HashMap::from([(
+            CURRENT_SYNC_COMMITTEE_GINDICES[0],
+            current.committee.hash_tree_root(),
+        )]);
+        checkpoint_header.beacon.state_root = tree_node(&state, 1, 5);
+        let bootstrap = LightClientBootstrap {
+            current_sync_committee: current.committee.clone(),
+            current_sync_committee_branch: tree_branch(
+                &state,
+                CURRENT_SYNC_COMMITTEE_GINDICES[0],
+                5,
+            ),
+            header: checkpoint_header.clone(),
+        };
+
+        let finalized_header = header(80, 116);
+        let mut attested_header = header(96, 132);
+        let state = HashMap::from([
+            (
+                FINALIZED_ROOT_GINDICES[0],
+                finalized_header.beacon.hash_tree_root(),
+            ),
+            (
+                NEXT_SYNC_COMMITTEE_GINDICES[0],
+                next.committee.hash_tree_root(),
+            ),
+        ]);
+        attested_header.beacon.state_root = tree_node(&state, 1, 6);
+        let update = LightClientUpdate {
+            sync_aggregate: current.sign(&attested_header.beacon, &[0xff; 64]),
+            attested_header,
+            next_sync_committee: Some(next.committee.clone()),
+            next_sync_committee_branch: tree_branch(&state, NEXT_SYNC_COMMITTEE_GINDICES[0], 6),
+            finalized_header: Some(finalized_header),
+            finality_branch: tree_branch(&state, FINALIZED_ROOT_GINDICES[0], 6),
+            signature_slot: 97,
+        };
+
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            &dir,
+            "genesis.json",
+            &Response::from(Genesis {
+                genesis_time: 0,
+                genesis_validators_root: GENESIS_VALIDATORS_ROOT,
+                genesis_fork_version: H32([0, 0, 0, 0]),
+            }),
+        );
+        write(
+            &dir,
+            "fork_schedule.json",
+            &Response::from(vec![Fork {
+                previous_version: H32([2, 0, 0, 0]),
+                current_version: CAPELLA_FORK_VERSION,
+                epoch: 0,
+            }]),
+        );
+        write(
+            &dir,
+            "bootstrap.json",
+            &Response {
+                version: Some("capella".into()),
+                data: bootstrap,
+            },
+        );
+        write(
+            &dir,
+            "updates.json",
+            &[Response {
+                version: Some("capella".into()),
+                data: update.clone(),
+            }],
+        );
+
+        Fixture {
+            dir,
+            checkpoint: checkpoint_header.beacon.hash_tree_root(),
+            update,
+            current,
+            next,
+        }
+    }
+
+    #[tokio::test]
+    async fn replay_fixture() {
+        let fixture = fixture();
+        let mut client = LightClient::new(LightClientOptions {
+            source: source_from_str(fixture.dir.path().to_str().unwrap()).unwrap(),
+            checkpoint: fixture.checkpoint,
+        });
+
+        assert_eq!(
+            client.sync().await.unwrap(),
+            Some(ExternalForkChoice {
+                head_block: H256::from_low_u64_be(132),
+                finalized_block: H256::from_low_u64_be(116),
+            })
+        );
+        let store = client.store.as_ref().unwrap();
+        assert_eq!(store.next_sync_committee, Some(fixture.next.committee));
+
+        // Replaying the same recording changes nothing.
+        assert!(!client.process_update(fixture.update).unwrap());
+    }
+
+    async fn bootstrapped(fixture: &Fixture) -> LightClient {
+        let mut client = LightClient::new(LightClientOptions {
+            source: Arc::new(FixtureSource::new(fixture.dir.path())),
+            checkpoint: fixture.checkpoint,
+        });
+        client.bootstrap().await.unwrap();
+        client
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_updates() {
+        let fixture = fixture();
+
+        let mut client = LightClient::new(LightClientOptions {
+            source: Arc::new(FixtureSource::new(fixture.dir.path())),
+            checkpoint: H256::repeat_byte(0xaa),
+        });
+        assert!(client.sync().await.is_err());
+
+        let mut update = fixture.update.clone();
+        update
+            .finalized_header
+            .as_mut()
+            .unwrap()
+            .execution
+            .block_hash = H256::repeat_byte(0xbb);
+        assert!(bootstrapped(&fixture).await.process_update(update).is_err());
+
+        let mut update = fixture.update.clone();
+        let mut bits = [0xff; 64];
+        bits[..24].fill(0);
+        update.sync_aggregate = fixture.current.sign(&update.attested_header.beacon, &bits);
+        assert!(bootstrapped(&fixture).await.process_update(update).is_err());
+
+        let mut update = fixture.update.clone();
+        update.sync_aggregate = fixture
+            .next
+            .sign(&update.attested_header.beacon, &[0xff; 64]);
+        assert!(bootstrapped(&fixture).await.process_update(update).is_err());
+
+        let mut update = fixture.update.clone();
+        update.signature_slot = u64::MAX;
+        assert!(bootstrapped(&fixture).await.process_update(update).is_err());
+
+        assert!(bootstrapped(&fixture)
+            .await
+            .process_update(fixture.update.clone())
+            .unwrap());
+    }
+}
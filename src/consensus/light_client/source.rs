@@ -0,0 +1,221 @@
+use super::types::*;
+use crate::models::*;
+use anyhow::{ensure, format_err, Context};
+use async_trait::async_trait;
+use hyper::{client::HttpConnector, Client, StatusCode, Uri};
+use serde::de::DeserializeOwned;
+use std::{fmt::Debug, io::ErrorKind, path::PathBuf, sync::Arc};
+
+/// Source of light client data, shaped after the light client endpoints of the beacon node API.
+#[async_trait]
+pub trait LightClientSource: Debug + Send + Sync + 'static {
+    async fn genesis(&self) -> anyhow::Result<Genesis>;
+    async fn fork_schedule(&self) -> anyhow::Result<Vec<Fork>>;
+    async fn bootstrap(&self, block_root: H256) -> anyhow::Result<LightClientBootstrap>;
+    async fn updates(
+        &self,
+        start_period: u64,
+        count: u64,
+    ) -> anyhow::Result<Vec<LightClientUpdate>>;
+    async fn finality_update(&self) -> anyhow::Result<Option<LightClientUpdate>>;
+    async fn optimistic_update(&self) -> anyhow::Result<Option<LightClientUpdate>>;
+}
+
+/// Beacon node REST API served over plain HTTP, e. g. by a local beacon node.
+#[derive(Debug)]
+pub struct HttpSource {
+    base: String,
+    client: Client<HttpConnector>,
+}
+
+impl HttpSource {
+    pub fn new(base: impl Into<String>) -> Self {
+        Self {
+            base: base.into().trim_end_matches('/').to_string(),
+            client: Client::new(),
+        }
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<Option<T>> {
+        let uri = format!("{}{path}", self.base).parse::<Uri>()?;
+        let res = self.client.get(uri).await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        ensure!(
+            res.status().is_success(),
+            "beacon API request {path} failed: {}",
+            res.status()
+        );
+
+        let body = hyper::body::to_bytes(res.into_body()).await?;
+        Ok(Some(serde_json::from_slice(&body).with_context(|| {
+            format!("failed to decode beacon API response to {path}")
+        })?))
+    }
+
+    async fn get_required<T: DeserializeOwned>(&self, path: &str) -> anyhow::Result<T> {
+        self.get(path)
+            .await?
+            .ok_or_else(|| format_err!("beacon API has no data for {path}"))
+    }
+}
+
+#[async_trait]
+impl LightClientSource for HttpSource {
+    async fn genesis(&self) -> anyhow::Result<Genesis> {
+        Ok(self
+            .get_required::<Response<_>>("/eth/v1/beacon/genesis")
+            .await?
+            .data)
+    }
+
+    async fn fork_schedule(&self) -> anyhow::Result<Vec<Fork>> {
+        Ok(self
+            .get_required::<Response<_>>("/eth/v1/config/fork_schedule")
+            .await?
+            .data)
+    }
+
+    async fn bootstrap(&self, block_root: H256) -> anyhow::Result<LightClientBootstrap> {
+        Ok(self
+            .get_required::<Response<_>>(&format!(
+                "/eth/v1/beacon/light_client/bootstrap/{block_root:?}"
+            ))
+            .await?
+            .data)
+    }
+
+    async fn updates(
+        &self,
+        start_period: u64,
+        count: u64,
+    ) -> anyhow::Result<Vec<LightClientUpdate>> {
+        Ok(self
+            .get::<Vec<Response<_>>>(&format!(
+                "/eth/v1/beacon/light_client/updates?start_period={start_period}&count={count}"
+            ))
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|res| res.data)
+            .collect())
+    }
+
+    async fn finality_update(&self) -> anyhow::Result<Option<LightClientUpdate>> {
+        Ok(self
+            .get::<Response<_>>("/eth/v1/beacon/light_client/finality_update")
+            .await?
+            .map(|res| res.data))
+    }
+
+    async fn optimistic_update(&self) -> anyhow::Result<Option<LightClientUpdate>> {
+        Ok(self
+            .get::<Response<_>>("/eth/v1/beacon/light_client/optimistic_update")
+            .await?
+            .map(|res| res.data))
+    }
+}
+
+/// Replays beacon API responses recorded into a directory, one file per endpoint:
+/// `genesis.json`, `fork_schedule.json`, `bootstrap.json`, `updates.json` and, optionally,
+/// `finality_update.json` and `optimistic_update.json`. Files hold the response bodies as
+/// returned by the beacon node, so `curl <beacon>/eth/v1/beacon/genesis > genesis.json` and so on
+/// records a fixture.
+#[derive(Debug)]
+pub struct FixtureSource {
+    dir: PathBuf,
+}
+
+impl FixtureSource {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    async fn read<T: DeserializeOwned>(&self, name: &str) -> anyhow::Result<Option<T>> {
+        let path = self.dir.join(name);
+        match tokio::fs::read(&path).await {
+            Ok(data) => {
+                Ok(Some(serde_json::from_slice(&data).with_context(|| {
+                    format!("failed to decode {}", path.display())
+                })?))
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn read_required<T: DeserializeOwned>(&self, name: &str) -> anyhow::Result<T> {
+        self.read(name)
+            .await?
+            .ok_or_else(|| format_err!("{name} missing from {}", self.dir.display()))
+    }
+}
+
+#[async_trait]
+impl LightClientSource for FixtureSource {
+    async fn genesis(&self) -> anyhow::Result<Genesis> {
+        Ok(self
+            .read_required::<Response<_>>("genesis.json")
+            .await?
+            .data)
+    }
+
+    async fn fork_schedule(&self) -> anyhow::Result<Vec<Fork>> {
+        Ok(self
+            .read::<Response<_>>("fork_schedule.json")
+            .await?
+            .map(|res| res.data)
+            .unwrap_or_default())
+    }
+
+    async fn bootstrap(&self, _: H256) -> anyhow::Result<LightClientBootstrap> {
+        Ok(self
+            .read_required::<Response<_>>("bootstrap.json")
+            .await?
+            .data)
+    }
+
+    async fn updates(
+        &self,
+        start_period: u64,
+        count: u64,
+    ) -> anyhow::Result<Vec<LightClientUpdate>> {
+        Ok(self
+            .read::<Vec<Response<LightClientUpdate>>>("updates.json")
+            .await?
+            .unwrap_or_default()
+            .into_iter()
+            .map(|res| res.data)
+            .filter(|update| {
+                let period = super::sync_committee_period(update.attested_header.beacon.slot);
+                period >= start_period && period < start_period + count
+            })
+            .collect())
+    }
+
+    async fn finality_update(&self) -> anyhow::Result<Option<LightClientUpdate>> {
+        Ok(self
+            .read::<Response<_>>("finality_update.json")
+            .await?
+            .map(|res| res.data))
+    }
+
+    async fn optimistic_update(&self) -> anyhow::Result<Option<LightClientUpdate>> {
+        Ok(self
+            .read::<Response<_>>("optimistic_update.json")
+            .await?
+            .map(|res| res.data))
+    }
+}
+
+/// Beacon API URL (`http://host:port`) or a directory of recorded responses.
+pub fn source_from_str(s: &str) -> anyhow::Result<Arc<dyn LightClientSource>> {
+    Ok(if s.starts_with("http://") {
+        Arc::new(HttpSource::new(s))
+    } else if s.contains("://") {
+        anyhow::bail!("only plain HTTP beacon API endpoints are supported: {s}");
+    } else {
+        Arc::new(FixtureSource::new(s))
+    })
+}
@@ -0,0 +1,371 @@
+//! Light client containers in their beacon API JSON form, together with their SSZ hash tree roots.
+
+use crate::{models::*, util::hexbytes};
+use bytes::Bytes;
+use ethereum_types::H32;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DisplayFromStr};
+use sha2::{Digest, Sha256};
+
+pub const SYNC_COMMITTEE_SIZE: usize = 512;
+
+/// Generalized index of `execution` in `BeaconBlockBody`, stable since Capella.
+pub const EXECUTION_PAYLOAD_GINDEX: u64 = 25;
+/// Generalized indices of beacon state fields before and after Electra, which deepened the state
+/// tree by one level. The right one is picked by the length of the supplied branch.
+pub const FINALIZED_ROOT_GINDICES: [u64; 2] = [105, 169];
+pub const CURRENT_SYNC_COMMITTEE_GINDICES: [u64; 2] = [54, 86];
+pub const NEXT_SYNC_COMMITTEE_GINDICES: [u64; 2] = [55, 87];
+
+/// Beacon API response envelope.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Response<T> {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub data: T,
+}
+
+impl<T> From<T> for Response<T> {
+    fn from(data: T) -> Self {
+        Self {
+            version: None,
+            data,
+        }
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Genesis {
+    #[serde_as(as = "DisplayFromStr")]
+    pub genesis_time: u64,
+    pub genesis_validators_root: H256,
+    pub genesis_fork_version: H32,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fork {
+    pub previous_version: H32,
+    pub current_version: H32,
+    #[serde_as(as = "DisplayFromStr")]
+    pub epoch: u64,
+}
+
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BeaconBlockHeader {
+    #[serde_as(as = "DisplayFromStr")]
+    pub slot: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub proposer_index: u64,
+    pub parent_root: H256,
+    pub state_root: H256,
+    pub body_root: H256,
+}
+
+/// Capella execution payload header. Deneb and later add the blob gas fields.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExecutionPayloadHeader {
+    pub parent_hash: H256,
+    pub fee_recipient: Address,
+    pub state_root: H256,
+    pub receipts_root: H256,
+    pub logs_bloom: Bloom,
+    pub prev_randao: H256,
+    #[serde_as(as = "DisplayFromStr")]
+    pub block_number: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub gas_limit: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub gas_used: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub timestamp: u64,
+    #[serde(with = "hexbytes")]
+    pub extra_data: Bytes,
+    #[serde_as(as = "DisplayFromStr")]
+    pub base_fee_per_gas: U256,
+    pub block_hash: H256,
+    pub transactions_root: H256,
+    pub withdrawals_root: H256,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blob_gas_used: Option<u64>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub excess_blob_gas: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightClientHeader {
+    pub beacon: BeaconBlockHeader,
+    pub execution: ExecutionPayloadHeader,
+    pub execution_branch: Vec<H256>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct BlsPublicKey(#[serde(with = "hexbytes")] pub Bytes);
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncCommittee {
+    pub pubkeys: Vec<BlsPublicKey>,
+    pub aggregate_pubkey: BlsPublicKey,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncAggregate {
+    #[serde(with = "hexbytes")]
+    pub sync_committee_bits: Bytes,
+    #[serde(with = "hexbytes")]
+    pub sync_committee_signature: Bytes,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightClientBootstrap {
+    pub header: LightClientHeader,
+    pub current_sync_committee: SyncCommittee,
+    pub current_sync_committee_branch: Vec<H256>,
+}
+
+/// Full, finality or optimistic update. The latter two lack the sync committee and finality
+/// fields respectively.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightClientUpdate {
+    pub attested_header: LightClientHeader,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_sync_committee: Option<SyncCommittee>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub next_sync_committee_branch: Vec<H256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finalized_header: Option<LightClientHeader>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub finality_branch: Vec<H256>,
+    pub sync_aggregate: SyncAggregate,
+    #[serde_as(as = "DisplayFromStr")]
+    pub signature_slot: u64,
+}
+
+impl LightClientUpdate {
+    /// Full updates always carry these fields and zero them out when not applicable.
+    pub fn is_sync_committee_update(&self) -> bool {
+        self.next_sync_committee.is_some()
+            && self.next_sync_committee_branch.iter().any(|h| !h.is_zero())
+    }
+
+    pub fn is_finality_update(&self) -> bool {
+        self.finalized_header.is_some() && self.finality_branch.iter().any(|h| !h.is_zero())
+    }
+}
+
+pub fn sha256_pair(left: &[u8], right: &[u8]) -> H256 {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    H256::from_slice(&hasher.finalize())
+}
+
+fn uint64_chunk(v: u64) -> H256 {
+    let mut chunk = H256::zero();
+    chunk.0[..8].copy_from_slice(&v.to_le_bytes());
+    chunk
+}
+
+fn bytes_chunks(data: &[u8]) -> Vec<H256> {
+    data.chunks(32)
+        .map(|c| {
+            let mut chunk = H256::zero();
+            chunk.0[..c.len()].copy_from_slice(c);
+            chunk
+        })
+        .collect()
+}
+
+/// Merkleizes `layer` padded with zero chunks up to `limit` leaves, which must be a power of two.
+fn merkleize(mut layer: Vec<H256>, limit: usize) -> H256 {
+    let mut zero_hash = H256::zero();
+    for _ in 0..limit.trailing_zeros() {
+        if layer.len() % 2 == 1 {
+            layer.push(zero_hash);
+        }
+        layer = layer
+            .chunks(2)
+            .map(|pair| sha256_pair(pair[0].as_bytes(), pair[1].as_bytes()))
+            .collect();
+        zero_hash = sha256_pair(zero_hash.as_bytes(), zero_hash.as_bytes());
+    }
+    layer.first().copied().unwrap_or(zero_hash)
+}
+
+fn merkleize_container(fields: Vec<H256>) -> H256 {
+    let limit = fields.len().next_power_of_two();
+    merkleize(fields, limit)
+}
+
+/// Depth of a generalized index in its tree.
+pub fn gindex_depth(gindex: u64) -> usize {
+    (63 - gindex.leading_zeros()) as usize
+}
+
+pub fn is_valid_merkle_branch(leaf: H256, branch: &[H256], gindex: u64, root: H256) -> bool {
+    if branch.len() != gindex_depth(gindex) {
+        return false;
+    }
+
+    let mut value = leaf;
+    for (i, node) in branch.iter().enumerate() {
+        value = if (gindex >> i) & 1 == 1 {
+            sha256_pair(node.as_bytes(), value.as_bytes())
+        } else {
+            sha256_pair(value.as_bytes(), node.as_bytes())
+        };
+    }
+    value == root
+}
+
+/// Checks a beacon state field proof against whichever of the fork-dependent `gindices` matches
+/// the depth of `branch`.
+pub fn is_valid_state_branch(leaf: H256, branch: &[H256], gindices: [u64; 2], root: H256) -> bool {
+    gindices
+        .into_iter()
+        .find(|gindex| gindex_depth(*gindex) == branch.len())
+        .map(|gindex| is_valid_merkle_branch(leaf, branch, gindex, root))
+        .unwrap_or(false)
+}
+
+impl BeaconBlockHeader {
+    pub fn hash_tree_root(&self) -> H256 {
+        merkleize_container(vec![
+            uint64_chunk(self.slot),
+            uint64_chunk(self.proposer_index),
+            self.parent_root,
+            self.state_root,
+            self.body_root,
+        ])
+    }
+}
+
+impl ExecutionPayloadHeader {
+    pub fn hash_tree_root(&self) -> H256 {
+        let extra_data_root = sha256_pair(
+            merkleize(bytes_chunks(&self.extra_data), 1).as_bytes(),
+            uint64_chunk(self.extra_data.len() as u64).as_bytes(),
+        );
+
+        let mut fields = vec![
+            self.parent_hash,
+            bytes_chunks(self.fee_recipient.as_bytes())[0],
+            self.state_root,
+            self.receipts_root,
+            merkleize(bytes_chunks(self.logs_bloom.as_bytes()), 8),
+            self.prev_randao,
+            uint64_chunk(self.block_number),
+            uint64_chunk(self.gas_limit),
+            uint64_chunk(self.gas_used),
+            uint64_chunk(self.timestamp),
+            extra_data_root,
+            H256(self.base_fee_per_gas.to_le_bytes()),
+            self.block_hash,
+            self.transactions_root,
+            self.withdrawals_root,
+        ];
+        if let (Some(blob_gas_used), Some(excess_blob_gas)) =
+            (self.blob_gas_used, self.excess_blob_gas)
+        {
+            fields.push(uint64_chunk(blob_gas_used));
+            fields.push(uint64_chunk(excess_blob_gas));
+        }
+        merkleize_container(fields)
+    }
+}
+
+impl LightClientHeader {
+    /// Checks that the execution payload header is part of the beacon block body.
+    pub fn is_valid(&self) -> bool {
+        self.execution.extra_data.len() <= 32
+            && is_valid_merkle_branch(
+                self.execution.hash_tree_root(),
+                &self.execution_branch,
+                EXECUTION_PAYLOAD_GINDEX,
+                self.beacon.body_root,
+            )
+    }
+}
+
+impl BlsPublicKey {
+    pub fn hash_tree_root(&self) -> H256 {
+        merkleize(bytes_chunks(&self.0), 2)
+    }
+}
+
+impl SyncCommittee {
+    pub fn hash_tree_root(&self) -> H256 {
+        let pubkeys_root = merkleize(
+            self.pubkeys
+                .iter()
+                .map(BlsPublicKey::hash_tree_root)
+                .collect(),
+            SYNC_COMMITTEE_SIZE,
+        );
+        sha256_pair(
+            pubkeys_root.as_bytes(),
+            self.aggregate_pubkey.hash_tree_root().as_bytes(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn beacon_block_header_root() {
+        let header = BeaconBlockHeader {
+            slot: 6209536,
+            proposer_index: 28897,
+            parent_root: H256::repeat_byte(0x11),
+            state_root: H256::repeat_byte(0x22),
+            body_root: H256::repeat_byte(0x33),
+        };
+        assert_eq!(
+            header.hash_tree_root(),
+            H256(hex!(
+                "c7fcad76ea5bb9d4a47e12a933c6c3135062dae9fce61bb3b09df0628813e409"
+            ))
+        );
+
+        // Empty subtrees hash to the well-known zero hashes.
+        assert_eq!(
+            merkleize(vec![], 8),
+            H256(hex!(
+                "c78009fdf07fc56a11f122370658a353aaa542ed63e44c4bc15ff4cd105ab33c"
+            ))
+        );
+
+        let json = serde_json::to_value(&header).unwrap();
+        assert_eq!(json["slot"], "6209536");
+        assert_eq!(
+            serde_json::from_value::<BeaconBlockHeader>(json).unwrap(),
+            header
+        );
+    }
+
+    #[test]
+    fn merkle_branches() {
+        let leaves = (0..8).map(H256::from_low_u64_be).collect::<Vec<_>>();
+        let root = merkleize(leaves.clone(), 8);
+
+        // Leaf 5 has generalized index 8 + 5 = 13.
+        let l01 = sha256_pair(leaves[0].as_bytes(), leaves[1].as_bytes());
+        let l23 = sha256_pair(leaves[2].as_bytes(), leaves[3].as_bytes());
+        let l67 = sha256_pair(leaves[6].as_bytes(), leaves[7].as_bytes());
+        let branch = [leaves[4], l67, sha256_pair(l01.as_bytes(), l23.as_bytes())];
+        assert!(is_valid_merkle_branch(leaves[5], &branch, 13, root));
+        assert!(!is_valid_merkle_branch(leaves[4], &branch, 13, root));
+        assert!(!is_valid_merkle_branch(leaves[5], &branch[..2], 13, root));
+        assert!(is_valid_state_branch(leaves[5], &branch, [13, 29], root));
+    }
+}
@@ -3,10 +3,12 @@ mod beacon;
 mod blockchain;
 mod clique;
 pub mod fork_choice_graph;
+pub mod light_client;
 
-use self::fork_choice_graph::ForkChoiceGraph;
 pub use self::{base::*, beacon::*, blockchain::*, clique::*};
+use self::{fork_choice_graph::ForkChoiceGraph, light_client::LightClientOptions};
 use crate::{
+    execution::analysis_cache::AnalysisCache,
     kv::{mdbx::*, MdbxWithDirHandle},
     models::*,
     BlockReader,
@@ -316,12 +318,18 @@ pub fn pre_validate_transaction(
     Ok(())
 }
 
+/// With `db`, beacon consensus serves the engine API, along with `eth` methods sharing the
+/// analysis cache.
 pub fn engine_factory(
-    db: Option<Arc<MdbxWithDirHandle<WriteMap>>>,
+    db: Option<(Arc<MdbxWithDirHandle<WriteMap>>, AnalysisCache)>,
     chain_config: ChainSpec,
     listen_addr: Option<SocketAddr>,
+    light_client: Option<LightClientOptions>,
 ) -> anyhow::Result<Box<dyn Consensus>> {
     Ok(match chain_config.consensus.seal_verification {
+        SealVerificationParams::Clique { .. } if light_client.is_some() => {
+            bail!("Beacon light client requires a proof-of-stake chain.")
+        }
         SealVerificationParams::Clique { period, epoch } => {
             let initial_signers = match chain_config.genesis.seal {
                 Seal::Clique {
@@ -361,6 +369,7 @@ pub fn engine_factory(
             terminal_block_hash,
             terminal_block_number,
             since,
+            light_client,
         )),
     })
 }
@@ -0,0 +1,577 @@
+//! Era1 archives of pre-merge history.
+//!
+//! An era1 file is an e2store file holding up to [`EPOCH_SIZE`] consecutive pre-merge blocks
+//! starting at a multiple of [`EPOCH_SIZE`]. Every block is stored as snappy-framed header, body
+//! and receipts followed by its total difficulty. The file is sealed with the accumulator root of
+//! the epoch and an index of block offsets.
+
+use crate::{
+    accessors::chain,
+    consensus::engine_factory,
+    execution::{analysis_cache::AnalysisCache, processor::ExecutionProcessor, tracer::NoopTracer},
+    kv::mdbx::*,
+    models::*,
+    trie::root_hash,
+    Buffer,
+};
+use anyhow::{bail, ensure, format_err};
+use bytes::{Bytes, BytesMut};
+use fastrlp::{Decodable, Encodable};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+};
+use tracing::*;
+
+/// Number of blocks in a single era1 file.
+pub const EPOCH_SIZE: u64 = 8192;
+
+type EntryType = [u8; 2];
+
+const VERSION: EntryType = [0x65, 0x32];
+const COMPRESSED_HEADER: EntryType = [0x03, 0x00];
+const COMPRESSED_BODY: EntryType = [0x04, 0x00];
+const COMPRESSED_RECEIPTS: EntryType = [0x05, 0x00];
+const TOTAL_DIFFICULTY: EntryType = [0x06, 0x00];
+const ACCUMULATOR: EntryType = [0x07, 0x00];
+const BLOCK_INDEX: EntryType = [0x66, 0x32];
+
+const ENTRY_HEADER_LEN: usize = 8;
+
+/// Block as stored in an era1 file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Era1Block {
+    pub header: BlockHeader,
+    pub body: BlockBody,
+    /// RLP-encoded receipts. Kept opaque because pre-Byzantium receipts carry post-state roots.
+    pub receipts: Bytes,
+    pub total_difficulty: U256,
+}
+
+/// Contents of a single era1 file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Era1 {
+    pub blocks: Vec<Era1Block>,
+    pub accumulator: H256,
+}
+
+fn write_entry(out: &mut impl Write, ty: EntryType, data: &[u8]) -> anyhow::Result<usize> {
+    out.write_all(&ty)?;
+    out.write_all(&u32::try_from(data.len())?.to_le_bytes())?;
+    out.write_all(&[0; 2])?;
+    out.write_all(data)?;
+
+    Ok(ENTRY_HEADER_LEN + data.len())
+}
+
+fn read_entry(input: &mut impl Read) -> anyhow::Result<Option<(EntryType, Vec<u8>)>> {
+    let mut header = [0; ENTRY_HEADER_LEN];
+    match input.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+
+    ensure!(
+        header[6..] == [0, 0],
+        "reserved bytes of e2store entry are not zero"
+    );
+
+    let mut data = vec![0; u32::from_le_bytes(header[2..6].try_into().unwrap()) as usize];
+    input.read_exact(&mut data)?;
+
+    Ok(Some(([header[0], header[1]], data)))
+}
+
+fn compress(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut encoder = snap::write::FrameEncoder::new(Vec::new());
+    encoder.write_all(data)?;
+    encoder
+        .into_inner()
+        .map_err(|e| format_err!("failed to compress entry: {}", e.error()))
+}
+
+fn decompress(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut out = Vec::new();
+    snap::read::FrameDecoder::new(data).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+fn rlp_encode(v: &impl Encodable) -> Vec<u8> {
+    let mut out = BytesMut::new();
+    v.encode(&mut out);
+    out.to_vec()
+}
+
+fn sha256_pair(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+/// SSZ hash tree root of `List[HeaderRecord, EPOCH_SIZE]`, where `HeaderRecord` is the
+/// `(block hash, total difficulty)` pair of each block of the epoch.
+pub fn accumulator_root(records: impl IntoIterator<Item = (H256, U256)>) -> H256 {
+    let mut layer = records
+        .into_iter()
+        .map(|(hash, td)| sha256_pair(&hash.0, &td.to_le_bytes()))
+        .collect::<Vec<_>>();
+    let len = layer.len() as u64;
+
+    let mut zero_hash = [0; 32];
+    for _ in 0..EPOCH_SIZE.trailing_zeros() {
+        if layer.len() % 2 == 1 {
+            layer.push(zero_hash);
+        }
+        layer = layer
+            .chunks(2)
+            .map(|pair| sha256_pair(&pair[0], &pair[1]))
+            .collect();
+        zero_hash = sha256_pair(&zero_hash, &zero_hash);
+    }
+    let root = layer.first().copied().unwrap_or(zero_hash);
+
+    let mut length = [0; 32];
+    length[..8].copy_from_slice(&len.to_le_bytes());
+
+    H256(sha256_pair(&root, &length))
+}
+
+impl Era1 {
+    /// First block of the file.
+    pub fn start(&self) -> Option<BlockNumber> {
+        self.blocks.first().map(|block| block.header.number)
+    }
+
+    pub fn epoch(&self) -> Option<u64> {
+        self.start().map(|start| start.0 / EPOCH_SIZE)
+    }
+
+    /// Standard file name: `<network>-<epoch>-<short accumulator root>.era1`.
+    pub fn file_name(&self, network: &str) -> String {
+        format!(
+            "{network}-{:05}-{}.era1",
+            self.epoch().unwrap_or_default(),
+            hex::encode(&self.accumulator[..4])
+        )
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> anyhow::Result<()> {
+        let start = self
+            .start()
+            .ok_or_else(|| format_err!("era1 file must contain at least one block"))?;
+
+        let mut offset = write_entry(out, VERSION, &[])?;
+        let mut block_offsets = Vec::with_capacity(self.blocks.len());
+        for block in &self.blocks {
+            block_offsets.push(offset);
+            offset += write_entry(
+                out,
+                COMPRESSED_HEADER,
+                &compress(&rlp_encode(&block.header))?,
+            )?;
+            offset += write_entry(out, COMPRESSED_BODY, &compress(&rlp_encode(&block.body))?)?;
+            offset += write_entry(out, COMPRESSED_RECEIPTS, &compress(&block.receipts)?)?;
+            offset += write_entry(out, TOTAL_DIFFICULTY, &block.total_difficulty.to_le_bytes())?;
+        }
+        offset += write_entry(out, ACCUMULATOR, &self.accumulator.0)?;
+
+        // Offsets in the index are relative to the start of the index entry itself.
+        let mut index = Vec::with_capacity(16 + 8 * block_offsets.len());
+        index.extend_from_slice(&start.0.to_le_bytes());
+        for block_offset in block_offsets {
+            index.extend_from_slice(&(block_offset as i64 - offset as i64).to_le_bytes());
+        }
+        index.extend_from_slice(&(self.blocks.len() as u64).to_le_bytes());
+        write_entry(out, BLOCK_INDEX, &index)?;
+
+        Ok(())
+    }
+
+    pub fn read(input: &mut impl Read) -> anyhow::Result<Self> {
+        match read_entry(input)? {
+            Some((VERSION, _)) => {}
+            _ => bail!("not an era1 file: version entry missing"),
+        }
+
+        let mut blocks = Vec::new();
+        let mut header = None;
+        let mut body = None;
+        let mut receipts = None;
+        let mut accumulator = None;
+        let mut index = None;
+        while let Some((ty, data)) = read_entry(input)? {
+            match ty {
+                COMPRESSED_HEADER => {
+                    header = Some(BlockHeader::decode(&mut &*decompress(&data)?)?);
+                }
+                COMPRESSED_BODY => {
+                    body = Some(BlockBody::decode(&mut &*decompress(&data)?)?);
+                }
+                COMPRESSED_RECEIPTS => {
+                    receipts = Some(Bytes::from(decompress(&data)?));
+                }
+                TOTAL_DIFFICULTY => {
+                    let total_difficulty = U256::from_le_bytes(
+                        data.try_into()
+                            .map_err(|_| format_err!("total difficulty must be 32 bytes"))?,
+                    );
+                    let (header, body, receipts) = header
+                        .take()
+                        .zip(body.take())
+                        .zip(receipts.take())
+                        .map(|((header, body), receipts)| (header, body, receipts))
+                        .ok_or_else(|| format_err!("incomplete block tuple"))?;
+                    blocks.push(Era1Block {
+                        header,
+                        body,
+                        receipts,
+                        total_difficulty,
+                    });
+                }
+                ACCUMULATOR => {
+                    accumulator =
+                        Some(H256::from_slice(data.get(..32).ok_or_else(|| {
+                            format_err!("accumulator must be 32 bytes")
+                        })?));
+                }
+                BLOCK_INDEX => {
+                    ensure!(data.len() >= 16, "block index too short");
+                    let start = u64::from_le_bytes(data[..8].try_into().unwrap());
+                    let count = u64::from_le_bytes(data[data.len() - 8..].try_into().unwrap());
+                    index = Some((start, count));
+                }
+                _ => {}
+            }
+        }
+
+        let accumulator = accumulator.ok_or_else(|| format_err!("accumulator missing"))?;
+        let (start, count) = index.ok_or_else(|| format_err!("block index missing"))?;
+        ensure!(
+            count == blocks.len() as u64,
+            "block index lists {count} blocks, found {}",
+            blocks.len()
+        );
+        ensure!(
+            blocks.first().map(|block| block.header.number.0) == Some(start),
+            "block index starts at {start}, first block is {:?}",
+            blocks.first().map(|block| block.header.number)
+        );
+
+        Ok(Self {
+            blocks,
+            accumulator,
+        })
+    }
+
+    pub fn read_file(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        Self::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    /// Checks that blocks are consecutive, link to each other and match their bodies, and that
+    /// the accumulator root commits to them.
+    pub fn verify(&self) -> anyhow::Result<()> {
+        let start = self
+            .start()
+            .ok_or_else(|| format_err!("era1 file contains no blocks"))?;
+        ensure!(
+            start.0 % EPOCH_SIZE == 0,
+            "era1 file starts at block {start} which is not an epoch boundary"
+        );
+        ensure!(
+            self.blocks.len() as u64 <= EPOCH_SIZE,
+            "era1 file contains {} blocks, more than an epoch",
+            self.blocks.len()
+        );
+
+        let mut records = Vec::with_capacity(self.blocks.len());
+        let mut parent: Option<(H256, &Era1Block)> = None;
+        for block in &self.blocks {
+            let number = block.header.number;
+            let hash = block.header.hash();
+
+            if let Some((parent_hash, parent)) = parent {
+                ensure!(
+                    number == parent.header.number + 1 && block.header.parent_hash == parent_hash,
+                    "block {number} does not attach to its parent"
+                );
+                ensure!(
+                    block.total_difficulty == parent.total_difficulty + block.header.difficulty,
+                    "wrong total difficulty for block {number}"
+                );
+            }
+            ensure!(
+                block.body.transactions_root() == block.header.transactions_root,
+                "transactions root mismatch for block {number}"
+            );
+            ensure!(
+                block.body.ommers_hash() == block.header.ommers_hash,
+                "ommers hash mismatch for block {number}"
+            );
+
+            records.push((hash, block.total_difficulty));
+            parent = Some((hash, block));
+        }
+
+        let root = accumulator_root(records);
+        ensure!(
+            root == self.accumulator,
+            "accumulator mismatch: file has {:?}, computed {:?}",
+            self.accumulator,
+            root
+        );
+
+        Ok(())
+    }
+}
+
+/// Era1 files of `dir` by epoch.
+pub fn list_files(dir: impl AsRef<Path>) -> anyhow::Result<BTreeMap<u64, PathBuf>> {
+    let mut files = BTreeMap::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("era1") {
+            continue;
+        }
+
+        let epoch = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split('-').rev().nth(1))
+            .and_then(|epoch| epoch.parse::<u64>().ok());
+        if let Some(epoch) = epoch {
+            if let Some(other) = files.insert(epoch, path.clone()) {
+                bail!(
+                    "duplicate era1 files for epoch {epoch}: {} and {}",
+                    other.display(),
+                    path.display()
+                );
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+/// Reads trusted accumulator roots, one hex-encoded root per epoch and line.
+pub fn read_accumulators(path: impl AsRef<Path>) -> anyhow::Result<Vec<H256>> {
+    std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            line.parse()
+                .map_err(|e| format_err!("invalid accumulator root {line}: {e}"))
+        })
+        .collect()
+}
+
+fn read_receipts<K: TransactionKind, E: EnvironmentKind>(
+    txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
+    chain_spec: &ChainSpec,
+    header: &BlockHeader,
+) -> anyhow::Result<Vec<Receipt>> {
+    let block_number = header.number;
+    if block_number == 0 {
+        return Ok(vec![]);
+    }
+
+    let block_body = chain::block_body::read_with_senders(txn, block_number)?
+        .ok_or_else(|| format_err!("body not found for block #{block_number}"))?;
+
+    let mut buffer = Buffer::new(txn, Some(BlockNumber(block_number.0 - 1)));
+    let block_execution_spec = chain_spec.collect_block_spec(block_number);
+    let mut engine = engine_factory(None, chain_spec.clone(), None, None)?;
+    let mut tracer = NoopTracer;
+
+    let mut processor = ExecutionProcessor::new(
+        &mut buffer,
+        &mut tracer,
+        analysis_cache,
+        &mut *engine,
+        header,
+        &block_body,
+        &block_execution_spec,
+    );
+
+    Ok(processor.execute_block_no_post_validation()?)
+}
+
+/// Builds the era1 file of `epoch` from the canonical chain. Stops at the merge, so the last
+/// epoch may be partial. Receipts are recomputed by re-executing blocks, which requires
+/// historical state, and checked against the receipts root of each header.
+///
+/// Pre-Byzantium receipts commit to the state root after each transaction, which cannot be
+/// recomputed from history, so epochs with pre-Byzantium transactions cannot be exported.
+pub fn export_epoch<K: TransactionKind, E: EnvironmentKind>(
+    txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
+    epoch: u64,
+) -> anyhow::Result<Option<Era1>> {
+    let chain_spec = chain::chain_config::read(txn)?
+        .ok_or_else(|| format_err!("chain specification not found"))?;
+
+    let mut blocks = Vec::new();
+    for block_number in epoch * EPOCH_SIZE..(epoch + 1) * EPOCH_SIZE {
+        let block_number = BlockNumber(block_number);
+        let header = match chain::header::read(txn, block_number)? {
+            Some(header) => header,
+            None => break,
+        };
+        if block_number > 0 && header.difficulty == U256::ZERO {
+            break;
+        }
+
+        let body = chain::block_body::read_without_senders(txn, block_number)?
+            .ok_or_else(|| format_err!("body not found for block #{block_number}"))?;
+        let total_difficulty = chain::td::read(txn, block_number)?
+            .ok_or_else(|| format_err!("total difficulty not found for block #{block_number}"))?;
+        if chain_spec.collect_block_spec(block_number).revision < Revision::Byzantium {
+            ensure!(
+                body.transactions.is_empty(),
+                "block #{block_number} predates Byzantium, its receipts need post-state roots"
+            );
+        }
+        let receipts = read_receipts(txn, analysis_cache, &chain_spec, &header)?;
+        ensure!(
+            root_hash(&receipts) == header.receipts_root,
+            "receipts root mismatch for block #{block_number}"
+        );
+
+        blocks.push(Era1Block {
+            header,
+            body,
+            receipts: rlp_encode(&receipts).into(),
+            total_difficulty,
+        });
+    }
+
+    if blocks.is_empty() {
+        return Ok(None);
+    }
+
+    let accumulator = accumulator_root(
+        blocks
+            .iter()
+            .map(|block| (block.header.hash(), block.total_difficulty)),
+    );
+
+    Ok(Some(Era1 {
+        blocks,
+        accumulator,
+    }))
+}
+
+/// Exports the era1 file of `epoch` into `dir`, returning its path.
+pub fn export_epoch_to_dir<K: TransactionKind, E: EnvironmentKind>(
+    txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
+    epoch: u64,
+    network: &str,
+    dir: impl AsRef<Path>,
+) -> anyhow::Result<Option<PathBuf>> {
+    let era1 = match export_epoch(txn, analysis_cache, epoch)? {
+        Some(era1) => era1,
+        None => return Ok(None),
+    };
+
+    let path = dir.as_ref().join(era1.file_name(network));
+    let mut out = BufWriter::new(File::create(&path)?);
+    era1.write(&mut out)?;
+    out.flush()?;
+
+    info!(
+        "Exported {} blocks of epoch {epoch} to {}",
+        era1.blocks.len(),
+        path.display()
+    );
+
+    Ok(Some(path))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chain(len: u64) -> Era1 {
+        let mut blocks = Vec::<Era1Block>::new();
+        for number in 0..len {
+            let parent = blocks.last();
+            let difficulty = (131_072 + number).as_u256();
+            let header = BlockHeader {
+                parent_hash: parent.map(|p| p.header.hash()).unwrap_or_default(),
+                number: BlockNumber(number),
+                difficulty,
+                ommers_hash: EMPTY_LIST_HASH,
+                transactions_root: EMPTY_ROOT,
+                ..Default::default()
+            };
+            let total_difficulty =
+                parent.map(|p| p.total_difficulty).unwrap_or_default() + difficulty;
+            blocks.push(Era1Block {
+                header,
+                body: BlockBody::default(),
+                receipts: rlp_encode(&Vec::<Receipt>::new()).into(),
+                total_difficulty,
+            });
+        }
+
+        let accumulator = accumulator_root(
+            blocks
+                .iter()
+                .map(|block| (block.header.hash(), block.total_difficulty)),
+        );
+        Era1 {
+            blocks,
+            accumulator,
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let era1 = chain(10);
+        era1.verify().unwrap();
+
+        let mut buf = Vec::new();
+        era1.write(&mut buf).unwrap();
+        assert_eq!(&buf[..ENTRY_HEADER_LEN], &[0x65, 0x32, 0, 0, 0, 0, 0, 0]);
+
+        let decoded = Era1::read(&mut &buf[..]).unwrap();
+        assert_eq!(decoded, era1);
+        decoded.verify().unwrap();
+
+        assert_eq!(
+            era1.file_name("mainnet"),
+            format!("mainnet-00000-{}.era1", hex::encode(&era1.accumulator[..4]))
+        );
+    }
+
+    #[test]
+    fn rejects_tampering() {
+        let mut era1 = chain(4);
+        era1.blocks[2].total_difficulty += 1.as_u256();
+        assert!(era1.verify().is_err());
+
+        let mut era1 = chain(4);
+        era1.accumulator = H256::repeat_byte(1);
+        assert!(era1.verify().is_err());
+    }
+
+    #[test]
+    fn empty_accumulator() {
+        // Root of an empty list is the zero subtree root mixed with zero length.
+        let mut zero_hash = [0; 32];
+        for _ in 0..13 {
+            zero_hash = sha256_pair(&zero_hash, &zero_hash);
+        }
+        assert_eq!(
+            accumulator_root(vec![]),
+            H256(sha256_pair(&zero_hash, &[0; 32]))
+        );
+    }
+}
@@ -1,31 +1,221 @@
 use super::evm::AnalyzedCode;
+use crate::kv::{mdbx::*, tables};
 use ethereum_types::H256;
 use lru::LruCache;
-use std::num::NonZeroUsize;
+use parking_lot::Mutex;
+use std::sync::Arc;
+use tracing::*;
+
+/// Default limit of analyzed code size held in the cache, in bytes.
+pub const DEFAULT_ANALYSIS_CACHE_SIZE: usize = 256 * 1024 * 1024;
 
 #[derive(Debug)]
+struct Inner {
+    entries: LruCache<H256, Arc<AnalyzedCode>>,
+    size: usize,
+    max_size: usize,
+    /// Whether entries were added or evicted since the last save.
+    changed: bool,
+}
+
+/// Cache of analyzed code keyed by code hash.
+///
+/// The cache is a cheaply cloneable handle: all clones share the same entries, so that a
+/// single instance may be owned by the node and shared between block execution and RPC.
+/// Total size of cached code is bounded, least recently used entries are evicted first.
+#[derive(Clone, Debug)]
 pub struct AnalysisCache {
-    inner: LruCache<H256, AnalyzedCode>,
+    inner: Arc<Mutex<Inner>>,
 }
 
 impl Default for AnalysisCache {
     fn default() -> Self {
-        Self::new(NonZeroUsize::new(5000).unwrap())
+        Self::new(DEFAULT_ANALYSIS_CACHE_SIZE)
     }
 }
 
+fn code_size(code: &AnalyzedCode) -> usize {
+    code.size()
+}
+
 impl AnalysisCache {
-    pub fn new(cap: NonZeroUsize) -> Self {
+    /// Creates a cache holding at most `max_size` bytes of code.
+    pub fn new(max_size: usize) -> Self {
         Self {
-            inner: LruCache::new(cap),
+            inner: Arc::new(Mutex::new(Inner {
+                entries: LruCache::unbounded(),
+                size: 0,
+                max_size,
+                changed: false,
+            })),
+        }
+    }
+
+    pub fn get(&self, code_hash: &H256) -> Option<Arc<AnalyzedCode>> {
+        self.inner.lock().entries.get(code_hash).cloned()
+    }
+
+    pub fn put(&self, code_hash: H256, code: Arc<AnalyzedCode>) {
+        let size = code_size(&code);
+
+        let mut inner = self.inner.lock();
+        if size > inner.max_size {
+            return;
+        }
+
+        if let Some(old) = inner.entries.put(code_hash, code) {
+            inner.size -= code_size(&old);
+        } else {
+            inner.changed = true;
+        }
+        inner.size += size;
+
+        while inner.size > inner.max_size {
+            match inner.entries.pop_lru() {
+                Some((_, evicted)) => {
+                    inner.size -= code_size(&evicted);
+                    inner.changed = true;
+                }
+                None => break,
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total size of cached analyzed code, in bytes.
+    pub fn size(&self) -> usize {
+        self.inner.lock().size
+    }
+
+    /// Code hashes of cached entries, most recently used first.
+    pub fn hot_code_hashes(&self) -> Vec<H256> {
+        self.inner
+            .lock()
+            .entries
+            .iter()
+            .map(|(code_hash, _)| *code_hash)
+            .collect()
+    }
+
+    /// Persists hashes of cached code, so that the cache can be warmed up after restart. Does
+    /// nothing unless the set of cached code changed since the last save.
+    pub fn save<E: EnvironmentKind>(&self, tx: &MdbxTransaction<'_, RW, E>) -> anyhow::Result<()> {
+        let hot_code_hashes = {
+            let mut inner = self.inner.lock();
+            if !inner.changed {
+                return Ok(());
+            }
+            inner.changed = false;
+            inner
+                .entries
+                .iter()
+                .map(|(code_hash, _)| *code_hash)
+                .collect::<Vec<_>>()
+        };
+
+        tx.set(tables::HotCode, (), hot_code_hashes)
+    }
+
+    /// Analyzes code persisted by [`save`](Self::save). Returns the number of loaded entries.
+    pub fn warm_up<K: TransactionKind, E: EnvironmentKind>(
+        &self,
+        tx: &MdbxTransaction<'_, K, E>,
+    ) -> anyhow::Result<usize> {
+        let code_hashes = tx.get(tables::HotCode, ())?.unwrap_or_default();
+
+        let mut loaded = 0;
+        // Insert least recently used first to restore the order of eviction.
+        for code_hash in code_hashes.into_iter().rev() {
+            if let Some(code) = tx.get(tables::Code, code_hash)? {
+                self.put(code_hash, Arc::new(AnalyzedCode::analyze(&code)));
+                loaded += 1;
+            } else {
+                debug!("Code {:?} of analysis cache not found", code_hash);
+            }
         }
+        // Persisted already.
+        self.inner.lock().changed = false;
+
+        Ok(loaded)
     }
+}
 
-    pub fn get(&mut self, code_hash: &H256) -> Option<&AnalyzedCode> {
-        self.inner.get(code_hash)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let code = |len| Arc::new(AnalyzedCode::analyze(&vec![0; len]));
+        let size = code(4).size();
+        assert!(size > 4);
+
+        let cache = AnalysisCache::new(2 * size + 1);
+        cache.put(H256::repeat_byte(1), code(4));
+        cache.put(H256::repeat_byte(2), code(4));
+        assert_eq!(cache.size(), 2 * size);
+
+        // Touch the first entry so that the second one is evicted.
+        assert!(cache.get(&H256::repeat_byte(1)).is_some());
+        cache.put(H256::repeat_byte(3), code(4));
+        assert_eq!(
+            cache.hot_code_hashes(),
+            vec![H256::repeat_byte(3), H256::repeat_byte(1)]
+        );
+        assert_eq!(cache.size(), 2 * size);
+
+        // Code larger than the whole cache is not cached at all.
+        cache.put(H256::repeat_byte(4), code(2 * size));
+        assert_eq!(cache.len(), 2);
+
+        // Clones share entries.
+        let clone = cache.clone();
+        let other = Arc::new(AnalyzedCode::analyze(&hex!("6000")));
+        let other_size = other.size();
+        clone.put(H256::repeat_byte(1), other);
+        assert_eq!(cache.size(), size + other_size);
     }
 
-    pub fn put(&mut self, code_hash: H256, code: AnalyzedCode) {
-        self.inner.put(code_hash, code);
+    #[test]
+    fn persistence() {
+        let db = crate::kv::new_mem_chaindata().unwrap();
+        let tx = db.begin_mutable().unwrap();
+
+        let code = bytes::Bytes::from(hex!("600160020100").to_vec());
+        let code_hash = crate::crypto::keccak256(&code);
+        tx.set(tables::Code, code_hash, code.clone()).unwrap();
+
+        let cache = AnalysisCache::default();
+        cache.put(code_hash, Arc::new(AnalyzedCode::analyze(&code)));
+        cache.put(
+            H256::repeat_byte(0xff),
+            Arc::new(AnalyzedCode::analyze(&[])),
+        );
+        cache.save(&tx).unwrap();
+
+        let warmed_up = AnalysisCache::default();
+        assert_eq!(warmed_up.warm_up(&tx).unwrap(), 1);
+        assert_eq!(warmed_up.get(&code_hash).unwrap().orig_code(), &code[..]);
+
+        // Unchanged set of code is not written again.
+        tx.set(tables::HotCode, (), vec![]).unwrap();
+        cache.get(&code_hash);
+        cache.save(&tx).unwrap();
+        assert_eq!(tx.get(tables::HotCode, ()).unwrap(), Some(vec![]));
+
+        cache.put(
+            H256::repeat_byte(0xee),
+            Arc::new(AnalyzedCode::analyze(&[])),
+        );
+        cache.save(&tx).unwrap();
+        assert_eq!(tx.get(tables::HotCode, ()).unwrap().unwrap().len(), 3);
     }
 }
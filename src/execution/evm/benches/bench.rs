@@ -409,55 +409,109 @@ fn synthetic_benchmarks(c: &mut Criterion) {
         }
     }
 
-    c.bench_function("/total/synth/loop_v1", |b| {
-        b.iter_batched(
-            || {
-                prepare(
-                    AnalyzedCode::analyze(&generate_loop_v1(Bytecode::new()).build()),
-                    Bytes::new(),
-                )
-            },
-            execute,
-            BatchSize::SmallInput,
-        )
-    });
-    c.bench_function("/total/synth/loop_v2", |b| {
-        b.iter_batched(
-            || {
-                prepare(
-                    AnalyzedCode::analyze(&generate_loop_v2(Bytecode::new()).build()),
-                    Bytes::new(),
-                )
-            },
-            execute,
-            BatchSize::SmallInput,
-        )
-    });
+    bench_code(
+        c,
+        "/total/synth/loop_v1",
+        &generate_loop_v1(Bytecode::new()).build(),
+        Bytes::new(),
+    );
+    bench_code(
+        c,
+        "/total/synth/loop_v2",
+        &generate_loop_v2(Bytecode::new()).build(),
+        Bytes::new(),
+    );
 
     for params in params_list {
-        c.bench_function(
+        bench_code(
+            c,
             &format!(
                 "/total/synth/{}/{}{}",
                 params.opcode,
                 InstructionCategory::from_opcode(params.opcode),
                 params.mode as usize
             ),
-            |b| {
-                b.iter_batched(
-                    || {
-                        prepare(
-                            AnalyzedCode::analyze(&generate_code(&params).build()),
-                            Bytes::new(),
-                        )
-                    },
-                    execute,
-                    BatchSize::SmallInput,
-                )
-            },
+            &generate_code(&params).build(),
+            Bytes::new(),
         );
     }
 }
 
+/// Benchmarks of instruction sequences fused into superinstructions.
+fn superinstruction_benchmarks(c: &mut Criterion) {
+    // DUP1 PUSH1 MSTORE DUP1 PUSH1 MSTORE ...
+    bench_code(
+        c,
+        "/total/synth/PUSH+MSTORE",
+        &generate_loop_v2(
+            STACK_LIMIT
+                * Bytecode::new()
+                    .opcode(OpCode::DUP1)
+                    .pushv(0)
+                    .opcode(OpCode::MSTORE),
+        )
+        .build(),
+        Bytes::new(),
+    );
+
+    // DUP1 DUP1 SWAP1 POP DUP1 SWAP1 POP ... POP
+    bench_code(
+        c,
+        "/total/synth/DUP+SWAP",
+        &generate_loop_v2(
+            Bytecode::new()
+                .opcode(OpCode::DUP1)
+                .append_bc(
+                    STACK_LIMIT
+                        * Bytecode::new()
+                            .opcode(OpCode::DUP1)
+                            .opcode(OpCode::SWAP1)
+                            .opcode(OpCode::POP),
+                )
+                .opcode(OpCode::POP),
+        )
+        .build(),
+        Bytes::new(),
+    );
+
+    // PUSH2 JUMP JUMPDEST PUSH2 JUMP JUMPDEST ...
+    // The loop label is preceded by PUSH32 of the loop counter.
+    let inner_code_offset = 34;
+    let mut inner_code = Bytecode::new();
+    for _ in 0..STACK_LIMIT {
+        let destination = inner_code_offset + inner_code.len() + 4;
+        inner_code = inner_code
+            .pushb((destination as u16).to_be_bytes())
+            .opcode(OpCode::JUMP)
+            .opcode(OpCode::JUMPDEST);
+    }
+    bench_code(
+        c,
+        "/total/synth/PUSH+JUMP",
+        &generate_loop_v2(inner_code).build(),
+        Bytes::new(),
+    );
+}
+
+/// Benchmarks the code both with and without analysis-time optimizations.
+fn bench_code(c: &mut Criterion, name: &str, code: &[u8], input_data: Bytes) {
+    for (suffix, analyzed_code) in [
+        ("", AnalyzedCode::analyze(code)),
+        ("/unoptimized", AnalyzedCode::analyze_unoptimized(code)),
+    ] {
+        let res = execute(prepare(analyzed_code.clone(), input_data.clone()));
+        assert_eq!(res.status_code, StatusCode::Success, "{}", name);
+
+        c.bench_function(&format!("{}{}", name, suffix), |b| {
+            b.iter_batched(
+                || prepare(analyzed_code.clone(), input_data.clone()),
+                execute,
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
 fn prepare(
     code: AnalyzedCode,
     input_data: Bytes,
@@ -541,23 +595,19 @@ fn main_benchmarks(c: &mut Criterion) {
             }
 
             let expected_output = hex::decode(&params.out).unwrap();
-            let analyzed_code = AnalyzedCode::analyze(&code);
             let input_data = Bytes::from(input_data);
 
-            let res = execute(prepare(analyzed_code.clone(), input_data.clone()));
+            for analyzed_code in [
+                AnalyzedCode::analyze(&code),
+                AnalyzedCode::analyze_unoptimized(&code),
+            ] {
+                let res = execute(prepare(analyzed_code, input_data.clone()));
 
-            assert_eq!(res.status_code, StatusCode::Success);
-            assert_eq!(res.output_data, expected_output);
+                assert_eq!(res.status_code, StatusCode::Success);
+                assert_eq!(res.output_data, expected_output);
+            }
 
-            c.bench_function(&format!("{}/{}", name, bench), move |b| {
-                let analyzed_code = analyzed_code.clone();
-                let input_data = input_data.clone();
-                b.iter_batched(
-                    move || prepare(analyzed_code.clone(), input_data.clone()),
-                    execute,
-                    BatchSize::SmallInput,
-                )
-            });
+            bench_code(c, &format!("{}/{}", name, bench), &code, input_data);
         }
     }
 }
@@ -565,7 +615,7 @@ fn main_benchmarks(c: &mut Criterion) {
 criterion_group!(
     name = benches;
     config = Criterion::default().with_profiler(FlamegraphProfiler::new(100));
-    targets = main_benchmarks, synthetic_benchmarks
+    targets = main_benchmarks, synthetic_benchmarks, superinstruction_benchmarks
 );
 
 criterion_main!(benches);
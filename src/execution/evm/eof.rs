@@ -0,0 +1,519 @@
+//! [EVM Object Format](https://eips.ethereum.org/EIPS/eip-3540) v1 containers.
+//!
+//! Containers are validated once, at analysis time ([EIP-3670](https://eips.ethereum.org/EIPS/eip-3670),
+//! [EIP-4200](https://eips.ethereum.org/EIPS/eip-4200), [EIP-4750](https://eips.ethereum.org/EIPS/eip-4750),
+//! [EIP-5450](https://eips.ethereum.org/EIPS/eip-5450)), which lets the interpreter skip jump
+//! destination and most of the stack checks while executing them.
+//!
+//! Nested containers are not supported, and CALL*, CREATE* and GAS are still allowed inside of
+//! code sections.
+
+use super::{
+    instructions::{instruction_table::EOF_INSTRUCTION_TABLE, PROPERTIES},
+    opcode::OpCode,
+    state::STACK_SIZE,
+};
+use strum_macros::Display;
+
+pub const MAGIC: [u8; 2] = [0xef, 0x00];
+pub const VERSION: u8 = 0x01;
+
+const KIND_TYPES: u8 = 0x01;
+const KIND_CODE: u8 = 0x02;
+const KIND_DATA: u8 = 0xff;
+const TERMINATOR: u8 = 0x00;
+
+const TYPE_SIZE: usize = 4;
+const MAX_CODE_SECTIONS: usize = 1024;
+const MAX_INPUTS_OUTPUTS: u8 = 0x7f;
+const MAX_STACK_HEIGHT: u16 = 0x03ff;
+
+/// Maximum depth of CALLF return stack.
+pub const RETURN_STACK_LIMIT: usize = 1024;
+
+/// Number of outputs designating a code section that never returns to its caller.
+pub const NON_RETURNING_FUNCTION: u8 = 0x80;
+
+/// Container validation error.
+#[derive(Clone, Copy, Debug, Display, PartialEq, Eq)]
+pub enum EofValidationError {
+    #[strum(serialize = "invalid prefix")]
+    InvalidPrefix,
+    #[strum(serialize = "unknown version")]
+    UnknownVersion,
+    #[strum(serialize = "missing type section header")]
+    MissingTypeHeader,
+    #[strum(serialize = "missing code section header")]
+    MissingCodeHeader,
+    #[strum(serialize = "missing data section header")]
+    MissingDataHeader,
+    #[strum(serialize = "incomplete section number")]
+    IncompleteSectionNumber,
+    #[strum(serialize = "incomplete section size")]
+    IncompleteSectionSize,
+    #[strum(serialize = "header terminator missing")]
+    HeaderTerminatorMissing,
+    #[strum(serialize = "zero section size")]
+    ZeroSectionSize,
+    #[strum(serialize = "too many code sections")]
+    TooManyCodeSections,
+    #[strum(serialize = "invalid type section size")]
+    InvalidTypeSectionSize,
+    #[strum(serialize = "invalid section bodies size")]
+    InvalidSectionBodiesSize,
+    #[strum(serialize = "invalid first section type")]
+    InvalidFirstSectionType,
+    #[strum(serialize = "inputs/outputs number above limit")]
+    InputsOutputsNumAboveLimit,
+    #[strum(serialize = "max stack height above limit")]
+    MaxStackHeightAboveLimit,
+    #[strum(serialize = "undefined instruction")]
+    UndefinedInstruction,
+    #[strum(serialize = "truncated instruction")]
+    TruncatedInstruction,
+    #[strum(serialize = "invalid relative jump destination")]
+    InvalidRjumpDestination,
+    #[strum(serialize = "invalid code section index")]
+    InvalidCodeSectionIndex,
+    #[strum(serialize = "CALLF to non-returning function")]
+    CallfToNonReturningFunction,
+    #[strum(serialize = "RETF in non-returning function")]
+    RetfInNonReturningFunction,
+    #[strum(serialize = "JUMPF destination has incompatible outputs")]
+    JumpfDestinationIncompatibleOutputs,
+    #[strum(serialize = "invalid DATALOADN index")]
+    InvalidDataloadnIndex,
+    #[strum(serialize = "no terminating instruction")]
+    NoTerminatingInstruction,
+    #[strum(serialize = "stack underflow")]
+    StackUnderflow,
+    #[strum(serialize = "stack overflow")]
+    StackOverflow,
+    #[strum(serialize = "stack height mismatch")]
+    StackHeightMismatch,
+    #[strum(serialize = "invalid max stack height")]
+    InvalidMaxStackHeight,
+    #[strum(serialize = "unreachable instructions")]
+    UnreachableInstructions,
+}
+
+impl std::error::Error for EofValidationError {}
+
+/// Entry of the type section.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FunctionType {
+    pub inputs: u8,
+    pub outputs: u8,
+    pub max_stack_height: u16,
+}
+
+impl FunctionType {
+    pub fn is_returning(&self) -> bool {
+        self.outputs != NON_RETURNING_FUNCTION
+    }
+}
+
+/// Layout of a validated container.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EofHeader {
+    pub types: Vec<FunctionType>,
+    /// Offsets of code sections from the beginning of the container.
+    pub code_offsets: Vec<usize>,
+    pub code_sizes: Vec<usize>,
+    pub data_offset: usize,
+    pub data_size: usize,
+}
+
+impl EofHeader {
+    pub fn code_section<'c>(&self, container: &'c [u8], index: usize) -> &'c [u8] {
+        &container[self.code_offsets[index]..][..self.code_sizes[index]]
+    }
+
+    pub fn data<'c>(&self, container: &'c [u8]) -> &'c [u8] {
+        &container[self.data_offset..][..self.data_size]
+    }
+}
+
+/// Whether the code is meant to be an EOF container. It is not necessarily a valid one.
+pub fn is_eof(code: &[u8]) -> bool {
+    code.starts_with(&MAGIC)
+}
+
+struct Cursor<'c> {
+    data: &'c [u8],
+    pos: usize,
+}
+
+impl<'c> Cursor<'c> {
+    fn u8(&mut self) -> Option<u8> {
+        let v = *self.data.get(self.pos)?;
+        self.pos += 1;
+        Some(v)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        let v = self.data.get(self.pos..self.pos + 2)?;
+        self.pos += 2;
+        Some(u16::from_be_bytes([v[0], v[1]]))
+    }
+}
+
+/// Parses the container header and checks that it is consistent with the container size.
+pub fn read_header(container: &[u8]) -> Result<EofHeader, EofValidationError> {
+    if !is_eof(container) {
+        return Err(EofValidationError::InvalidPrefix);
+    }
+    if container.get(MAGIC.len()) != Some(&VERSION) {
+        return Err(EofValidationError::UnknownVersion);
+    }
+
+    let mut cursor = Cursor {
+        data: container,
+        pos: MAGIC.len() + 1,
+    };
+
+    if cursor.u8() != Some(KIND_TYPES) {
+        return Err(EofValidationError::MissingTypeHeader);
+    }
+    let types_size = cursor
+        .u16()
+        .ok_or(EofValidationError::IncompleteSectionSize)? as usize;
+    if types_size == 0 || types_size % TYPE_SIZE != 0 {
+        return Err(EofValidationError::InvalidTypeSectionSize);
+    }
+
+    if cursor.u8() != Some(KIND_CODE) {
+        return Err(EofValidationError::MissingCodeHeader);
+    }
+    let num_code_sections = cursor
+        .u16()
+        .ok_or(EofValidationError::IncompleteSectionNumber)? as usize;
+    if num_code_sections == 0 {
+        return Err(EofValidationError::ZeroSectionSize);
+    }
+    if num_code_sections > MAX_CODE_SECTIONS {
+        return Err(EofValidationError::TooManyCodeSections);
+    }
+    if types_size / TYPE_SIZE != num_code_sections {
+        return Err(EofValidationError::InvalidTypeSectionSize);
+    }
+
+    let mut code_sizes = Vec::with_capacity(num_code_sections);
+    for _ in 0..num_code_sections {
+        let size = cursor
+            .u16()
+            .ok_or(EofValidationError::IncompleteSectionSize)? as usize;
+        if size == 0 {
+            return Err(EofValidationError::ZeroSectionSize);
+        }
+        code_sizes.push(size);
+    }
+
+    if cursor.u8() != Some(KIND_DATA) {
+        return Err(EofValidationError::MissingDataHeader);
+    }
+    let data_size = cursor
+        .u16()
+        .ok_or(EofValidationError::IncompleteSectionSize)? as usize;
+
+    if cursor.u8() != Some(TERMINATOR) {
+        return Err(EofValidationError::HeaderTerminatorMissing);
+    }
+
+    let types_offset = cursor.pos;
+    let mut code_offsets = Vec::with_capacity(num_code_sections);
+    let mut offset = types_offset + types_size;
+    for size in &code_sizes {
+        code_offsets.push(offset);
+        offset += size;
+    }
+    let data_offset = offset;
+
+    if data_offset + data_size != container.len() {
+        return Err(EofValidationError::InvalidSectionBodiesSize);
+    }
+
+    let types = container[types_offset..][..types_size]
+        .chunks_exact(TYPE_SIZE)
+        .map(|t| FunctionType {
+            inputs: t[0],
+            outputs: t[1],
+            max_stack_height: u16::from_be_bytes([t[2], t[3]]),
+        })
+        .collect::<Vec<_>>();
+
+    for ty in &types {
+        if ty.inputs > MAX_INPUTS_OUTPUTS
+            || (ty.outputs > MAX_INPUTS_OUTPUTS && ty.outputs != NON_RETURNING_FUNCTION)
+        {
+            return Err(EofValidationError::InputsOutputsNumAboveLimit);
+        }
+        if ty.max_stack_height > MAX_STACK_HEIGHT {
+            return Err(EofValidationError::MaxStackHeightAboveLimit);
+        }
+    }
+
+    if types[0].inputs != 0 || types[0].outputs != NON_RETURNING_FUNCTION {
+        return Err(EofValidationError::InvalidFirstSectionType);
+    }
+
+    Ok(EofHeader {
+        types,
+        code_offsets,
+        code_sizes,
+        data_offset,
+        data_size,
+    })
+}
+
+/// Fully validates the container and returns its header.
+pub fn validate(container: &[u8]) -> Result<EofHeader, EofValidationError> {
+    let header = read_header(container)?;
+
+    for section in 0..header.types.len() {
+        let code = header.code_section(container, section);
+        validate_instructions(&header, section, code)?;
+        validate_stack_heights(&header, section, code)?;
+    }
+
+    Ok(header)
+}
+
+#[inline]
+fn read_u16(code: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([code[pos], code[pos + 1]])
+}
+
+#[inline]
+fn read_i16(code: &[u8], pos: usize) -> i16 {
+    i16::from_be_bytes([code[pos], code[pos + 1]])
+}
+
+/// Size of immediate arguments of the instruction at `pos`.
+fn immediate_size(code: &[u8], pos: usize) -> usize {
+    match OpCode(code[pos]) {
+        op if op.to_u8() >= OpCode::PUSH1.to_u8() && op.to_u8() <= OpCode::PUSH32.to_u8() => {
+            (op.to_u8() - OpCode::PUSH1.to_u8() + 1) as usize
+        }
+        OpCode::RJUMP | OpCode::RJUMPI | OpCode::CALLF | OpCode::JUMPF | OpCode::DATALOADN => 2,
+        OpCode::RJUMPV => {
+            1 + code
+                .get(pos + 1)
+                .map(|&max_index| (max_index as usize + 1) * 2)
+                .unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// Destinations of a relative jump instruction at `pos`.
+fn rjump_targets(code: &[u8], pos: usize) -> Vec<isize> {
+    let next = (pos + 1 + immediate_size(code, pos)) as isize;
+    match OpCode(code[pos]) {
+        OpCode::RJUMP | OpCode::RJUMPI => vec![next + read_i16(code, pos + 1) as isize],
+        OpCode::RJUMPV => (0..=code[pos + 1] as usize)
+            .map(|case| next + read_i16(code, pos + 2 + case * 2) as isize)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn validate_instructions(
+    header: &EofHeader,
+    section: usize,
+    code: &[u8],
+) -> Result<(), EofValidationError> {
+    let mut instruction_starts = vec![false; code.len()];
+    let mut targets = vec![];
+
+    let mut pos = 0;
+    while pos < code.len() {
+        let op = OpCode(code[pos]);
+        if EOF_INSTRUCTION_TABLE[op.to_usize()].gas_cost < 0 {
+            return Err(EofValidationError::UndefinedInstruction);
+        }
+
+        if op == OpCode::RJUMPV && pos + 1 >= code.len() {
+            return Err(EofValidationError::TruncatedInstruction);
+        }
+        let next = pos + 1 + immediate_size(code, pos);
+        if next > code.len() {
+            return Err(EofValidationError::TruncatedInstruction);
+        }
+
+        match op {
+            OpCode::RJUMP | OpCode::RJUMPI | OpCode::RJUMPV => {
+                targets.extend(rjump_targets(code, pos));
+            }
+            OpCode::CALLF => {
+                let ty = header
+                    .types
+                    .get(read_u16(code, pos + 1) as usize)
+                    .ok_or(EofValidationError::InvalidCodeSectionIndex)?;
+                if !ty.is_returning() {
+                    return Err(EofValidationError::CallfToNonReturningFunction);
+                }
+            }
+            OpCode::JUMPF => {
+                if read_u16(code, pos + 1) as usize >= header.types.len() {
+                    return Err(EofValidationError::InvalidCodeSectionIndex);
+                }
+            }
+            OpCode::RETF => {
+                if !header.types[section].is_returning() {
+                    return Err(EofValidationError::RetfInNonReturningFunction);
+                }
+            }
+            OpCode::DATALOADN => {
+                if read_u16(code, pos + 1) as usize + 32 > header.data_size {
+                    return Err(EofValidationError::InvalidDataloadnIndex);
+                }
+            }
+            _ => {}
+        }
+
+        instruction_starts[pos] = true;
+        pos = next;
+    }
+
+    for target in targets {
+        if target < 0
+            || !instruction_starts
+                .get(target as usize)
+                .copied()
+                .unwrap_or(false)
+        {
+            return Err(EofValidationError::InvalidRjumpDestination);
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that every instruction is reachable with a single statically known stack height.
+///
+/// Must be called after [`validate_instructions`], which guarantees that immediates and jump
+/// destinations are well-formed.
+fn validate_stack_heights(
+    header: &EofHeader,
+    section: usize,
+    code: &[u8],
+) -> Result<(), EofValidationError> {
+    let ty = header.types[section];
+
+    let mut heights = vec![None::<i32>; code.len()];
+    heights[0] = Some(ty.inputs.into());
+    let mut max_height = i32::from(ty.inputs);
+
+    let mut worklist = vec![0];
+    while let Some(pos) = worklist.pop() {
+        let op = OpCode(code[pos]);
+        let height = heights[pos].unwrap();
+
+        let (required, change) = match op {
+            OpCode::CALLF => {
+                let callee = header.types[read_u16(code, pos + 1) as usize];
+                if height + i32::from(callee.max_stack_height) - i32::from(callee.inputs)
+                    > STACK_SIZE as i32
+                {
+                    return Err(EofValidationError::StackOverflow);
+                }
+                (
+                    i32::from(callee.inputs),
+                    i32::from(callee.outputs) - i32::from(callee.inputs),
+                )
+            }
+            OpCode::JUMPF => {
+                let callee = header.types[read_u16(code, pos + 1) as usize];
+                if height + i32::from(callee.max_stack_height) - i32::from(callee.inputs)
+                    > STACK_SIZE as i32
+                {
+                    return Err(EofValidationError::StackOverflow);
+                }
+                if callee.is_returning() {
+                    // Callee returns directly to our caller, so it has to leave the stack
+                    // exactly as RETF in this section would.
+                    if !ty.is_returning() || ty.outputs < callee.outputs {
+                        return Err(EofValidationError::JumpfDestinationIncompatibleOutputs);
+                    }
+                    let expected = i32::from(ty.outputs) + i32::from(callee.inputs)
+                        - i32::from(callee.outputs);
+                    if height != expected {
+                        return Err(EofValidationError::StackHeightMismatch);
+                    }
+                }
+                (i32::from(callee.inputs), 0)
+            }
+            OpCode::RETF => {
+                if height != i32::from(ty.outputs) {
+                    return Err(EofValidationError::StackHeightMismatch);
+                }
+                (0, 0)
+            }
+            _ => {
+                let properties = PROPERTIES[op.to_usize()].unwrap();
+                (
+                    properties.stack_height_required.into(),
+                    properties.stack_height_change.into(),
+                )
+            }
+        };
+
+        if height < required {
+            return Err(EofValidationError::StackUnderflow);
+        }
+        let next_height = height + change;
+        if next_height > i32::from(MAX_STACK_HEIGHT) {
+            return Err(EofValidationError::StackOverflow);
+        }
+        max_height = max_height.max(next_height);
+
+        let next = pos + 1 + immediate_size(code, pos);
+        let successors = match op {
+            OpCode::STOP
+            | OpCode::RETURN
+            | OpCode::REVERT
+            | OpCode::INVALID
+            | OpCode::RETF
+            | OpCode::JUMPF => vec![],
+            OpCode::RJUMP => rjump_targets(code, pos),
+            OpCode::RJUMPI | OpCode::RJUMPV => {
+                let mut successors = vec![next as isize];
+                successors.extend(rjump_targets(code, pos));
+                successors
+            }
+            _ => vec![next as isize],
+        };
+
+        for successor in successors {
+            let successor = successor as usize;
+            if successor >= code.len() {
+                return Err(EofValidationError::NoTerminatingInstruction);
+            }
+            match heights[successor] {
+                None => {
+                    heights[successor] = Some(next_height);
+                    worklist.push(successor);
+                }
+                Some(h) if h != next_height => {
+                    return Err(EofValidationError::StackHeightMismatch);
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    let mut pos = 0;
+    while pos < code.len() {
+        if heights[pos].is_none() {
+            return Err(EofValidationError::UnreachableInstructions);
+        }
+        pos += 1 + immediate_size(code, pos);
+    }
+
+    if max_height != i32::from(ty.max_stack_height) {
+        return Err(EofValidationError::InvalidMaxStackHeight);
+    }
+
+    Ok(())
+}
@@ -1,4 +1,9 @@
-use crate::execution::evm::{interpreter::JumpdestMap, state::ExecutionState, StatusCode};
+use crate::execution::evm::{
+    eof::{EofHeader, RETURN_STACK_LIMIT},
+    interpreter::JumpdestMap,
+    state::{ExecutionState, Stack, STACK_SIZE},
+    StatusCode,
+};
 use ethnum::U256;
 
 #[inline]
@@ -50,3 +55,111 @@ pub(crate) fn calldatasize(state: &mut ExecutionState) {
         .into();
     state.stack.push(res);
 }
+
+#[inline]
+fn read_u16(code: &[u8], pos: usize) -> usize {
+    u16::from_be_bytes([code[pos], code[pos + 1]]).into()
+}
+
+#[inline]
+fn relative_destination(code: &[u8], immediate: usize, next_pc: usize) -> usize {
+    let offset = i16::from_be_bytes([code[immediate], code[immediate + 1]]);
+    (next_pc as isize + offset as isize) as usize
+}
+
+/// Returns the position of the next instruction after RJUMP at `pc`.
+#[inline]
+pub(crate) fn rjump(code: &[u8], pc: usize) -> usize {
+    relative_destination(code, pc + 1, pc + 3)
+}
+
+#[inline]
+pub(crate) fn rjumpi(stack: &mut Stack, code: &[u8], pc: usize) -> usize {
+    if stack.pop() != 0 {
+        rjump(code, pc)
+    } else {
+        pc + 3
+    }
+}
+
+#[inline]
+pub(crate) fn rjumpv(stack: &mut Stack, code: &[u8], pc: usize) -> usize {
+    let case = stack.pop();
+    let max_index = code[pc + 1] as usize;
+    let next_pc = pc + 2 + (max_index + 1) * 2;
+
+    match case.try_into() {
+        Ok(case) if case <= max_index => relative_destination(code, pc + 2 + case * 2, next_pc),
+        _ => next_pc,
+    }
+}
+
+#[inline]
+fn check_function_stack(stack: &Stack, header: &EofHeader, index: usize) -> Result<(), StatusCode> {
+    let ty = &header.types[index];
+    if stack.len() + ty.max_stack_height as usize - ty.inputs as usize > STACK_SIZE {
+        return Err(StatusCode::StackOverflow);
+    }
+    Ok(())
+}
+
+#[inline]
+pub(crate) fn callf(
+    stack: &Stack,
+    return_stack: &mut Vec<usize>,
+    header: &EofHeader,
+    code: &[u8],
+    pc: usize,
+) -> Result<usize, StatusCode> {
+    let index = read_u16(code, pc + 1);
+    check_function_stack(stack, header, index)?;
+
+    if return_stack.len() == RETURN_STACK_LIMIT {
+        return Err(StatusCode::StackOverflow);
+    }
+    return_stack.push(pc + 3);
+
+    Ok(header.code_offsets[index])
+}
+
+#[inline]
+pub(crate) fn jumpf(
+    stack: &Stack,
+    header: &EofHeader,
+    code: &[u8],
+    pc: usize,
+) -> Result<usize, StatusCode> {
+    let index = read_u16(code, pc + 1);
+    check_function_stack(stack, header, index)?;
+
+    Ok(header.code_offsets[index])
+}
+
+#[inline]
+pub(crate) fn dataload(stack: &mut Stack, data: &[u8]) {
+    let index = stack.pop();
+
+    let res = match usize::try_from(index) {
+        Ok(index) if index < data.len() => {
+            let end = core::cmp::min(index + 32, data.len());
+
+            let mut word = [0; 32];
+            word[..end - index].copy_from_slice(&data[index..end]);
+
+            U256::from_be_bytes(word)
+        }
+        _ => U256::ZERO,
+    };
+    stack.push(res);
+}
+
+#[inline]
+pub(crate) fn dataloadn(stack: &mut Stack, code: &[u8], pc: usize, data: &[u8]) {
+    let index = read_u16(code, pc + 1);
+    stack.push(U256::from_be_bytes(data[index..][..32].try_into().unwrap()));
+}
+
+#[inline]
+pub(crate) fn datasize(stack: &mut Stack, data: &[u8]) {
+    stack.push(u128::try_from(data.len()).unwrap().into());
+}
@@ -44,6 +44,15 @@ pub(crate) fn mstore(state: &mut ExecutionState) -> Result<(), StatusCode> {
     let index = state.stack.pop();
     let value = state.stack.pop();
 
+    mstore_at(state, index, value)
+}
+
+#[inline]
+pub(crate) fn mstore_at(
+    state: &mut ExecutionState,
+    index: U256,
+    value: U256,
+) -> Result<(), StatusCode> {
     let region = get_memory_region_u64(state, index, NonZeroUsize::new(32).unwrap())?;
 
     state.memory[region.offset..][..32].copy_from_slice(&value.to_be_bytes());
@@ -183,6 +192,10 @@ pub(crate) fn codecopy(state: &mut ExecutionState, code: &[u8]) -> Result<(), St
     copy(state, code)
 }
 
+pub(crate) fn datacopy(state: &mut ExecutionState, data: &[u8]) -> Result<(), StatusCode> {
+    copy(state, data)
+}
+
 pub(crate) fn keccak256(state: &mut ExecutionState) -> Result<(), StatusCode> {
     let index = state.stack.pop();
     let size = state.stack.pop();
@@ -174,6 +174,18 @@ pub const fn properties<const OPCODE: OpCode>() -> Properties {
         OpCode::LOG3 => Properties::new(5, -5),
         OpCode::LOG4 => Properties::new(6, -6),
 
+        OpCode::DATALOAD => Properties::new(1, 0),
+        OpCode::DATALOADN => Properties::new(0, 1),
+        OpCode::DATASIZE => Properties::new(0, 1),
+        OpCode::DATACOPY => Properties::new(3, -3),
+
+        OpCode::RJUMP => Properties::new(0, 0),
+        OpCode::RJUMPI => Properties::new(1, -1),
+        OpCode::RJUMPV => Properties::new(1, -1),
+        OpCode::CALLF => Properties::new(0, 0),
+        OpCode::RETF => Properties::new(0, 0),
+        OpCode::JUMPF => Properties::new(0, 0),
+
         OpCode::CREATE => Properties::new(3, -2),
         OpCode::CALL => Properties::new(7, -6),
         OpCode::CALLCODE => Properties::new(7, -6),
@@ -386,29 +398,57 @@ const fn gas_costs() -> GasCostTable {
 
     table[Revision::Paris as usize] = table[Revision::London as usize];
 
+    table[Revision::Eof as usize] = table[Revision::Paris as usize];
+
     table
 }
 
 pub const GAS_COSTS: GasCostTable = gas_costs();
 
-pub const fn has_const_gas_cost<const OPCODE: OpCode>() -> bool {
-    const LATEST: Revision = Revision::latest();
+/// Gas costs for code in EOF containers.
+///
+/// Instructions introduced with EOF are only defined inside of containers, while instructions
+/// that inspect or depend on the code layout are not allowed there.
+const fn eof_gas_costs() -> [i16; 256] {
+    let mut table = GAS_COSTS[Revision::Eof as usize];
+
+    table[OpCode::DATALOAD.to_usize()] = 4;
+    table[OpCode::DATALOADN.to_usize()] = 3;
+    table[OpCode::DATASIZE.to_usize()] = 2;
+    table[OpCode::DATACOPY.to_usize()] = 3;
+
+    table[OpCode::RJUMP.to_usize()] = 2;
+    table[OpCode::RJUMPI.to_usize()] = 4;
+    table[OpCode::RJUMPV.to_usize()] = 4;
+    table[OpCode::CALLF.to_usize()] = 5;
+    table[OpCode::RETF.to_usize()] = 3;
+    table[OpCode::JUMPF.to_usize()] = 5;
+
+    table[OpCode::JUMP.to_usize()] = -1;
+    table[OpCode::JUMPI.to_usize()] = -1;
+    table[OpCode::PC.to_usize()] = -1;
+    table[OpCode::CODESIZE.to_usize()] = -1;
+    table[OpCode::CODECOPY.to_usize()] = -1;
+    table[OpCode::CALLCODE.to_usize()] = -1;
+    table[OpCode::SELFDESTRUCT.to_usize()] = -1;
 
+    table
+}
+
+pub const EOF_GAS_COSTS: [i16; 256] = eof_gas_costs();
+
+pub const fn has_const_gas_cost<const OPCODE: OpCode>() -> bool {
     let g = GAS_COSTS[Revision::Frontier as usize][OPCODE.to_usize()];
     let revtable = Revision::iter();
     let mut iter = 0;
-    loop {
+    while iter < revtable.len() {
         let rev = revtable[iter];
 
         if GAS_COSTS[rev as usize][OPCODE.to_usize()] != g {
             return false;
         }
 
-        if matches!(rev, LATEST) {
-            break;
-        } else {
-            iter += 1;
-        }
+        iter += 1;
     }
     true
 }
@@ -563,6 +603,18 @@ const fn property_table() -> [Option<Properties>; 256] {
     table[OpCode::LOG3.to_usize()] = Some(Properties::new(5, -5));
     table[OpCode::LOG4.to_usize()] = Some(Properties::new(6, -6));
 
+    table[OpCode::DATALOAD.to_usize()] = Some(Properties::new(1, 0));
+    table[OpCode::DATALOADN.to_usize()] = Some(Properties::new(0, 1));
+    table[OpCode::DATASIZE.to_usize()] = Some(Properties::new(0, 1));
+    table[OpCode::DATACOPY.to_usize()] = Some(Properties::new(3, -3));
+
+    table[OpCode::RJUMP.to_usize()] = Some(Properties::new(0, 0));
+    table[OpCode::RJUMPI.to_usize()] = Some(Properties::new(1, -1));
+    table[OpCode::RJUMPV.to_usize()] = Some(Properties::new(1, -1));
+    table[OpCode::CALLF.to_usize()] = Some(Properties::new(0, 0));
+    table[OpCode::RETF.to_usize()] = Some(Properties::new(0, 0));
+    table[OpCode::JUMPF.to_usize()] = Some(Properties::new(0, 0));
+
     table[OpCode::CREATE.to_usize()] = Some(Properties::new(3, -2));
     table[OpCode::CALL.to_usize()] = Some(Properties::new(7, -6));
     table[OpCode::CALLCODE.to_usize()] = Some(Properties::new(7, -6));
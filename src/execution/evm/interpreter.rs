@@ -1,7 +1,9 @@
 use self::instruction_table::*;
 use super::{
     common::{InterpreterMessage, *},
+    eof::{self, EofHeader},
     instructions::{control::*, stack_manip::*, *},
+    optimization::{read_immediate_usize, BlockCursor, OptimizedCode, Superinstruction},
     state::*,
     *,
 };
@@ -55,11 +57,28 @@ const PADDING: usize = 32;
 pub struct AnalyzedCode {
     jumpdest_map: JumpdestMap,
     code: Vec<u8>,
+    eof: Option<Arc<EofHeader>>,
+    optimized: Option<Arc<OptimizedCode>>,
 }
 
 impl AnalyzedCode {
     /// Analyze code and prepare it for execution.
+    ///
+    /// Valid EOF containers are additionally recognized as such, but are only executed as EOF
+    /// with the experimental [`Revision::Eof`].
     pub fn analyze(code: &[u8]) -> Self {
+        Self::analyze_inner(code, true)
+    }
+
+    /// Analyze code without splitting it into basic blocks and fusing instructions.
+    ///
+    /// Executes every instruction with its own gas and stack checks, which is mostly useful for
+    /// comparison.
+    pub fn analyze_unoptimized(code: &[u8]) -> Self {
+        Self::analyze_inner(code, false)
+    }
+
+    fn analyze_inner(code: &[u8], optimize: bool) -> Self {
         const JUMPDEST: u8 = OpCode::JUMPDEST.0;
         const PUSH1: u8 = OpCode::PUSH1.0;
         const PUSH32: u8 = OpCode::PUSH32.0;
@@ -89,9 +108,20 @@ impl AnalyzedCode {
             t
         };
 
+        let eof = if eof::is_eof(&code) {
+            eof::validate(&code[..code_len]).ok().map(Arc::new)
+        } else {
+            None
+        };
+
+        let optimized = (optimize && eof.is_none())
+            .then(|| Arc::new(OptimizedCode::analyze(&code[..code_len], &jumpdest_map)));
+
         Self {
             jumpdest_map: JumpdestMap(Arc::new(jumpdest_map)),
             code,
+            eof,
+            optimized,
         }
     }
 
@@ -132,7 +162,7 @@ impl AnalyzedCode {
             &mut state,
             host,
             Frontier Homestead Tangerine Spurious Byzantium Constantinople
-            Petersburg Istanbul Berlin London Paris
+            Petersburg Istanbul Berlin London Paris Eof
         );
 
         match res {
@@ -155,6 +185,29 @@ impl AnalyzedCode {
         // length of `code` is bigger or equal to `PADDING`
         &self.code[..self.code.len() - PADDING]
     }
+
+    /// Header of the EOF container, if the code is a valid one.
+    pub fn eof_header(&self) -> Option<&EofHeader> {
+        self.eof.as_deref()
+    }
+
+    /// Approximate memory held by the analyzed code, in bytes: padded code, jump destinations and
+    /// the results of analysis.
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.code.capacity()
+            + self.jumpdest_map.0.capacity() / 8
+            + self.eof.as_ref().map_or(0, |eof| {
+                std::mem::size_of::<EofHeader>()
+                    + eof.types.capacity() * std::mem::size_of::<eof::FunctionType>()
+                    + (eof.code_offsets.capacity() + eof.code_sizes.capacity())
+                        * std::mem::size_of::<usize>()
+            })
+            + self
+                .optimized
+                .as_ref()
+                .map_or(0, |optimized| optimized.size())
+    }
 }
 
 #[allow(clippy::needless_borrow)]
@@ -177,10 +230,35 @@ where
         }};
     }
 
-    let instruction_table = get_instruction_table(REVISION);
+    let eof = if REVISION >= Revision::Eof {
+        s.eof_header()
+    } else {
+        None
+    };
+
+    let instruction_table = if eof.is_some() {
+        &EOF_INSTRUCTION_TABLE
+    } else {
+        get_instruction_table(REVISION)
+    };
 
-    let mut pc = 0;
     let code = s.orig_code();
+    let data = eof.map(|header| header.data(code)).unwrap_or_default();
+    let mut return_stack = Vec::new();
+
+    // Block-wise checks are not used when tracing, since tracers observe gas of each instruction.
+    let optimized = if TRACE || eof.is_some() {
+        None
+    } else {
+        s.optimized.as_deref()
+    };
+    let mut blocks = BlockCursor::new(optimized.map(|o| &o.blocks[..]).unwrap_or_default());
+    let superinstructions = optimized
+        .map(|o| &o.superinstructions[..])
+        .unwrap_or_default();
+    let mut block_charged = false;
+
+    let mut pc = eof.map(|header| header.code_offsets[0]).unwrap_or(0);
     let reverted = loop {
         let op = match code.get(pc) {
             Some(&op) => OpCode(op),
@@ -189,6 +267,10 @@ where
 
         let metrics = &instruction_table[op.to_usize()];
 
+        if blocks.is_block_start(pc) {
+            block_charged = blocks.enter::<REVISION>(state);
+        }
+
         if metrics.gas_cost < 0 {
             return Err(StatusCode::UndefinedInstruction);
         }
@@ -205,7 +287,46 @@ where
             });
         }
 
-        check_requirements(metrics, state)?;
+        if block_charged {
+            let stack = &mut state.stack;
+            match superinstructions.get(pc) {
+                Some(Superinstruction::PushJump) => {
+                    let len = (op.0 - OpCode::PUSH1.0 + 1) as usize;
+                    pc = read_immediate_usize(code, pc, len).unwrap();
+                    blocks.jump(pc);
+                    continue;
+                }
+                Some(Superinstruction::PushJumpi) => {
+                    let len = (op.0 - OpCode::PUSH1.0 + 1) as usize;
+                    if stack.pop() != 0 {
+                        pc = read_immediate_usize(code, pc, len).unwrap();
+                        blocks.jump(pc);
+                    } else {
+                        pc += len + 2;
+                    }
+                    continue;
+                }
+                Some(Superinstruction::PushMstore) => {
+                    let len = (op.0 - OpCode::PUSH1.0 + 1) as usize;
+                    let index = u256_from_slice(&code[pc + 1..][..len]);
+                    let value = stack.pop();
+                    memory::mstore_at(state, index, value)?;
+                    pc += len + 2;
+                    continue;
+                }
+                Some(Superinstruction::DupSwap) => {
+                    let dup_height = (op.0 - OpCode::DUP1.0 + 1) as usize;
+                    let swap_height = (code[pc + 1] - OpCode::SWAP1.0 + 1) as usize;
+                    stack.push(*stack.get(dup_height - 1));
+                    stack.swap_top(swap_height);
+                    pc += 2;
+                    continue;
+                }
+                _ => {}
+            }
+        } else {
+            check_requirements(metrics, state)?;
+        }
 
         let stack = &mut state.stack;
         match op {
@@ -268,6 +389,7 @@ where
             OpCode::JUMP => {
                 let dst = stack.pop();
                 pc = op_jump(dst, &s.jumpdest_map)?;
+                blocks.jump(pc);
                 continue;
             }
             OpCode::JUMPI => {
@@ -275,6 +397,7 @@ where
                 let b = stack.pop();
                 if b != 0 {
                     pc = op_jump(dst, &s.jumpdest_map)?;
+                    blocks.jump(pc);
                     continue;
                 }
             }
@@ -359,6 +482,39 @@ where
             OpCode::LOG2 => external::do_log::<_, 2>(state, host)?,
             OpCode::LOG3 => external::do_log::<_, 3>(state, host)?,
             OpCode::LOG4 => external::do_log::<_, 4>(state, host)?,
+            OpCode::DATALOAD => dataload(stack, data),
+            OpCode::DATALOADN => {
+                dataloadn(stack, code, pc, data);
+                pc += 2;
+            }
+            OpCode::DATASIZE => datasize(stack, data),
+            OpCode::DATACOPY => memory::datacopy(state, data)?,
+            OpCode::RJUMP => {
+                pc = rjump(code, pc);
+                continue;
+            }
+            OpCode::RJUMPI => {
+                pc = rjumpi(stack, code, pc);
+                continue;
+            }
+            OpCode::RJUMPV => {
+                pc = rjumpv(stack, code, pc);
+                continue;
+            }
+            // EOF instructions are undefined in legacy code, so the header is always present here.
+            OpCode::CALLF => {
+                pc = callf(stack, &mut return_stack, eof.unwrap(), code, pc)?;
+                continue;
+            }
+            OpCode::RETF => {
+                // Validation guarantees that RETF is only reachable through CALLF.
+                pc = return_stack.pop().unwrap();
+                continue;
+            }
+            OpCode::JUMPF => {
+                pc = jumpf(stack, eof.unwrap(), code, pc)?;
+                continue;
+            }
             OpCode::CREATE => call::do_create::<_, REVISION, false>(state, host)?,
             OpCode::CREATE2 => call::do_create::<_, REVISION, true>(state, host)?,
             OpCode::CALL => call::do_call::<_, REVISION, { CallKind::Call }, false>(state, host)?,
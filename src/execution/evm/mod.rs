@@ -11,11 +11,13 @@ pub use state::{ExecutionState, Stack};
 pub const MAX_CODE_SIZE: usize = 0x6000;
 
 mod common;
+pub mod eof;
 pub mod host;
 #[macro_use]
 pub mod instructions;
 mod interpreter;
 pub mod opcode;
+mod optimization;
 mod state;
 pub mod util;
 
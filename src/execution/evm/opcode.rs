@@ -157,6 +157,18 @@ impl OpCode {
     pub const LOG3: OpCode = OpCode(0xa3);
     pub const LOG4: OpCode = OpCode(0xa4);
 
+    pub const DATALOAD: OpCode = OpCode(0xd0);
+    pub const DATALOADN: OpCode = OpCode(0xd1);
+    pub const DATASIZE: OpCode = OpCode(0xd2);
+    pub const DATACOPY: OpCode = OpCode(0xd3);
+
+    pub const RJUMP: OpCode = OpCode(0xe0);
+    pub const RJUMPI: OpCode = OpCode(0xe1);
+    pub const RJUMPV: OpCode = OpCode(0xe2);
+    pub const CALLF: OpCode = OpCode(0xe3);
+    pub const RETF: OpCode = OpCode(0xe4);
+    pub const JUMPF: OpCode = OpCode(0xe5);
+
     pub const CREATE: OpCode = OpCode(0xf0);
     pub const CALL: OpCode = OpCode(0xf1);
     pub const CALLCODE: OpCode = OpCode(0xf2);
@@ -307,6 +319,16 @@ impl OpCode {
             OpCode::LOG2 => "LOG2",
             OpCode::LOG3 => "LOG3",
             OpCode::LOG4 => "LOG4",
+            OpCode::DATALOAD => "DATALOAD",
+            OpCode::DATALOADN => "DATALOADN",
+            OpCode::DATASIZE => "DATASIZE",
+            OpCode::DATACOPY => "DATACOPY",
+            OpCode::RJUMP => "RJUMP",
+            OpCode::RJUMPI => "RJUMPI",
+            OpCode::RJUMPV => "RJUMPV",
+            OpCode::CALLF => "CALLF",
+            OpCode::RETF => "RETF",
+            OpCode::JUMPF => "JUMPF",
             OpCode::CREATE => "CREATE",
             OpCode::CALL => "CALL",
             OpCode::CALLCODE => "CALLCODE",
@@ -0,0 +1,279 @@
+//! Analysis-time optimizations of legacy code.
+//!
+//! Code is split into basic blocks, so that static gas is charged and stack requirements are
+//! checked once per block rather than once per instruction. A block ends before every JUMPDEST
+//! and after every instruction that transfers control or observes the amount of gas left, which
+//! keeps precharging unobservable. If a block's requirements do not hold when it is entered, the
+//! block is executed with per-instruction checks, so that errors are reported exactly where they
+//! happen.
+//!
+//! Additionally, common pairs of instructions inside of a block are marked as
+//! [superinstructions](Superinstruction) and are dispatched at once.
+
+use super::{
+    instructions::{properties::GAS_COSTS, PROPERTIES},
+    state::{ExecutionState, STACK_SIZE},
+    OpCode,
+};
+use crate::models::Revision;
+use bitvec::vec::BitVec;
+
+/// Pair of instructions executed as a single one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub(crate) enum Superinstruction {
+    None,
+    /// PUSH of a valid jump destination followed by JUMP.
+    PushJump,
+    /// PUSH of a valid jump destination followed by JUMPI.
+    PushJumpi,
+    /// PUSH of memory offset followed by MSTORE.
+    PushMstore,
+    /// DUPn followed by SWAPm.
+    DupSwap,
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct BasicBlock {
+    pub start: usize,
+    /// Total static gas cost of block instructions, per revision.
+    pub gas_cost: [u32; Revision::len()],
+    /// Minimum stack height required to execute the whole block.
+    pub stack_required: u16,
+    /// Maximum stack height growth relative to the height at block start.
+    pub stack_max_growth: u16,
+}
+
+#[derive(Clone, Debug, Default)]
+pub(crate) struct OptimizedCode {
+    /// Blocks sorted by their start position.
+    pub blocks: Vec<BasicBlock>,
+    /// Superinstruction starting at each position of the code, empty if there are none.
+    pub superinstructions: Vec<Superinstruction>,
+}
+
+impl OptimizedCode {
+    /// Memory held by the analysis, in bytes.
+    pub fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.blocks.capacity() * std::mem::size_of::<BasicBlock>()
+            + self.superinstructions.capacity() * std::mem::size_of::<Superinstruction>()
+    }
+}
+
+#[inline]
+fn push_size(op: OpCode) -> usize {
+    if op.to_u8() >= OpCode::PUSH1.to_u8() && op.to_u8() <= OpCode::PUSH32.to_u8() {
+        (op.to_u8() - OpCode::PUSH1.to_u8() + 1) as usize
+    } else {
+        0
+    }
+}
+
+/// Whether the instruction is the last one in its basic block.
+#[inline]
+fn ends_block(op: OpCode) -> bool {
+    matches!(
+        op,
+        OpCode::STOP
+            | OpCode::JUMP
+            | OpCode::JUMPI
+            | OpCode::RETURN
+            | OpCode::REVERT
+            | OpCode::INVALID
+            | OpCode::SELFDESTRUCT
+            | OpCode::GAS
+            | OpCode::SSTORE
+            | OpCode::CALL
+            | OpCode::CALLCODE
+            | OpCode::DELEGATECALL
+            | OpCode::STATICCALL
+            | OpCode::CREATE
+            | OpCode::CREATE2
+    )
+}
+
+/// Reads immediate value of a PUSH instruction, provided that it fits `usize`.
+#[inline]
+pub(crate) fn read_immediate_usize(code: &[u8], pc: usize, len: usize) -> Option<usize> {
+    code.get(pc + 1..pc + 1 + len)?
+        .iter()
+        .try_fold(0_usize, |acc, &b| {
+            acc.checked_mul(256).map(|acc| acc + b as usize)
+        })
+}
+
+struct BlockBuilder {
+    block: BasicBlock,
+    height: i32,
+    required: i32,
+    max_growth: i32,
+}
+
+impl BlockBuilder {
+    fn new(start: usize) -> Self {
+        Self {
+            block: BasicBlock {
+                start,
+                gas_cost: [0; Revision::len()],
+                stack_required: 0,
+                stack_max_growth: 0,
+            },
+            height: 0,
+            required: 0,
+            max_growth: 0,
+        }
+    }
+
+    fn add(&mut self, op: OpCode) {
+        for revision in Revision::iter() {
+            let cost = GAS_COSTS[revision as usize][op.to_usize()].max(0) as u32;
+            let total = &mut self.block.gas_cost[revision as usize];
+            *total = total.saturating_add(cost);
+        }
+
+        if let Some(properties) = &PROPERTIES[op.to_usize()] {
+            self.required = self
+                .required
+                .max(i32::from(properties.stack_height_required) - self.height);
+            self.height += i32::from(properties.stack_height_change);
+            self.max_growth = self.max_growth.max(self.height);
+        }
+    }
+
+    fn build(self) -> BasicBlock {
+        BasicBlock {
+            stack_required: self.required.clamp(0, u16::MAX.into()) as u16,
+            stack_max_growth: self.max_growth.clamp(0, u16::MAX.into()) as u16,
+            ..self.block
+        }
+    }
+}
+
+impl OptimizedCode {
+    pub fn analyze(code: &[u8], jumpdest_map: &BitVec) -> Self {
+        let mut blocks = Vec::new();
+        let mut superinstructions = Vec::new();
+
+        let fuse = |superinstructions: &mut Vec<Superinstruction>, pc: usize, s| {
+            if superinstructions.is_empty() {
+                superinstructions.resize(code.len(), Superinstruction::None);
+            }
+            superinstructions[pc] = s;
+        };
+
+        let mut current: Option<BlockBuilder> = None;
+        let mut pc = 0;
+        while pc < code.len() {
+            let op = OpCode(code[pc]);
+
+            if op == OpCode::JUMPDEST {
+                blocks.extend(current.take().map(BlockBuilder::build));
+            }
+            current.get_or_insert_with(|| BlockBuilder::new(pc)).add(op);
+
+            let push_len = push_size(op);
+            let next = pc + 1 + push_len;
+
+            if push_len > 0 {
+                match code.get(next).copied().map(OpCode) {
+                    Some(next_op @ (OpCode::JUMP | OpCode::JUMPI)) => {
+                        let is_valid_destination = read_immediate_usize(code, pc, push_len)
+                            .and_then(|dst| jumpdest_map.get(dst).map(|b| *b))
+                            .unwrap_or(false);
+                        if is_valid_destination {
+                            fuse(
+                                &mut superinstructions,
+                                pc,
+                                if next_op == OpCode::JUMP {
+                                    Superinstruction::PushJump
+                                } else {
+                                    Superinstruction::PushJumpi
+                                },
+                            );
+                        }
+                    }
+                    Some(OpCode::MSTORE) => {
+                        fuse(&mut superinstructions, pc, Superinstruction::PushMstore)
+                    }
+                    _ => {}
+                }
+            } else if (OpCode::DUP1.to_u8()..=OpCode::DUP16.to_u8()).contains(&op.to_u8())
+                && code
+                    .get(next)
+                    .map(|b| (OpCode::SWAP1.to_u8()..=OpCode::SWAP16.to_u8()).contains(b))
+                    .unwrap_or(false)
+            {
+                fuse(&mut superinstructions, pc, Superinstruction::DupSwap);
+            }
+
+            if ends_block(op) {
+                blocks.extend(current.take().map(BlockBuilder::build));
+            }
+
+            pc = next;
+        }
+        blocks.extend(current.take().map(BlockBuilder::build));
+
+        Self {
+            blocks,
+            superinstructions,
+        }
+    }
+}
+
+/// Tracks which basic block is entered next during execution.
+pub(crate) struct BlockCursor<'a> {
+    blocks: &'a [BasicBlock],
+    next: usize,
+    next_start: usize,
+}
+
+impl<'a> BlockCursor<'a> {
+    pub fn new(blocks: &'a [BasicBlock]) -> Self {
+        Self {
+            blocks,
+            next: 0,
+            next_start: blocks.first().map(|b| b.start).unwrap_or(usize::MAX),
+        }
+    }
+
+    #[inline(always)]
+    pub fn is_block_start(&self, pc: usize) -> bool {
+        pc == self.next_start
+    }
+
+    /// Enters the next block, charging its static gas if it can be executed without
+    /// per-instruction checks. Returns whether it has been charged.
+    #[inline]
+    pub fn enter<const REVISION: Revision>(&mut self, state: &mut ExecutionState) -> bool {
+        let block = &self.blocks[self.next];
+        self.next += 1;
+        self.next_start = self
+            .blocks
+            .get(self.next)
+            .map(|b| b.start)
+            .unwrap_or(usize::MAX);
+
+        let gas_cost = block.gas_cost[REVISION as usize] as i64;
+        let stack_height = state.stack.len();
+        if state.gas_left >= gas_cost
+            && stack_height >= block.stack_required as usize
+            && stack_height + block.stack_max_growth as usize <= STACK_SIZE
+        {
+            state.gas_left -= gas_cost;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Makes the block starting at jump destination `dst` the next one.
+    #[inline]
+    pub fn jump(&mut self, dst: usize) {
+        if let Ok(next) = self.blocks.binary_search_by_key(&dst, |b| b.start) {
+            self.next = next;
+            self.next_start = dst;
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use crate::{
+    execution::evm::{eof::*, opcode::*, util::*, *},
+    models::*,
+};
+use hex_literal::hex;
+
+/// Builds a container out of `(inputs, outputs, max_stack_height, code)` sections.
+fn container(sections: &[(u8, u8, u16, Vec<u8>)], data: &[u8]) -> Vec<u8> {
+    let mut out = MAGIC.to_vec();
+    out.push(VERSION);
+    out.push(0x01);
+    out.extend_from_slice(&(sections.len() as u16 * 4).to_be_bytes());
+    out.push(0x02);
+    out.extend_from_slice(&(sections.len() as u16).to_be_bytes());
+    for (_, _, _, code) in sections {
+        out.extend_from_slice(&(code.len() as u16).to_be_bytes());
+    }
+    out.push(0xff);
+    out.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    out.push(0x00);
+    for &(inputs, outputs, max_stack_height, _) in sections {
+        out.push(inputs);
+        out.push(outputs);
+        out.extend_from_slice(&max_stack_height.to_be_bytes());
+    }
+    for (_, _, _, code) in sections {
+        out.extend_from_slice(code);
+    }
+    out.extend_from_slice(data);
+    out
+}
+
+fn single_section(max_stack_height: u16, code: impl Into<Vec<u8>>) -> Vec<u8> {
+    container(
+        &[(0, NON_RETURNING_FUNCTION, max_stack_height, code.into())],
+        &[],
+    )
+}
+
+#[test]
+fn minimal_container() {
+    let code = single_section(0, hex!("00"));
+    assert_eq!(code, hex!("ef0001 010004 0200010001 ff0000 00 00800000 00"));
+
+    let header = validate(&code).unwrap();
+    assert_eq!(header.code_offsets, vec![19]);
+    assert_eq!(header.code_sizes, vec![1]);
+    assert_eq!(header.data_size, 0);
+}
+
+#[test]
+fn invalid_headers() {
+    for (code, err) in [
+        (hex!("ef").to_vec(), EofValidationError::InvalidPrefix),
+        (hex!("ef0002").to_vec(), EofValidationError::UnknownVersion),
+        (
+            hex!("ef0001").to_vec(),
+            EofValidationError::MissingTypeHeader,
+        ),
+        (
+            hex!("ef0001 0100").to_vec(),
+            EofValidationError::IncompleteSectionSize,
+        ),
+        (
+            hex!("ef0001 010004 03").to_vec(),
+            EofValidationError::MissingCodeHeader,
+        ),
+        (
+            hex!("ef0001 010004 020000").to_vec(),
+            EofValidationError::ZeroSectionSize,
+        ),
+        (
+            hex!("ef0001 010008 0200010001").to_vec(),
+            EofValidationError::InvalidTypeSectionSize,
+        ),
+        (
+            hex!("ef0001 010004 0200010001 040000").to_vec(),
+            EofValidationError::MissingDataHeader,
+        ),
+        (
+            hex!("ef0001 010004 0200010001 ff0000 ff").to_vec(),
+            EofValidationError::HeaderTerminatorMissing,
+        ),
+        (
+            hex!("ef0001 010004 0200010001 ff0001 00 00800000 00").to_vec(),
+            EofValidationError::InvalidSectionBodiesSize,
+        ),
+        (
+            hex!("ef0001 010004 0200010001 ff0000 00 01800001 00").to_vec(),
+            EofValidationError::InvalidFirstSectionType,
+        ),
+        (
+            hex!("ef0001 010004 0200010001 ff0000 00 00800400 00").to_vec(),
+            EofValidationError::MaxStackHeightAboveLimit,
+        ),
+    ] {
+        assert_eq!(validate(&code), Err(err), "{}", hex::encode(&code));
+    }
+}
+
+#[test]
+fn invalid_code() {
+    for (code, err) in [
+        (
+            single_section(1, hex!("600056")),
+            EofValidationError::UndefinedInstruction,
+        ),
+        (
+            single_section(0, hex!("6100")),
+            EofValidationError::TruncatedInstruction,
+        ),
+        (
+            single_section(0, hex!("e2")),
+            EofValidationError::TruncatedInstruction,
+        ),
+        (
+            single_section(0, hex!("e0fffe")),
+            EofValidationError::InvalidRjumpDestination,
+        ),
+        (
+            single_section(0, hex!("e00001")),
+            EofValidationError::InvalidRjumpDestination,
+        ),
+        (
+            single_section(0, hex!("e3000100")),
+            EofValidationError::InvalidCodeSectionIndex,
+        ),
+        (
+            single_section(0, hex!("e4")),
+            EofValidationError::RetfInNonReturningFunction,
+        ),
+        (
+            single_section(1, hex!("d1000000")),
+            EofValidationError::InvalidDataloadnIndex,
+        ),
+        (
+            single_section(1, hex!("600050")),
+            EofValidationError::NoTerminatingInstruction,
+        ),
+        (
+            single_section(0, hex!("0100")),
+            EofValidationError::StackUnderflow,
+        ),
+        (
+            single_section(0, hex!("0000")),
+            EofValidationError::UnreachableInstructions,
+        ),
+        (
+            single_section(1, hex!("00")),
+            EofValidationError::InvalidMaxStackHeight,
+        ),
+        (
+            // Loop which grows the stack on every iteration.
+            single_section(1, hex!("6000e0fffb")),
+            EofValidationError::StackHeightMismatch,
+        ),
+        (
+            container(
+                &[
+                    (0, NON_RETURNING_FUNCTION, 0, hex!("e3000100").to_vec()),
+                    (0, NON_RETURNING_FUNCTION, 0, hex!("00").to_vec()),
+                ],
+                &[],
+            ),
+            EofValidationError::CallfToNonReturningFunction,
+        ),
+        (
+            container(
+                &[
+                    (0, NON_RETURNING_FUNCTION, 1, hex!("e3000100").to_vec()),
+                    (0, 1, 0, hex!("e4").to_vec()),
+                ],
+                &[],
+            ),
+            EofValidationError::StackHeightMismatch,
+        ),
+    ] {
+        assert_eq!(validate(&code), Err(err), "{}", hex::encode(&code));
+    }
+}
+
+#[test]
+fn eof_is_legacy_without_eof_revision() {
+    let code = single_section(0, hex!("00"));
+
+    EvmTester::new()
+        .code(code.clone())
+        .revision(Revision::Paris)
+        .status(StatusCode::UndefinedInstruction)
+        .check();
+
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .gas_used(0)
+        .check();
+}
+
+#[test]
+fn legacy_code_cannot_use_eof_instructions() {
+    EvmTester::new()
+        .code(hex!("e00000 00"))
+        .revision(Revision::Eof)
+        .status(StatusCode::UndefinedInstruction)
+        .check();
+}
+
+#[test]
+fn rjumpi() {
+    // push(1) rjumpi(+5) revert(0, 0) return(0, 0)
+    let code = single_section(2, hex!("6001 e10005 60006000fd 60006000f3"));
+    assert!(validate(&code).is_ok());
+
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .gas_used(13)
+        .check();
+}
+
+#[test]
+fn rjumpv() {
+    for (case, status) in [
+        (0_u8, StatusCode::Revert),
+        (1, StatusCode::Success),
+        (2, StatusCode::Revert),
+        (u8::MAX, StatusCode::Revert),
+    ] {
+        // push(case) rjumpv[0, +5] revert(0, 0) return(0, 0)
+        let mut code = vec![OpCode::PUSH1.to_u8(), case];
+        code.extend_from_slice(&hex!("e2 01 0000 0005 60006000fd 60006000f3"));
+        let code = single_section(2, code);
+
+        EvmTester::new()
+            .code(code)
+            .revision(Revision::Eof)
+            .status(status)
+            .check();
+    }
+}
+
+#[test]
+fn callf_retf() {
+    // Section 0: mstore(0, callf_1(2)) return(0, 32)
+    // Section 1: mul(3, arg) retf
+    let code = container(
+        &[
+            (
+                0,
+                NON_RETURNING_FUNCTION,
+                2,
+                hex!("6002 e30001 600052 60206000f3").to_vec(),
+            ),
+            (1, 1, 2, hex!("6003 02 e4").to_vec()),
+        ],
+        &[],
+    );
+    assert!(validate(&code).is_ok());
+
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .output_value(6)
+        .check();
+}
+
+#[test]
+fn jumpf() {
+    // Section 0: jumpf_1
+    // Section 1: return(0, 0)
+    let code = container(
+        &[
+            (0, NON_RETURNING_FUNCTION, 0, hex!("e50001").to_vec()),
+            (0, NON_RETURNING_FUNCTION, 2, hex!("60006000f3").to_vec()),
+        ],
+        &[],
+    );
+
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .gas_used(11)
+        .check();
+}
+
+#[test]
+fn data_instructions() {
+    let data = (1..=40).collect::<Vec<u8>>();
+
+    // mstore(0, dataloadn(8)) return(0, 32)
+    let code = container(
+        &[(
+            0,
+            NON_RETURNING_FUNCTION,
+            2,
+            hex!("d10008 600052 60206000f3").to_vec(),
+        )],
+        &data,
+    );
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .output_data(data[8..40].to_vec())
+        .check();
+
+    // mstore(0, dataload(16)) return(0, 32)
+    let code = container(
+        &[(
+            0,
+            NON_RETURNING_FUNCTION,
+            2,
+            hex!("6010 d0 600052 60206000f3").to_vec(),
+        )],
+        &data,
+    );
+    let mut expected = data[16..].to_vec();
+    expected.resize(32, 0);
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .output_data(expected)
+        .check();
+
+    // datacopy(0, 30, datasize()) return(0, 10)
+    let code = container(
+        &[(
+            0,
+            NON_RETURNING_FUNCTION,
+            3,
+            hex!("d2 601e 6000 d3 600a6000f3").to_vec(),
+        )],
+        &data,
+    );
+    EvmTester::new()
+        .code(code)
+        .revision(Revision::Eof)
+        .status(StatusCode::Success)
+        .output_data(data[30..40].to_vec())
+        .check();
+}
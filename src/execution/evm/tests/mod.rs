@@ -1,6 +1,8 @@
 mod basefee;
 mod call;
 mod eip2929;
+mod eof;
 mod execute;
+mod optimization;
 mod other;
 mod state;
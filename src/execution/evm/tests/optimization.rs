@@ -0,0 +1,144 @@
+use crate::{
+    execution::evm::{
+        opcode::*,
+        optimization::{OptimizedCode, Superinstruction},
+        util::{mocked_host::MockedHost, *},
+        *,
+    },
+    models::*,
+};
+use bitvec::bitvec;
+use bytes::Bytes;
+use ethereum_types::Address;
+use ethnum::U256;
+use hex_literal::hex;
+
+fn run(code: &AnalyzedCode, gas: i64, revision: Revision) -> Output {
+    let message = InterpreterMessage {
+        kind: CallKind::Call,
+        is_static: false,
+        depth: 0,
+        gas,
+        recipient: Address::zero(),
+        sender: Address::zero(),
+        real_sender: Address::zero(),
+        code_address: Address::zero(),
+        input_data: Bytes::new(),
+        value: U256::ZERO,
+    };
+    code.execute(&mut MockedHost::default(), &message, revision)
+}
+
+/// Optimized code must be indistinguishable from code executed instruction by instruction,
+/// including the exact status code and gas left on failures.
+fn check_equivalence(code: impl Into<Bytecode>) {
+    let code = code.into().build();
+    let optimized = AnalyzedCode::analyze(&code);
+    let unoptimized = AnalyzedCode::analyze_unoptimized(&code);
+
+    for revision in [Revision::Frontier, Revision::Istanbul, Revision::London] {
+        for gas in [0, 1, 2, 3, 5, 8, 13, 21, 34, 100, 1_000, 10_000, 1_000_000] {
+            assert_eq!(
+                run(&optimized, gas, revision),
+                run(&unoptimized, gas, revision),
+                "gas {} revision {}",
+                gas,
+                revision
+            );
+        }
+    }
+}
+
+#[test]
+fn blocks() {
+    // PUSH1 0x04 JUMP INVALID JUMPDEST PUSH1 0x01 DUP1 SWAP1 GAS POP STOP
+    let code = hex!("600456fe5b600180905a5000");
+    let mut jumpdest_map = bitvec![0; code.len()];
+    jumpdest_map.set(4, true);
+
+    let optimized = OptimizedCode::analyze(&code, &jumpdest_map);
+
+    assert_eq!(
+        optimized.blocks.iter().map(|b| b.start).collect::<Vec<_>>(),
+        vec![0, 3, 4, 10]
+    );
+
+    let block = &optimized.blocks[2];
+    assert_eq!(block.gas_cost[Revision::London as usize], 1 + 3 + 3 + 3 + 2);
+    assert_eq!(block.stack_required, 0);
+    assert_eq!(block.stack_max_growth, 3);
+
+    let block = &optimized.blocks[3];
+    assert_eq!(block.stack_required, 1);
+    assert_eq!(block.stack_max_growth, 0);
+
+    assert_eq!(optimized.superinstructions[0], Superinstruction::PushJump);
+    assert_eq!(optimized.superinstructions[7], Superinstruction::DupSwap);
+    assert_eq!(optimized.superinstructions[5], Superinstruction::None);
+}
+
+#[test]
+fn push_jump_to_invalid_destination_is_not_fused() {
+    let code = hex!("600356005b00");
+    let jumpdest_map = bitvec![0, 0, 0, 0, 1, 0];
+
+    let optimized = OptimizedCode::analyze(&code, &jumpdest_map);
+    assert!(optimized.superinstructions.is_empty());
+
+    check_equivalence(code);
+}
+
+#[test]
+fn loops() {
+    // Counts down from 10, storing the counter in memory on every iteration.
+    let code = Bytecode::new()
+        .pushv(10)
+        .opcode(OpCode::JUMPDEST)
+        .opcode(OpCode::DUP1)
+        .pushv(0)
+        .opcode(OpCode::MSTORE)
+        .pushv(1)
+        .opcode(OpCode::SWAP1)
+        .opcode(OpCode::SUB)
+        .opcode(OpCode::DUP1)
+        .pushv(2)
+        .opcode(OpCode::JUMPI)
+        .ret(0, 32);
+
+    check_equivalence(code.clone());
+
+    EvmTester::new()
+        .code(code)
+        .status(StatusCode::Success)
+        .output_value(1)
+        .check();
+}
+
+#[test]
+fn gas_is_exact_where_observed() {
+    check_equivalence(
+        Bytecode::new()
+            .pushv(1)
+            .pushv(2)
+            .opcode(OpCode::ADD)
+            .opcode(OpCode::GAS)
+            .pushv(0)
+            .opcode(OpCode::MSTORE)
+            .ret(0, 32),
+    );
+}
+
+#[test]
+fn stack_errors() {
+    check_equivalence(hex!("600101"));
+    check_equivalence(1024 * Bytecode::from(OpCode::CALLER) + OpCode::POP);
+    check_equivalence(1025 * Bytecode::from(OpCode::CALLER));
+    check_equivalence(hex!("6001 8190"));
+}
+
+#[test]
+fn undefined_instruction_in_block() {
+    // SHL is undefined before Constantinople.
+    check_equivalence(hex!("6001 6001 1b 00"));
+    check_equivalence(hex!("6001 0c 6001 00"));
+}
@@ -8,7 +8,7 @@ use crate::{
     chain::protocol_param::{fee, param},
     crypto::keccak256,
     execution::evm::{
-        host::*, AnalyzedCode, CallKind, CreateMessage, InterpreterMessage, Output, StatusCode,
+        eof, host::*, AnalyzedCode, CallKind, CreateMessage, InterpreterMessage, Output, StatusCode,
     },
     h256_to_u256,
     models::*,
@@ -16,7 +16,7 @@ use crate::{
 };
 use anyhow::Context;
 use bytes::Bytes;
-use std::{cmp::min, convert::TryFrom};
+use std::{cmp::min, convert::TryFrom, sync::Arc};
 
 pub struct CallResult {
     /// EVM exited with this status code.
@@ -154,6 +154,15 @@ where
             return Ok(res);
         }
 
+        // https://eips.ethereum.org/EIPS/eip-3540
+        let eof_initcode =
+            self.block_spec.revision >= Revision::Eof && eof::is_eof(&message.initcode);
+        if eof_initcode && eof::validate(&message.initcode).is_err() {
+            res.status_code = StatusCode::ContractValidationFailure;
+            res.gas_left = 0;
+            return Ok(res);
+        }
+
         let snapshot = self.state.take_snapshot();
 
         self.state.create_contract(contract_addr)?;
@@ -184,7 +193,11 @@ where
             let code_len = res.output_data.len();
             let code_deploy_gas = code_len as u64 * fee::G_CODE_DEPOSIT;
 
-            if self.block_spec.revision >= Revision::London
+            if eof_initcode && eof::validate(&res.output_data).is_err() {
+                // https://eips.ethereum.org/EIPS/eip-3540
+                res.status_code = StatusCode::ContractValidationFailure;
+            } else if !eof_initcode
+                && self.block_spec.revision >= Revision::London
                 && code_len > 0
                 && res.output_data[0] == 0xEF
             {
@@ -346,15 +359,15 @@ where
         code_hash: Option<&H256>,
     ) -> anyhow::Result<Output> {
         let analysis = if let Some(code_hash) = code_hash {
-            if let Some(cache) = self.analysis_cache.get(code_hash).cloned() {
+            if let Some(cache) = self.analysis_cache.get(code_hash) {
                 cache
             } else {
-                let analysis = AnalyzedCode::analyze(code);
+                let analysis = Arc::new(AnalyzedCode::analyze(code));
                 self.analysis_cache.put(*code_hash, analysis.clone());
                 analysis
             }
         } else {
-            AnalyzedCode::analyze(code)
+            Arc::new(AnalyzedCode::analyze(code))
         };
 
         let revision = self.block_spec.revision;
@@ -378,9 +391,11 @@ where
             Revision::Byzantium | Revision::Constantinople | Revision::Petersburg => {
                 precompiled::NUM_OF_BYZANTIUM_CONTRACTS as u8
             }
-            Revision::Istanbul | Revision::Berlin | Revision::London | Revision::Paris => {
-                precompiled::NUM_OF_ISTANBUL_CONTRACTS as u8
-            }
+            Revision::Istanbul
+            | Revision::Berlin
+            | Revision::London
+            | Revision::Paris
+            | Revision::Eof => precompiled::NUM_OF_ISTANBUL_CONTRACTS as u8,
         }
     }
 
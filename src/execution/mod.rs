@@ -9,6 +9,7 @@ pub mod address;
 pub mod analysis_cache;
 pub mod evm;
 pub mod evmglue;
+mod parallel;
 pub mod precompiled;
 pub mod processor;
 pub mod tracer;
@@ -20,7 +21,7 @@ pub fn execute_block<S: State>(
     block: &BlockBodyWithSenders,
 ) -> Result<Vec<Receipt>, DuoError> {
     let mut analysis_cache = AnalysisCache::default();
-    let mut engine = consensus::engine_factory(None, config.clone(), None)?;
+    let mut engine = consensus::engine_factory(None, config.clone(), None, None)?;
     let mut tracer = NoopTracer;
 
     let config = config.collect_block_spec(header.number);
@@ -0,0 +1,330 @@
+//! Optimistic parallel execution of block transactions.
+//!
+//! Every transaction is first executed speculatively against the state as of the beginning of
+//! the block, recording each account and storage value that it reads. Transactions are then
+//! committed in block order: if every value a transaction has read is unchanged by the
+//! transactions committed before it, the speculative result is exactly what sequential
+//! execution would have produced and its changes are replayed. Otherwise the transaction is
+//! executed again on top of the committed state.
+//!
+//! This is a single speculative pass followed by sequential re-execution of conflicting
+//! transactions, not Block-STM: there is no multi-versioned state that would let speculative
+//! runs observe writes of earlier transactions, so blocks with long dependency chains gain
+//! little.
+//!
+//! State may be backed by an MDBX transaction, which must not be used from other threads.
+//! Speculative runs on the rayon thread pool therefore send their reads to the thread that
+//! owns the state, which serves them until all runs are finished.
+
+use super::{
+    analysis_cache::AnalysisCache,
+    processor::{execute_transaction_inner, validate_transaction, TransactionValidationError},
+    tracer::MergeableTracer,
+};
+use crate::{
+    consensus::DuoError,
+    models::*,
+    state::{IntraBlockState, WriteSet},
+    HeaderReader, StateReader,
+};
+use anyhow::format_err;
+use bytes::Bytes;
+use parking_lot::Mutex;
+use rayon::prelude::*;
+use std::{
+    collections::HashMap,
+    sync::mpsc::{self, Sender, SyncSender},
+};
+
+/// Values observed by a speculatively executed transaction.
+#[derive(Debug, Default)]
+pub(crate) struct ReadSet {
+    pub accounts: HashMap<Address, Option<Account>>,
+    pub storage: HashMap<(Address, U256), U256>,
+}
+
+/// Result of a transaction executed against the state at the beginning of the block.
+#[derive(Debug)]
+pub(crate) struct Speculation<T> {
+    pub reads: ReadSet,
+    pub write_set: WriteSet,
+    /// Receipt with `cumulative_gas_used` holding gas used by this transaction alone.
+    pub receipt: Receipt,
+    pub tracer: T,
+}
+
+/// State reader that records the first value of every account and storage slot read through it.
+#[derive(Debug)]
+struct RecordingReader<'s, S> {
+    inner: &'s S,
+    reads: Mutex<ReadSet>,
+}
+
+impl<'s, S> RecordingReader<'s, S> {
+    fn new(inner: &'s S) -> Self {
+        Self {
+            inner,
+            reads: Default::default(),
+        }
+    }
+}
+
+impl<'s, S> HeaderReader for RecordingReader<'s, S>
+where
+    S: HeaderReader,
+{
+    fn read_header(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockHeader>> {
+        self.inner.read_header(block_number, block_hash)
+    }
+}
+
+impl<'s, S> StateReader for RecordingReader<'s, S>
+where
+    S: StateReader,
+{
+    fn read_account(&self, address: Address) -> anyhow::Result<Option<Account>> {
+        let account = self.inner.read_account(address)?;
+        self.reads.lock().accounts.entry(address).or_insert(account);
+        Ok(account)
+    }
+
+    fn read_code(&self, code_hash: H256) -> anyhow::Result<Bytes> {
+        // Code is content-addressed, it cannot be changed by other transactions.
+        self.inner.read_code(code_hash)
+    }
+
+    fn read_storage(&self, address: Address, location: U256) -> anyhow::Result<U256> {
+        let value = self.inner.read_storage(address, location)?;
+        self.reads
+            .lock()
+            .storage
+            .entry((address, location))
+            .or_insert(value);
+        Ok(value)
+    }
+}
+
+type Reply<T> = SyncSender<anyhow::Result<T>>;
+
+/// Read made by a speculative run, served by the thread owning the state.
+#[derive(Debug)]
+enum ReadRequest {
+    Header(BlockNumber, H256, Reply<Option<BlockHeader>>),
+    Account(Address, Reply<Option<Account>>),
+    Code(H256, Reply<Bytes>),
+    Storage(Address, U256, Reply<U256>),
+}
+
+impl ReadRequest {
+    fn serve<S>(self, state: &S)
+    where
+        S: HeaderReader + StateReader,
+    {
+        // Reply is not needed if the run is gone.
+        match self {
+            Self::Header(block_number, block_hash, reply) => {
+                let _ = reply.send(state.read_header(block_number, block_hash));
+            }
+            Self::Account(address, reply) => {
+                let _ = reply.send(state.read_account(address));
+            }
+            Self::Code(code_hash, reply) => {
+                let _ = reply.send(state.read_code(code_hash));
+            }
+            Self::Storage(address, location, reply) => {
+                let _ = reply.send(state.read_storage(address, location));
+            }
+        }
+    }
+}
+
+/// State reader of a rayon worker, forwarding reads to the thread owning the state.
+#[derive(Debug)]
+struct RemoteReader {
+    requests: Mutex<Sender<ReadRequest>>,
+}
+
+impl RemoteReader {
+    fn new(requests: Sender<ReadRequest>) -> Self {
+        Self {
+            requests: Mutex::new(requests),
+        }
+    }
+
+    fn read<T>(&self, request: impl FnOnce(Reply<T>) -> ReadRequest) -> anyhow::Result<T> {
+        let (reply_tx, reply_rx) = mpsc::sync_channel(1);
+        self.requests
+            .lock()
+            .send(request(reply_tx))
+            .map_err(|_| format_err!("state is no longer served"))?;
+        reply_rx
+            .recv()
+            .map_err(|_| format_err!("state is no longer served"))?
+    }
+}
+
+impl HeaderReader for RemoteReader {
+    fn read_header(
+        &self,
+        block_number: BlockNumber,
+        block_hash: H256,
+    ) -> anyhow::Result<Option<BlockHeader>> {
+        self.read(|reply| ReadRequest::Header(block_number, block_hash, reply))
+    }
+}
+
+impl StateReader for RemoteReader {
+    fn read_account(&self, address: Address) -> anyhow::Result<Option<Account>> {
+        self.read(|reply| ReadRequest::Account(address, reply))
+    }
+
+    fn read_code(&self, code_hash: H256) -> anyhow::Result<Bytes> {
+        self.read(|reply| ReadRequest::Code(code_hash, reply))
+    }
+
+    fn read_storage(&self, address: Address, location: U256) -> anyhow::Result<U256> {
+        self.read(|reply| ReadRequest::Storage(address, location, reply))
+    }
+}
+
+/// Executes a transaction in isolation on top of the state at the beginning of the block.
+/// Transactions that are invalid in isolation yield `None`, as they may be valid on top of the
+/// transactions before them.
+fn speculate<T>(
+    state: &RemoteReader,
+    analysis_cache: &mut AnalysisCache,
+    block_spec: &BlockExecutionSpec,
+    header: &BlockHeader,
+    txn: &MessageWithSender,
+    beneficiary: Address,
+) -> anyhow::Result<Option<Speculation<T>>>
+where
+    T: MergeableTracer,
+{
+    let mut reader = RecordingReader::new(state);
+    let mut tracer = T::default();
+    let mut gas_used = 0;
+
+    let mut intra_block_state = IntraBlockState::new(&mut reader);
+    match validate_transaction(
+        &mut intra_block_state,
+        block_spec,
+        header,
+        header.gas_limit,
+        &txn.message,
+        txn.sender,
+    ) {
+        Ok(()) => {}
+        Err(TransactionValidationError::Validation(_)) => return Ok(None),
+        Err(TransactionValidationError::Internal(e)) => return Err(e),
+    }
+    let receipt = match execute_transaction_inner(
+        &mut intra_block_state,
+        block_spec,
+        header,
+        &mut tracer,
+        analysis_cache,
+        &mut gas_used,
+        &txn.message,
+        txn.sender,
+        beneficiary,
+        false,
+    ) {
+        Ok((_, receipt)) => receipt,
+        Err(DuoError::Validation(_)) => return Ok(None),
+        Err(DuoError::Internal(e)) => return Err(e),
+    };
+    let write_set = intra_block_state.into_write_set();
+
+    Ok(Some(Speculation {
+        reads: reader.reads.into_inner(),
+        write_set,
+        receipt,
+        tracer,
+    }))
+}
+
+/// Executes all transactions of the block on the rayon thread pool, each in isolation on top of
+/// `state`, which is only read from the calling thread. Must not be called from within the
+/// rayon thread pool.
+///
+/// Beneficiary is not rewarded in speculative runs, since every transaction in the block
+/// would otherwise conflict on its balance. Transactions that are invalid in isolation yield
+/// `None` and must be executed sequentially, errors reading the state are returned.
+pub(crate) fn execute_speculatively<S, T>(
+    state: &S,
+    block_spec: &BlockExecutionSpec,
+    header: &BlockHeader,
+    transactions: &[MessageWithSender],
+    beneficiary: Address,
+    analysis_cache: &AnalysisCache,
+) -> anyhow::Result<Vec<Option<Speculation<T>>>>
+where
+    S: HeaderReader + StateReader,
+    T: MergeableTracer,
+{
+    let (requests_tx, requests_rx) = mpsc::channel();
+    let mut speculations = None;
+    rayon::in_place_scope(|scope| {
+        let speculations = &mut speculations;
+        scope.spawn(move |_| {
+            *speculations = Some(
+                transactions
+                    .par_iter()
+                    .map_init(
+                        || {
+                            (
+                                analysis_cache.clone(),
+                                RemoteReader::new(requests_tx.clone()),
+                            )
+                        },
+                        |(analysis_cache, reader), txn| {
+                            speculate(reader, analysis_cache, block_spec, header, txn, beneficiary)
+                        },
+                    )
+                    .collect::<anyhow::Result<Vec<_>>>(),
+            );
+        });
+
+        // Readers hang up once all runs are finished.
+        for request in requests_rx {
+            request.serve(state);
+        }
+    });
+
+    speculations.expect("speculative runs have finished")
+}
+
+/// Checks whether a speculative run observed the same values as a sequential one would have.
+pub(crate) fn is_speculation_valid<S>(
+    state: &mut IntraBlockState<'_, S>,
+    reads: &ReadSet,
+    beneficiary: Address,
+) -> anyhow::Result<bool>
+where
+    S: StateReader,
+{
+    // Sequential execution credits the beneficiary before self-destructs are processed,
+    // which cannot be reproduced by replaying the speculative run.
+    if reads.accounts.contains_key(&beneficiary) {
+        return Ok(false);
+    }
+
+    for (&address, &account) in &reads.accounts {
+        if state.get_account(address)? != account {
+            return Ok(false);
+        }
+    }
+
+    for (&(address, location), &value) in &reads.storage {
+        if state.get_current_storage(address, location)? != value {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
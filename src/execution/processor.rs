@@ -1,4 +1,8 @@
-use super::{analysis_cache::AnalysisCache, tracer::Tracer};
+use super::{
+    analysis_cache::AnalysisCache,
+    parallel,
+    tracer::{MergeableTracer, Tracer},
+};
 use crate::{
     chain::{
         intrinsic_gas::*,
@@ -74,6 +78,37 @@ pub fn execute_transaction<'r, S>(
     sender: Address,
     beneficiary: Address,
 ) -> Result<(Bytes, Receipt), DuoError>
+where
+    S: HeaderReader + StateReader,
+{
+    execute_transaction_inner(
+        state,
+        block_spec,
+        header,
+        tracer,
+        analysis_cache,
+        cumulative_gas_used,
+        message,
+        sender,
+        beneficiary,
+        true,
+    )
+}
+
+/// Executes the transaction, optionally leaving the beneficiary's priority fee unpaid so that
+/// it can be credited separately.
+pub(crate) fn execute_transaction_inner<'r, S>(
+    state: &mut IntraBlockState<'r, S>,
+    block_spec: &BlockExecutionSpec,
+    header: &BlockHeader,
+    tracer: &mut dyn Tracer,
+    analysis_cache: &mut AnalysisCache,
+    cumulative_gas_used: &mut u64,
+    message: &Message,
+    sender: Address,
+    beneficiary: Address,
+    reward_beneficiary: bool,
+) -> Result<(Bytes, Receipt), DuoError>
 where
     S: HeaderReader + StateReader,
 {
@@ -140,11 +175,13 @@ where
             vm_res.gas_left as u64,
         )?;
 
-    // award the miner
-    let priority_fee_per_gas = message
-        .priority_fee_per_gas(base_fee_per_gas)
-        .ok_or(ValidationError::MaxFeeLessThanBase)?;
-    state.add_to_balance(beneficiary, U256::from(gas_used) * priority_fee_per_gas)?;
+    if reward_beneficiary {
+        // award the miner
+        let priority_fee_per_gas = message
+            .priority_fee_per_gas(base_fee_per_gas)
+            .ok_or(ValidationError::MaxFeeLessThanBase)?;
+        state.add_to_balance(beneficiary, U256::from(gas_used) * priority_fee_per_gas)?;
+    }
 
     state.destruct_selfdestructs()?;
     if rev >= Revision::Spurious {
@@ -179,6 +216,70 @@ impl From<anyhow::Error> for TransactionValidationError {
     }
 }
 
+pub(crate) fn validate_transaction<'r, S>(
+    state: &mut IntraBlockState<'r, S>,
+    block_spec: &BlockExecutionSpec,
+    header: &BlockHeader,
+    available_gas: u64,
+    message: &Message,
+    sender: Address,
+) -> Result<(), TransactionValidationError>
+where
+    S: StateReader,
+{
+    pre_validate_transaction(message, block_spec.params.chain_id, header.base_fee_per_gas)
+        .expect("Tx must have been prevalidated");
+    if state.get_code_hash(sender)? != EMPTY_HASH {
+        return Err(TransactionValidationError::Validation(
+            BadTransactionError::SenderNoEOA { sender },
+        ));
+    }
+
+    let expected_nonce = state.get_nonce(sender)?;
+    if expected_nonce != message.nonce() {
+        return Err(TransactionValidationError::Validation(
+            BadTransactionError::WrongNonce {
+                account: sender,
+                expected: expected_nonce,
+                got: message.nonce(),
+            },
+        ));
+    }
+
+    // https://github.com/ethereum/EIPs/pull/3594
+    let max_gas_cost = U512::from(message.gas_limit())
+        * U512::from(ethereum_types::U256::from(
+            message.max_fee_per_gas().to_be_bytes(),
+        ));
+    // See YP, Eq (57) in Section 6.2 "Execution"
+    let v0 = max_gas_cost + U512::from(ethereum_types::U256::from(message.value().to_be_bytes()));
+    let available_balance =
+        ethereum_types::U256::from(state.get_balance(sender)?.to_be_bytes()).into();
+    if available_balance < v0 {
+        return Err(TransactionValidationError::Validation(
+            BadTransactionError::InsufficientFunds {
+                account: sender,
+                available: available_balance,
+                required: v0,
+            },
+        ));
+    }
+
+    if available_gas < message.gas_limit() {
+        // Corresponds to the final condition of Eq (58) in Yellow Paper Section 6.2 "Execution".
+        // The sum of the transaction’s gas limit and the gas utilized in this block prior
+        // must be no greater than the block’s gas limit.
+        return Err(TransactionValidationError::Validation(
+            BadTransactionError::BlockGasLimitExceeded {
+                available: available_gas,
+                required: message.gas_limit(),
+            },
+        ));
+    }
+
+    Ok(())
+}
+
 impl<'r, 'tracer, 'analysis, 'e, 'h, 'b, 'c, S>
     ExecutionProcessor<'r, 'tracer, 'analysis, 'e, 'h, 'b, 'c, S>
 where
@@ -226,63 +327,15 @@ where
         message: &Message,
         sender: Address,
     ) -> Result<(), TransactionValidationError> {
-        pre_validate_transaction(
+        let available_gas = self.available_gas();
+        validate_transaction(
+            &mut self.state,
+            self.block_spec,
+            self.header,
+            available_gas,
             message,
-            self.block_spec.params.chain_id,
-            self.header.base_fee_per_gas,
+            sender,
         )
-        .expect("Tx must have been prevalidated");
-        if self.state.get_code_hash(sender)? != EMPTY_HASH {
-            return Err(TransactionValidationError::Validation(
-                BadTransactionError::SenderNoEOA { sender },
-            ));
-        }
-
-        let expected_nonce = self.state.get_nonce(sender)?;
-        if expected_nonce != message.nonce() {
-            return Err(TransactionValidationError::Validation(
-                BadTransactionError::WrongNonce {
-                    account: sender,
-                    expected: expected_nonce,
-                    got: message.nonce(),
-                },
-            ));
-        }
-
-        // https://github.com/ethereum/EIPs/pull/3594
-        let max_gas_cost = U512::from(message.gas_limit())
-            * U512::from(ethereum_types::U256::from(
-                message.max_fee_per_gas().to_be_bytes(),
-            ));
-        // See YP, Eq (57) in Section 6.2 "Execution"
-        let v0 =
-            max_gas_cost + U512::from(ethereum_types::U256::from(message.value().to_be_bytes()));
-        let available_balance =
-            ethereum_types::U256::from(self.state.get_balance(sender)?.to_be_bytes()).into();
-        if available_balance < v0 {
-            return Err(TransactionValidationError::Validation(
-                BadTransactionError::InsufficientFunds {
-                    account: sender,
-                    available: available_balance,
-                    required: v0,
-                },
-            ));
-        }
-
-        let available_gas = self.available_gas();
-        if available_gas < message.gas_limit() {
-            // Corresponds to the final condition of Eq (58) in Yellow Paper Section 6.2 "Execution".
-            // The sum of the transaction’s gas limit and the gas utilized in this block prior
-            // must be no greater than the block’s gas limit.
-            return Err(TransactionValidationError::Validation(
-                BadTransactionError::BlockGasLimitExceeded {
-                    available: available_gas,
-                    required: message.gas_limit(),
-                },
-            ));
-        }
-
-        Ok(())
     }
 
     pub fn execute_transaction(
@@ -306,31 +359,29 @@ where
         .map(|(_, receipt)| receipt)
     }
 
-    pub fn execute_block_no_post_validation_while(
+    fn validate_block_transaction(
         &mut self,
-        mut pred: impl FnMut(usize, &MessageWithSender) -> bool,
-    ) -> Result<Vec<Receipt>, DuoError> {
-        let mut receipts = Vec::with_capacity(self.block.transactions.len());
+        index: usize,
+        txn: &MessageWithSender,
+    ) -> Result<(), DuoError> {
+        self.validate_transaction(&txn.message, txn.sender)
+            .map_err(|e| match e {
+                TransactionValidationError::Validation(error) => {
+                    DuoError::Validation(ValidationError::BadTransaction { index, error })
+                }
+                TransactionValidationError::Internal(e) => DuoError::Internal(e),
+            })
+    }
 
+    fn apply_balance_changes(&mut self) -> Result<(), DuoError> {
         for (&address, &balance) in &self.block_spec.balance_changes {
             self.state.set_balance(address, balance)?;
         }
 
-        for (i, txn) in self.block.transactions.iter().enumerate() {
-            if !(pred)(i, txn) {
-                return Ok(receipts);
-            }
-
-            self.validate_transaction(&txn.message, txn.sender)
-                .map_err(|e| match e {
-                    TransactionValidationError::Validation(error) => {
-                        DuoError::Validation(ValidationError::BadTransaction { index: i, error })
-                    }
-                    TransactionValidationError::Internal(e) => DuoError::Internal(e),
-                })?;
-            receipts.push(self.execute_transaction(&txn.message, txn.sender)?);
-        }
+        Ok(())
+    }
 
+    fn apply_finalization_changes(&mut self) -> Result<(), DuoError> {
         for change in self.engine.finalize(self.header, &self.block.ommers)? {
             match change {
                 FinalizationChange::Reward {
@@ -343,6 +394,28 @@ where
             }
         }
 
+        Ok(())
+    }
+
+    pub fn execute_block_no_post_validation_while(
+        &mut self,
+        mut pred: impl FnMut(usize, &MessageWithSender) -> bool,
+    ) -> Result<Vec<Receipt>, DuoError> {
+        let mut receipts = Vec::with_capacity(self.block.transactions.len());
+
+        self.apply_balance_changes()?;
+
+        for (i, txn) in self.block.transactions.iter().enumerate() {
+            if !(pred)(i, txn) {
+                return Ok(receipts);
+            }
+
+            self.validate_block_transaction(i, txn)?;
+            receipts.push(self.execute_transaction(&txn.message, txn.sender)?);
+        }
+
+        self.apply_finalization_changes()?;
+
         Ok(receipts)
     }
 
@@ -350,9 +423,118 @@ where
         self.execute_block_no_post_validation_while(|_, _| true)
     }
 
+    /// Same as [Self::execute_block_no_post_validation], but transactions are executed
+    /// speculatively on the rayon thread pool and only re-executed sequentially on conflict.
+    /// State is only accessed from the calling thread, which must not belong to the pool.
+    ///
+    /// Tracer set for this processor is not used. Instead, each transaction is traced with
+    /// its own instance of `T`, which are then merged into `tracer` in block order.
+    pub fn execute_block_no_post_validation_parallel<T>(
+        &mut self,
+        tracer: &mut T,
+    ) -> Result<Vec<Receipt>, DuoError>
+    where
+        T: MergeableTracer,
+    {
+        let mut receipts = Vec::with_capacity(self.block.transactions.len());
+
+        let beneficiary = self.engine.get_beneficiary(self.header);
+        let speculations = parallel::execute_speculatively::<_, T>(
+            &*self.state.db(),
+            self.block_spec,
+            self.header,
+            &self.block.transactions,
+            beneficiary,
+            self.analysis_cache,
+        )?;
+
+        self.apply_balance_changes()?;
+
+        let base_fee_per_gas = self.header.base_fee_per_gas.unwrap_or(U256::ZERO);
+        for (i, (txn, speculation)) in self.block.transactions.iter().zip(speculations).enumerate()
+        {
+            self.validate_block_transaction(i, txn)?;
+
+            let speculation = match speculation {
+                Some(speculation)
+                    if parallel::is_speculation_valid(
+                        &mut self.state,
+                        &speculation.reads,
+                        beneficiary,
+                    )? =>
+                {
+                    Some(speculation)
+                }
+                _ => None,
+            };
+
+            if let Some(speculation) = speculation {
+                let gas_used = speculation.receipt.cumulative_gas_used;
+
+                self.state.clear_journal_and_substate();
+                self.state.apply_write_set(speculation.write_set)?;
+
+                let priority_fee_per_gas = txn
+                    .message
+                    .priority_fee_per_gas(base_fee_per_gas)
+                    .ok_or(ValidationError::MaxFeeLessThanBase)?;
+                self.state
+                    .add_to_balance(beneficiary, U256::from(gas_used) * priority_fee_per_gas)?;
+                if self.block_spec.revision >= Revision::Spurious
+                    && self.state.is_dead(beneficiary)?
+                {
+                    self.state.destruct(beneficiary)?;
+                }
+                self.state.finalize_transaction();
+
+                self.cumulative_gas_used += gas_used;
+                tracer.merge(speculation.tracer);
+                receipts.push(Receipt {
+                    cumulative_gas_used: self.cumulative_gas_used,
+                    ..speculation.receipt
+                });
+            } else {
+                let mut txn_tracer = T::default();
+                let (_, receipt) = execute_transaction(
+                    &mut self.state,
+                    self.block_spec,
+                    self.header,
+                    &mut txn_tracer,
+                    self.analysis_cache,
+                    &mut self.cumulative_gas_used,
+                    &txn.message,
+                    txn.sender,
+                    beneficiary,
+                )?;
+                tracer.merge(txn_tracer);
+                receipts.push(receipt);
+            }
+        }
+
+        self.apply_finalization_changes()?;
+
+        Ok(receipts)
+    }
+
     pub fn execute_and_check_block(&mut self) -> Result<Vec<Receipt>, DuoError> {
         let receipts = self.execute_block_no_post_validation()?;
 
+        self.check_receipts(receipts)
+    }
+
+    pub fn execute_and_check_block_parallel<T>(
+        &mut self,
+        tracer: &mut T,
+    ) -> Result<Vec<Receipt>, DuoError>
+    where
+        T: MergeableTracer,
+    {
+        let receipts = self.execute_block_no_post_validation_parallel(tracer)?;
+
+        self.check_receipts(receipts)
+    }
+
+    fn check_receipts(&self, receipts: Vec<Receipt>) -> Result<Vec<Receipt>, DuoError> {
         let gas_used = receipts.last().map(|r| r.cumulative_gas_used).unwrap_or(0);
 
         if gas_used != self.header.gas_used {
@@ -416,6 +598,20 @@ where
 
         Ok(receipts)
     }
+
+    pub fn execute_and_write_block_parallel<T>(
+        mut self,
+        tracer: &mut T,
+    ) -> Result<Vec<Receipt>, DuoError>
+    where
+        T: MergeableTracer,
+    {
+        let receipts = self.execute_and_check_block_parallel(tracer)?;
+
+        self.state.write_to_state(self.header.number)?;
+
+        Ok(receipts)
+    }
 }
 
 #[cfg(test)]
@@ -456,7 +652,7 @@ mod tests {
 
         let mut state = InMemoryState::default();
         let mut analysis_cache = AnalysisCache::default();
-        let mut engine = engine_factory(None, MAINNET.clone(), None).unwrap();
+        let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
         let block_spec = MAINNET.collect_block_spec(header.number);
         let mut tracer = NoopTracer;
         let mut processor = ExecutionProcessor::new(
@@ -499,7 +695,7 @@ mod tests {
 
         let mut state = InMemoryState::default();
         let mut analysis_cache = AnalysisCache::default();
-        let mut engine = engine_factory(None, MAINNET.clone(), None).unwrap();
+        let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
         let block_spec = MAINNET.collect_block_spec(header.number);
         let mut tracer = NoopTracer;
         let mut processor = ExecutionProcessor::new(
@@ -564,7 +760,7 @@ mod tests {
 
         let mut state = InMemoryState::default();
         let mut analysis_cache = AnalysisCache::default();
-        let mut engine = engine_factory(None, MAINNET.clone(), None).unwrap();
+        let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
         let block_spec = MAINNET.collect_block_spec(header.number);
         let mut tracer = NoopTracer;
         let mut processor = ExecutionProcessor::new(
@@ -682,7 +878,7 @@ mod tests {
 
         let mut state = InMemoryState::default();
         let mut analysis_cache = AnalysisCache::default();
-        let mut engine = engine_factory(None, MAINNET.clone(), None).unwrap();
+        let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
         let block_spec = MAINNET.collect_block_spec(header.number);
         let mut tracer = NoopTracer;
         let mut processor = ExecutionProcessor::new(
@@ -789,7 +985,7 @@ mod tests {
         };
 
         let mut analysis_cache = AnalysisCache::default();
-        let mut engine = engine_factory(None, MAINNET.clone(), None).unwrap();
+        let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
         let block_spec = MAINNET.collect_block_spec(header.number);
         let mut tracer = NoopTracer;
         let mut processor = ExecutionProcessor::new(
@@ -845,7 +1041,7 @@ mod tests {
 
         let mut state = InMemoryState::default();
         let mut analysis_cache = AnalysisCache::default();
-        let mut engine = engine_factory(None, MAINNET.clone(), None).unwrap();
+        let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
         let block_spec = MAINNET.collect_block_spec(header.number);
         let mut tracer = NoopTracer;
         let mut processor = ExecutionProcessor::new(
@@ -868,4 +1064,112 @@ mod tests {
         // suicide_beneficiary should've been touched and deleted
         assert_eq!(state.read_account(suicide_beneficiary).unwrap(), None);
     }
+
+    #[test]
+    fn parallel_execution_matches_sequential() {
+        let block_number = 13_500_001.into();
+
+        let partial_header = PartialHeader {
+            number: block_number,
+            gas_limit: 1_000_000,
+            beneficiary: hex!("5a0b54d5dc17e0aadc383d2db43b0a0d3e029c4c").into(),
+            base_fee_per_gas: Some(U256::from(GIGA)),
+            ..PartialHeader::empty()
+        };
+        let header = BlockHeader::new(partial_header, EMPTY_LIST_HASH, EMPTY_ROOT);
+
+        let alice = hex!("b685342b8c54347aad148e1f22eff3eb3eb29391").into();
+        let bob = hex!("71562b71999873db5b286df957af199ec94617f7").into();
+        let carol = hex!("c789e5aba05051b1468ac980e30068e19fad8587").into();
+        let dave = hex!("834e9b529ac9fa63b39a06f8d8c9b0d6791fa5df").into();
+
+        let t = |action, input: Bytes, nonce, value: u128| Message::EIP1559 {
+            chain_id: MAINNET.params.chain_id,
+            nonce,
+            max_priority_fee_per_gas: U256::from(GIGA),
+            max_fee_per_gas: U256::from(20 * GIGA),
+            gas_limit: 100_000,
+            action,
+            value: value.as_u256(),
+            input,
+            access_list: Default::default(),
+        };
+
+        // This contract sets its 0th storage to 0x2a on deployment.
+        let deployment_code = hex!("602a60005560098060106000396000f36000358060005531");
+
+        let block = BlockBodyWithSenders {
+            transactions: vec![
+                // Independent transfer
+                MessageWithSender {
+                    message: (t)(TransactionAction::Call(carol), Bytes::new(), 0, ETHER),
+                    sender: alice,
+                },
+                // Depends on the previous transaction through the sender's nonce and balance
+                MessageWithSender {
+                    message: (t)(TransactionAction::Call(bob), Bytes::new(), 1, ETHER),
+                    sender: alice,
+                },
+                // Spends funds received earlier in the block
+                MessageWithSender {
+                    message: (t)(TransactionAction::Call(dave), Bytes::new(), 0, ETHER / 2),
+                    sender: carol,
+                },
+                // Independent contract creation
+                MessageWithSender {
+                    message: (t)(
+                        TransactionAction::Create,
+                        deployment_code.to_vec().into(),
+                        0,
+                        0,
+                    ),
+                    sender: dave,
+                },
+            ],
+            ommers: Default::default(),
+        };
+
+        let block_spec = MAINNET.collect_block_spec(header.number);
+
+        let mut results = vec![];
+        for parallel in [false, true] {
+            let mut state = InMemoryState::default();
+            for address in [alice, dave] {
+                state.update_account(
+                    address,
+                    None,
+                    Some(Account {
+                        balance: (10 * ETHER).as_u256(),
+                        ..Default::default()
+                    }),
+                );
+            }
+
+            let mut analysis_cache = AnalysisCache::default();
+            let mut engine = engine_factory(None, MAINNET.clone(), None, None).unwrap();
+            let mut tracer = NoopTracer;
+            let mut processor = ExecutionProcessor::new(
+                &mut state,
+                &mut tracer,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block,
+                &block_spec,
+            );
+
+            let receipts = if parallel {
+                processor.execute_block_no_post_validation_parallel(&mut NoopTracer)
+            } else {
+                processor.execute_block_no_post_validation()
+            }
+            .unwrap();
+            processor.into_state().write_to_state(block_number).unwrap();
+
+            assert!(receipts.iter().all(|receipt| receipt.success));
+            results.push((receipts, state.state_root_hash()));
+        }
+
+        assert_eq!(results[0], results[1]);
+    }
 }
@@ -0,0 +1,454 @@
+//! Interactive debugger pausing execution at instructions.
+//!
+//! Execution runs on a dedicated thread with [`DebuggerTracer`] installed. Whenever the tracer
+//! decides to pause, it reports a [`Snapshot`] and blocks until it is told how to proceed, so
+//! that the state of the interpreter can be inspected in between.
+
+use super::*;
+use crate::execution::evm::{ExecutionState, OpCode, Output, StatusCode};
+use anyhow::format_err;
+use bytes::Bytes;
+use std::{
+    sync::mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+const SESSION_THREAD_STACK_SIZE: usize = 128 * 1024 * 1024;
+
+/// Where to pause execution next.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepMode {
+    /// Pause at the next instruction, entering calls.
+    Into,
+    /// Pause at the next instruction of the current or a parent call.
+    Over,
+    /// Pause at the next instruction of a parent call.
+    Out,
+    /// Pause at breakpoints only.
+    Continue,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Instruction at this position, optionally only in code of this account.
+    Pc {
+        code_address: Option<Address>,
+        pc: usize,
+    },
+    /// Any instruction with this opcode.
+    Opcode(OpCode),
+}
+
+#[derive(Debug)]
+pub enum DebuggerCommand {
+    SetBreakpoints(Vec<Breakpoint>),
+    Resume(StepMode),
+}
+
+/// State of the interpreter before execution of an instruction.
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub pc: usize,
+    pub opcode: OpCode,
+    pub depth: u16,
+    pub gas_left: i64,
+    /// Account whose storage is used.
+    pub address: Address,
+    /// Account whose code is executed.
+    pub code_address: Address,
+    /// Stack items, top item last.
+    pub stack: Vec<U256>,
+    pub memory: Bytes,
+    /// Storage of `address` accessed so far.
+    pub storage: BTreeMap<U256, U256>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ExecutionResult {
+    pub success: bool,
+    pub gas_used: u64,
+    /// Output data, if available.
+    pub output: Option<Bytes>,
+}
+
+#[derive(Clone, Debug)]
+pub enum DebuggerEvent {
+    Paused(Box<Snapshot>),
+    Finished(ExecutionResult),
+    Failed(String),
+}
+
+/// Tracer which pauses execution according to commands it receives.
+#[derive(Debug)]
+pub struct DebuggerTracer {
+    commands: Receiver<DebuggerCommand>,
+    events: Sender<DebuggerEvent>,
+    breakpoints: Vec<Breakpoint>,
+    /// Current mode with the depth it has been set at, `None` once the debugger is gone.
+    mode: Option<(StepMode, u16)>,
+    storage: HashMap<Address, BTreeMap<U256, U256>>,
+    /// Previous values of written storage slots, for rollback of failed calls.
+    journal: Vec<(u16, Address, U256, Option<U256>)>,
+    /// SLOAD whose result is observed at the next instruction.
+    pending_sload: Option<(Address, U256)>,
+}
+
+impl DebuggerTracer {
+    fn new(commands: Receiver<DebuggerCommand>, events: Sender<DebuggerEvent>) -> Self {
+        Self {
+            commands,
+            events,
+            breakpoints: Vec::new(),
+            mode: Some((StepMode::Into, 0)),
+            storage: HashMap::new(),
+            journal: Vec::new(),
+            pending_sload: None,
+        }
+    }
+
+    fn should_pause(&self, code_address: Address, pc: usize, op: OpCode, depth: u16) -> bool {
+        let (mode, mode_depth) = match self.mode {
+            Some(mode) => mode,
+            None => return false,
+        };
+
+        let at_breakpoint = self.breakpoints.iter().any(|breakpoint| match breakpoint {
+            Breakpoint::Pc {
+                code_address: breakpoint_address,
+                pc: breakpoint_pc,
+            } => {
+                *breakpoint_pc == pc
+                    && breakpoint_address
+                        .map(|address| address == code_address)
+                        .unwrap_or(true)
+            }
+            Breakpoint::Opcode(opcode) => *opcode == op,
+        });
+
+        at_breakpoint
+            || match mode {
+                StepMode::Into => true,
+                StepMode::Over => depth <= mode_depth,
+                StepMode::Out => depth < mode_depth,
+                StepMode::Continue => false,
+            }
+    }
+
+    fn write_storage(&mut self, depth: u16, address: Address, location: U256, value: U256) {
+        let previous = self
+            .storage
+            .entry(address)
+            .or_default()
+            .insert(location, value);
+        self.journal.push((depth, address, location, previous));
+    }
+
+    fn pause(&mut self, snapshot: Snapshot) {
+        let depth = snapshot.depth;
+        if self
+            .events
+            .send(DebuggerEvent::Paused(Box::new(snapshot)))
+            .is_err()
+        {
+            self.mode = None;
+            return;
+        }
+
+        loop {
+            match self.commands.recv() {
+                Ok(DebuggerCommand::SetBreakpoints(breakpoints)) => self.breakpoints = breakpoints,
+                Ok(DebuggerCommand::Resume(mode)) => {
+                    self.mode = Some((mode, depth));
+                    return;
+                }
+                Err(_) => {
+                    // Debugger is gone, run to completion.
+                    self.mode = None;
+                    return;
+                }
+            }
+        }
+    }
+}
+
+impl Tracer for DebuggerTracer {
+    fn trace_instructions(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    fn capture_state(&mut self, env: &ExecutionState, pc: usize, op: OpCode, _: u64, depth: u16) {
+        let address = env.message.recipient;
+
+        if let Some((sload_address, location)) = self.pending_sload.take() {
+            if !env.stack.is_empty() {
+                self.storage
+                    .entry(sload_address)
+                    .or_default()
+                    .entry(location)
+                    .or_insert(*env.stack.get(0));
+            }
+        }
+
+        let code_address = env.message.code_address;
+        if self.should_pause(code_address, pc, op, depth) {
+            self.pause(Snapshot {
+                pc,
+                opcode: op,
+                depth,
+                gas_left: env.gas_left,
+                address,
+                code_address,
+                stack: env.stack.0.to_vec(),
+                memory: Bytes::copy_from_slice(&env.memory),
+                storage: self.storage.get(&address).cloned().unwrap_or_default(),
+            });
+        }
+
+        match op {
+            OpCode::SLOAD if !env.stack.is_empty() => {
+                self.pending_sload = Some((address, *env.stack.get(0)));
+            }
+            OpCode::SSTORE if env.stack.len() >= 2 => {
+                self.write_storage(depth, address, *env.stack.get(0), *env.stack.get(1));
+            }
+            _ => {}
+        }
+    }
+
+    fn capture_end(&mut self, depth: usize, _: u64, output: &Output) {
+        self.pending_sload = None;
+
+        if output.status_code != StatusCode::Success {
+            while let Some(&(entry_depth, address, location, previous)) = self.journal.last() {
+                if usize::from(entry_depth) < depth {
+                    break;
+                }
+                self.journal.pop();
+
+                let storage = self.storage.entry(address).or_default();
+                match previous {
+                    Some(value) => storage.insert(location, value),
+                    None => storage.remove(&location),
+                };
+            }
+        }
+    }
+}
+
+/// Execution paused by [`DebuggerTracer`], running on its own thread.
+///
+/// Dropping the session lets the execution run to completion.
+#[derive(Debug)]
+pub struct DebugSession {
+    commands: Sender<DebuggerCommand>,
+    events: Receiver<DebuggerEvent>,
+    timeout: Duration,
+    last_event: DebuggerEvent,
+}
+
+impl DebugSession {
+    /// Starts execution with `f` and waits until it pauses for the first time or finishes.
+    pub fn start<F>(timeout: Duration, f: F) -> anyhow::Result<Self>
+    where
+        F: FnOnce(&mut DebuggerTracer) -> anyhow::Result<ExecutionResult> + Send + 'static,
+    {
+        let (command_tx, command_rx) = mpsc::channel();
+        let (event_tx, event_rx) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("debug-session".into())
+            .stack_size(SESSION_THREAD_STACK_SIZE)
+            .spawn(move || {
+                let mut tracer = DebuggerTracer::new(command_rx, event_tx.clone());
+                let _ = event_tx.send(match (f)(&mut tracer) {
+                    Ok(result) => DebuggerEvent::Finished(result),
+                    Err(e) => DebuggerEvent::Failed(format!("{e:?}")),
+                });
+            })?;
+
+        let mut session = Self {
+            commands: command_tx,
+            events: event_rx,
+            timeout,
+            last_event: DebuggerEvent::Failed("not started".into()),
+        };
+        if let DebuggerEvent::Failed(e) = session.wait()? {
+            return Err(format_err!("{e}"));
+        }
+
+        Ok(session)
+    }
+
+    /// Waits for the next event. If none comes within the timeout, the session is detached from
+    /// execution, which then runs to completion without pausing, so that events of later
+    /// commands cannot be mixed up with the missed one.
+    fn wait(&mut self) -> anyhow::Result<&DebuggerEvent> {
+        self.last_event = match self.events.recv_timeout(self.timeout) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => {
+                let error = format!("execution has not paused within {:?}", self.timeout);
+                self.detach();
+                self.last_event = DebuggerEvent::Failed(error.clone());
+                return Err(format_err!("{error}"));
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                return Err(format_err!("execution has already finished"))
+            }
+        };
+
+        Ok(&self.last_event)
+    }
+
+    /// Drops both ends of the channels to the tracer, making it stop pausing.
+    fn detach(&mut self) {
+        self.commands = mpsc::channel().0;
+        self.events = mpsc::channel().1;
+    }
+
+    /// Most recent event of the session.
+    pub fn last_event(&self) -> &DebuggerEvent {
+        &self.last_event
+    }
+
+    pub fn set_breakpoints(&mut self, breakpoints: Vec<Breakpoint>) -> anyhow::Result<()> {
+        self.commands
+            .send(DebuggerCommand::SetBreakpoints(breakpoints))
+            .map_err(|_| format_err!("execution has already finished"))
+    }
+
+    /// Resumes execution and waits until it pauses again or finishes.
+    pub fn resume(&mut self, mode: StepMode) -> anyhow::Result<&DebuggerEvent> {
+        if !matches!(self.last_event, DebuggerEvent::Paused(_)) {
+            return Ok(&self.last_event);
+        }
+
+        self.commands
+            .send(DebuggerCommand::Resume(mode))
+            .map_err(|_| format_err!("execution has already finished"))?;
+        self.wait()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        execution::{analysis_cache::AnalysisCache, evmglue},
+        res::chainspec::MAINNET,
+        InMemoryState, IntraBlockState,
+    };
+    use hex_literal::hex;
+
+    #[test]
+    fn stepping_and_breakpoints() {
+        let session = DebugSession::start(Duration::from_secs(10), |tracer| {
+            let contract = Address::repeat_byte(0xcc);
+            let header = BlockHeader::new(PartialHeader::empty(), EMPTY_LIST_HASH, EMPTY_ROOT);
+
+            let mut db = InMemoryState::default();
+            let mut state = IntraBlockState::new(&mut db);
+            // sstore(0, 0x2a) sload(0) stop
+            state.set_code(contract, hex!("602a6000556000540000").to_vec().into())?;
+
+            let message = Message::Legacy {
+                chain_id: None,
+                nonce: 0,
+                gas_price: U256::ZERO,
+                gas_limit: 100_000,
+                action: TransactionAction::Call(contract),
+                value: U256::ZERO,
+                input: Bytes::new(),
+            };
+            let res = evmglue::execute(
+                &mut state,
+                tracer,
+                &mut AnalysisCache::default(),
+                &header,
+                &MAINNET.collect_block_spec(header.number),
+                &message,
+                Address::zero(),
+                Address::zero(),
+                message.gas_limit(),
+            )?;
+
+            Ok(ExecutionResult {
+                success: res.status_code == StatusCode::Success,
+                gas_used: message.gas_limit() - res.gas_left as u64,
+                output: Some(res.output_data),
+            })
+        });
+        let mut session = session.unwrap();
+
+        let pc = |event: &DebuggerEvent| match event {
+            DebuggerEvent::Paused(snapshot) => snapshot.pc,
+            other => panic!("unexpected event {other:?}"),
+        };
+
+        assert_eq!(pc(session.last_event()), 0);
+        assert_eq!(pc(session.resume(StepMode::Over).unwrap()), 2);
+
+        session
+            .set_breakpoints(vec![Breakpoint::Opcode(OpCode::SLOAD)])
+            .unwrap();
+        match session.resume(StepMode::Continue).unwrap() {
+            DebuggerEvent::Paused(snapshot) => {
+                assert_eq!(snapshot.pc, 7);
+                assert_eq!(snapshot.opcode, OpCode::SLOAD);
+                assert_eq!(snapshot.stack, vec![U256::ZERO]);
+                assert_eq!(
+                    snapshot.storage,
+                    [(U256::ZERO, U256::from(0x2a_u8))].into_iter().collect()
+                );
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+
+        assert!(matches!(
+            session.resume(StepMode::Continue).unwrap(),
+            DebuggerEvent::Finished(ExecutionResult { success: true, .. })
+        ));
+    }
+
+    #[test]
+    fn step_after_timeout() {
+        let snapshot = |pc| Snapshot {
+            pc,
+            opcode: OpCode::JUMPDEST,
+            depth: 0,
+            gas_left: 0,
+            address: Address::zero(),
+            code_address: Address::zero(),
+            stack: vec![],
+            memory: Bytes::new(),
+            storage: BTreeMap::new(),
+        };
+
+        let mut session = DebugSession::start(Duration::from_millis(100), move |tracer| {
+            tracer.pause(snapshot(0));
+            thread::sleep(Duration::from_millis(500));
+            tracer.pause(snapshot(1));
+            tracer.pause(snapshot(2));
+
+            Ok(ExecutionResult {
+                success: true,
+                gas_used: 0,
+                output: None,
+            })
+        })
+        .unwrap();
+        assert!(
+            matches!(session.last_event(), DebuggerEvent::Paused(snapshot) if snapshot.pc == 0)
+        );
+
+        assert!(session.resume(StepMode::Into).is_err());
+        assert!(matches!(session.last_event(), DebuggerEvent::Failed(_)));
+
+        // Pause the execution has reached in the meantime is not reported for the next step.
+        thread::sleep(Duration::from_millis(600));
+        assert!(matches!(
+            session.resume(StepMode::Into).unwrap(),
+            DebuggerEvent::Failed(_)
+        ));
+    }
+}
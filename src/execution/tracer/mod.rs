@@ -1,4 +1,5 @@
 pub mod adhoc;
+pub mod debugger;
 pub mod eip3155_tracer;
 
 use auto_impl::auto_impl;
@@ -75,36 +76,70 @@ pub trait Tracer: Debug + Send {
     fn capture_account_write(&mut self, account: Address) {}
 }
 
+/// Tracer that can be run separately for each transaction, with results merged afterwards.
+/// Required for parallel execution.
+pub trait MergeableTracer: Tracer + Default {
+    /// Merges results of a tracer used for a later transaction into this one.
+    fn merge(&mut self, other: Self);
+}
+
 /// Tracer which does nothing.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct NoopTracer;
 
 impl Tracer for NoopTracer {}
 
+impl MergeableTracer for NoopTracer {
+    fn merge(&mut self, _: Self) {}
+}
+
 #[derive(Clone, Copy, Debug, Default)]
 pub struct CallTracerFlags {
     pub from: bool,
     pub to: bool,
 }
 
+/// Contract creation attempt, successful or not.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContractCreation {
+    /// Index of the creating transaction within the block.
+    pub transaction_index: usize,
+    pub address: Address,
+    pub creator: Address,
+}
+
 #[derive(Debug, Default)]
 pub struct CallTracer {
     addresses: HashMap<Address, CallTracerFlags>,
+    transactions: usize,
+    creations: Vec<ContractCreation>,
 }
 
 impl Tracer for CallTracer {
     fn capture_start(
         &mut self,
-        _: u16,
-        _: Address,
-        _: Address,
+        depth: u16,
+        sender: Address,
+        recipient: Address,
         real_sender: Address,
         code_address: Address,
-        _: MessageKind,
+        call_type: MessageKind,
         _: Bytes,
         _: u64,
         _: U256,
     ) {
+        if depth == 0 {
+            self.transactions += 1;
+        }
+
+        if let MessageKind::Create { .. } = call_type {
+            self.creations.push(ContractCreation {
+                transaction_index: self.transactions - 1,
+                address: recipient,
+                creator: sender,
+            });
+        }
+
         self.addresses.entry(real_sender).or_default().from = true;
         self.addresses.entry(code_address).or_default().to = true;
     }
@@ -115,6 +150,24 @@ impl Tracer for CallTracer {
     }
 }
 
+impl MergeableTracer for CallTracer {
+    fn merge(&mut self, other: Self) {
+        for (address, flags) in other.addresses {
+            let entry = self.addresses.entry(address).or_default();
+            entry.from |= flags.from;
+            entry.to |= flags.to;
+        }
+
+        let offset = self.transactions;
+        self.creations
+            .extend(other.creations.into_iter().map(|creation| ContractCreation {
+                transaction_index: offset + creation.transaction_index,
+                ..creation
+            }));
+        self.transactions += other.transactions;
+    }
+}
+
 impl CallTracer {
     pub fn into_sorted_iter(&self) -> impl Iterator<Item = (Address, CallTracerFlags)> {
         self.addresses
@@ -123,4 +176,10 @@ impl CallTracer {
             .collect::<BTreeMap<_, _>>()
             .into_iter()
     }
+
+    /// Contract creations attempted in traced transactions, in execution order.
+    /// Creations which failed or were reverted are included too.
+    pub fn contract_creations(&self) -> &[ContractCreation] {
+        &self.creations
+    }
 }
@@ -0,0 +1,76 @@
+//! HTTP liveness and readiness probes.
+//!
+//! `/health` succeeds as long as the node is running, `/ready` succeeds once staged sync has
+//! reached the tip of the chain.
+
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use std::{convert::Infallible, net::SocketAddr};
+use tokio::sync::watch::Receiver as WatchReceiver;
+use tracing::*;
+
+fn respond(status: StatusCode, body: &'static str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+fn handle(req: Request<Body>, reached_tip: &WatchReceiver<bool>) -> Response<Body> {
+    if req.method() != Method::GET {
+        return respond(StatusCode::METHOD_NOT_ALLOWED, "");
+    }
+
+    match req.uri().path() {
+        "/health" => respond(StatusCode::OK, "OK"),
+        "/ready" => {
+            if *reached_tip.borrow() {
+                respond(StatusCode::OK, "OK")
+            } else {
+                respond(StatusCode::SERVICE_UNAVAILABLE, "syncing")
+            }
+        }
+        _ => respond(StatusCode::NOT_FOUND, ""),
+    }
+}
+
+/// Serves `/health` and `/ready` over HTTP.
+pub async fn serve(
+    listen_address: SocketAddr,
+    reached_tip: WatchReceiver<bool>,
+) -> anyhow::Result<()> {
+    let server = Server::try_bind(&listen_address)?.serve(make_service_fn(move |_| {
+        let reached_tip = reached_tip.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req| {
+                let res = handle(req, &reached_tip);
+                async move { Ok::<_, Infallible>(res) }
+            }))
+        }
+    }));
+    info!("Health server listening on {}", listen_address);
+
+    server.await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn readiness_follows_sync() {
+        let (tx, rx) = tokio::sync::watch::channel(false);
+        let get = |path| handle(Request::get(path).body(Body::empty()).unwrap(), &rx).status();
+
+        assert_eq!(get("/health"), StatusCode::OK);
+        assert_eq!(get("/ready"), StatusCode::SERVICE_UNAVAILABLE);
+
+        tx.send(true).unwrap();
+        assert_eq!(get("/ready"), StatusCode::OK);
+        assert_eq!(get("/"), StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,563 @@
+use super::{Problem, MAX_REPORTED_MISMATCHES};
+use crate::{
+    accessors::chain,
+    consensus::engine_factory,
+    execution::{analysis_cache::AnalysisCache, processor::ExecutionProcessor, tracer::NoopTracer},
+    kv::{
+        mdbx::*,
+        tables::{self, AccountChange, BitmapKey, StorageChange, StorageChangeKey},
+        traits::*,
+    },
+    models::*,
+    u256_to_h256, Buffer,
+};
+use anyhow::format_err;
+use croaring::Treemap;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    ops::RangeInclusive,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum HistoryMismatch {
+    /// Changeset entry without its block in the history index (`Missing`), or block in the
+    /// history index without a changeset entry (`Extra`).
+    AccountHistory {
+        problem: Problem,
+        block: BlockNumber,
+        address: Address,
+    },
+    StorageHistory {
+        problem: Problem,
+        block: BlockNumber,
+        address: Address,
+        location: H256,
+    },
+    /// Change made by re-executing the block compared to the changeset entry.
+    AccountChangeSet {
+        problem: Problem,
+        block: BlockNumber,
+        address: Address,
+    },
+    StorageChangeSet {
+        problem: Problem,
+        block: BlockNumber,
+        address: Address,
+        location: H256,
+    },
+    Execution {
+        block: BlockNumber,
+        error: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HistoryReport {
+    pub from: BlockNumber,
+    pub to: BlockNumber,
+    pub checked_blocks: u64,
+    pub account_changes: u64,
+    pub storage_changes: u64,
+    pub executed_blocks: u64,
+    /// First [`MAX_REPORTED_MISMATCHES`] mismatches found.
+    pub mismatches: Vec<HistoryMismatch>,
+    pub total_mismatches: u64,
+}
+
+impl HistoryReport {
+    fn push(&mut self, mismatch: HistoryMismatch) {
+        if self.mismatches.len() < MAX_REPORTED_MISMATCHES {
+            self.mismatches.push(mismatch);
+        }
+        self.total_mismatches += 1;
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.total_mismatches == 0
+    }
+}
+
+fn is_indexed<'tx, K, TK, T>(
+    cursor: &mut MdbxCursor<'tx, TK, T>,
+    key: K,
+    block: BlockNumber,
+) -> anyhow::Result<bool>
+where
+    TK: TransactionKind,
+    K: Copy + PartialEq,
+    BitmapKey<K>: TableDecode,
+    T: Table<Key = BitmapKey<K>, Value = Treemap, SeekKey = BitmapKey<K>>,
+{
+    // Chunks are keyed by their last block, so the first chunk at or after the block holds it.
+    Ok(matches!(
+        cursor.seek(BitmapKey {
+            inner: key,
+            block_number: block,
+        })?,
+        Some((BitmapKey { inner, .. }, bitmap)) if inner == key && bitmap.contains(*block)
+    ))
+}
+
+fn diff_changes<K: Ord + Copy, V: PartialEq>(
+    recorded: &BTreeMap<K, V>,
+    executed: &BTreeMap<K, V>,
+    mut f: impl FnMut(Problem, K),
+) {
+    for (key, value) in executed {
+        match recorded.get(key) {
+            None => f(Problem::Missing, *key),
+            Some(recorded) if recorded != value => f(Problem::Wrong, *key),
+            _ => {}
+        }
+    }
+    for key in recorded.keys() {
+        if !executed.contains_key(key) {
+            f(Problem::Extra, *key);
+        }
+    }
+}
+
+type ExecutedChanges = (
+    BTreeMap<Address, Option<Account>>,
+    BTreeMap<(Address, H256), U256>,
+);
+
+/// Re-executes the block on historical state, returning changes as they would be recorded.
+fn execute_block<K, E>(
+    txn: &MdbxTransaction<'_, K, E>,
+    chain_spec: &ChainSpec,
+    analysis_cache: &mut AnalysisCache,
+    block_number: BlockNumber,
+) -> anyhow::Result<ExecutedChanges>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    let header = chain::header::read(txn, block_number)?
+        .ok_or_else(|| format_err!("header not found for block #{block_number}"))?;
+    let block_body = chain::block_body::read_with_senders(txn, block_number)?
+        .ok_or_else(|| format_err!("body not found for block #{block_number}"))?;
+
+    let mut buffer = Buffer::new(txn, Some(BlockNumber(block_number.0 - 1)));
+    let block_execution_spec = chain_spec.collect_block_spec(block_number);
+    let mut engine = engine_factory(None, chain_spec.clone(), None, None)?;
+
+    ExecutionProcessor::new(
+        &mut buffer,
+        &mut NoopTracer,
+        analysis_cache,
+        &mut *engine,
+        &header,
+        &block_body,
+        &block_execution_spec,
+    )
+    .execute_block_no_post_validation()?;
+
+    let accounts = buffer
+        .account_changes()
+        .get(&block_number)
+        .cloned()
+        .unwrap_or_default();
+    let storage = buffer
+        .storage_changes()
+        .get(&block_number)
+        .into_iter()
+        .flatten()
+        .flat_map(|(&address, slots)| {
+            slots
+                .iter()
+                .map(move |(&location, &value)| ((address, u256_to_h256(location)), value))
+        })
+        .collect();
+
+    Ok((accounts, storage))
+}
+
+/// Checks changesets of `blocks` against history indexes in both directions and, if `execute`
+/// is set, against changes made by re-executing the blocks. Only every `sample_every`-th block
+/// of the range is checked. Blocks must be covered by execution and both history index stages.
+///
+/// Re-execution reads historical state through the same history indexes, and storage of
+/// self-destructed accounts from current state, so a mismatch found by it is not necessarily
+/// in the block it is reported for.
+pub fn verify_history<K, E>(
+    txn: &MdbxTransaction<'_, K, E>,
+    blocks: RangeInclusive<BlockNumber>,
+    sample_every: u64,
+    execute: bool,
+) -> anyhow::Result<HistoryReport>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    let (from, to) = (*blocks.start(), *blocks.end());
+    let sample_every = sample_every.max(1);
+    let sampled =
+        |block: u64| (from.0..=to.0).contains(&block) && (block - from.0) % sample_every == 0;
+
+    let chain_spec = chain::chain_config::read(txn)?
+        .ok_or_else(|| format_err!("chain specification not found"))?;
+    let mut analysis_cache = AnalysisCache::default();
+
+    let mut report = HistoryReport {
+        from,
+        to,
+        checked_blocks: 0,
+        account_changes: 0,
+        storage_changes: 0,
+        executed_blocks: 0,
+        mismatches: vec![],
+        total_mismatches: 0,
+    };
+
+    let mut account_changesets = txn.cursor(tables::AccountChangeSet)?;
+    let mut storage_changesets = txn.cursor(tables::StorageChangeSet)?;
+    let mut account_history = txn.cursor(tables::AccountHistory)?;
+    let mut storage_history = txn.cursor(tables::StorageHistory)?;
+
+    let mut last_message = Instant::now();
+    for block in (from.0..=to.0)
+        .step_by(sample_every as usize)
+        .map(BlockNumber)
+    {
+        let mut account_changes = BTreeMap::new();
+        let mut entry = account_changesets.seek_exact(block)?;
+        while let Some((_, AccountChange { address, account })) = entry {
+            account_changes.insert(address, account);
+            entry = account_changesets.next_dup()?;
+        }
+
+        let mut storage_changes = BTreeMap::new();
+        let mut entry = storage_changesets.seek(block)?;
+        while let Some((
+            StorageChangeKey {
+                block_number,
+                address,
+            },
+            StorageChange { location, value },
+        )) = entry
+        {
+            if block_number != block {
+                break;
+            }
+            storage_changes.insert((address, location), value);
+            entry = storage_changesets.next()?;
+        }
+
+        for &address in account_changes.keys() {
+            if !is_indexed(&mut account_history, address, block)? {
+                report.push(HistoryMismatch::AccountHistory {
+                    problem: Problem::Missing,
+                    block,
+                    address,
+                });
+            }
+        }
+        for &(address, location) in storage_changes.keys() {
+            if !is_indexed(&mut storage_history, (address, location), block)? {
+                report.push(HistoryMismatch::StorageHistory {
+                    problem: Problem::Missing,
+                    block,
+                    address,
+                    location,
+                });
+            }
+        }
+
+        if execute && block > 0 {
+            match execute_block(txn, &chain_spec, &mut analysis_cache, block) {
+                Ok((executed_accounts, executed_storage)) => {
+                    diff_changes(&account_changes, &executed_accounts, |problem, address| {
+                        report.push(HistoryMismatch::AccountChangeSet {
+                            problem,
+                            block,
+                            address,
+                        })
+                    });
+                    diff_changes(
+                        &storage_changes,
+                        &executed_storage,
+                        |problem, (address, location)| {
+                            report.push(HistoryMismatch::StorageChangeSet {
+                                problem,
+                                block,
+                                address,
+                                location,
+                            })
+                        },
+                    );
+                }
+                Err(e) => report.push(HistoryMismatch::Execution {
+                    block,
+                    error: e.to_string(),
+                }),
+            }
+            report.executed_blocks += 1;
+        }
+
+        report.checked_blocks += 1;
+        report.account_changes += account_changes.len() as u64;
+        report.storage_changes += storage_changes.len() as u64;
+
+        if last_message.elapsed() > Duration::from_secs(30) {
+            info!("Checked changesets of block #{block}");
+            last_message = Instant::now();
+        }
+    }
+
+    info!("Checking account history index");
+    for item in txn.cursor(tables::AccountHistory)?.walk(None) {
+        let (BitmapKey { inner: address, .. }, bitmap) = item?;
+        for block in bitmap
+            .iter()
+            .filter(|&block| sampled(block))
+            .map(BlockNumber)
+        {
+            if account_changesets.find_account(block, address)?.is_none() {
+                report.push(HistoryMismatch::AccountHistory {
+                    problem: Problem::Extra,
+                    block,
+                    address,
+                });
+            }
+        }
+    }
+
+    info!("Checking storage history index");
+    for item in txn.cursor(tables::StorageHistory)?.walk(None) {
+        let (
+            BitmapKey {
+                inner: (address, location),
+                ..
+            },
+            bitmap,
+        ) = item?;
+        for block in bitmap
+            .iter()
+            .filter(|&block| sampled(block))
+            .map(BlockNumber)
+        {
+            let found = storage_changesets
+                .seek_both_range(
+                    StorageChangeKey {
+                        block_number: block,
+                        address,
+                    },
+                    location,
+                )?
+                .map_or(false, |change| change.location == location);
+            if !found {
+                report.push(HistoryMismatch::StorageHistory {
+                    problem: Problem::Extra,
+                    block,
+                    address,
+                    location,
+                });
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_chaindata;
+
+    #[test]
+    fn history_index_mismatches() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        txn.set(tables::Config, (), crate::res::chainspec::MAINNET.clone())
+            .unwrap();
+
+        let (a, b, c) = (
+            Address::repeat_byte(0xa),
+            Address::repeat_byte(0xb),
+            Address::repeat_byte(0xc),
+        );
+        let location = H256::repeat_byte(0x1);
+
+        let mut changes = txn.cursor(tables::AccountChangeSet).unwrap();
+        for (block, address) in [(1, a), (2, a), (2, b)] {
+            changes
+                .append_dup(
+                    BlockNumber(block),
+                    AccountChange {
+                        address,
+                        account: None,
+                    },
+                )
+                .unwrap();
+        }
+        txn.cursor(tables::StorageChangeSet)
+            .unwrap()
+            .append_dup(
+                StorageChangeKey {
+                    block_number: BlockNumber(1),
+                    address: a,
+                },
+                StorageChange {
+                    location,
+                    value: 1.into(),
+                },
+            )
+            .unwrap();
+
+        // Account b is not indexed at block 2, account c and the storage slot at block 3 have no
+        // changes.
+        for (address, blocks) in [(a, vec![1_u64, 2]), (c, vec![3])] {
+            txn.set(
+                tables::AccountHistory,
+                BitmapKey {
+                    inner: address,
+                    block_number: BlockNumber(u64::MAX),
+                },
+                blocks.into_iter().collect(),
+            )
+            .unwrap();
+        }
+        txn.set(
+            tables::StorageHistory,
+            BitmapKey {
+                inner: (a, location),
+                block_number: BlockNumber(u64::MAX),
+            },
+            [1_u64, 3].into_iter().collect(),
+        )
+        .unwrap();
+
+        let report = verify_history(&txn, BlockNumber(0)..=BlockNumber(3), 1, false).unwrap();
+        assert_eq!(report.checked_blocks, 4);
+        assert_eq!(report.account_changes, 3);
+        assert_eq!(report.storage_changes, 1);
+        assert_eq!(
+            report.mismatches,
+            vec![
+                HistoryMismatch::AccountHistory {
+                    problem: Problem::Missing,
+                    block: BlockNumber(2),
+                    address: b,
+                },
+                HistoryMismatch::AccountHistory {
+                    problem: Problem::Extra,
+                    block: BlockNumber(3),
+                    address: c,
+                },
+                HistoryMismatch::StorageHistory {
+                    problem: Problem::Extra,
+                    block: BlockNumber(3),
+                    address: a,
+                    location,
+                },
+            ]
+        );
+
+        let report = verify_history(&txn, BlockNumber(0)..=BlockNumber(3), 2, false).unwrap();
+        assert_eq!(report.checked_blocks, 2);
+        assert_eq!(
+            report.mismatches,
+            vec![HistoryMismatch::AccountHistory {
+                problem: Problem::Missing,
+                block: BlockNumber(2),
+                address: b,
+            }]
+        );
+    }
+
+    #[test]
+    fn execution_mismatches() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        crate::genesis::initialize_genesis(
+            &txn,
+            &tempfile::tempdir().unwrap(),
+            false,
+            Some(crate::res::chainspec::MAINNET.clone()),
+        )
+        .unwrap();
+
+        let (beneficiary, other) = (Address::repeat_byte(0xb), Address::repeat_byte(0xc));
+
+        // Empty block paying its reward to an account that does not exist before it.
+        let genesis = chain::header::read(&txn, 0).unwrap().unwrap();
+        txn.set(
+            tables::Header,
+            BlockNumber(1),
+            BlockHeader {
+                parent_hash: genesis.hash(),
+                beneficiary,
+                number: BlockNumber(1),
+                gas_limit: genesis.gas_limit,
+                timestamp: genesis.timestamp + 15,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        chain::storage_body::write(
+            &txn,
+            1,
+            &BodyForStorage {
+                base_tx_id: TxIndex(0),
+                tx_amount: 0,
+                ommers: Default::default(),
+            },
+        )
+        .unwrap();
+
+        let mut changes = txn.cursor(tables::AccountChangeSet).unwrap();
+        let index = |address| {
+            txn.set(
+                tables::AccountHistory,
+                BitmapKey {
+                    inner: address,
+                    block_number: BlockNumber(u64::MAX),
+                },
+                [1_u64].into_iter().collect(),
+            )
+            .unwrap()
+        };
+        changes
+            .append_dup(
+                BlockNumber(1),
+                AccountChange {
+                    address: beneficiary,
+                    account: None,
+                },
+            )
+            .unwrap();
+        index(beneficiary);
+
+        let report = verify_history(&txn, BlockNumber(1)..=BlockNumber(1), 1, true).unwrap();
+        assert_eq!(report.executed_blocks, 1);
+        assert!(report.is_ok(), "{:?}", report.mismatches);
+
+        // Changeset entry that is consistent with the history index, but was not made by the block.
+        changes
+            .append_dup(
+                BlockNumber(1),
+                AccountChange {
+                    address: other,
+                    account: None,
+                },
+            )
+            .unwrap();
+        index(other);
+
+        let report = verify_history(&txn, BlockNumber(1)..=BlockNumber(1), 1, true).unwrap();
+        assert_eq!(
+            report.mismatches,
+            vec![HistoryMismatch::AccountChangeSet {
+                problem: Problem::Extra,
+                block: BlockNumber(1),
+                address: other,
+            }]
+        );
+    }
+}
@@ -0,0 +1,112 @@
+//! Offline consistency checks of derived tables against the data they are derived from.
+
+mod history;
+mod state;
+
+pub use self::{history::*, state::*};
+
+use serde::Serialize;
+use std::{cmp::Ordering, fmt::Display};
+
+/// Maximum number of mismatches kept in a report, the rest are only counted.
+pub const MAX_REPORTED_MISMATCHES: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Problem {
+    /// Entry is expected, but not in database.
+    Missing,
+    /// Entry is in database, but not expected.
+    Extra,
+    /// Entry is in database with a different value.
+    Wrong,
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Missing => "missing",
+            Self::Extra => "extra",
+            Self::Wrong => "wrong",
+        })
+    }
+}
+
+/// Walks two iterators of entries sorted by key side by side, calling `f` with every key that
+/// is not in both of them or differs in value.
+fn diff_sorted(
+    mut existing: impl Iterator<Item = anyhow::Result<(Vec<u8>, Vec<u8>)>>,
+    mut expected: impl Iterator<Item = anyhow::Result<(Vec<u8>, Vec<u8>)>>,
+    mut f: impl FnMut(Problem, Vec<u8>),
+) -> anyhow::Result<()> {
+    let mut e = existing.next().transpose()?;
+    let mut x = expected.next().transpose()?;
+    loop {
+        match (e.take(), x.take()) {
+            (None, None) => return Ok(()),
+            (Some((key, _)), None) => {
+                f(Problem::Extra, key);
+                e = existing.next().transpose()?;
+            }
+            (None, Some((key, _))) => {
+                f(Problem::Missing, key);
+                x = expected.next().transpose()?;
+            }
+            (Some((existing_key, existing_value)), Some((expected_key, expected_value))) => {
+                match existing_key.cmp(&expected_key) {
+                    Ordering::Less => {
+                        f(Problem::Extra, existing_key);
+                        e = existing.next().transpose()?;
+                        x = Some((expected_key, expected_value));
+                    }
+                    Ordering::Greater => {
+                        f(Problem::Missing, expected_key);
+                        e = Some((existing_key, existing_value));
+                        x = expected.next().transpose()?;
+                    }
+                    Ordering::Equal => {
+                        if existing_value != expected_value {
+                            f(Problem::Wrong, existing_key);
+                        }
+                        e = existing.next().transpose()?;
+                        x = expected.next().transpose()?;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        let entries = |v: &[(u8, u8)]| {
+            v.iter()
+                .map(|&(k, v)| Ok((vec![k], vec![v])))
+                .collect::<Vec<_>>()
+                .into_iter()
+        };
+
+        let mut out = vec![];
+        diff_sorted(
+            entries(&[(1, 1), (2, 2), (4, 4), (6, 6)]),
+            entries(&[(0, 0), (2, 2), (4, 5), (5, 5)]),
+            |problem, key| out.push((problem, key[0])),
+        )
+        .unwrap();
+
+        assert_eq!(
+            out,
+            vec![
+                (Problem::Missing, 0),
+                (Problem::Extra, 1),
+                (Problem::Wrong, 4),
+                (Problem::Missing, 5),
+                (Problem::Extra, 6),
+            ]
+        );
+    }
+}
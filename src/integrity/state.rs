@@ -0,0 +1,452 @@
+use super::{diff_sorted, Problem, MAX_REPORTED_MISMATCHES};
+use crate::{
+    accessors::chain,
+    crypto::keccak256,
+    etl::collector::{Collector, TableCollector, OPTIMAL_BUFFER_CAPACITY},
+    kv::{mdbx::*, tables},
+    models::*,
+    stages::{
+        promote_clean_accounts, promote_clean_storage, EXECUTION, HASH_STATE, INTERMEDIATE_HASHES,
+    },
+    trie::{regenerate_intermediate_hashes, DbTrieLoader, PrefixSet},
+};
+use anyhow::format_err;
+use std::fmt::Display;
+use tempfile::TempDir;
+use tracing::*;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateMismatch {
+    HashedAccount {
+        problem: Problem,
+        /// Unknown for extra entries.
+        address: Option<Address>,
+        hashed_address: H256,
+    },
+    HashedStorage {
+        problem: Problem,
+        /// Unknown for extra entries.
+        address: Option<Address>,
+        /// Unknown for extra entries.
+        location: Option<H256>,
+        hashed_address: H256,
+        hashed_location: H256,
+    },
+    TrieAccount {
+        problem: Problem,
+        /// Unpacked nibbles of the node path.
+        prefix: Vec<u8>,
+    },
+    TrieStorage {
+        problem: Problem,
+        hashed_address: H256,
+        /// Unpacked nibbles of the node path in the account storage trie.
+        prefix: Vec<u8>,
+    },
+    StateRoot {
+        block: BlockNumber,
+        expected: H256,
+        got: H256,
+    },
+}
+
+struct Nibbles<'a>(&'a [u8]);
+
+impl Display for Nibbles<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("0x")?;
+        for nibble in self.0 {
+            write!(f, "{nibble:x}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for StateMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::HashedAccount {
+                problem,
+                address: Some(address),
+                ..
+            } => write!(f, "{problem} hashed account of {address:?}"),
+            Self::HashedAccount {
+                problem,
+                address: None,
+                hashed_address,
+            } => write!(f, "{problem} hashed account {hashed_address:?}"),
+            Self::HashedStorage {
+                problem,
+                address: Some(address),
+                location: Some(location),
+                ..
+            } => write!(f, "{problem} hashed storage of {address:?} at {location:?}"),
+            Self::HashedStorage {
+                problem,
+                hashed_address,
+                hashed_location,
+                ..
+            } => write!(
+                f,
+                "{problem} hashed storage {hashed_address:?} at {hashed_location:?}"
+            ),
+            Self::TrieAccount { problem, prefix } => {
+                write!(f, "{problem} account trie node {}", Nibbles(prefix))
+            }
+            Self::TrieStorage {
+                problem,
+                hashed_address,
+                prefix,
+            } => write!(
+                f,
+                "{problem} storage trie node {} of {hashed_address:?}",
+                Nibbles(prefix)
+            ),
+            Self::StateRoot {
+                block,
+                expected,
+                got,
+            } => write!(
+                f,
+                "state root of block #{block} is {expected:?}, computed {got:?}"
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct StateReport {
+    /// Block the plain state is at.
+    pub block: BlockNumber,
+    pub accounts: u64,
+    pub storage_slots: u64,
+    /// State root computed from hashed state.
+    pub state_root: H256,
+    /// First [`MAX_REPORTED_MISMATCHES`] mismatches found.
+    pub mismatches: Vec<StateMismatch>,
+    pub total_mismatches: u64,
+}
+
+impl StateReport {
+    fn push(&mut self, mismatch: StateMismatch) {
+        if self.mismatches.len() < MAX_REPORTED_MISMATCHES {
+            self.mismatches.push(mismatch);
+        }
+        self.total_mismatches += 1;
+    }
+
+    pub fn is_ok(&self) -> bool {
+        self.total_mismatches == 0
+    }
+}
+
+fn verify_hashed_accounts<E>(
+    txn: &MdbxTransaction<'_, RW, E>,
+    etl_dir: &TempDir,
+    report: &mut StateReport,
+) -> anyhow::Result<()>
+where
+    E: EnvironmentKind,
+{
+    let mut expected = Collector::<Vec<u8>, Vec<u8>>::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    let mut hashed = txn.cursor(tables::HashedAccount)?;
+    for item in txn.cursor(tables::Account)?.walk(None) {
+        let (address, account) = item?;
+        let hashed_address = keccak256(address);
+
+        let problem = match hashed.seek_exact(hashed_address)? {
+            None => Some(Problem::Missing),
+            Some((_, hashed_account)) if hashed_account != account => Some(Problem::Wrong),
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            report.push(StateMismatch::HashedAccount {
+                problem,
+                address: Some(address),
+                hashed_address,
+            });
+        }
+        expected.push(hashed_address.as_bytes().to_vec(), vec![]);
+
+        report.accounts += 1;
+        if report.accounts % 5_000_000 == 0 {
+            info!("Verified {} accounts", report.accounts);
+        }
+    }
+
+    diff_sorted(
+        txn.cursor(tables::HashedAccount)?.walk(None).map(|item| {
+            item.map(|(hashed_address, _)| (hashed_address.as_bytes().to_vec(), vec![]))
+        }),
+        expected.iter(),
+        |problem, key| {
+            // Missing and wrong entries are already reported along with their address.
+            if problem == Problem::Extra {
+                report.push(StateMismatch::HashedAccount {
+                    problem,
+                    address: None,
+                    hashed_address: H256::from_slice(&key),
+                });
+            }
+        },
+    )
+}
+
+fn verify_hashed_storage<E>(
+    txn: &MdbxTransaction<'_, RW, E>,
+    etl_dir: &TempDir,
+    report: &mut StateReport,
+) -> anyhow::Result<()>
+where
+    E: EnvironmentKind,
+{
+    let mut expected = Collector::<Vec<u8>, Vec<u8>>::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    let mut hashed = txn.cursor(tables::HashedStorage)?;
+    for item in txn.cursor(tables::Storage)?.walk(None) {
+        let (address, (location, value)) = item?;
+        let hashed_address = keccak256(address);
+        let hashed_location = keccak256(location);
+
+        let problem = match hashed.seek_both_range(hashed_address, hashed_location)? {
+            Some((l, v)) if l == hashed_location => (v != value).then_some(Problem::Wrong),
+            _ => Some(Problem::Missing),
+        };
+        if let Some(problem) = problem {
+            report.push(StateMismatch::HashedStorage {
+                problem,
+                address: Some(address),
+                location: Some(location),
+                hashed_address,
+                hashed_location,
+            });
+        }
+        expected.push(
+            [hashed_address.as_bytes(), hashed_location.as_bytes()].concat(),
+            vec![],
+        );
+
+        report.storage_slots += 1;
+        if report.storage_slots % 5_000_000 == 0 {
+            info!("Verified {} storage slots", report.storage_slots);
+        }
+    }
+
+    diff_sorted(
+        txn.cursor(tables::HashedStorage)?.walk(None).map(|item| {
+            item.map(|(hashed_address, (hashed_location, _))| {
+                (
+                    [hashed_address.as_bytes(), hashed_location.as_bytes()].concat(),
+                    vec![],
+                )
+            })
+        }),
+        expected.iter(),
+        |problem, key| {
+            if problem == Problem::Extra {
+                report.push(StateMismatch::HashedStorage {
+                    problem,
+                    address: None,
+                    location: None,
+                    hashed_address: H256::from_slice(&key[..KECCAK_LENGTH]),
+                    hashed_location: H256::from_slice(&key[KECCAK_LENGTH..]),
+                });
+            }
+        },
+    )
+}
+
+/// Verifies hashed state against plain state, then recomputes the state root and all trie
+/// nodes from hashed state from scratch and compares them to the header and the trie tables.
+///
+/// Recomputation clears trie tables, so it needs a write transaction. The transaction is
+/// consumed and aborted, nothing is written.
+pub fn verify_state<E>(
+    txn: MdbxTransaction<'_, RW, E>,
+    etl_dir: &TempDir,
+) -> anyhow::Result<StateReport>
+where
+    E: EnvironmentKind,
+{
+    let block = EXECUTION.get_progress(&txn)?.unwrap_or_default();
+    for stage in [HASH_STATE, INTERMEDIATE_HASHES] {
+        let progress = stage.get_progress(&txn)?.unwrap_or_default();
+        if progress != block {
+            warn!("{stage} is at block #{progress}, while plain state is at #{block}");
+        }
+    }
+    let header = chain::header::read(&txn, block)?
+        .ok_or_else(|| format_err!("no header for block #{block}"))?;
+
+    let mut report = StateReport {
+        block,
+        accounts: 0,
+        storage_slots: 0,
+        state_root: H256::zero(),
+        mismatches: vec![],
+        total_mismatches: 0,
+    };
+
+    info!("Verifying hashed accounts");
+    verify_hashed_accounts(&txn, etl_dir, &mut report)?;
+    info!("Verifying hashed storage");
+    verify_hashed_storage(&txn, etl_dir, &mut report)?;
+
+    let mut existing_accounts =
+        Collector::<Vec<u8>, Vec<u8>>::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    for item in txn.cursor(tables::TrieAccount)?.walk(None) {
+        let (key, node) = item?;
+        existing_accounts.push(key, node);
+    }
+    let mut existing_storage = Collector::<Vec<u8>, Vec<u8>>::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    for item in txn.cursor(tables::TrieStorage)?.walk(None) {
+        let (key, node) = item?;
+        existing_storage.push(key, node);
+    }
+
+    info!("Recomputing state root");
+    txn.clear_table(tables::TrieAccount)?;
+    txn.clear_table(tables::TrieStorage)?;
+    let mut account_collector = TableCollector::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    let mut storage_collector = TableCollector::new(etl_dir, OPTIMAL_BUFFER_CAPACITY);
+    report.state_root = DbTrieLoader::new(&txn, &mut account_collector, &mut storage_collector)
+        .calculate_root(&mut PrefixSet::new(), &mut PrefixSet::new())?;
+
+    if report.state_root != header.state_root {
+        report.push(StateMismatch::StateRoot {
+            block,
+            expected: header.state_root,
+            got: report.state_root,
+        });
+    }
+
+    info!("Verifying trie nodes");
+    diff_sorted(
+        existing_accounts.iter(),
+        account_collector.iter(),
+        |problem, prefix| report.push(StateMismatch::TrieAccount { problem, prefix }),
+    )?;
+    diff_sorted(
+        existing_storage.iter(),
+        storage_collector.iter(),
+        |problem, key| {
+            report.push(StateMismatch::TrieStorage {
+                problem,
+                hashed_address: H256::from_slice(&key[..KECCAK_LENGTH]),
+                prefix: key[KECCAK_LENGTH..].to_vec(),
+            })
+        },
+    )?;
+
+    Ok(report)
+}
+
+/// Rebuilds hashed state from plain state and regenerates trie tables, checking the result
+/// against the state root of the header. Returns the block state is repaired at.
+pub fn repair_state<E>(
+    txn: &MdbxTransaction<'_, RW, E>,
+    etl_dir: &TempDir,
+) -> anyhow::Result<BlockNumber>
+where
+    E: EnvironmentKind,
+{
+    let block = EXECUTION.get_progress(txn)?.unwrap_or_default();
+    let header = chain::header::read(txn, block)?
+        .ok_or_else(|| format_err!("no header for block #{block}"))?;
+
+    info!("Rebuilding hashed state at block #{block}");
+    promote_clean_accounts(txn, etl_dir)?;
+    promote_clean_storage(txn, etl_dir)?;
+
+    info!("Regenerating trie");
+    regenerate_intermediate_hashes(txn, etl_dir, Some(header.state_root))?;
+
+    HASH_STATE.save_progress(txn, block)?;
+    INTERMEDIATE_HASHES.save_progress(txn, block)?;
+
+    Ok(block)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_chaindata;
+
+    #[test]
+    fn verify_and_repair() {
+        let db = new_mem_chaindata().unwrap();
+        let etl_dir = TempDir::new().unwrap();
+
+        let txn = db.begin_mutable().unwrap();
+        crate::genesis::initialize_genesis(
+            &txn,
+            &etl_dir,
+            false,
+            Some(crate::res::chainspec::MAINNET.clone()),
+        )
+        .unwrap();
+        txn.commit().unwrap();
+
+        let report = verify_state(db.begin_mutable().unwrap(), &etl_dir).unwrap();
+        assert!(report.is_ok(), "{:?}", report.mismatches);
+        assert_eq!(report.accounts, 8893);
+        let state_root = report.state_root;
+
+        // Verification must leave trie tables in place.
+        let txn = db.begin_mutable().unwrap();
+        assert!(txn
+            .cursor(tables::TrieAccount)
+            .unwrap()
+            .first()
+            .unwrap()
+            .is_some());
+
+        let (address, _) = txn
+            .cursor(tables::Account)
+            .unwrap()
+            .first()
+            .unwrap()
+            .unwrap();
+        txn.del(tables::HashedAccount, keccak256(address), None)
+            .unwrap();
+        let extra = (H256::repeat_byte(0xaa), (H256::repeat_byte(0xbb), 1.into()));
+        txn.set(tables::HashedStorage, extra.0, extra.1).unwrap();
+        txn.set(tables::TrieAccount, vec![0xf, 0xf, 0xf], vec![0; 6])
+            .unwrap();
+        txn.commit().unwrap();
+
+        let report = verify_state(db.begin_mutable().unwrap(), &etl_dir).unwrap();
+        for mismatch in [
+            StateMismatch::HashedAccount {
+                problem: Problem::Missing,
+                address: Some(address),
+                hashed_address: keccak256(address),
+            },
+            StateMismatch::HashedStorage {
+                problem: Problem::Extra,
+                address: None,
+                location: None,
+                hashed_address: extra.0,
+                hashed_location: extra.1 .0,
+            },
+            StateMismatch::TrieAccount {
+                problem: Problem::Extra,
+                prefix: vec![0xf, 0xf, 0xf],
+            },
+            StateMismatch::StateRoot {
+                block: BlockNumber(0),
+                expected: state_root,
+                got: report.state_root,
+            },
+        ] {
+            assert!(report.mismatches.contains(&mismatch), "{mismatch}");
+        }
+
+        let txn = db.begin_mutable().unwrap();
+        repair_state(&txn, &etl_dir).unwrap();
+        txn.commit().unwrap();
+
+        let report = verify_state(db.begin_mutable().unwrap(), &etl_dir).unwrap();
+        assert!(report.is_ok(), "{:?}", report.mismatches);
+        assert_eq!(report.state_root, state_root);
+    }
+}
@@ -0,0 +1,172 @@
+//! Zstd compression of table values.
+//!
+//! Values of compressed tables are prefixed with a tag byte telling how the rest is stored: raw,
+//! as a zstd frame, or as a zstd frame made with the dictionary embedded for the table.
+//! Dictionaries live in `src/kv/dictionaries` and are trained by `hana-toolbox
+//! train-dictionaries`. They are part of the database format: data compressed with one
+//! dictionary cannot be read with another, so the database records the ID of the dictionary of
+//! each table and refuses to open with different ones. Replacing a dictionary file requires a
+//! database version bump and a migration recompressing the table.
+//!
+//! Dictionary files are empty for now, so values are plain zstd frames. Dictionaries are to be
+//! trained on mainnet data and shipped along with such a migration.
+
+use anyhow::bail;
+use std::{borrow::Cow, fmt::Debug, io::Read};
+use zstd::dict::{DecoderDictionary, EncoderDictionary};
+
+/// Zstd compression level of table values.
+pub const LEVEL: i32 = 3;
+
+/// Values shorter than this are stored raw: zstd frame overhead eats any gain.
+pub const MIN_COMPRESSED_LEN: usize = 32;
+
+const RAW: u8 = 0;
+const ZSTD: u8 = 1;
+const ZSTD_DICTIONARY: u8 = 2;
+
+const DICTIONARY_MAGIC: [u8; 4] = 0xEC30A437_u32.to_le_bytes();
+
+struct Dictionary {
+    id: u32,
+    encoder: EncoderDictionary<'static>,
+    decoder: DecoderDictionary<'static>,
+}
+
+/// ID from the header of a zstd dictionary, raw content dictionaries have none.
+fn dictionary_id(dictionary: &[u8]) -> u32 {
+    match dictionary {
+        [m0, m1, m2, m3, a, b, c, d, ..] if [*m0, *m1, *m2, *m3] == DICTIONARY_MAGIC => {
+            u32::from_le_bytes([*a, *b, *c, *d])
+        }
+        _ => 0,
+    }
+}
+
+/// Compression of values of a single table.
+pub struct Compression {
+    dictionary: Option<Dictionary>,
+}
+
+impl Debug for Compression {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Compression")
+            .field("dictionary", &self.dictionary.is_some())
+            .finish()
+    }
+}
+
+impl Compression {
+    /// Empty `dictionary` means compression without a dictionary.
+    pub fn new(dictionary: &[u8]) -> Self {
+        Self {
+            dictionary: (!dictionary.is_empty()).then(|| Dictionary {
+                id: dictionary_id(dictionary),
+                encoder: EncoderDictionary::copy(dictionary, LEVEL),
+                decoder: DecoderDictionary::copy(dictionary),
+            }),
+        }
+    }
+
+    /// ID of the dictionary, 0 if there is none.
+    pub fn dictionary_id(&self) -> u32 {
+        self.dictionary
+            .as_ref()
+            .map(|dictionary| dictionary.id)
+            .unwrap_or(0)
+    }
+
+    pub fn compress(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if data.len() >= MIN_COMPRESSED_LEN {
+            let (tag, compressed) = if let Some(dictionary) = &self.dictionary {
+                (
+                    ZSTD_DICTIONARY,
+                    zstd::bulk::Compressor::with_prepared_dictionary(&dictionary.encoder)?
+                        .compress(data)?,
+                )
+            } else {
+                (ZSTD, zstd::bulk::compress(data, LEVEL)?)
+            };
+
+            if compressed.len() < data.len() {
+                let mut out = Vec::with_capacity(1 + compressed.len());
+                out.push(tag);
+                out.extend_from_slice(&compressed);
+                return Ok(out);
+            }
+        }
+
+        let mut out = Vec::with_capacity(1 + data.len());
+        out.push(RAW);
+        out.extend_from_slice(data);
+        Ok(out)
+    }
+
+    pub fn decompress<'a>(&self, data: &'a [u8]) -> anyhow::Result<Cow<'a, [u8]>> {
+        let (tag, payload) = match data.split_first() {
+            Some(v) => v,
+            None => bail!("empty compressed value"),
+        };
+
+        Ok(match *tag {
+            RAW => Cow::Borrowed(payload),
+            ZSTD => Cow::Owned(zstd::stream::decode_all(payload)?),
+            ZSTD_DICTIONARY => {
+                let dictionary = match &self.dictionary {
+                    Some(dictionary) => dictionary,
+                    None => bail!("value compressed with a dictionary, but table has none"),
+                };
+                let mut out = Vec::new();
+                zstd::stream::read::Decoder::with_prepared_dictionary(
+                    payload,
+                    &dictionary.decoder,
+                )?
+                .read_to_end(&mut out)?;
+                Cow::Owned(out)
+            }
+            other => bail!("unknown compression tag {other}"),
+        })
+    }
+}
+
+/// Trains a dictionary of at most `max_size` bytes on encoded values of a table.
+pub fn train_dictionary(samples: &[Vec<u8>], max_size: usize) -> anyhow::Result<Vec<u8>> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let samples = (0..1000_u32)
+            .map(|i| {
+                let mut v = b"calldata of a transfer(address,uint256) call ".to_vec();
+                v.extend_from_slice(&i.to_be_bytes());
+                v.extend_from_slice(&[0; 28]);
+                v
+            })
+            .collect::<Vec<_>>();
+        let dictionary = train_dictionary(&samples, 4096).unwrap();
+
+        for compression in [Compression::new(&[]), Compression::new(&dictionary)] {
+            for value in [&b""[..], &b"short"[..], &samples[42][..], &[0xff; 1024][..]] {
+                let compressed = compression.compress(value).unwrap();
+                assert!(compressed.len() <= value.len() + 1);
+                assert_eq!(&*compression.decompress(&compressed).unwrap(), value);
+            }
+        }
+
+        assert_eq!(Compression::new(&[]).dictionary_id(), 0);
+        assert_eq!(
+            Compression::new(&dictionary).dictionary_id(),
+            u32::from_le_bytes(dictionary[4..8].try_into().unwrap())
+        );
+        assert_ne!(Compression::new(&dictionary).dictionary_id(), 0);
+
+        let compressed = Compression::new(&dictionary).compress(&samples[0]).unwrap();
+        assert_eq!(compressed[0], ZSTD_DICTIONARY);
+        assert!(Compression::new(&[]).decompress(&compressed).is_err());
+    }
+}
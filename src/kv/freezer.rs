@@ -0,0 +1,553 @@
+//! Append-only storage of finalized headers, bodies and transactions outside of MDBX.
+//!
+//! Data is split into segments of [`SEGMENT_SIZE`] blocks. Each segment consists of a pair of
+//! files per kind of data: `<kind>-<segment>.seg` holding snappy-compressed values in the
+//! encoding of their table, and `<kind>-<segment>.idx` holding the first key of the segment
+//! followed by offsets of all values in the `.seg` file and the offset of its end.
+
+use crate::{
+    kv::traits::{TableDecode, TableEncode},
+    models::*,
+};
+use anyhow::{bail, ensure, format_err};
+use parking_lot::RwLock;
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+};
+use tracing::*;
+
+/// Number of blocks in one segment.
+pub const SEGMENT_SIZE: u64 = 100_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrozenKind {
+    Headers,
+    Bodies,
+    Transactions,
+}
+
+impl FrozenKind {
+    const ALL: [Self; 3] = [Self::Headers, Self::Bodies, Self::Transactions];
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Headers => "headers",
+            Self::Bodies => "bodies",
+            Self::Transactions => "transactions",
+        }
+    }
+
+    fn paths(self, dir: &Path, segment: u64) -> (PathBuf, PathBuf) {
+        let stem = format!("{}-{segment:06}", self.name());
+        (
+            dir.join(format!("{stem}.seg")),
+            dir.join(format!("{stem}.idx")),
+        )
+    }
+}
+
+#[derive(Debug)]
+struct Segment {
+    first_key: u64,
+    len: u64,
+    data: File,
+    index: File,
+}
+
+impl Segment {
+    fn open(data_path: &Path, index_path: &Path) -> anyhow::Result<Self> {
+        let index = File::open(index_path)?;
+        let index_len = index.metadata()?.len();
+        ensure!(
+            index_len >= 16 && index_len % 8 == 0,
+            "corrupted segment index {}",
+            index_path.display()
+        );
+
+        let mut first_key = [0; 8];
+        index.read_exact_at(&mut first_key, 0)?;
+
+        Ok(Self {
+            first_key: u64::from_le_bytes(first_key),
+            len: index_len / 8 - 2,
+            data: File::open(data_path)?,
+            index,
+        })
+    }
+
+    fn end_key(&self) -> u64 {
+        self.first_key + self.len
+    }
+
+    fn get<T: TableDecode>(&self, key: u64) -> anyhow::Result<T> {
+        let mut offsets = [0; 16];
+        self.index
+            .read_exact_at(&mut offsets, 8 * (1 + key - self.first_key))?;
+        let start = u64::from_le_bytes(offsets[..8].try_into().unwrap());
+        let end = u64::from_le_bytes(offsets[8..].try_into().unwrap());
+
+        let len = end
+            .checked_sub(start)
+            .ok_or_else(|| format_err!("corrupted segment index: offset {end} is below {start}"))?;
+
+        let mut compressed = vec![0; len as usize];
+        self.data.read_exact_at(&mut compressed, start)?;
+
+        T::decode(&snap::raw::Decoder::new().decompress_vec(&compressed)?)
+    }
+}
+
+fn write_segment<T: TableEncode>(
+    dir: &Path,
+    kind: FrozenKind,
+    segment: u64,
+    first_key: u64,
+    values: Vec<T>,
+) -> anyhow::Result<()> {
+    let (data_path, index_path) = kind.paths(dir, segment);
+    let tmp_data_path = data_path.with_extension("seg.tmp");
+    let tmp_index_path = index_path.with_extension("idx.tmp");
+
+    let open = |path: &Path| {
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+    };
+    let mut data = BufWriter::new(open(&tmp_data_path)?);
+    let mut index = BufWriter::new(open(&tmp_index_path)?);
+
+    let mut encoder = snap::raw::Encoder::new();
+    let mut offset = 0_u64;
+    index.write_all(&first_key.to_le_bytes())?;
+    for value in values {
+        let compressed = encoder.compress_vec(value.encode().as_ref())?;
+        index.write_all(&offset.to_le_bytes())?;
+        data.write_all(&compressed)?;
+        offset += compressed.len() as u64;
+    }
+    index.write_all(&offset.to_le_bytes())?;
+
+    for file in [data, index] {
+        file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+    }
+
+    // Segment is visible only once its index is in place.
+    std::fs::rename(tmp_data_path, data_path)?;
+    std::fs::rename(tmp_index_path, index_path)?;
+
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+struct Segments {
+    headers: Vec<Segment>,
+    bodies: Vec<Segment>,
+    transactions: Vec<Segment>,
+}
+
+impl Segments {
+    /// Opens segments written after the ones already open, stopping at the first incomplete one.
+    /// Returns the number of segments opened.
+    fn scan(&mut self, dir: &Path) -> anyhow::Result<u64> {
+        let mut count = 0;
+        'segments: for segment in self.headers.len() as u64.. {
+            let mut opened = Vec::with_capacity(FrozenKind::ALL.len());
+            for kind in FrozenKind::ALL {
+                let (data_path, index_path) = kind.paths(dir, segment);
+                if !index_path.exists() {
+                    // Interrupted or ongoing freeze, segment will be written again.
+                    break 'segments;
+                }
+                opened.push(Segment::open(&data_path, &index_path)?);
+            }
+
+            let mut opened = opened.into_iter();
+            let (headers, bodies, transactions) = (
+                opened.next().unwrap(),
+                opened.next().unwrap(),
+                opened.next().unwrap(),
+            );
+            ensure!(
+                headers.first_key == segment * SEGMENT_SIZE
+                    && headers.len == SEGMENT_SIZE
+                    && bodies.first_key == headers.first_key
+                    && bodies.len == headers.len,
+                "corrupted freezer segment {segment}"
+            );
+            if let Some(prev) = self.transactions.last() {
+                ensure!(
+                    transactions.first_key == prev.end_key(),
+                    "gap in frozen transactions before segment {segment}"
+                );
+            }
+
+            self.headers.push(headers);
+            self.bodies.push(bodies);
+            self.transactions.push(transactions);
+            count += 1;
+        }
+
+        Ok(count)
+    }
+}
+
+/// Static segment files of finalized blocks. Blocks are frozen in whole segments, starting from
+/// genesis, so that every block below [`Freezer::frozen_blocks`] is in the freezer.
+#[derive(Debug)]
+pub struct Freezer {
+    dir: PathBuf,
+    segments: RwLock<Segments>,
+}
+
+impl Freezer {
+    pub fn open(dir: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+
+        let mut segments = Segments::default();
+        segments.scan(&dir)?;
+
+        debug!(
+            "Opened freezer at {} with {} segments",
+            dir.display(),
+            segments.headers.len()
+        );
+
+        Ok(Self {
+            dir,
+            segments: RwLock::new(segments),
+        })
+    }
+
+    /// Picks up segments frozen by another process sharing the directory since this freezer was
+    /// opened, such as the node when this freezer belongs to a separate RPC daemon. Returns the
+    /// number of new segments.
+    pub fn refresh(&self) -> anyhow::Result<u64> {
+        let mut segments = self.segments.write();
+
+        // Segments dropped by the other process are reopened from scratch.
+        if let Some(last) = segments.headers.len().checked_sub(1) {
+            let (_, index_path) = FrozenKind::Headers.paths(&self.dir, last as u64);
+            if !index_path.exists() {
+                *segments = Segments::default();
+            }
+        }
+
+        let count = segments.scan(&self.dir)?;
+        if count > 0 {
+            debug!(
+                "Refreshed freezer at {}, now with {} segments",
+                self.dir.display(),
+                segments.headers.len()
+            );
+        }
+
+        Ok(count)
+    }
+
+    /// Number of blocks in the freezer, all blocks below this number are frozen.
+    pub fn frozen_blocks(&self) -> u64 {
+        self.segments.read().headers.len() as u64 * SEGMENT_SIZE
+    }
+
+    /// Number of transactions in the freezer, all transactions below this index are frozen.
+    pub fn frozen_transactions(&self) -> u64 {
+        self.segments
+            .read()
+            .transactions
+            .last()
+            .map(Segment::end_key)
+            .unwrap_or(0)
+    }
+
+    pub fn header(&self, number: BlockNumber) -> anyhow::Result<Option<BlockHeader>> {
+        self.segments
+            .read()
+            .headers
+            .get((number.0 / SEGMENT_SIZE) as usize)
+            .map(|segment| segment.get(number.0))
+            .transpose()
+    }
+
+    pub fn body(&self, number: BlockNumber) -> anyhow::Result<Option<BodyForStorage>> {
+        self.segments
+            .read()
+            .bodies
+            .get((number.0 / SEGMENT_SIZE) as usize)
+            .map(|segment| segment.get(number.0))
+            .transpose()
+    }
+
+    /// Reads `amount` frozen transactions starting from `base_tx_id`.
+    pub fn transactions(
+        &self,
+        base_tx_id: TxIndex,
+        amount: usize,
+    ) -> anyhow::Result<Vec<MessageWithSignature>> {
+        let segments = self.segments.read();
+        let mut out = Vec::with_capacity(amount);
+        for id in *base_tx_id..*base_tx_id + amount as u64 {
+            let idx = segments
+                .transactions
+                .partition_point(|segment| segment.end_key() <= id);
+            let segment = segments
+                .transactions
+                .get(idx)
+                .ok_or_else(|| format_err!("transaction {id} is not frozen"))?;
+            out.push(segment.get(id)?);
+        }
+
+        Ok(out)
+    }
+
+    /// Appends the next segment. `bodies` and `headers` must hold all blocks of the segment and
+    /// `transactions` all transactions of these bodies.
+    pub fn freeze(
+        &self,
+        segment: u64,
+        headers: Vec<BlockHeader>,
+        bodies: Vec<BodyForStorage>,
+        transactions: Vec<MessageWithSignature>,
+    ) -> anyhow::Result<()> {
+        let mut guard = self.segments.write();
+        let segments = &mut *guard;
+
+        let next_segment = segments.headers.len() as u64;
+        if segment != next_segment {
+            bail!("cannot freeze segment {segment}, next segment is {next_segment}");
+        }
+        ensure!(
+            headers.len() as u64 == SEGMENT_SIZE && bodies.len() as u64 == SEGMENT_SIZE,
+            "segment {segment} is incomplete"
+        );
+
+        let first_tx = *bodies[0].base_tx_id;
+        let tx_amount = bodies.iter().map(|body| body.tx_amount).sum::<u64>();
+        ensure!(
+            tx_amount == transactions.len() as u64,
+            "segment {segment} has {tx_amount} transactions, got {}",
+            transactions.len()
+        );
+        let frozen_transactions = segments
+            .transactions
+            .last()
+            .map(Segment::end_key)
+            .unwrap_or(0);
+        ensure!(
+            first_tx == frozen_transactions,
+            "segment {segment} starts with transaction {first_tx}, expected {frozen_transactions}"
+        );
+
+        let first_block = segment * SEGMENT_SIZE;
+        write_segment(
+            &self.dir,
+            FrozenKind::Transactions,
+            segment,
+            first_tx,
+            transactions,
+        )?;
+        write_segment(&self.dir, FrozenKind::Bodies, segment, first_block, bodies)?;
+        write_segment(
+            &self.dir,
+            FrozenKind::Headers,
+            segment,
+            first_block,
+            headers,
+        )?;
+
+        for (kind, segments) in [
+            (FrozenKind::Headers, &mut segments.headers),
+            (FrozenKind::Bodies, &mut segments.bodies),
+            (FrozenKind::Transactions, &mut segments.transactions),
+        ] {
+            let (data_path, index_path) = kind.paths(&self.dir, segment);
+            segments.push(Segment::open(&data_path, &index_path)?);
+        }
+
+        Ok(())
+    }
+
+    /// Drops segments holding blocks at or above `blocks`, keeping the freezer in line with a
+    /// database that never committed their pruning.
+    pub fn truncate(&self, blocks: u64) -> anyhow::Result<()> {
+        let mut guard = self.segments.write();
+        let segments = &mut *guard;
+
+        let keep = blocks / SEGMENT_SIZE;
+        let count = segments.headers.len() as u64;
+        if keep >= count {
+            return Ok(());
+        }
+
+        segments.headers.truncate(keep as usize);
+        segments.bodies.truncate(keep as usize);
+        segments.transactions.truncate(keep as usize);
+
+        for segment in (keep..count).rev() {
+            // Index first, its absence marks the segment incomplete.
+            for kind in FrozenKind::ALL {
+                let (_, index_path) = kind.paths(&self.dir, segment);
+                std::fs::remove_file(index_path)?;
+            }
+            for kind in FrozenKind::ALL {
+                let (data_path, _) = kind.paths(&self.dir, segment);
+                std::fs::remove_file(data_path)?;
+            }
+        }
+
+        warn!("Dropped uncommitted freezer segments {keep}..{count}");
+
+        Ok(())
+    }
+
+    /// Copies all segments into `dir`, hard-linking them when possible since they never change.
+    /// Returns the number of blocks copied.
+    pub fn copy_to(&self, dir: &Path) -> anyhow::Result<u64> {
+        let segments = self.segments.read();
+        std::fs::create_dir_all(dir)?;
+
+        let count = segments.headers.len() as u64;
+        for segment in 0..count {
+            for kind in FrozenKind::ALL {
+                let (data_path, index_path) = kind.paths(&self.dir, segment);
+                // Index last, its presence marks the segment complete.
+                for path in [data_path, index_path] {
+                    let to = dir.join(path.file_name().unwrap());
+                    if std::fs::hard_link(&path, &to).is_err() {
+                        std::fs::copy(&path, &to)?;
+                    }
+                }
+            }
+        }
+
+        Ok(count * SEGMENT_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn segment() -> (
+        Vec<BlockHeader>,
+        Vec<BodyForStorage>,
+        Vec<MessageWithSignature>,
+    ) {
+        let headers = (0..SEGMENT_SIZE)
+            .map(|number| BlockHeader {
+                number: BlockNumber(number),
+                ..BlockHeader::empty()
+            })
+            .collect::<Vec<_>>();
+        let bodies = (0..SEGMENT_SIZE)
+            .map(|number| BodyForStorage {
+                base_tx_id: TxIndex(number.saturating_sub(SEGMENT_SIZE - 2)),
+                tx_amount: u64::from(number >= SEGMENT_SIZE - 2),
+                ommers: Default::default(),
+            })
+            .collect::<Vec<_>>();
+        let transactions = (0..2)
+            .map(|nonce| MessageWithSignature {
+                message: Message::Legacy {
+                    chain_id: None,
+                    nonce,
+                    gas_price: 1.as_u256(),
+                    gas_limit: 21_000,
+                    action: TransactionAction::Create,
+                    value: 0.as_u256(),
+                    input: Bytes::new(),
+                },
+                signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                    .unwrap(),
+            })
+            .collect::<Vec<_>>();
+
+        (headers, bodies, transactions)
+    }
+
+    #[test]
+    fn freeze_and_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let freezer = Freezer::open(dir.path()).unwrap();
+        assert_eq!(freezer.frozen_blocks(), 0);
+        assert_eq!(freezer.header(BlockNumber(0)).unwrap(), None);
+
+        let (headers, bodies, transactions) = segment();
+
+        assert!(freezer
+            .freeze(1, headers.clone(), bodies.clone(), transactions.clone())
+            .is_err());
+        freezer
+            .freeze(0, headers.clone(), bodies.clone(), transactions.clone())
+            .unwrap();
+        drop(freezer);
+
+        let freezer = Freezer::open(dir.path()).unwrap();
+        assert_eq!(freezer.frozen_blocks(), SEGMENT_SIZE);
+        assert_eq!(freezer.frozen_transactions(), 2);
+        assert_eq!(
+            freezer.header(BlockNumber(12345)).unwrap().as_ref(),
+            Some(&headers[12345])
+        );
+        assert_eq!(
+            freezer
+                .body(BlockNumber(SEGMENT_SIZE - 1))
+                .unwrap()
+                .as_ref(),
+            Some(&bodies[SEGMENT_SIZE as usize - 1])
+        );
+        assert_eq!(freezer.body(BlockNumber(SEGMENT_SIZE)).unwrap(), None);
+        assert_eq!(freezer.transactions(TxIndex(0), 2).unwrap(), transactions);
+        assert!(freezer.transactions(TxIndex(1), 2).is_err());
+
+        freezer.truncate(SEGMENT_SIZE - 1).unwrap();
+        assert_eq!(freezer.frozen_blocks(), 0);
+        assert_eq!(freezer.header(BlockNumber(12345)).unwrap(), None);
+        drop(freezer);
+
+        // Segment can be frozen again after being dropped.
+        let freezer = Freezer::open(dir.path()).unwrap();
+        assert_eq!(freezer.frozen_blocks(), 0);
+        freezer
+            .freeze(0, headers.clone(), bodies, transactions)
+            .unwrap();
+        assert_eq!(
+            freezer.header(BlockNumber(12345)).unwrap().as_ref(),
+            Some(&headers[12345])
+        );
+    }
+
+    #[test]
+    fn refresh() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = Freezer::open(dir.path()).unwrap();
+        let reader = Freezer::open(dir.path()).unwrap();
+
+        let (headers, bodies, transactions) = segment();
+        writer
+            .freeze(0, headers.clone(), bodies, transactions.clone())
+            .unwrap();
+
+        assert_eq!(reader.frozen_blocks(), 0);
+        assert_eq!(reader.header(BlockNumber(12345)).unwrap(), None);
+
+        assert_eq!(reader.refresh().unwrap(), 1);
+        assert_eq!(reader.refresh().unwrap(), 0);
+        assert_eq!(reader.frozen_blocks(), SEGMENT_SIZE);
+        assert_eq!(
+            reader.header(BlockNumber(12345)).unwrap().as_ref(),
+            Some(&headers[12345])
+        );
+        assert_eq!(reader.transactions(TxIndex(0), 2).unwrap(), transactions);
+
+        writer.truncate(0).unwrap();
+        assert_eq!(reader.refresh().unwrap(), 0);
+        assert_eq!(reader.frozen_blocks(), 0);
+    }
+}
@@ -1,8 +1,10 @@
-use crate::kv::{traits::*, *};
+use crate::kv::{freezer::Freezer, traits::*, *};
 use ::mdbx::{DatabaseFlags, WriteFlags};
 pub use ::mdbx::{EnvironmentKind, Geometry, NoWriteMap, TransactionKind, WriteMap, RO, RW};
 use anyhow::Context;
-use std::{collections::HashMap, fs::DirBuilder, marker::PhantomData, ops::Deref, path::Path};
+use std::{
+    collections::HashMap, fs::DirBuilder, marker::PhantomData, ops::Deref, path::Path, sync::Arc,
+};
 use tables::*;
 
 #[derive(Clone, Debug)]
@@ -22,9 +24,56 @@ where
     }
 }
 
+/// Decodes values of table `T`, decompressing them first if the table is compressed.
+struct TableValueWrapper<T: Table>(T::Value);
+
+impl<'tx, T> ::mdbx::TableObject<'tx> for TableValueWrapper<T>
+where
+    T: Table,
+{
+    fn decode(data_val: &[u8]) -> Result<Self, ::mdbx::Error>
+    where
+        Self: Sized,
+    {
+        match T::compression() {
+            Some(compression) => compression
+                .decompress(data_val)
+                .and_then(|data| T::Value::decode(&data)),
+            None => T::Value::decode(data_val),
+        }
+        .map_err(|e| ::mdbx::Error::DecodeError(e.into()))
+        .map(Self)
+    }
+}
+
+enum EncodedValue<T> {
+    Plain(T),
+    Compressed(Vec<u8>),
+}
+
+impl<T: AsRef<[u8]>> AsRef<[u8]> for EncodedValue<T> {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Self::Plain(v) => v.as_ref(),
+            Self::Compressed(v) => v,
+        }
+    }
+}
+
+fn encode_value<T: Table>(
+    value: T::Value,
+) -> anyhow::Result<EncodedValue<<T::Value as TableEncode>::Encoded>> {
+    let encoded = value.encode();
+    Ok(match T::compression() {
+        Some(compression) => EncodedValue::Compressed(compression.compress(encoded.as_ref())?),
+        None => EncodedValue::Plain(encoded),
+    })
+}
+
 #[derive(Debug)]
 pub struct MdbxEnvironment<E: EnvironmentKind> {
     inner: ::mdbx::Environment<E>,
+    freezer: Option<Arc<Freezer>>,
 }
 
 impl<E: EnvironmentKind> MdbxEnvironment<E> {
@@ -53,6 +102,7 @@ impl<E: EnvironmentKind> MdbxEnvironment<E> {
             inner: b
                 .open(path)
                 .with_context(|| format!("failed to open database at {}", path.display()))?,
+            freezer: None,
         })
     }
 
@@ -99,15 +149,27 @@ impl<E: EnvironmentKind> Deref for MdbxEnvironment<E> {
 }
 
 impl<E: EnvironmentKind> MdbxEnvironment<E> {
+    /// Attaches ancient storage, transactions of this environment will read frozen blocks
+    /// from it.
+    pub fn set_freezer(&mut self, freezer: Arc<Freezer>) {
+        self.freezer = Some(freezer);
+    }
+
+    pub fn freezer(&self) -> Option<&Arc<Freezer>> {
+        self.freezer.as_ref()
+    }
+
     pub fn begin(&self) -> anyhow::Result<MdbxTransaction<'_, RO, E>> {
         Ok(MdbxTransaction {
             inner: self.inner.begin_ro_txn()?,
+            freezer: self.freezer.as_deref(),
         })
     }
 
     pub fn begin_mutable(&self) -> anyhow::Result<MdbxTransaction<'_, RW, E>> {
         Ok(MdbxTransaction {
             inner: self.inner.begin_rw_txn()?,
+            freezer: self.freezer.as_deref(),
         })
     }
 }
@@ -119,6 +181,7 @@ where
     E: EnvironmentKind,
 {
     inner: ::mdbx::Transaction<'env, K, E>,
+    freezer: Option<&'env Freezer>,
 }
 
 impl<'env, E> MdbxTransaction<'env, RO, E>
@@ -164,6 +227,10 @@ where
         self.inner.id()
     }
 
+    pub fn freezer(&self) -> Option<&'env Freezer> {
+        self.freezer
+    }
+
     pub fn cursor<'tx, T>(&'tx self, table: T) -> anyhow::Result<MdbxCursor<'tx, K, T>>
     where
         'env: 'tx,
@@ -182,7 +249,7 @@ where
     pub fn get<T: Table>(&self, table: T, key: T::Key) -> anyhow::Result<Option<T::Value>> {
         Ok(self
             .inner
-            .get::<TableObjectWrapper<_>>(
+            .get::<TableValueWrapper<T>>(
                 &self.inner.open_db(Some(table.db_name().as_ref()))?,
                 key.encode().as_ref(),
             )?
@@ -198,7 +265,7 @@ impl<'env, E: EnvironmentKind> MdbxTransaction<'env, RW, E> {
         Ok(self.inner.put(
             &self.inner.open_db(Some(table.db_name().as_ref()))?,
             &k.encode(),
-            &v.encode(),
+            &encode_value::<T>(v)?,
             WriteFlags::UPSERT,
         )?)
     }
@@ -208,7 +275,7 @@ impl<'env, E: EnvironmentKind> MdbxTransaction<'env, RW, E> {
         T: Table,
     {
         let mut vref = None;
-        let value = value.map(TableEncode::encode);
+        let value = value.map(encode_value::<T>).transpose()?;
 
         if let Some(v) = &value {
             vref = Some(v.as_ref());
@@ -249,7 +316,7 @@ where
 }
 
 fn map_res_inner<T, E>(
-    v: Result<Option<(TableObjectWrapper<T::Key>, TableObjectWrapper<T::Value>)>, E>,
+    v: Result<Option<(TableObjectWrapper<T::Key>, TableValueWrapper<T>)>, E>,
 ) -> anyhow::Result<Option<(T::Key, T::Value)>>
 where
     T: Table,
@@ -380,7 +447,7 @@ where
     where
         T::Key: Clone,
     {
-        let res = self.inner.get_both_range::<TableObjectWrapper<T::Value>>(
+        let res = self.inner.get_both_range::<TableValueWrapper<T>>(
             key.encode().as_ref(),
             value.encode().as_ref(),
         )?;
@@ -396,10 +463,7 @@ where
     where
         T::Key: TableDecode,
     {
-        Ok(self
-            .inner
-            .last_dup::<TableObjectWrapper<T::Value>>()?
-            .map(|v| v.0))
+        Ok(self.inner.last_dup::<TableValueWrapper<T>>()?.map(|v| v.0))
     }
 
     pub fn next_dup(&mut self) -> anyhow::Result<Option<(T::Key, T::Value)>>
@@ -485,7 +549,7 @@ where
     pub fn put(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
         Ok(self.inner.put(
             key.encode().as_ref(),
-            value.encode().as_ref(),
+            encode_value::<T>(value)?.as_ref(),
             WriteFlags::default(),
         )?)
     }
@@ -493,7 +557,7 @@ where
     pub fn upsert(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
         Ok(self.inner.put(
             key.encode().as_ref(),
-            value.encode().as_ref(),
+            encode_value::<T>(value)?.as_ref(),
             WriteFlags::UPSERT,
         )?)
     }
@@ -501,7 +565,7 @@ where
     pub fn append(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
         Ok(self.inner.put(
             key.encode().as_ref(),
-            value.encode().as_ref(),
+            encode_value::<T>(value)?.as_ref(),
             WriteFlags::APPEND,
         )?)
     }
@@ -523,7 +587,7 @@ where
     pub fn append_dup(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
         Ok(self.inner.put(
             key.encode().as_ref(),
-            value.encode().as_ref(),
+            encode_value::<T>(value)?.as_ref(),
             WriteFlags::APPEND_DUP,
         )?)
     }
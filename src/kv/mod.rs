@@ -1,3 +1,5 @@
+pub mod compression;
+pub mod freezer;
 pub mod mdbx;
 pub mod tables;
 pub mod traits;
@@ -7,7 +9,7 @@ use crate::kv::{mdbx::*, tables::CHAINDATA_TABLES};
 use byte_unit::*;
 use bytes::Bytes;
 use derive_more::Deref;
-use std::fmt::Debug;
+use std::{fmt::Debug, sync::Arc};
 
 #[derive(Debug)]
 pub struct CustomTable(pub string::String<Bytes>);
@@ -42,6 +44,13 @@ where
     _tmpdir: Option<tempfile::TempDir>,
 }
 
+impl<E: EnvironmentKind> MdbxWithDirHandle<E> {
+    pub fn with_freezer(mut self, freezer: Arc<freezer::Freezer>) -> Self {
+        self.inner.set_freezer(freezer);
+        self
+    }
+}
+
 impl<E: EnvironmentKind> From<mdbx::MdbxEnvironment<E>> for MdbxWithDirHandle<E> {
     fn from(inner: mdbx::MdbxEnvironment<E>) -> Self {
         Self {
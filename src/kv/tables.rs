@@ -1,5 +1,5 @@
 use super::*;
-use crate::{models::*, zeroless_view, StageId};
+use crate::{kv::compression::Compression, models::*, zeroless_view, StageId};
 use anyhow::{bail, format_err};
 use arrayref::array_ref;
 use arrayvec::ArrayVec;
@@ -27,6 +27,10 @@ where
     fn db_name(&self) -> string::String<Bytes> {
         self.0.db_name()
     }
+
+    fn compression() -> Option<&'static Compression> {
+        T::compression()
+    }
 }
 
 impl<T> ErasedTable<T>
@@ -59,7 +63,7 @@ where
 
 #[macro_export]
 macro_rules! decl_table {
-    ($name:ident => $key:ty => $value:ty => $seek_key:ty) => {
+    (@impl $name:ident => $key:ty => $value:ty => $seek_key:ty, $compression:expr) => {
         #[derive(Clone, Copy, Debug, Default)]
         pub struct $name;
 
@@ -75,6 +79,10 @@ macro_rules! decl_table {
                     ))
                 }
             }
+
+            fn compression() -> Option<&'static $crate::kv::compression::Compression> {
+                $compression
+            }
         }
 
         impl $name {
@@ -93,9 +101,27 @@ macro_rules! decl_table {
             }
         }
     };
+    ($name:ident => $key:ty => $value:ty => $seek_key:ty) => {
+        decl_table!(@impl $name => $key => $value => $seek_key, None);
+    };
     ($name:ident => $key:ty => $value:ty) => {
         decl_table!($name => $key => $value => $key);
     };
+    // Values are zstd-compressed with the dictionary in `dictionaries/<name>.dict`, without one
+    // if the file is empty.
+    ($name:ident => $key:ty => $value:ty, compressed) => {
+        decl_table!(@impl $name => $key => $value => $key, {
+            static COMPRESSION: once_cell::sync::Lazy<$crate::kv::compression::Compression> =
+                once_cell::sync::Lazy::new(|| {
+                    $crate::kv::compression::Compression::new(include_bytes!(concat!(
+                        "dictionaries/",
+                        stringify!($name),
+                        ".dict"
+                    )))
+                });
+            Some(&*COMPRESSION)
+        });
+    };
 }
 
 #[derive(Clone, Debug, Default, Deserialize)]
@@ -703,6 +729,9 @@ impl DupSort for HashedStorage {
 impl DupSort for CallTraceSet {
     type SeekBothKey = Vec<u8>;
 }
+impl DupSort for ContractCreationSet {
+    type SeekBothKey = Address;
+}
 impl DupSort for LogAddressesByBlock {
     type SeekBothKey = Address;
 }
@@ -873,6 +902,108 @@ impl TableDecode for CallTraceSetEntry {
     }
 }
 
+/// Contract created in a block, along with the index of the creating transaction and the account
+/// which created it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContractCreationEntry {
+    pub address: Address,
+    pub transaction_index: u64,
+    pub creator: Address,
+}
+
+impl TableEncode for ContractCreationEntry {
+    type Encoded = [u8; ADDRESS_LENGTH + 8 + ADDRESS_LENGTH];
+
+    fn encode(self) -> Self::Encoded {
+        let mut v = [0; ADDRESS_LENGTH + 8 + ADDRESS_LENGTH];
+        v[..ADDRESS_LENGTH].copy_from_slice(&self.address.encode());
+        v[ADDRESS_LENGTH..ADDRESS_LENGTH + 8].copy_from_slice(&self.transaction_index.encode());
+        v[ADDRESS_LENGTH + 8..].copy_from_slice(&self.creator.encode());
+        v
+    }
+}
+
+impl TableDecode for ContractCreationEntry {
+    fn decode(b: &[u8]) -> anyhow::Result<Self> {
+        const LEN: usize = ADDRESS_LENGTH + 8 + ADDRESS_LENGTH;
+        if b.len() != LEN {
+            return Err(InvalidLength::<LEN> { got: b.len() }.into());
+        }
+
+        Ok(Self {
+            address: Address::decode(&b[..ADDRESS_LENGTH])?,
+            transaction_index: u64::decode(&b[ADDRESS_LENGTH..ADDRESS_LENGTH + 8])?,
+            creator: Address::decode(&b[ADDRESS_LENGTH + 8..])?,
+        })
+    }
+}
+
+/// Ether issued and burnt in a block, along with running totals up to and including it.
+/// Balance allocations of the chain specification count as issuance of their block, genesis
+/// allocations as issuance of block 0.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BlockIssuance {
+    pub block_reward: U256,
+    pub uncle_reward: U256,
+    /// Balances allocated by the chain specification at this block.
+    pub allocated: U256,
+    /// Base fee burnt as per EIP-1559.
+    pub burnt_fees: U256,
+    pub total_issued: U256,
+    pub total_burnt: U256,
+}
+
+impl BlockIssuance {
+    pub fn issuance(&self) -> U256 {
+        self.block_reward + self.uncle_reward + self.allocated
+    }
+
+    pub fn total_supply(&self) -> U256 {
+        self.total_issued - self.total_burnt
+    }
+}
+
+impl TableEncode for BlockIssuance {
+    type Encoded = [u8; KECCAK_LENGTH * 6];
+
+    fn encode(self) -> Self::Encoded {
+        let mut v = [0; KECCAK_LENGTH * 6];
+        for (i, value) in [
+            self.block_reward,
+            self.uncle_reward,
+            self.burnt_fees,
+            self.total_issued,
+            self.total_burnt,
+            self.allocated,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            v[i * KECCAK_LENGTH..(i + 1) * KECCAK_LENGTH].copy_from_slice(&value.to_be_bytes());
+        }
+        v
+    }
+}
+
+impl TableDecode for BlockIssuance {
+    fn decode(b: &[u8]) -> anyhow::Result<Self> {
+        if b.len() != KECCAK_LENGTH * 6 {
+            return Err(InvalidLength::<{ KECCAK_LENGTH * 6 }> { got: b.len() }.into());
+        }
+
+        let value =
+            |i: usize| U256::from_be_bytes(*array_ref!(b, i * KECCAK_LENGTH, KECCAK_LENGTH));
+        Ok(Self {
+            block_reward: value(0),
+            uncle_reward: value(1),
+            burnt_fees: value(2),
+            total_issued: value(3),
+            total_burnt: value(4),
+            allocated: value(5),
+        })
+    }
+}
+
 decl_table!(Account => Address => crate::models::Account);
 decl_table!(Storage => Address => (H256, U256));
 decl_table!(AccountChangeSet => AccountChangeKey => AccountChange);
@@ -881,7 +1012,7 @@ decl_table!(HashedAccount => H256 => crate::models::Account);
 decl_table!(HashedStorage => H256 => (H256, U256));
 decl_table!(AccountHistory => BitmapKey<Address> => RoaringTreemap);
 decl_table!(StorageHistory => BitmapKey<(Address, H256)> => RoaringTreemap);
-decl_table!(Code => H256 => Bytes);
+decl_table!(Code => H256 => Bytes, compressed);
 decl_table!(TrieAccount => Vec<u8> => Vec<u8>);
 decl_table!(TrieStorage => Vec<u8> => Vec<u8>);
 decl_table!(HeaderNumber => H256 => BlockNumber);
@@ -889,7 +1020,7 @@ decl_table!(CanonicalHeader => BlockNumber => H256);
 decl_table!(Header => BlockNumber => BlockHeader);
 decl_table!(HeadersTotalDifficulty => BlockNumber => U256);
 decl_table!(BlockBody => BlockNumber => BodyForStorage);
-decl_table!(BlockTransaction => TxIndex => MessageWithSignature);
+decl_table!(BlockTransaction => TxIndex => MessageWithSignature, compressed);
 decl_table!(TotalGas => BlockNumber => u64);
 decl_table!(TotalTx => BlockNumber => u64);
 decl_table!(LogAddressIndex => BitmapKey<Address> => RoaringTreemap);
@@ -900,11 +1031,18 @@ decl_table!(CallTraceSet => BlockNumber => CallTraceSetEntry);
 decl_table!(CallFromIndex => BitmapKey<Address> => RoaringTreemap);
 decl_table!(CallToIndex => BitmapKey<Address> => RoaringTreemap);
 decl_table!(BlockTransactionLookup => H256 => TruncateStart<BlockNumber>);
+decl_table!(ContractCreationSet => BlockNumber => ContractCreationEntry);
+decl_table!(ContractCreator => Address => (H256, Address));
+decl_table!(ContractCreationStart => () => BlockNumber);
+decl_table!(SenderNonceIndex => (Address, u64) => H256);
 decl_table!(Config => () => ChainSpec);
 decl_table!(SyncStage => StageId => BlockNumber);
 decl_table!(TxSender => BlockNumber => Vec<Address>);
-decl_table!(Issuance => Vec<u8> => Vec<u8>);
+decl_table!(Issuance => BlockNumber => BlockIssuance);
 decl_table!(Version => () => u64);
+decl_table!(CompressionDictionary => Vec<u8> => u64);
+decl_table!(CompressionProgress => Vec<u8> => Vec<u8>);
+decl_table!(HotCode => () => Vec<H256>);
 
 pub type DatabaseChart = BTreeMap<&'static str, TableInfo>;
 
@@ -949,11 +1087,18 @@ pub static CHAINDATA_TABLES: Lazy<Arc<DatabaseChart>> = Lazy::new(|| {
             table_entry!(CallFromIndex),
             table_entry!(CallToIndex),
             table_entry!(BlockTransactionLookup),
+            table_entry!(ContractCreationSet),
+            table_entry!(ContractCreator),
+            table_entry!(ContractCreationStart),
+            table_entry!(SenderNonceIndex),
             table_entry!(Config),
             table_entry!(SyncStage),
             table_entry!(TxSender),
             table_entry!(Issuance),
             table_entry!(Version),
+            table_entry!(CompressionDictionary),
+            table_entry!(CompressionProgress),
+            table_entry!(HotCode),
         ]
         .into_iter()
         .collect(),
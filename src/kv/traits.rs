@@ -1,3 +1,4 @@
+use crate::kv::compression::Compression;
 use bytes::Bytes;
 use std::{
     fmt::Debug,
@@ -26,6 +27,11 @@ pub trait Table: Send + Sync + Debug + 'static {
     type SeekKey: TableEncode;
 
     fn db_name(&self) -> string::String<Bytes>;
+
+    /// Compression applied to encoded values of this table.
+    fn compression() -> Option<&'static Compression> {
+        None
+    }
 }
 pub trait DupSort: Table {
     type SeekBothKey: TableObject;
@@ -25,17 +25,24 @@
 #![doc = include_str!("../README.md")]
 
 pub mod accessors;
+pub mod backup;
 #[doc(hidden)]
 pub mod binutil;
 mod bitmapdb;
+pub mod blockfile;
 pub mod chain;
 pub mod consensus;
 pub mod crypto;
+pub mod era1;
 pub mod etl;
 pub mod execution;
+pub mod health;
+pub mod integrity;
 pub mod kv;
+pub mod metrics;
 pub mod models;
 pub mod p2p;
+pub mod replay;
 pub mod res;
 pub mod rpc;
 pub mod sentry;
@@ -0,0 +1,341 @@
+//! Prometheus metrics of the node.
+//!
+//! All metrics are registered in the default registry and exported in text format by
+//! [`serve`].
+
+use crate::{
+    kv::{
+        mdbx::{EnvironmentKind, MdbxEnvironment},
+        MdbxWithDirHandle,
+    },
+    p2p::types::MessageId,
+    stagedsync::StageExecutionReceipt,
+};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use jsonrpsee::{
+    core::server::rpc_module::Methods,
+    server::logger::{HttpRequest, Logger, MethodKind, Params, TransportProtocol},
+};
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_gauge, register_histogram_vec, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Encoder, Gauge, HistogramVec, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+use std::{
+    collections::HashSet,
+    convert::Infallible,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+pub static STAGE_PROGRESS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "hana_stage_progress",
+        "Block number each stage has reached",
+        &["stage"]
+    )
+    .unwrap()
+});
+
+pub static STAGE_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "hana_stage_duration_seconds",
+        "Time spent in each stage per staged sync cycle",
+        &["stage"],
+        prometheus::exponential_buckets(0.01, 4.0, 12).unwrap()
+    )
+    .unwrap()
+});
+
+pub static EXECUTION_BLOCKS: Lazy<IntCounter> =
+    Lazy::new(|| register_int_counter!("hana_execution_blocks_total", "Blocks executed").unwrap());
+
+pub static EXECUTION_TRANSACTIONS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("hana_execution_transactions_total", "Transactions executed").unwrap()
+});
+
+pub static EXECUTION_GAS: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!("hana_execution_gas_total", "Gas used by executed blocks").unwrap()
+});
+
+pub static EXECUTION_GAS_PER_SECOND: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "hana_execution_gas_per_second",
+        "Execution throughput since the last progress report, in gas per second"
+    )
+    .unwrap()
+});
+
+pub static EXECUTION_TRANSACTIONS_PER_SECOND: Lazy<Gauge> = Lazy::new(|| {
+    register_gauge!(
+        "hana_execution_transactions_per_second",
+        "Execution throughput since the last progress report, in transactions per second"
+    )
+    .unwrap()
+});
+
+pub static DB_TABLE_SIZE: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "hana_db_table_size_bytes",
+        "Size of each database table",
+        &["table"]
+    )
+    .unwrap()
+});
+
+pub static DB_READERS: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "hana_db_readers",
+        "Number of reader slots in use by open transactions"
+    )
+    .unwrap()
+});
+
+pub static DB_LAST_TXN_ID: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "hana_db_last_txn_id",
+        "Id of the last committed write transaction"
+    )
+    .unwrap()
+});
+
+pub static P2P_PEERS: Lazy<IntGauge> =
+    Lazy::new(|| register_int_gauge!("hana_p2p_peers", "Peers connected to all sentries").unwrap());
+
+pub static P2P_MESSAGES_RECEIVED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "hana_p2p_messages_received_total",
+        "Messages received from sentries",
+        &["message"]
+    )
+    .unwrap()
+});
+
+pub static P2P_MESSAGES_SENT: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "hana_p2p_messages_sent_total",
+        "Messages sent through sentries, counted once per receiving peer",
+        &["message"]
+    )
+    .unwrap()
+});
+
+pub static RPC_CALLS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!("hana_rpc_calls_total", "JSON-RPC method calls", &["method"]).unwrap()
+});
+
+pub static RPC_ERRORS: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "hana_rpc_errors_total",
+        "JSON-RPC method calls which returned an error",
+        &["method"]
+    )
+    .unwrap()
+});
+
+pub static RPC_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "hana_rpc_latency_seconds",
+        "JSON-RPC method call latency",
+        &["method"],
+        prometheus::exponential_buckets(0.0005, 4.0, 10).unwrap()
+    )
+    .unwrap()
+});
+
+pub fn record_stage(receipt: &StageExecutionReceipt) {
+    STAGE_PROGRESS
+        .with_label_values(&[receipt.stage_id.0])
+        .set(receipt.progress.0 as i64);
+    STAGE_DURATION
+        .with_label_values(&[receipt.stage_id.0])
+        .observe(receipt.duration.as_secs_f64());
+}
+
+pub fn record_message_received(id: MessageId) {
+    P2P_MESSAGES_RECEIVED.with_label_values(&[id.into()]).inc();
+}
+
+/// Records a message sent to `peers` peers.
+pub fn record_message_sent(id: MessageId, peers: usize) {
+    P2P_MESSAGES_SENT
+        .with_label_values(&[id.into()])
+        .inc_by(peers as u64);
+}
+
+/// Updates table sizes and transaction statistics of the database.
+pub fn record_db_stats<E: EnvironmentKind>(db: &MdbxEnvironment<E>) -> anyhow::Result<()> {
+    for (table, size) in db.begin()?.table_sizes()? {
+        DB_TABLE_SIZE.with_label_values(&[&table]).set(size as i64);
+    }
+
+    let info = db.info()?;
+    DB_READERS.set(info.num_readers() as i64);
+    DB_LAST_TXN_ID.set(info.last_txnid() as i64);
+
+    Ok(())
+}
+
+/// Periodically updates database metrics.
+pub async fn run_db_stats_loop<E: EnvironmentKind>(
+    db: Arc<MdbxWithDirHandle<E>>,
+    interval: Duration,
+) {
+    loop {
+        let res = tokio::task::spawn_blocking({
+            let db = db.clone();
+            move || record_db_stats(&db)
+        })
+        .await;
+
+        match res {
+            Ok(Err(e)) => warn!("Failed to collect database metrics: {e:?}"),
+            Err(e) => warn!("Failed to collect database metrics: {e}"),
+            Ok(Ok(())) => {}
+        }
+
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Method label of calls to methods the server does not serve, so that clients cannot create
+/// new time series at will.
+const UNKNOWN_METHOD: &str = "unknown";
+
+/// Records per-method call counts, errors and latency of the JSON-RPC server.
+#[derive(Clone, Debug, Default)]
+pub struct RpcMetrics {
+    methods: Arc<HashSet<&'static str>>,
+}
+
+impl RpcMetrics {
+    pub fn new(methods: &Methods) -> Self {
+        Self {
+            methods: Arc::new(methods.method_names().collect()),
+        }
+    }
+
+    fn method_label<'a>(&self, method_name: &'a str) -> &'a str {
+        if self.methods.contains(method_name) {
+            method_name
+        } else {
+            UNKNOWN_METHOD
+        }
+    }
+}
+
+impl Logger for RpcMetrics {
+    type Instant = Instant;
+
+    fn on_connect(&self, _: SocketAddr, _: &HttpRequest, _: TransportProtocol) {}
+
+    fn on_request(&self, _: TransportProtocol) -> Self::Instant {
+        Instant::now()
+    }
+
+    fn on_call(&self, method_name: &str, _: Params, kind: MethodKind, _: TransportProtocol) {
+        let method_name = match kind {
+            MethodKind::NotFound => UNKNOWN_METHOD,
+            _ => self.method_label(method_name),
+        };
+        RPC_CALLS.with_label_values(&[method_name]).inc();
+    }
+
+    fn on_result(
+        &self,
+        method_name: &str,
+        success: bool,
+        started_at: Self::Instant,
+        _: TransportProtocol,
+    ) {
+        let method_name = self.method_label(method_name);
+        RPC_LATENCY
+            .with_label_values(&[method_name])
+            .observe(started_at.elapsed().as_secs_f64());
+        if !success {
+            RPC_ERRORS.with_label_values(&[method_name]).inc();
+        }
+    }
+
+    fn on_response(&self, _: &str, _: Self::Instant, _: TransportProtocol) {}
+
+    fn on_disconnect(&self, _: SocketAddr, _: TransportProtocol) {}
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::GET || req.uri().path() != "/metrics" {
+        return Ok(Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty())
+            .unwrap());
+    }
+
+    let encoder = TextEncoder::new();
+    let mut buffer = Vec::new();
+    if let Err(e) = encoder.encode(&prometheus::gather(), &mut buffer) {
+        return Ok(Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from(e.to_string()))
+            .unwrap());
+    }
+
+    Ok(Response::builder()
+        .header(CONTENT_TYPE, encoder.format_type())
+        .body(Body::from(buffer))
+        .unwrap())
+}
+
+/// Serves metrics at `/metrics` over HTTP.
+pub async fn serve(listen_address: SocketAddr) -> anyhow::Result<()> {
+    let server = Server::try_bind(&listen_address)?.serve(make_service_fn(|_| async {
+        Ok::<_, Infallible>(service_fn(handle))
+    }));
+    info!("Metrics server listening on {}", listen_address);
+
+    server.await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn exports_text_format() {
+        record_message_received(MessageId::BlockHeaders);
+
+        let res = handle(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let body = hyper::body::to_bytes(res.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        assert!(body.contains("hana_p2p_messages_received_total{message=\"BlockHeaders\"}"));
+
+        let res = handle(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn unknown_rpc_methods() {
+        let mut module = jsonrpsee::core::server::rpc_module::RpcModule::new(());
+        module
+            .register_method("eth_blockNumber", |_, _| Ok(0_u64))
+            .unwrap();
+        let metrics = RpcMetrics::new(&module.into());
+
+        assert_eq!(metrics.method_label("eth_blockNumber"), "eth_blockNumber");
+        assert_eq!(metrics.method_label("eth_nonExistent"), UNKNOWN_METHOD);
+    }
+}
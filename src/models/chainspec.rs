@@ -39,6 +39,7 @@ impl ChainSpec {
         let mut revision = Revision::Frontier;
         let mut active_transitions = HashSet::new();
         for (fork, r) in [
+            (self.upgrades.eof, Revision::Eof),
             (self.upgrades.paris, Revision::Paris),
             (self.upgrades.london, Revision::London),
             (self.upgrades.berlin, Revision::Berlin),
@@ -100,6 +101,7 @@ impl ChainSpec {
             self.upgrades.berlin,
             self.upgrades.london,
             // self.upgrades.paris,
+            self.upgrades.eof,
         ]
         .iter()
         .copied()
@@ -264,6 +266,13 @@ pub struct Upgrades {
         with = "::serde_with::rust::unwrap_or_skip"
     )]
     pub paris: Option<BlockNumber>,
+    /// Experimental EOF v1, see [`Revision::Eof`].
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "::serde_with::rust::unwrap_or_skip"
+    )]
+    pub eof: Option<BlockNumber>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -428,6 +437,7 @@ mod tests {
                     berlin: Some(8290928.into()),
                     london: Some(8897988.into()),
                     paris: None,
+                    eof: None,
                 },
                 params: Params {
                     chain_id: ChainId(4),
@@ -37,6 +37,11 @@ pub enum Revision {
 
     /// [The Paris revision.](https://github.com/ethereum/eth1.0-specs/blob/master/network-upgrades/mainnet-upgrades/paris.md)
     Paris = 10,
+
+    /// Experimental [EVM Object Format v1](https://eips.ethereum.org/EIPS/eip-3540) on top of
+    /// Paris. Not part of any network upgrade, only active on chains that schedule it in their
+    /// spec.
+    Eof = 11,
 }
 
 impl Revision {
@@ -53,6 +58,7 @@ impl Revision {
             Self::Berlin,
             Self::London,
             Self::Paris,
+            Self::Eof,
         ]
     }
 
@@ -60,7 +66,8 @@ impl Revision {
         Self::Paris
     }
 
+    /// Number of revisions, experimental ones included.
     pub const fn len() -> usize {
-        Self::latest() as usize + 1
+        Self::Eof as usize + 1
     }
 }
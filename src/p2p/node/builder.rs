@@ -1,5 +1,6 @@
-use super::{stash::Stash, Node, Sentry};
+use super::{stash::Stash, Node, Sentry, SentryHealth};
 use crate::{
+    consensus::Consensus,
     models::{BlockNumber, ChainConfig, H256, U256},
     p2p::types::Status,
 };
@@ -13,7 +14,9 @@ use tonic::transport::Channel;
 #[derive(Debug)]
 pub struct NodeBuilder {
     sentries: Vec<Sentry>,
+    sentry_health: Vec<SentryHealth>,
     stash: Option<Arc<dyn Stash>>,
+    consensus: Option<Arc<dyn Consensus>>,
     config: ChainConfig,
     status: Option<Status>,
 }
@@ -24,15 +27,17 @@ impl NodeBuilder {
             config,
 
             sentries: Default::default(),
+            sentry_health: Default::default(),
             stash: Default::default(),
+            consensus: Default::default(),
             status: Default::default(),
         }
     }
 
     pub fn add_sentry(mut self, endpoint: impl Into<Uri>) -> Self {
-        self.sentries.push(Sentry::new(
-            Channel::builder(endpoint.into()).connect_lazy(),
-        ));
+        let channel = Channel::builder(endpoint.into()).connect_lazy();
+        self.sentries.push(Sentry::new(channel.clone()));
+        self.sentry_health.push(SentryHealth::new(channel));
         self
     }
 
@@ -51,6 +56,13 @@ impl NodeBuilder {
         self
     }
 
+    /// Consensus engine to validate blocks with before relaying them. Without one, blocks are
+    /// not relayed.
+    pub fn set_consensus(mut self, consensus: Arc<dyn Consensus>) -> Self {
+        self.consensus = Some(consensus);
+        self
+    }
+
     pub fn build(self) -> anyhow::Result<Node> {
         let stash = self.stash.unwrap_or_else(|| Arc::new(()));
         let sentries = self.sentries;
@@ -66,7 +78,9 @@ impl NodeBuilder {
 
         Ok(Node {
             stash,
+            consensus: self.consensus,
             sentries,
+            sentry_health: self.sentry_health,
             status,
             config,
             chain_tip,
@@ -74,6 +88,9 @@ impl NodeBuilder {
             bad_blocks: Default::default(),
             block_cache: Mutex::new(LruCache::new(64)),
             block_cache_notify: Notify::new(),
+            scheduler: Default::default(),
+            gossip: Default::default(),
+            reputations: Mutex::new(LruCache::new(1024)),
             forks,
         })
     }
@@ -0,0 +1,168 @@
+use super::PeerKey;
+use crate::models::{MessageWithSignature, H256};
+use hashlink::LruCache;
+use parking_lot::Mutex;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
+
+/// Block hashes remembered per peer.
+const MAX_KNOWN_BLOCKS: usize = 1024;
+/// Transaction hashes remembered per peer.
+const MAX_KNOWN_TRANSACTIONS: usize = 32_768;
+/// Relayed transactions kept around to answer `GetPooledTransactions`.
+const MAX_CACHED_TRANSACTIONS: usize = 4096;
+
+#[derive(Debug)]
+struct KnownHashes {
+    blocks: LruCache<H256, ()>,
+    transactions: LruCache<H256, ()>,
+}
+
+impl Default for KnownHashes {
+    fn default() -> Self {
+        Self {
+            blocks: LruCache::new(MAX_KNOWN_BLOCKS),
+            transactions: LruCache::new(MAX_KNOWN_TRANSACTIONS),
+        }
+    }
+}
+
+/// Block and transaction hashes each peer is known to have, so that announcements are not echoed
+/// back, and recently relayed transactions.
+#[derive(Debug)]
+pub struct Gossip {
+    peers: Mutex<HashMap<PeerKey, KnownHashes>>,
+    transactions: Mutex<LruCache<H256, MessageWithSignature>>,
+}
+
+impl Default for Gossip {
+    fn default() -> Self {
+        Self {
+            peers: Default::default(),
+            transactions: Mutex::new(LruCache::new(MAX_CACHED_TRANSACTIONS)),
+        }
+    }
+}
+
+impl Gossip {
+    /// Announced transactions remembered as already requested.
+    pub const MAX_REQUESTED_TRANSACTIONS: usize = 4096;
+
+    pub fn add_peer(&self, peer: PeerKey) {
+        self.peers.lock().entry(peer).or_default();
+    }
+
+    pub fn remove_peer(&self, peer: PeerKey) {
+        self.peers.lock().remove(&peer);
+    }
+
+    pub fn mark_block(&self, peer: PeerKey, hash: H256) {
+        self.peers
+            .lock()
+            .entry(peer)
+            .or_default()
+            .blocks
+            .insert(hash, ());
+    }
+
+    pub fn mark_transactions(&self, peer: PeerKey, hashes: impl IntoIterator<Item = H256>) {
+        let mut peers = self.peers.lock();
+        let known = peers.entry(peer).or_default();
+        for hash in hashes {
+            known.transactions.insert(hash, ());
+        }
+    }
+
+    /// Peers not known to have the block. The block is marked as known to them, as it is about to
+    /// be sent.
+    pub fn peers_without_block(&self, hash: H256) -> Vec<PeerKey> {
+        self.peers
+            .lock()
+            .iter_mut()
+            .filter_map(|(&peer, known)| known.blocks.insert(hash, ()).is_none().then_some(peer))
+            .collect()
+    }
+
+    /// Transactions among `hashes` not known to each peer. They are marked as known, as they are
+    /// about to be sent.
+    pub fn peers_without_transactions(&self, hashes: &[H256]) -> Vec<(PeerKey, Vec<H256>)> {
+        self.peers
+            .lock()
+            .iter_mut()
+            .filter_map(|(&peer, known)| {
+                let unknown = hashes
+                    .iter()
+                    .copied()
+                    .filter(|&hash| known.transactions.insert(hash, ()).is_none())
+                    .collect::<Vec<_>>();
+                (!unknown.is_empty()).then_some((peer, unknown))
+            })
+            .collect()
+    }
+
+    /// Caches transactions, returning those not seen before.
+    pub fn insert_transactions(
+        &self,
+        transactions: impl IntoIterator<Item = (H256, MessageWithSignature)>,
+    ) -> Vec<(H256, MessageWithSignature)> {
+        let mut cache = self.transactions.lock();
+        transactions
+            .into_iter()
+            .filter(|(hash, transaction)| cache.insert(*hash, transaction.clone()).is_none())
+            .collect()
+    }
+
+    pub fn has_transaction(&self, hash: H256) -> bool {
+        self.transactions.lock().contains_key(&hash)
+    }
+
+    pub fn get_transactions(&self, hashes: &[H256]) -> Vec<MessageWithSignature> {
+        let mut cache = self.transactions.lock();
+        hashes
+            .iter()
+            .filter_map(|hash| cache.get(hash).cloned())
+            .collect()
+    }
+}
+
+/// Splits peers into `push` random ones which get the full block or transactions, and the rest
+/// which only get the hashes.
+pub fn split_push_announce<T>(mut peers: Vec<T>, push: usize) -> (Vec<T>, Vec<T>) {
+    peers.shuffle(&mut rand::thread_rng());
+    let announce = peers.split_off(push.min(peers.len()));
+    (peers, announce)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::node::PeerId;
+
+    #[test]
+    fn does_not_echo() {
+        let gossip = Gossip::default();
+        let a = (0, PeerId::repeat_byte(1));
+        let b = (0, PeerId::repeat_byte(2));
+        let block = H256::repeat_byte(0xbb);
+        let tx = H256::repeat_byte(0xcc);
+
+        gossip.add_peer(a);
+        gossip.add_peer(b);
+
+        gossip.mark_block(a, block);
+        assert_eq!(gossip.peers_without_block(block), vec![b]);
+        assert!(gossip.peers_without_block(block).is_empty());
+
+        gossip.mark_transactions(b, [tx]);
+        let mut unknown = gossip.peers_without_transactions(&[tx, block]);
+        unknown.sort();
+        assert_eq!(unknown, vec![(a, vec![tx, block]), (b, vec![block])]);
+        assert!(gossip.peers_without_transactions(&[tx]).is_empty());
+
+        gossip.remove_peer(a);
+        assert_eq!(gossip.peers_without_block(H256::zero()), vec![b]);
+
+        let (push, announce) = split_push_announce(vec![1, 2, 3, 4, 5], 2);
+        assert_eq!((push.len(), announce.len()), (2, 3));
+    }
+}
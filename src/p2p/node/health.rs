@@ -0,0 +1,144 @@
+use super::Sentry;
+use anyhow::bail;
+use ethereum_interfaces::sentry::PeerCountRequest;
+use rand::Rng;
+use std::{
+    sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    time::Duration,
+};
+use tonic::{transport::Channel, Code};
+use tonic_health::proto::{
+    health_check_response::ServingStatus, health_client::HealthClient, HealthCheckRequest,
+};
+use tracing::*;
+
+/// Interval between health checks of a healthy sentry.
+pub const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+/// Upper bound of the reconnection backoff of an unhealthy sentry.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+/// Name under which sentries report their health.
+const SENTRY_SERVICE: &str = "sentry.Sentry";
+
+/// Liveness and peer count of a sentry, kept up to date by periodic health checks.
+#[derive(Debug)]
+pub struct SentryHealth {
+    client: HealthClient<Channel>,
+    healthy: AtomicBool,
+    peer_count: AtomicU64,
+    /// Failed checks since the last successful one.
+    failures: AtomicU32,
+}
+
+impl SentryHealth {
+    pub fn new(channel: Channel) -> Self {
+        Self {
+            client: HealthClient::new(channel),
+            // Assume healthy until the first check says otherwise
+            healthy: AtomicBool::new(true),
+            peer_count: AtomicU64::new(0),
+            failures: AtomicU32::new(0),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    pub fn peer_count(&self) -> u64 {
+        self.peer_count.load(Ordering::Relaxed)
+    }
+
+    /// Checks the sentry and refreshes its peer count. Returns `true` if the sentry has just
+    /// recovered.
+    pub async fn check(&self, sentry_id: usize, sentry: &mut Sentry) -> bool {
+        let res = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, async {
+            match self
+                .client
+                .clone()
+                .check(HealthCheckRequest {
+                    service: SENTRY_SERVICE.to_string(),
+                })
+                .await
+            {
+                Ok(res) if res.get_ref().status != ServingStatus::Serving as i32 => {
+                    bail!("not serving")
+                }
+                // Sentries without the health service are judged by peer count requests alone
+                Ok(_) => {}
+                Err(status) if status.code() == Code::Unimplemented => {}
+                Err(status) => return Err(status.into()),
+            }
+
+            Ok(sentry
+                .peer_count(PeerCountRequest {})
+                .await?
+                .into_inner()
+                .count)
+        })
+        .await
+        .unwrap_or_else(|_| Err(anyhow::format_err!("timed out")));
+
+        match res {
+            Ok(peer_count) => {
+                self.peer_count.store(peer_count, Ordering::Relaxed);
+                self.failures.store(0, Ordering::Relaxed);
+                !self.healthy.swap(true, Ordering::Relaxed)
+            }
+            Err(e) => {
+                self.failures.fetch_add(1, Ordering::Relaxed);
+                if self.healthy.swap(false, Ordering::Relaxed) {
+                    warn!("Sentry {sentry_id} is unhealthy: {e}");
+                }
+                false
+            }
+        }
+    }
+
+    /// Delay before the next check, backing off exponentially while the sentry is down.
+    pub fn next_check_in(&self) -> Duration {
+        backoff(self.failures.load(Ordering::Relaxed))
+    }
+}
+
+fn backoff(failures: u32) -> Duration {
+    HEALTH_CHECK_INTERVAL
+        .saturating_mul(1 << failures.saturating_sub(1).min(16))
+        .min(MAX_BACKOFF)
+}
+
+/// Orders sentries randomly, with the likelihood of coming first proportional to the weight.
+pub fn weighted_order(weights: impl IntoIterator<Item = (usize, u64)>) -> Vec<usize> {
+    let mut rng = rand::thread_rng();
+    // Efraimidis-Spirakis: sort by u^(1/w). Every sentry gets a non-zero weight so that those
+    // without peers yet are still tried as a last resort.
+    let mut keys = weights
+        .into_iter()
+        .map(|(id, weight)| (id, rng.gen::<f64>().powf(1.0 / (weight as f64 + 1.0))))
+        .collect::<Vec<_>>();
+    keys.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    keys.into_iter().map(|(id, _)| id).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_and_weights() {
+        assert_eq!(backoff(0), HEALTH_CHECK_INTERVAL);
+        assert_eq!(backoff(1), HEALTH_CHECK_INTERVAL);
+        assert_eq!(backoff(2), HEALTH_CHECK_INTERVAL * 2);
+        assert_eq!(backoff(100), MAX_BACKOFF);
+
+        let mut order = weighted_order([(0, 0), (1, 50), (2, 5)]);
+        assert_eq!(order.len(), 3);
+        order.sort_unstable();
+        assert_eq!(order, vec![0, 1, 2]);
+
+        let first_heavy = (0..1000)
+            .filter(|_| weighted_order([(0, 0), (1, 1000)])[0] == 1)
+            .count();
+        assert!(first_heavy > 900, "{first_heavy}");
+    }
+}
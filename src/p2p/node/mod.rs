@@ -1,8 +1,13 @@
 #![allow(clippy::module_inception)]
 
 mod builder;
+mod gossip;
+mod health;
 mod node;
+mod scheduler;
 mod stash;
 mod stream;
 
-pub use self::{builder::*, node::*, stream::NodeStream};
+pub use self::{
+    builder::*, gossip::*, health::SentryHealth, node::*, scheduler::*, stream::NodeStream,
+};
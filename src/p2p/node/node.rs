@@ -1,9 +1,22 @@
 #![allow(unreachable_code)]
 
-use super::{stash::Stash, stream::*};
+use super::{
+    gossip::{split_push_announce, Gossip},
+    health::{weighted_order, SentryHealth},
+    scheduler::{PeerKey, RequestKind, RequestScheduler},
+    stash::Stash,
+    stream::*,
+};
 use crate::{
-    models::{BlockNumber, ChainConfig, MessageWithSignature, H256},
+    chain::intrinsic_gas::intrinsic_gas,
+    consensus::{pre_validate_transaction, Consensus},
+    metrics,
+    models::{
+        BlockHeader, BlockNumber, ChainConfig, MessageWithSignature, Revision, EMPTY_HASH, H256,
+        U512,
+    },
     p2p::types::*,
+    sentry::devp2p::{PeerAction, Reputation},
 };
 use bytes::{BufMut, BytesMut};
 use dashmap::DashSet;
@@ -18,13 +31,13 @@ use hashlink::LruCache;
 use parking_lot::{Mutex, RwLock};
 use rand::{thread_rng, Rng};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     future::{pending, Future},
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use task_group::TaskGroup;
-use tokio::sync::{watch, Notify};
+use tokio::sync::{watch, Notify, Semaphore};
 use tokio_stream::StreamExt;
 use tonic::transport::Channel;
 use tracing::*;
@@ -39,8 +52,12 @@ pub type RequestId = u64;
 #[derive(Debug)]
 pub struct Node {
     pub stash: Arc<dyn Stash>,
+    /// Consensus engine relayed blocks are validated with.
+    pub consensus: Option<Arc<dyn Consensus>>,
     /// The sentry clients.
     pub sentries: Vec<Sentry>,
+    /// Liveness and peer counts of the sentries, by sentry ID.
+    pub sentry_health: Vec<SentryHealth>,
     /// The current Node status message.
     pub status: RwLock<Status>,
     /// Node chain config.
@@ -55,10 +72,18 @@ pub struct Node {
     pub bad_blocks: DashSet<H256>,
     /// Chain forks.
     pub forks: Vec<u64>,
+    /// Per-peer performance tracking for header and body requests.
+    pub scheduler: RequestScheduler,
+    /// Blocks and transactions known to peers, for relaying.
+    pub gossip: Gossip,
+    /// Reputation of penalized peers, they are kicked once it falls too low.
+    pub reputations: Mutex<LruCache<PeerId, Reputation>>,
 }
 
 impl Node {
     const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+    /// Maximum number of blocks being validated and relayed at once.
+    const MAX_BLOCK_RELAYS: usize = 16;
 
     /// Start node synchronization.
     pub async fn start_sync(self: Arc<Self>, tip_discovery: bool) -> anyhow::Result<()> {
@@ -66,6 +91,7 @@ impl Node {
 
         let (tx, mut rx) = tokio::sync::mpsc::channel(128);
         let requested = Arc::new(Mutex::new(LruCache::new(128)));
+        let block_relays = Arc::new(Semaphore::new(Self::MAX_BLOCK_RELAYS));
 
         tasks.spawn({
             let handler = self.clone();
@@ -78,10 +104,15 @@ impl Node {
                         let peer_id = msg.peer_id;
                         let sentry_id = msg.sentry_id;
 
+                        handler.scheduler.add_peer((sentry_id, peer_id));
+                        handler.gossip.add_peer((sentry_id, peer_id));
+
                         match msg.msg {
                             Message::NewBlockHashes(ref blocks) => {
                                 let mut max_block = None;
                                 for b in &blocks.0 {
+                                    handler.gossip.mark_block((sentry_id, peer_id), b.hash);
+
                                     if tip_discovery && b.number > handler.chain_tip.borrow().0 {
                                         let id = thread_rng().gen::<u64>();
                                         tx.send((
@@ -141,12 +172,33 @@ impl Node {
                                 let hash = inner.block.header.hash();
                                 let number = inner.block.header.number;
 
-                                handler
+                                handler.gossip.mark_block((sentry_id, peer_id), hash);
+                                let is_new = handler
                                     .block_cache
                                     .lock()
-                                    .insert(hash, (sentry_id, peer_id, inner.block));
+                                    .insert(hash, (sentry_id, peer_id, inner.block.clone()))
+                                    .is_none();
                                 handler.block_cache_notify.notify_one();
 
+                                // Block propagation is only allowed before the merge
+                                if tip_discovery
+                                    && is_new
+                                    && !handler.bad_blocks.contains(&hash)
+                                    && number > handler.status.read().height
+                                {
+                                    // Drop the relay rather than queue it if too many are in flight
+                                    if let Ok(permit) = block_relays.clone().try_acquire_owned() {
+                                        tokio::spawn({
+                                            let handler = handler.clone();
+                                            let new_block = *inner;
+                                            async move {
+                                                handler.propagate_block(new_block).await;
+                                                drop(permit);
+                                            }
+                                        });
+                                    }
+                                }
+
                                 if tip_discovery && number > handler.chain_tip.borrow().0 {
                                     let _ = handler.chain_tip_sender.send((number, hash));
                                     for skip in 1..4_u64 {
@@ -164,6 +216,119 @@ impl Node {
             }
         });
 
+        for (sentry_id, mut sentry) in self.sentries.iter().cloned().enumerate() {
+            let handler = self.clone();
+
+            tasks.spawn(async move {
+                loop {
+                    let health = &handler.sentry_health[sentry_id];
+                    if health.check(sentry_id, &mut sentry).await {
+                        // The sentry may have restarted and lost the status
+                        info!("Sentry {sentry_id} is back online");
+                        Self::send_status(sentry.clone(), handler.status_data()).await;
+                    }
+                    tokio::time::sleep(health.next_check_in()).await;
+                }
+
+                Ok::<_, anyhow::Error>(())
+            });
+        }
+
+        for (sentry_id, mut sentry) in self.sentries.iter().cloned().enumerate() {
+            let handler = self.clone();
+
+            tasks.spawn(async move {
+                loop {
+                    match sentry.peer_events(grpc_sentry::PeerEventsRequest {}).await {
+                        Ok(events) => {
+                            let mut events = events.into_inner();
+                            while let Some(Ok(event)) = events.next().await {
+                                if let Some(peer_id) = event.peer_id {
+                                    let peer = (sentry_id, peer_id.into());
+                                    if event.event_id
+                                        == grpc_sentry::peer_event::PeerEventId::Connect as i32
+                                    {
+                                        handler.scheduler.add_peer(peer);
+                                        handler.gossip.add_peer(peer);
+                                    } else {
+                                        handler.scheduler.remove_peer(peer);
+                                        handler.gossip.remove_peer(peer);
+                                    }
+                                }
+                            }
+                        }
+                        Err(e) => debug!("Failed to subscribe to peer events: {e}"),
+                    }
+
+                    tokio::time::sleep(Self::SYNC_INTERVAL).await;
+                }
+
+                Ok::<_, anyhow::Error>(())
+            });
+        }
+
+        tasks.spawn({
+            let handler = self.clone();
+
+            async move {
+                let mut stream = handler.stream_transactions().await;
+                // Transactions recently requested from announcing peers
+                let mut requested = LruCache::new(Gossip::MAX_REQUESTED_TRANSACTIONS);
+                while let Some(msg) = stream.next().await {
+                    let (sentry_id, peer_id) = (msg.sentry_id, msg.peer_id);
+
+                    match msg.msg {
+                        Message::Transactions(Transactions(transactions))
+                        | Message::PooledTransactions(PooledTransactions {
+                            transactions, ..
+                        }) => {
+                            handler
+                                .propagate_transactions((sentry_id, peer_id), transactions)
+                                .await;
+                        }
+                        Message::NewPooledTransactionHashes(NewPooledTransactionHashes(hashes)) => {
+                            handler
+                                .gossip
+                                .mark_transactions((sentry_id, peer_id), hashes.iter().copied());
+
+                            let unknown = hashes
+                                .into_iter()
+                                .filter(|&hash| {
+                                    !handler.gossip.has_transaction(hash)
+                                        && requested.insert(hash, ()).is_none()
+                                })
+                                .collect::<Vec<_>>();
+                            if !unknown.is_empty() {
+                                handler
+                                    .get_pooled_transactions(
+                                        thread_rng().gen(),
+                                        &unknown,
+                                        PeerFilter::Peer(peer_id, sentry_id),
+                                    )
+                                    .await;
+                            }
+                        }
+                        Message::GetPooledTransactions(GetPooledTransactions {
+                            request_id,
+                            hashes,
+                        }) => {
+                            let transactions = handler.gossip.get_transactions(&hashes);
+                            handler
+                                .send_pooled_transactions(
+                                    request_id,
+                                    transactions,
+                                    PeerFilter::Peer(peer_id, sentry_id),
+                                )
+                                .await;
+                        }
+                        _ => {}
+                    }
+                }
+
+                Ok::<_, anyhow::Error>(())
+            }
+        });
+
         let _ = tasks.spawn({
             let handler = self.clone();
 
@@ -280,13 +445,17 @@ impl Node {
             *self.status.write() = val;
         }
 
+        self.set_status(self.status_data()).await
+    }
+
+    fn status_data(&self) -> grpc_sentry::StatusData {
         let Status {
             height,
             hash,
             total_difficulty,
         } = *self.status.read();
         let config = &self.config;
-        let status_data = grpc_sentry::StatusData {
+        grpc_sentry::StatusData {
             network_id: *config.network_id(),
             total_difficulty: Some(total_difficulty.into()),
             best_hash: Some(hash.into()),
@@ -295,8 +464,7 @@ impl Node {
                 forks: self.forks.clone(),
             }),
             max_block: *height,
-        };
-        self.set_status(status_data).await
+        }
     }
 
     pub async fn send_message(
@@ -361,6 +529,223 @@ impl Node {
         .await
     }
 
+    /// Checks the header and seal of the block against its parent through the consensus engine.
+    /// Blocks with an unknown parent are not considered valid.
+    fn validate_relayed_block(&self, header: &BlockHeader) -> anyhow::Result<bool> {
+        let consensus = match &self.consensus {
+            Some(consensus) => consensus,
+            None => return Ok(false),
+        };
+
+        let cached_parent = self
+            .block_cache
+            .lock()
+            .peek(&header.parent_hash)
+            .map(|(_, _, block)| block.header.clone());
+        let parent = match cached_parent {
+            Some(parent) => Some(parent),
+            None => self
+                .stash
+                .get_headers(GetBlockHeadersParams {
+                    start: BlockId::Hash(header.parent_hash),
+                    limit: 1,
+                    skip: 0,
+                    reverse: 0,
+                })?
+                .pop(),
+        };
+        let parent = match parent {
+            Some(parent) if parent.number + 1 == header.number => parent,
+            _ => return Ok(false),
+        };
+
+        Ok(consensus
+            .validate_block_header(header, &parent, true)
+            .and_then(|_| consensus.validate_header_parallel(header))
+            .is_ok())
+    }
+
+    /// Checks that the transaction could be included in the next block given the latest known
+    /// state of its sender: signature, chain ID, fees, intrinsic gas, nonce and balance.
+    fn is_relayable_transaction(&self, transaction: &MessageWithSignature) -> bool {
+        let sender = match transaction.recover_sender() {
+            Ok(sender) => sender,
+            Err(_) => return false,
+        };
+
+        let chain_spec = &self.config.chain_spec;
+        if pre_validate_transaction(transaction, chain_spec.params.chain_id, None).is_err() {
+            return false;
+        }
+
+        let revision = chain_spec
+            .collect_block_spec(self.status.read().height + 1)
+            .revision;
+        if u128::from(transaction.gas_limit())
+            < intrinsic_gas(
+                transaction,
+                revision >= Revision::Homestead,
+                revision >= Revision::Istanbul,
+            )
+        {
+            return false;
+        }
+
+        let account = match self.stash.get_account(sender) {
+            Ok(account) => account.unwrap_or_default(),
+            Err(_) => return false,
+        };
+        if account.code_hash != EMPTY_HASH || transaction.nonce() < account.nonce {
+            return false;
+        }
+
+        // https://github.com/ethereum/EIPs/pull/3594
+        let max_cost = U512::from(transaction.gas_limit())
+            * U512::from(ethereum_types::U256::from(
+                transaction.max_fee_per_gas().to_be_bytes(),
+            ))
+            + U512::from(ethereum_types::U256::from(
+                transaction.value().to_be_bytes(),
+            ));
+        U512::from(ethereum_types::U256::from(account.balance.to_be_bytes())) >= max_cost
+    }
+
+    /// Relays the block if it passes header and seal validation: full block to the square root
+    /// of peers, hash announcement to the rest.
+    async fn propagate_block(self: Arc<Self>, new_block: NewBlock) {
+        let hash = new_block.block.header.hash();
+        let number = new_block.block.header.number;
+
+        let valid = tokio::task::spawn_blocking({
+            let node = self.clone();
+            let header = new_block.block.header.clone();
+            move || node.validate_relayed_block(&header)
+        })
+        .await;
+        if !matches!(valid, Ok(Ok(true))) {
+            debug!("Not relaying block #{number}/{hash:?}: failed validation or unknown parent");
+            return;
+        }
+
+        let (push, announce) = split_push_announce(
+            self.gossip.peers_without_block(hash),
+            self.sqrt_peers().await.max(1),
+        );
+        debug!(
+            "Propagating block #{number}/{hash:?} to {} peers, announcing to {}",
+            push.len(),
+            announce.len()
+        );
+
+        let block_msg = Message::NewBlock(Box::new(new_block));
+        let hashes_msg =
+            Message::NewBlockHashes(NewBlockHashes(vec![BlockHashAndNumber { hash, number }]));
+        self.send_to_peers(
+            push.into_iter()
+                .map(|peer| (peer, block_msg.clone()))
+                .chain(announce.into_iter().map(|peer| (peer, hashes_msg.clone()))),
+        )
+        .await
+    }
+
+    /// Relays transactions not seen before that could be included in the next block: full
+    /// transactions to the square root of peers, hash announcements to the rest.
+    async fn propagate_transactions(&self, from: PeerKey, transactions: Vec<MessageWithSignature>) {
+        let transactions = transactions
+            .into_iter()
+            .map(|transaction| (transaction.hash(), transaction))
+            .collect::<Vec<_>>();
+        self.gossip
+            .mark_transactions(from, transactions.iter().map(|(hash, _)| *hash));
+
+        let transactions = self.gossip.insert_transactions(
+            transactions
+                .into_iter()
+                .filter(|(_, transaction)| self.is_relayable_transaction(transaction)),
+        );
+        if transactions.is_empty() {
+            return;
+        }
+
+        let hashes = transactions
+            .iter()
+            .map(|(hash, _)| *hash)
+            .collect::<Vec<_>>();
+        let transactions = transactions.into_iter().collect::<HashMap<_, _>>();
+
+        let (push, announce) = split_push_announce(
+            self.gossip.peers_without_transactions(&hashes),
+            self.sqrt_peers().await.max(1),
+        );
+        debug!(
+            "Propagating {} transactions to {} peers, announcing to {}",
+            hashes.len(),
+            push.len(),
+            announce.len()
+        );
+
+        self.send_to_peers(
+            push.into_iter()
+                .map(|(peer, hashes)| {
+                    (
+                        peer,
+                        Message::Transactions(Transactions(
+                            hashes
+                                .iter()
+                                .map(|hash| transactions[hash].clone())
+                                .collect(),
+                        )),
+                    )
+                })
+                .chain(announce.into_iter().map(|(peer, hashes)| {
+                    (
+                        peer,
+                        Message::NewPooledTransactionHashes(NewPooledTransactionHashes(hashes)),
+                    )
+                })),
+        )
+        .await
+    }
+
+    async fn send_to_peers(&self, messages: impl IntoIterator<Item = (PeerKey, Message)>) {
+        messages
+            .into_iter()
+            .map(|((sentry_id, peer_id), msg)| {
+                self.send_message(msg, PeerFilter::Peer(peer_id, sentry_id))
+            })
+            .collect::<FuturesUnordered<_>>()
+            .map(|_| ())
+            .collect::<()>()
+            .await
+    }
+
+    /// Sends the request to the best peer able to take it, see [`RequestScheduler`].
+    pub async fn send_scheduled(
+        &self,
+        request_id: RequestId,
+        kind: RequestKind,
+        msg: Message,
+    ) -> Option<(SentryId, PeerId)> {
+        for peer in self.scheduler.available_peers(kind) {
+            let (sentry_id, peer_id) = peer;
+
+            // Mark as sent first, the response may arrive before the send call returns.
+            self.scheduler.sent(request_id, peer, kind, Instant::now());
+            if self
+                .send_message(msg.clone(), PeerFilter::Peer(peer_id, sentry_id))
+                .await
+                .contains(&peer)
+            {
+                return Some(peer);
+            }
+
+            // Peer is gone, this also drops the request.
+            self.scheduler.remove_peer(peer);
+        }
+
+        None
+    }
+
     /// Sends a block bodies request to other peers.
     pub async fn send_block_request<'a>(
         &self,
@@ -527,10 +912,8 @@ impl Node {
 
     pub async fn total_peers(&self) -> usize {
         let mut s = self
-            .sentries
-            .clone()
-            .into_iter()
-            .map(|mut sentry| async move {
+            .healthy_sentries()
+            .map(|(_, mut sentry)| async move {
                 tokio::time::timeout(
                     Duration::from_secs(2),
                     sentry.peer_count(grpc_sentry::PeerCountRequest {}),
@@ -547,11 +930,26 @@ impl Node {
         sum
     }
 
-    pub async fn penalize_peer(&self, peer_id: impl Into<ethereum_interfaces::types::H512>) {
-        let request = grpc_sentry::PenalizePeerRequest {
-            peer_id: Some(peer_id.into()),
-            penalty: 0i32,
+    pub async fn penalize_peer(&self, penalty: Penalty) {
+        let now = Instant::now();
+        let change = -penalty.kind.weight();
+        let score = {
+            let mut reputations = self.reputations.lock();
+            match reputations.get_mut(&penalty.peer_id) {
+                Some(reputation) => reputation.change(change, now),
+                None => {
+                    let mut reputation = Reputation::new(now);
+                    let score = reputation.change(change, now);
+                    reputations.insert(penalty.peer_id, reputation);
+                    score
+                }
+            }
         };
+        if PeerAction::from_score(score) == PeerAction::None {
+            return;
+        }
+
+        let request = grpc_sentry::PenalizePeerRequest::from(penalty);
 
         self.sentries
             .clone()
@@ -572,12 +970,27 @@ impl Node {
 impl Node {
     const TIMEOUT: Duration = Duration::from_secs(2);
 
+    /// Sentries that passed their last health check, or all of them if none did, as the checks
+    /// may be stale.
+    fn healthy_sentries(&self) -> impl Iterator<Item = (SentryId, Sentry)> + '_ {
+        let any_healthy = self.sentry_health.iter().any(SentryHealth::is_healthy);
+        self.sentries
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(move |&(sentry_id, _)| {
+                !any_healthy || self.sentry_health[sentry_id].is_healthy()
+            })
+    }
+
     async fn send_raw(
         &self,
         data: impl Into<grpc_sentry::OutboundMessageData>,
         predicate: PeerFilter,
     ) -> HashSet<(SentryId, PeerId)> {
         let data = data.into();
+        let message_id =
+            grpc_sentry::MessageId::from_i32(data.id).and_then(|id| MessageId::try_from(id).ok());
 
         async fn map_await<T, I, F>(
             iter: T,
@@ -614,31 +1027,44 @@ impl Node {
                 .collect()
         }
 
-        match predicate {
+        let peers = match predicate {
             PeerFilter::All => {
                 map_await(
-                    self.sentries.iter().cloned().enumerate(),
+                    self.healthy_sentries(),
                     data,
                     |mut sentry, data| async move { sentry.send_message_to_all(data).await },
                 )
                 .await
             }
             PeerFilter::Random(max_peers) => {
-                map_await(
-                    self.sentries.iter().cloned().enumerate(),
-                    data,
-                    |mut sentry, data| async move {
-                        sentry
-                            .send_message_to_random_peers(
-                                grpc_sentry::SendMessageToRandomPeersRequest {
-                                    data: Some(data),
-                                    max_peers,
-                                },
-                            )
-                            .await
-                    },
-                )
-                .await
+                // Prefer sentries with more peers, failing over to the next one if a sentry
+                // could not deliver the message
+                let order =
+                    weighted_order(self.healthy_sentries().map(|(sentry_id, _)| {
+                        (sentry_id, self.sentry_health[sentry_id].peer_count())
+                    }));
+                let mut peers = HashSet::new();
+                for sentry_id in order {
+                    peers = map_await(
+                        std::iter::once((sentry_id, self.sentries[sentry_id].clone())),
+                        data.clone(),
+                        |mut sentry, data| async move {
+                            sentry
+                                .send_message_to_random_peers(
+                                    grpc_sentry::SendMessageToRandomPeersRequest {
+                                        data: Some(data),
+                                        max_peers,
+                                    },
+                                )
+                                .await
+                        },
+                    )
+                    .await;
+                    if !peers.is_empty() {
+                        break;
+                    }
+                }
+                peers
             }
             PeerFilter::Peer(peer_id, sentry_id) => {
                 let iter = std::iter::once((sentry_id, self.sentries[sentry_id].clone()));
@@ -654,7 +1080,7 @@ impl Node {
             }
             PeerFilter::MinBlock(min_block) => {
                 map_await(
-                    self.sentries.iter().cloned().enumerate(),
+                    self.healthy_sentries(),
                     data,
                     |mut sentry, data| async move {
                         sentry
@@ -668,24 +1094,27 @@ impl Node {
                 )
                 .await
             }
+        };
+
+        if let Some(message_id) = message_id {
+            metrics::record_message_sent(message_id, peers.len());
+        }
+
+        peers
+    }
+    async fn send_status(mut sentry: Sentry, status_data: grpc_sentry::StatusData) {
+        if let Err(err) = sentry.hand_shake(tonic::Request::new(())).await {
+            error!("Failed to handshake with sentry: {:?}", err);
+        };
+        if let Err(err) = sentry.set_status(tonic::Request::new(status_data)).await {
+            error!("Failed to set sentry status: {:?}", err);
         }
     }
-    async fn set_status(&self, status_data: grpc_sentry::StatusData) {
-        self.sentries
-            .clone()
-            .into_iter()
-            .map(move |mut sentry| {
-                let status_data = status_data.clone();
 
-                async move {
-                    if let Err(err) = sentry.hand_shake(tonic::Request::new(())).await {
-                        error!("Failed to handshake with sentry: {:?}", err);
-                    };
-                    if let Err(err) = sentry.set_status(tonic::Request::new(status_data)).await {
-                        error!("Failed to set sentry status: {:?}", err);
-                    }
-                }
-            })
+    /// Unhealthy sentries get the status once they recover.
+    async fn set_status(&self, status_data: grpc_sentry::StatusData) {
+        self.healthy_sentries()
+            .map(move |(_, sentry)| Self::send_status(sentry, status_data.clone()))
             .collect::<FuturesUnordered<_>>()
             .map(|_| ())
             .collect::<()>()
@@ -0,0 +1,277 @@
+use super::{PeerId, RequestId, SentryId};
+use parking_lot::Mutex;
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+pub type PeerKey = (SentryId, PeerId);
+
+/// Maximum number of scheduled requests a single peer may have in flight.
+pub const MAX_IN_FLIGHT_PER_PEER: usize = 4;
+/// Scheduled requests not answered within this time are considered lost and can be retried.
+pub const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Assumed throughput of peers that have not responded yet, high enough for them to be tried.
+const INITIAL_THROUGHPUT: f64 = 10_000.0;
+/// Weight of the latest sample in smoothed latency and throughput.
+const SMOOTHING: f64 = 0.25;
+
+fn smooth(prev: Option<f64>, sample: f64) -> f64 {
+    prev.map_or(sample, |prev| prev + (sample - prev) * SMOOTHING)
+}
+
+/// Kind of scheduled requests, peers are measured separately for each: serving bodies takes a lot
+/// more than serving headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RequestKind {
+    Headers,
+    Bodies,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ResponseStats {
+    /// Smoothed time to response.
+    pub latency: Option<Duration>,
+    /// Smoothed number of items received per second.
+    pub throughput: Option<f64>,
+    /// Timed out requests since the last response.
+    pub timeouts: u32,
+}
+
+impl ResponseStats {
+    fn score(&self) -> f64 {
+        // Every timeout in a row halves the score
+        self.throughput.unwrap_or(INITIAL_THROUGHPUT) / 2_f64.powi(self.timeouts.min(32) as i32)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PeerStats {
+    pub headers: ResponseStats,
+    pub bodies: ResponseStats,
+    /// Requests of any kind in flight.
+    pub in_flight: usize,
+}
+
+impl PeerStats {
+    pub fn of(&self, kind: RequestKind) -> &ResponseStats {
+        match kind {
+            RequestKind::Headers => &self.headers,
+            RequestKind::Bodies => &self.bodies,
+        }
+    }
+
+    fn of_mut(&mut self, kind: RequestKind) -> &mut ResponseStats {
+        match kind {
+            RequestKind::Headers => &mut self.headers,
+            RequestKind::Bodies => &mut self.bodies,
+        }
+    }
+
+    fn has_capacity(&self) -> bool {
+        self.in_flight < MAX_IN_FLIGHT_PER_PEER
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct InFlight {
+    peer: PeerKey,
+    kind: RequestKind,
+    sent_at: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    peers: HashMap<PeerKey, PeerStats>,
+    requests: HashMap<RequestId, InFlight>,
+}
+
+impl Inner {
+    fn finish(&mut self, request_id: RequestId) -> Option<(InFlight, &mut ResponseStats)> {
+        let request = self.requests.remove(&request_id)?;
+        let stats = self.peers.get_mut(&request.peer)?;
+        stats.in_flight = stats.in_flight.saturating_sub(1);
+        Some((request, stats.of_mut(request.kind)))
+    }
+}
+
+/// Tracks latency and throughput of peers and assigns requests to the best ones that are not
+/// saturated yet.
+#[derive(Debug, Default)]
+pub struct RequestScheduler {
+    inner: Mutex<Inner>,
+}
+
+impl RequestScheduler {
+    pub fn add_peer(&self, peer: PeerKey) {
+        self.inner.lock().peers.entry(peer).or_default();
+    }
+
+    /// Forgets the peer, its requests in flight become free to be retried.
+    pub fn remove_peer(&self, peer: PeerKey) {
+        let mut inner = self.inner.lock();
+        if inner.peers.remove(&peer).is_some() {
+            inner.requests.retain(|_, request| request.peer != peer);
+        }
+    }
+
+    pub fn num_peers(&self) -> usize {
+        self.inner.lock().peers.len()
+    }
+
+    pub fn stats(&self, peer: PeerKey) -> Option<PeerStats> {
+        self.inner.lock().peers.get(&peer).copied()
+    }
+
+    /// Peers that can take another request, best at serving `kind` first.
+    pub fn available_peers(&self, kind: RequestKind) -> Vec<PeerKey> {
+        let inner = self.inner.lock();
+        let mut peers = inner
+            .peers
+            .iter()
+            .filter(|(_, stats)| stats.has_capacity())
+            .map(|(&peer, stats)| (peer, stats.of(kind).score()))
+            .collect::<Vec<_>>();
+        peers.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        peers.into_iter().map(|(peer, _)| peer).collect()
+    }
+
+    pub fn is_pending(&self, request_id: RequestId) -> bool {
+        self.inner.lock().requests.contains_key(&request_id)
+    }
+
+    pub fn sent(&self, request_id: RequestId, peer: PeerKey, kind: RequestKind, now: Instant) {
+        let mut inner = self.inner.lock();
+        inner.peers.entry(peer).or_default().in_flight += 1;
+        inner.requests.insert(
+            request_id,
+            InFlight {
+                peer,
+                kind,
+                sent_at: now,
+            },
+        );
+    }
+
+    /// Records a response with `items` entries. Returns `false` if the request was not scheduled,
+    /// has already been completed or came from another peer.
+    pub fn received(
+        &self,
+        request_id: RequestId,
+        peer: PeerKey,
+        items: usize,
+        now: Instant,
+    ) -> bool {
+        let mut inner = self.inner.lock();
+        if inner.requests.get(&request_id).map(|request| request.peer) != Some(peer) {
+            return false;
+        }
+
+        if let Some((request, stats)) = inner.finish(request_id) {
+            let elapsed = now.saturating_duration_since(request.sent_at);
+            let elapsed_secs = elapsed.as_secs_f64().max(0.001);
+
+            stats.latency = Some(Duration::from_secs_f64(smooth(
+                stats.latency.map(|latency| latency.as_secs_f64()),
+                elapsed_secs,
+            )));
+            stats.throughput = Some(smooth(stats.throughput, items as f64 / elapsed_secs));
+            stats.timeouts = 0;
+        }
+
+        true
+    }
+
+    /// Marks the request as lost, penalizing the peer it was sent to.
+    pub fn timed_out(&self, request_id: RequestId) {
+        if let Some((request, stats)) = self.inner.lock().finish(request_id) {
+            stats.timeouts += 1;
+            debug!(
+                "{:?} request {request_id} to {}/{} timed out ({} in a row)",
+                request.kind, request.peer.0, request.peer.1, stats.timeouts
+            );
+        }
+    }
+
+    /// Times out all requests sent more than `REQUEST_TIMEOUT` ago, returning their IDs.
+    pub fn expire(&self, now: Instant) -> Vec<RequestId> {
+        let expired = self
+            .inner
+            .lock()
+            .requests
+            .iter()
+            .filter(|(_, request)| {
+                now.saturating_duration_since(request.sent_at) >= REQUEST_TIMEOUT
+            })
+            .map(|(&request_id, _)| request_id)
+            .collect::<Vec<_>>();
+
+        for &request_id in &expired {
+            self.timed_out(request_id);
+        }
+
+        expired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schedules_fastest_peers() {
+        let scheduler = RequestScheduler::default();
+        let fast = (0, PeerId::repeat_byte(1));
+        let slow = (0, PeerId::repeat_byte(2));
+        let now = Instant::now();
+
+        scheduler.add_peer(fast);
+        scheduler.add_peer(slow);
+
+        scheduler.sent(1, fast, RequestKind::Headers, now);
+        scheduler.sent(2, slow, RequestKind::Headers, now);
+        assert!(!scheduler.received(1, slow, 100, now));
+        assert!(scheduler.received(1, fast, 100, now + Duration::from_millis(100)));
+        assert!(scheduler.received(2, slow, 100, now + Duration::from_secs(5)));
+        assert!(!scheduler.is_pending(1));
+        assert_eq!(
+            scheduler.available_peers(RequestKind::Headers),
+            vec![fast, slow]
+        );
+
+        // Serving headers fast says nothing about bodies.
+        scheduler.sent(3, fast, RequestKind::Bodies, now);
+        scheduler.sent(4, slow, RequestKind::Bodies, now);
+        assert!(scheduler.received(3, fast, 1, now + Duration::from_secs(5)));
+        assert!(scheduler.received(4, slow, 100, now + Duration::from_secs(1)));
+        assert_eq!(
+            scheduler.available_peers(RequestKind::Bodies),
+            vec![slow, fast]
+        );
+        assert_eq!(
+            scheduler.available_peers(RequestKind::Headers),
+            vec![fast, slow]
+        );
+
+        for request_id in 0..MAX_IN_FLIGHT_PER_PEER as u64 {
+            scheduler.sent(10 + request_id, fast, RequestKind::Headers, now);
+        }
+        assert_eq!(scheduler.available_peers(RequestKind::Bodies), vec![slow]);
+
+        assert_eq!(
+            scheduler.expire(now + REQUEST_TIMEOUT).len(),
+            MAX_IN_FLIGHT_PER_PEER
+        );
+        let stats = scheduler.stats(fast).unwrap();
+        assert_eq!(stats.in_flight, 0);
+        assert_eq!(stats.headers.timeouts, MAX_IN_FLIGHT_PER_PEER as u32);
+        assert_eq!(stats.bodies.timeouts, 0);
+        assert_eq!(scheduler.available_peers(RequestKind::Headers).len(), 2);
+
+        scheduler.sent(20, slow, RequestKind::Bodies, now);
+        scheduler.remove_peer(slow);
+        assert!(!scheduler.is_pending(20));
+        assert_eq!(scheduler.num_peers(), 1);
+    }
+}
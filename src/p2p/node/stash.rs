@@ -1,7 +1,7 @@
 use crate::{
-    accessors::chain,
+    accessors::{chain, state},
     kv::{tables, MdbxWithDirHandle},
-    models::{BlockBody, BlockHeader, BlockNumber, H256},
+    models::{Account, Address, BlockBody, BlockHeader, BlockNumber, H256},
     p2p::types::{BlockId, GetBlockHeadersParams},
 };
 use mdbx::EnvironmentKind;
@@ -10,6 +10,8 @@ use std::fmt::Debug;
 pub trait Stash: Send + Sync + Debug {
     fn get_headers(&self, _: GetBlockHeadersParams) -> anyhow::Result<Vec<BlockHeader>>;
     fn get_bodies(&self, _: Vec<H256>) -> anyhow::Result<Vec<BlockBody>>;
+    /// Latest state of the account, for checking transactions before relaying them.
+    fn get_account(&self, _: Address) -> anyhow::Result<Option<Account>>;
 }
 
 impl Stash for () {
@@ -19,6 +21,9 @@ impl Stash for () {
     fn get_bodies(&self, _: Vec<H256>) -> anyhow::Result<Vec<BlockBody>> {
         Ok(vec![])
     }
+    fn get_account(&self, _: Address) -> anyhow::Result<Option<Account>> {
+        Ok(None)
+    }
 }
 
 impl<E> Stash for MdbxWithDirHandle<E>
@@ -42,7 +47,6 @@ where
 
         let mut headers = Vec::with_capacity(limit as usize);
         let mut number_cursor = txn.cursor(tables::HeaderNumber)?;
-        let mut header_cursor = txn.cursor(tables::Header)?;
 
         let mut next_number = match params.start {
             BlockId::Hash(hash) => number_cursor.seek_exact(hash)?.map(|(_, k)| k),
@@ -52,7 +56,7 @@ where
         for _ in 0..limit {
             match next_number {
                 Some(block_number) => {
-                    if let Some((_, header)) = header_cursor.seek_exact(block_number)? {
+                    if let Some(header) = chain::header::read(&txn, block_number)? {
                         headers.push(header);
                     }
                     next_number = u64::try_from(block_number.0 as i64 + add_op)
@@ -77,4 +81,8 @@ where
             })
             .collect::<Vec<_>>())
     }
+
+    fn get_account(&self, address: Address) -> anyhow::Result<Option<Account>> {
+        state::account::read(&self.begin()?, address, None)
+    }
 }
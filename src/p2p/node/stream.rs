@@ -1,24 +1,36 @@
-use super::Sentry;
-use crate::p2p::types::InboundMessage;
+use super::{PeerId, Sentry};
+use crate::{crypto::keccak256, metrics, models::H256, p2p::types::InboundMessage};
 use ethereum_interfaces::sentry::{self as grpc_sentry, PenalizePeerRequest};
 use futures::Stream;
-use std::{pin::Pin, time::Duration};
+use hashlink::LruCache;
+use parking_lot::Mutex;
+use std::{pin::Pin, sync::Arc, time::Duration};
 use tokio::sync::mpsc;
 use tokio_stream::StreamExt;
+use tracing::*;
 
 pub struct SentryStream;
 
 pub type NodeStream = Pin<Box<dyn Stream<Item = InboundMessage> + Send>>;
 
+/// Messages recently received from any sentry, so that copies of a message arriving from the same
+/// peer via several sentries are delivered once.
+type SeenMessages = Arc<Mutex<LruCache<(PeerId, i32, H256), ()>>>;
+
 impl SentryStream {
     const BACKOFF: Duration = Duration::from_millis(100);
+    const MAX_BACKOFF: Duration = Duration::from_secs(30);
+    const MAX_SEEN_MESSAGES: usize = 4096;
 
+    /// Subscribes to messages from the sentry, resubscribing with backoff whenever the
+    /// subscription fails or ends.
     #[allow(clippy::new_ret_no_self)]
-    pub async fn new(
+    fn new(
         sentry: &Sentry,
         sentry_id: usize,
         pred: Vec<i32>,
-    ) -> anyhow::Result<NodeStream> {
+        seen: Option<SeenMessages>,
+    ) -> NodeStream {
         let (penalize_tx, mut penalize_rx) = mpsc::channel(4);
         tokio::task::spawn({
             let mut sentry = sentry.clone();
@@ -34,29 +46,47 @@ impl SentryStream {
             }
         });
 
-        let stream = {
-            let mut sentry = sentry.clone();
-            let mut inner_stream = sentry
-                .messages(grpc_sentry::MessagesRequest { ids: pred })
-                .await?
-                .into_inner();
+        let mut sentry = sentry.clone();
+        Box::pin(async_stream::stream! {
+            let mut backoff = Self::BACKOFF;
+            loop {
+                match sentry
+                    .messages(grpc_sentry::MessagesRequest { ids: pred.clone() })
+                    .await
+                {
+                    Ok(inner_stream) => {
+                        backoff = Self::BACKOFF;
+                        let mut inner_stream = inner_stream.into_inner();
+                        while let Some(Ok(msg)) = inner_stream.next().await {
+                            let peer_id = msg.peer_id.clone();
+                            let id = msg.id;
+                            let data_hash = seen.as_ref().map(|_| keccak256(&msg.data));
 
-            Box::pin(async_stream::stream! {
-                loop {
-                    if let Some(Ok(msg)) = inner_stream.next().await {
-                        let peer_id = msg.peer_id.clone();
+                            if let Ok(msg) = InboundMessage::new(msg, sentry_id) {
+                                if let (Some(seen), Some(data_hash)) = (&seen, data_hash) {
+                                    let key = (msg.peer_id, id, data_hash);
+                                    if seen.lock().insert(key, ()).is_some() {
+                                        continue;
+                                    }
+                                }
 
-                        if let Ok(msg) = InboundMessage::new(msg, sentry_id) {
-                            yield msg;
-                        } else {
-                            let _ = penalize_tx.send(peer_id).await;
+                                metrics::record_message_received(msg.msg.id());
+                                yield msg;
+                            } else {
+                                let _ = penalize_tx.send(peer_id).await;
+                            }
                         }
+                        debug!("Message stream from sentry {sentry_id} ended, resubscribing");
+                    }
+                    Err(e) => {
+                        debug!("Failed to subscribe to messages from sentry {sentry_id}: {e}")
                     }
                 }
-            })
-        };
 
-        Ok::<_, anyhow::Error>(stream)
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(Self::MAX_BACKOFF);
+            }
+        })
     }
 
     pub async fn join_all<'sentry, T, P>(iter: T, pred: P) -> NodeStream
@@ -65,17 +95,15 @@ impl SentryStream {
         P: IntoIterator<Item = i32>,
     {
         let pred = pred.into_iter().collect::<Vec<_>>();
+        let sentries = iter.into_iter().collect::<Vec<_>>();
+        // The same peer may be connected to more than one sentry
+        let seen = (sentries.len() > 1)
+            .then(|| Arc::new(Mutex::new(LruCache::new(Self::MAX_SEEN_MESSAGES))));
 
         Box::pin(futures::stream::select_all(
-            futures::future::join_all(
-                iter.into_iter()
-                    .enumerate()
-                    .map(|(sentry_id, sentry)| Self::new(sentry, sentry_id, pred.clone()))
-                    .collect::<Vec<_>>(),
-            )
-            .await
-            .into_iter()
-            .filter_map(Result::ok),
+            sentries.into_iter().enumerate().map(|(sentry_id, sentry)| {
+                Self::new(sentry, sentry_id, pred.clone(), seen.clone())
+            }),
         ))
     }
 }
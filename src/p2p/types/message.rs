@@ -9,7 +9,7 @@ use fastrlp::*;
 use rand::Rng;
 use std::fmt::Display;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::EnumIter)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, strum::EnumIter, strum::IntoStaticStr)]
 pub enum MessageId {
     Status = 0,
     NewBlockHashes = 1,
@@ -13,18 +13,34 @@ pub enum PenaltyKind {
     TooFarPast,
 }
 
+impl PenaltyKind {
+    /// Reputation points the peer loses. A loss of 500 gets the peer kicked, and sentries ban
+    /// peers kicked again soon after, so no single offence bans a peer: a faulty block may come
+    /// from an honest peer relaying it.
+    pub fn weight(&self) -> i32 {
+        match self {
+            Self::BadBlock | Self::InvalidSeal => 500,
+            Self::WrongChildBlockHeight | Self::WrongChildDifficulty => 300,
+            Self::TooFarFuture => 200,
+            Self::TooFarPast => 100,
+            Self::DuplicateHeader => 50,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Penalty {
     pub peer_id: PeerId,
     pub kind: PenaltyKind,
 }
 
+/// Sentries are only asked to kick the peer, weights are accounted for by the node.
 impl From<Penalty> for grpc_sentry::PenalizePeerRequest {
     #[inline(always)]
     fn from(penalty: Penalty) -> Self {
         grpc_sentry::PenalizePeerRequest {
             peer_id: Some(penalty.peer_id.into()),
-            penalty: 0,
+            penalty: grpc_sentry::PenaltyKind::Kick as i32,
         }
     }
 }
@@ -0,0 +1,428 @@
+//! Replays of a single stage over a block range, for catching sync performance regressions.
+//!
+//! Replays modify the database they run on, so they are meant to be run on a throwaway copy.
+
+use crate::{
+    execution::analysis_cache::AnalysisCache,
+    kv::{mdbx::*, tables},
+    models::*,
+    stagedsync::stage::*,
+    stages::{stage_util::IndexParams, *},
+};
+use anyhow::{bail, ensure, format_err};
+use serde::{Deserialize, Serialize};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+use tempfile::TempDir;
+use tracing::*;
+
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+static LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+static PEAK_LIVE_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// System allocator counting allocations. Allocation stats of replays are only collected when
+/// the binary installs it as `#[global_allocator]`, otherwise they are zero.
+pub struct CountingAllocator;
+
+impl CountingAllocator {
+    fn record_alloc(size: usize) {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+        let live = LIVE_BYTES.fetch_add(size as u64, Ordering::Relaxed) + size as u64;
+        PEAK_LIVE_BYTES.fetch_max(live, Ordering::Relaxed);
+    }
+
+    fn record_dealloc(size: usize) {
+        LIVE_BYTES.fetch_sub(size as u64, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            Self::record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        Self::record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            Self::record_dealloc(layout.size());
+            Self::record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+/// Stages that can be replayed, in the order they are unwound.
+fn pipeline<'db, E>(temp_dir: Arc<TempDir>, to: BlockNumber) -> Vec<Box<dyn Stage<'db, E>>>
+where
+    E: EnvironmentKind,
+{
+    let index_params = IndexParams {
+        temp_dir: temp_dir.clone(),
+        flush_interval: 50_000,
+    };
+
+    vec![
+        Box::new(SenderNonceIndex {
+            temp_dir: temp_dir.clone(),
+        }) as Box<dyn Stage<'db, E>>,
+        Box::new(ContractCreatorIndex),
+        Box::new(CallTraceIndex {
+            temp_dir: temp_dir.clone(),
+            flush_interval: 50_000,
+        }),
+        Box::new(TxLookup {
+            temp_dir: temp_dir.clone(),
+        }),
+        Box::new(LogAddressIndex(index_params.clone())),
+        Box::new(LogTopicIndex(index_params.clone())),
+        Box::new(StorageHistoryIndex(index_params.clone())),
+        Box::new(AccountHistoryIndex(index_params)),
+        Box::new(HashState::new(temp_dir.clone(), None)),
+        Box::new(Interhashes::new(temp_dir, None)),
+        Box::new(Execution {
+            max_block: Some(to),
+            batch_size: 5_000_000_000_000,
+            history_batch_size: 250_000_000_000,
+            exit_after_batch: false,
+            batch_until: None,
+            commit_every: None,
+            parallel: false,
+            analysis_cache: AnalysisCache::default(),
+        }),
+    ]
+}
+
+async fn unwind<'db, E>(
+    db: &'db MdbxEnvironment<E>,
+    stage: &mut Box<dyn Stage<'db, E>>,
+    unwind_to: BlockNumber,
+) -> anyhow::Result<()>
+where
+    E: EnvironmentKind,
+{
+    let stage_id = stage.id();
+    let mut tx = db.begin_mutable()?;
+    let mut stage_progress = stage_id.get_progress(&tx)?.unwrap_or_default();
+    if stage_progress > unwind_to {
+        info!("Unwinding {stage_id} from #{stage_progress} to #{unwind_to}");
+    }
+    while stage_progress > unwind_to {
+        stage_progress = stage
+            .unwind(
+                &mut tx,
+                UnwindInput {
+                    stage_progress,
+                    unwind_to,
+                    bad_block: None,
+                },
+            )
+            .await?
+            .stage_progress;
+        stage_id.save_progress(&tx, stage_progress)?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn db_size<E: EnvironmentKind>(db: &MdbxEnvironment<E>) -> anyhow::Result<u64> {
+    Ok(db.begin()?.table_sizes()?.values().sum())
+}
+
+/// Bytes read from and written to storage by this process, Linux only.
+fn process_io() -> Option<(u64, u64)> {
+    let io = std::fs::read_to_string("/proc/self/io").ok()?;
+    let field = |name: &str| {
+        io.lines()
+            .find_map(|line| line.strip_prefix(name)?.trim().parse::<u64>().ok())
+    };
+    Some((field("read_bytes:")?, field("write_bytes:")?))
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayReport {
+    pub stage: String,
+    pub from: BlockNumber,
+    pub to: BlockNumber,
+    pub transactions: u64,
+    pub gas: u64,
+    pub seconds: f64,
+    pub blocks_per_second: f64,
+    pub mgas_per_second: f64,
+    pub allocations: u64,
+    pub allocated_bytes: u64,
+    /// Peak of heap in use during the replay.
+    pub peak_heap_bytes: u64,
+    /// Change of the total size of database pages in use.
+    pub db_growth_bytes: i64,
+    pub io_read_bytes: Option<u64>,
+    pub io_write_bytes: Option<u64>,
+}
+
+impl ReplayReport {
+    /// Describes every measurement worse than in `baseline` by more than `tolerance`, a
+    /// fraction of the baseline value.
+    pub fn regressions(&self, baseline: &Self, tolerance: f64) -> anyhow::Result<Vec<String>> {
+        ensure!(
+            self.stage == baseline.stage && self.from == baseline.from && self.to == baseline.to,
+            "baseline is of {} over blocks #{}..=#{}",
+            baseline.stage,
+            baseline.from,
+            baseline.to
+        );
+
+        let mut out = Vec::new();
+        let mut check = |name: &str, value: f64, baseline: f64| {
+            if value > baseline * (1.0 + tolerance) {
+                out.push(format!("{name} is {value}, baseline {baseline}"));
+            }
+        };
+        check("time in seconds", self.seconds, baseline.seconds);
+        check(
+            "allocations",
+            self.allocations as f64,
+            baseline.allocations as f64,
+        );
+        check(
+            "allocated bytes",
+            self.allocated_bytes as f64,
+            baseline.allocated_bytes as f64,
+        );
+        check(
+            "peak heap bytes",
+            self.peak_heap_bytes as f64,
+            baseline.peak_heap_bytes as f64,
+        );
+        if let (Some(value), Some(baseline)) = (self.io_read_bytes, baseline.io_read_bytes) {
+            check("read bytes", value as f64, baseline as f64);
+        }
+        if let (Some(value), Some(baseline)) = (self.io_write_bytes, baseline.io_write_bytes) {
+            check("written bytes", value as f64, baseline as f64);
+        }
+
+        Ok(out)
+    }
+}
+
+/// Rolls the database back to block `to`, then `stage` further back to `from - 1`, and
+/// measures executing it up to `to` again. The database must be synced past `to`.
+pub async fn replay_stage<'db, E>(
+    db: &'db MdbxEnvironment<E>,
+    temp_dir: Arc<TempDir>,
+    stage: &str,
+    from: BlockNumber,
+    to: BlockNumber,
+) -> anyhow::Result<ReplayReport>
+where
+    E: EnvironmentKind,
+{
+    ensure!(
+        from > 0 && from <= to,
+        "invalid block range #{from}..=#{to}"
+    );
+
+    let mut stages = pipeline(temp_dir, to);
+    let target = stages
+        .iter()
+        .position(|s| s.id().0 == stage)
+        .ok_or_else(|| {
+            format_err!(
+                "stage {stage} cannot be replayed, replayable stages are: {}",
+                stages
+                    .iter()
+                    .map(|s| s.id().0)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )
+        })?;
+
+    {
+        let tx = db.begin()?;
+        for s in &stages {
+            let progress = s.id().get_progress(&tx)?.unwrap_or_default();
+            ensure!(
+                progress >= to,
+                "{} is at block #{progress}, database must be synced past #{to}",
+                s.id()
+            );
+        }
+    }
+
+    for s in &mut stages {
+        unwind(db, s, to).await?;
+    }
+    let start_progress = BlockNumber(from.0 - 1);
+    let stage = &mut stages[target];
+    unwind(db, stage, start_progress).await?;
+
+    let stage_id = stage.id();
+    let previous_stage = if stage_id == EXECUTION {
+        SENDERS
+    } else {
+        EXECUTION
+    };
+
+    let (gas, transactions) = {
+        let tx = db.begin()?;
+        let gas = |block| {
+            tx.get(tables::TotalGas, block)?
+                .ok_or_else(|| format_err!("no cumulative gas for block #{block}"))
+        };
+        let transactions = |block| {
+            tx.get(tables::TotalTx, block)?
+                .ok_or_else(|| format_err!("no cumulative transaction count for block #{block}"))
+        };
+        (
+            gas(to)? - gas(start_progress)?,
+            transactions(to)? - transactions(start_progress)?,
+        )
+    };
+
+    info!("Replaying {stage_id} over blocks #{from}..=#{to}");
+
+    let db_size_before = db_size(db)?;
+    let io_before = process_io();
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    let allocated_bytes_before = ALLOCATED_BYTES.load(Ordering::Relaxed);
+    PEAK_LIVE_BYTES.store(LIVE_BYTES.load(Ordering::Relaxed), Ordering::Relaxed);
+    let started_at = Instant::now();
+
+    let mut stage_progress = start_progress;
+    while stage_progress < to {
+        let mut tx = db.begin_mutable()?;
+        match stage
+            .execute(
+                &mut tx,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (started_at, Some(start_progress)),
+                    previous_stage: Some((previous_stage, to)),
+                    stage_progress: Some(stage_progress),
+                },
+            )
+            .await
+        {
+            Ok(ExecOutput::Progress {
+                stage_progress: new_progress,
+                done,
+                ..
+            }) => {
+                stage_id.save_progress(&tx, new_progress)?;
+                tx.commit()?;
+
+                if done {
+                    break;
+                }
+                if new_progress <= stage_progress {
+                    bail!("{stage_id} made no progress past block #{stage_progress}");
+                }
+                stage_progress = new_progress;
+            }
+            Ok(ExecOutput::Unwind { unwind_to }) => {
+                bail!("{stage_id} requested unwind to block #{unwind_to}")
+            }
+            Err(StageError::Validation { block, error }) => {
+                bail!("block #{block} failed validation: {error:?}")
+            }
+            Err(StageError::Internal(e)) => return Err(e),
+        }
+    }
+
+    let seconds = started_at.elapsed().as_secs_f64();
+    let blocks = (to.0 - start_progress.0) as f64;
+    let io = process_io()
+        .zip(io_before)
+        .map(|((read, written), (read_before, written_before))| {
+            (read - read_before, written - written_before)
+        });
+
+    Ok(ReplayReport {
+        stage: stage_id.0.to_string(),
+        from,
+        to,
+        transactions,
+        gas,
+        seconds,
+        blocks_per_second: blocks / seconds,
+        mgas_per_second: gas as f64 / 1_000_000.0 / seconds,
+        allocations: ALLOCATIONS.load(Ordering::Relaxed) - allocations_before,
+        allocated_bytes: ALLOCATED_BYTES.load(Ordering::Relaxed) - allocated_bytes_before,
+        peak_heap_bytes: PEAK_LIVE_BYTES.load(Ordering::Relaxed),
+        db_growth_bytes: db_size(db)? as i64 - db_size_before as i64,
+        io_read_bytes: io.map(|(read, _)| read),
+        io_write_bytes: io.map(|(_, written)| written),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regressions() {
+        let baseline = ReplayReport {
+            stage: EXECUTION.0.to_string(),
+            from: BlockNumber(1),
+            to: BlockNumber(100),
+            transactions: 1000,
+            gas: 100_000_000,
+            seconds: 10.0,
+            blocks_per_second: 10.0,
+            mgas_per_second: 10.0,
+            allocations: 1000,
+            allocated_bytes: 100_000,
+            peak_heap_bytes: 10_000,
+            db_growth_bytes: 0,
+            io_read_bytes: Some(1000),
+            io_write_bytes: None,
+        };
+
+        let report = ReplayReport {
+            seconds: 10.5,
+            allocations: 1200,
+            io_read_bytes: Some(2000),
+            io_write_bytes: Some(1000),
+            ..baseline.clone()
+        };
+        assert_eq!(
+            report.regressions(&baseline, 0.1).unwrap(),
+            vec![
+                "allocations is 1200, baseline 1000".to_string(),
+                "read bytes is 2000, baseline 1000".to_string(),
+            ]
+        );
+        assert!(report.regressions(&report, 0.0).unwrap().is_empty());
+
+        let other = ReplayReport {
+            to: BlockNumber(200),
+            ..baseline.clone()
+        };
+        assert!(report.regressions(&other, 0.1).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+use crate::{
+    backup::{self, SnapshotInfo},
+    binutil::HanaDataDir,
+    kv::{mdbx::*, MdbxWithDirHandle},
+    sentry::{
+        devp2p::{DisconnectReason, NodeRecord, OutboundEvent, PeerId, Swarm},
+        eth::capability_name,
+        CapabilityServerImpl,
+    },
+};
+use anyhow::format_err;
+use jsonrpsee::core::{server::rpc_module::RpcModule, Error as RpcError};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{net::SocketAddr, sync::Arc};
+
+/// `admin` namespace: management of peers of the sentry running in this process.
+pub struct AdminApiServerImpl {
+    pub swarm: Arc<Swarm<CapabilityServerImpl>>,
+    pub listen_addr: SocketAddr,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfoPorts {
+    pub listener: u16,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeInfo {
+    pub id: String,
+    pub name: String,
+    pub enode: String,
+    pub listen_addr: SocketAddr,
+    pub ports: NodeInfoPorts,
+    pub protocols: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerNetworkInfo {
+    pub remote_address: Option<SocketAddr>,
+    pub inbound: bool,
+    pub trusted: bool,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerInfo {
+    pub id: String,
+    pub enode: Option<String>,
+    pub caps: Vec<String>,
+    pub network: PeerNetworkInfo,
+}
+
+fn parse_enode(enode: &str) -> anyhow::Result<NodeRecord> {
+    enode
+        .parse()
+        .map_err(|e| format_err!("invalid enode {enode}: {e}"))
+}
+
+impl AdminApiServerImpl {
+    fn node_info(&self) -> NodeInfo {
+        let id = self.swarm.local_id();
+        NodeInfo {
+            id: hex::encode(id),
+            name: self.swarm.client_version().to_string(),
+            enode: NodeRecord {
+                id,
+                addr: self.listen_addr,
+            }
+            .to_string(),
+            listen_addr: self.listen_addr,
+            ports: NodeInfoPorts {
+                listener: self.swarm.port(),
+            },
+            protocols: vec![capability_name().to_string()],
+        }
+    }
+
+    fn peers(&self) -> Vec<PeerInfo> {
+        let mut peers = self
+            .swarm
+            .connected_peers_info()
+            .into_iter()
+            .map(|(id, info)| PeerInfo {
+                id: hex::encode(id),
+                enode: info
+                    .remote_addr
+                    .map(|addr| NodeRecord { id, addr }.to_string()),
+                caps: info
+                    .capabilities
+                    .iter()
+                    .map(|cap| format!("{}/{}", cap.name, cap.version))
+                    .collect(),
+                network: PeerNetworkInfo {
+                    remote_address: info.remote_addr,
+                    inbound: info.inbound,
+                    trusted: self.swarm.is_trusted(id),
+                },
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by(|a, b| a.id.cmp(&b.id));
+        peers
+    }
+
+    async fn add_peer(&self, enode: String) -> anyhow::Result<bool> {
+        self.swarm.add_peer(parse_enode(&enode)?).await
+    }
+
+    fn add_trusted_peer(&self, enode: String) -> anyhow::Result<bool> {
+        Ok(self.swarm.add_trusted_peer(parse_enode(&enode)?))
+    }
+
+    async fn disconnect(&self, id: PeerId) -> bool {
+        if let Some(sender) = self.swarm.sender(id) {
+            return sender
+                .send(OutboundEvent::Disconnect {
+                    reason: DisconnectReason::DisconnectRequested,
+                })
+                .await
+                .is_ok();
+        }
+
+        false
+    }
+
+    /// Disconnects the peer, trusted peers are reconnected to later.
+    async fn remove_peer(&self, enode: String) -> anyhow::Result<bool> {
+        let NodeRecord { id, .. } = parse_enode(&enode)?;
+        Ok(self.disconnect(id).await)
+    }
+
+    fn remove_trusted_peer(&self, enode: String) -> anyhow::Result<bool> {
+        let NodeRecord { id, .. } = parse_enode(&enode)?;
+        Ok(self.swarm.remove_trusted_peer(id))
+    }
+
+    pub fn into_rpc(self) -> RpcModule<Self> {
+        let mut module = RpcModule::new(self);
+
+        module
+            .register_method("admin_nodeInfo", |_, ctx| {
+                Ok::<_, RpcError>(ctx.node_info())
+            })
+            .unwrap();
+        module
+            .register_method("admin_peers", |_, ctx| Ok::<_, RpcError>(ctx.peers()))
+            .unwrap();
+        module
+            .register_async_method("admin_addPeer", |params, ctx| async move {
+                let enode = params.one::<String>()?;
+                Ok::<_, RpcError>(ctx.add_peer(enode).await?)
+            })
+            .unwrap();
+        module
+            .register_method("admin_addTrustedPeer", |params, ctx| {
+                let enode = params.one::<String>()?;
+                Ok::<_, RpcError>(ctx.add_trusted_peer(enode)?)
+            })
+            .unwrap();
+        module
+            .register_async_method("admin_removePeer", |params, ctx| async move {
+                let enode = params.one::<String>()?;
+                Ok::<_, RpcError>(ctx.remove_peer(enode).await?)
+            })
+            .unwrap();
+        module
+            .register_method("admin_removeTrustedPeer", |params, ctx| {
+                let enode = params.one::<String>()?;
+                Ok::<_, RpcError>(ctx.remove_trusted_peer(enode)?)
+            })
+            .unwrap();
+
+        module
+    }
+}
+
+/// `admin_backup`: consistent copy of the database into a new data directory while the node
+/// keeps running.
+pub struct BackupApiServerImpl<E>
+where
+    E: EnvironmentKind,
+{
+    db: Arc<MdbxWithDirHandle<E>>,
+    running: Arc<Mutex<()>>,
+}
+
+impl<E> BackupApiServerImpl<E>
+where
+    E: EnvironmentKind,
+{
+    pub fn new(db: Arc<MdbxWithDirHandle<E>>) -> Self {
+        Self {
+            db,
+            running: Arc::new(Mutex::new(())),
+        }
+    }
+
+    async fn backup(&self, dir: String) -> anyhow::Result<SnapshotInfo> {
+        let dest = dir
+            .parse::<HanaDataDir>()
+            .map_err(|e| format_err!("invalid backup directory {dir}: {e}"))?;
+        let db = self.db.clone();
+        let running = self.running.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let _guard = running
+                .try_lock()
+                .ok_or_else(|| format_err!("backup already in progress"))?;
+            backup::backup(&db, &dest)
+        })
+        .await
+        .unwrap_or_else(|e| Err(e.into()))
+    }
+
+    pub fn into_rpc(self) -> RpcModule<Self> {
+        let mut module = RpcModule::new(self);
+
+        module
+            .register_async_method("admin_backup", |params, ctx| async move {
+                let dir = params.one::<String>()?;
+                Ok::<_, RpcError>(ctx.backup(dir).await?)
+            })
+            .unwrap();
+
+        module
+    }
+}
@@ -0,0 +1,487 @@
+use super::helpers;
+use crate::{
+    accessors::chain,
+    consensus::engine_factory,
+    execution::{
+        analysis_cache::AnalysisCache,
+        evm::{OpCode, StatusCode},
+        evmglue,
+        processor::ExecutionProcessor,
+        tracer::{
+            debugger::{
+                Breakpoint, DebugSession, DebuggerEvent, DebuggerTracer, ExecutionResult, StepMode,
+            },
+            NoopTracer,
+        },
+    },
+    kv::{mdbx::*, tables, MdbxWithDirHandle},
+    models::*,
+    u256_to_h256, Buffer, IntraBlockState,
+};
+use anyhow::format_err;
+use ethereum_jsonrpc::types;
+use jsonrpsee::core::{server::rpc_module::RpcModule, Error as RpcError};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Weak},
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+/// Sessions idle for longer than this are stopped.
+pub const DEFAULT_SESSION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+/// Maximum number of concurrently open sessions.
+///
+/// Every session keeps a thread and a read transaction open until it is stopped.
+pub const MAX_SESSIONS: usize = 16;
+/// How often idle sessions are looked for in the background.
+const REAP_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Default)]
+struct Sessions {
+    next_id: u64,
+    /// Sessions being started, counted against the limit.
+    starting: usize,
+    active: HashMap<u64, (Instant, Arc<Mutex<DebugSession>>)>,
+}
+
+impl Sessions {
+    fn reap_idle(&mut self, timeout: Duration) -> usize {
+        let before = self.active.len();
+        self.active
+            .retain(|_, (last_used, _)| last_used.elapsed() < timeout);
+        before - self.active.len()
+    }
+}
+
+/// Stops idle sessions until the API is dropped, so that abandoned sessions do not hold their
+/// thread and read transaction until the next request.
+async fn reap_idle_sessions(sessions: Weak<Mutex<Sessions>>, timeout: Duration) {
+    let mut interval = tokio::time::interval(REAP_INTERVAL);
+    loop {
+        interval.tick().await;
+
+        let sessions = match sessions.upgrade() {
+            Some(sessions) => sessions,
+            None => return,
+        };
+        let reaped = sessions.lock().reap_idle(timeout);
+        if reaped > 0 {
+            debug!("Stopped {reaped} idle debug sessions");
+        }
+    }
+}
+
+/// `debug_session*` namespace: step-by-step execution of transactions and calls.
+pub struct DebugSessionApiServerImpl<SE>
+where
+    SE: EnvironmentKind,
+{
+    pub db: Arc<MdbxWithDirHandle<SE>>,
+    pub call_gas_limit: u64,
+    pub analysis_cache: AnalysisCache,
+    /// Idle timeout of sessions, also bounds the time execution may run without pausing.
+    pub timeout: Duration,
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum BreakpointRequest {
+    Pc { pc: usize, address: Option<Address> },
+    Opcode { opcode: String },
+}
+
+impl TryFrom<BreakpointRequest> for Breakpoint {
+    type Error = anyhow::Error;
+
+    fn try_from(breakpoint: BreakpointRequest) -> Result<Self, Self::Error> {
+        Ok(match breakpoint {
+            BreakpointRequest::Pc { pc, address } => Breakpoint::Pc {
+                code_address: address,
+                pc,
+            },
+            BreakpointRequest::Opcode { opcode } => Breakpoint::Opcode(
+                (0..=u8::MAX)
+                    .map(OpCode)
+                    .find(|op| op.name() != "UNDEFINED" && op.name().eq_ignore_ascii_case(&opcode))
+                    .ok_or_else(|| format_err!("unknown opcode {opcode}"))?,
+            ),
+        })
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotResponse {
+    pub pc: usize,
+    pub op: &'static str,
+    pub depth: u16,
+    pub gas_left: i64,
+    pub address: Address,
+    pub code_address: Address,
+    /// Stack items, top item last.
+    pub stack: Vec<H256>,
+    pub memory: types::Bytes,
+    /// Storage slots of `address` accessed so far.
+    pub storage: BTreeMap<H256, H256>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResultResponse {
+    pub success: bool,
+    pub gas_used: U64,
+    pub output: Option<types::Bytes>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum SessionStateResponse {
+    Paused(SnapshotResponse),
+    Finished(ResultResponse),
+    Failed { error: String },
+}
+
+impl From<&DebuggerEvent> for SessionStateResponse {
+    fn from(event: &DebuggerEvent) -> Self {
+        match event {
+            DebuggerEvent::Paused(snapshot) => Self::Paused(SnapshotResponse {
+                pc: snapshot.pc,
+                op: snapshot.opcode.name(),
+                depth: snapshot.depth,
+                gas_left: snapshot.gas_left,
+                address: snapshot.address,
+                code_address: snapshot.code_address,
+                stack: snapshot.stack.iter().copied().map(u256_to_h256).collect(),
+                memory: snapshot.memory.clone().into(),
+                storage: snapshot
+                    .storage
+                    .iter()
+                    .map(|(&location, &value)| (u256_to_h256(location), u256_to_h256(value)))
+                    .collect(),
+            }),
+            DebuggerEvent::Finished(ExecutionResult {
+                success,
+                gas_used,
+                output,
+            }) => Self::Finished(ResultResponse {
+                success: *success,
+                gas_used: (*gas_used).into(),
+                output: output.clone().map(From::from),
+            }),
+            DebuggerEvent::Failed(error) => Self::Failed {
+                error: error.clone(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartSessionResponse {
+    pub id: U64,
+    #[serde(flatten)]
+    pub state: SessionStateResponse,
+}
+
+impl<SE> DebugSessionApiServerImpl<SE>
+where
+    SE: EnvironmentKind,
+{
+    pub fn new(
+        db: Arc<MdbxWithDirHandle<SE>>,
+        call_gas_limit: u64,
+        analysis_cache: AnalysisCache,
+    ) -> Self {
+        Self {
+            db,
+            call_gas_limit,
+            analysis_cache,
+            timeout: DEFAULT_SESSION_TIMEOUT,
+            sessions: Default::default(),
+        }
+    }
+
+    fn start_session<F>(&self, f: F) -> anyhow::Result<StartSessionResponse>
+    where
+        F: FnOnce(&mut DebuggerTracer) -> anyhow::Result<ExecutionResult> + Send + 'static,
+    {
+        {
+            let mut sessions = self.sessions.lock();
+            sessions.reap_idle(self.timeout);
+            if sessions.active.len() + sessions.starting >= MAX_SESSIONS {
+                return Err(format_err!(
+                    "too many debug sessions open, stop some of them first"
+                ));
+            }
+            sessions.starting += 1;
+        }
+
+        let session = DebugSession::start(self.timeout, f);
+
+        let mut sessions = self.sessions.lock();
+        sessions.starting -= 1;
+        let session = session?;
+        let state = session.last_event().into();
+
+        let id = sessions.next_id;
+        sessions.next_id += 1;
+        sessions
+            .active
+            .insert(id, (Instant::now(), Arc::new(Mutex::new(session))));
+
+        Ok(StartSessionResponse {
+            id: id.into(),
+            state,
+        })
+    }
+
+    fn session(&self, id: U64) -> anyhow::Result<Arc<Mutex<DebugSession>>> {
+        let mut sessions = self.sessions.lock();
+        sessions.reap_idle(self.timeout);
+
+        let (last_used, session) = sessions
+            .active
+            .get_mut(&id.as_u64())
+            .ok_or_else(|| format_err!("debug session {id} not found"))?;
+        *last_used = Instant::now();
+
+        Ok(session.clone())
+    }
+
+    fn resume(&self, id: U64, mode: StepMode) -> anyhow::Result<SessionStateResponse> {
+        let session = self.session(id)?;
+        let res = session.lock().resume(mode).map(SessionStateResponse::from);
+        if res.is_err() {
+            // Session timed out or lost its execution, it cannot be stepped any further.
+            self.sessions.lock().active.remove(&id.as_u64());
+        }
+
+        res
+    }
+
+    fn start_transaction(&self, hash: H256) -> anyhow::Result<StartSessionResponse> {
+        let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
+
+        self.start_session(move |tracer| {
+            let txn = db.begin()?;
+
+            let block_number = chain::tl::read(&txn, hash)?
+                .ok_or_else(|| format_err!("transaction {hash} not found"))?;
+            let block_hash = chain::canonical_hash::read(&txn, block_number)?
+                .ok_or_else(|| format_err!("no canonical header for block #{block_number:?}"))?;
+            let header = chain::header::read(&txn, block_number)?.ok_or_else(|| {
+                format_err!("header not found for block #{block_number}/{block_hash}")
+            })?;
+            let block_body = chain::block_body::read_with_senders(&txn, block_number)?
+                .ok_or_else(|| format_err!("body not found for block #{block_number}/{block_hash}"))?;
+            let chain_spec = chain::chain_config::read(&txn)?
+                .ok_or_else(|| format_err!("chain specification not found"))?;
+
+            let transaction_index = chain::block_body::read_without_senders(&txn, block_number)?
+                .ok_or_else(|| format_err!("body not found for block #{block_number}/{block_hash}"))?
+                .transactions
+                .into_iter()
+                .position(|tx| tx.hash() == hash)
+                .ok_or_else(|| {
+                    format_err!(
+                        "transaction {hash} not found in block #{block_number}/{block_hash} despite lookup index"
+                    )
+                })?;
+            let tx = block_body
+                .transactions
+                .get(transaction_index)
+                .ok_or_else(|| {
+                    format_err!(
+                        "block #{block_number}/{block_hash} too short: tx #{transaction_index} not in body"
+                    )
+                })?;
+
+            // Replay preceding transactions without pausing.
+            let mut buffer = Buffer::new(&txn, Some(BlockNumber(block_number.0 - 1)));
+
+            let block_execution_spec = chain_spec.collect_block_spec(block_number);
+            let mut engine = engine_factory(None, chain_spec, None, None)?;
+            let mut noop_tracer = NoopTracer;
+
+            let mut processor = ExecutionProcessor::new(
+                &mut buffer,
+                &mut noop_tracer,
+                &mut analysis_cache,
+                &mut *engine,
+                &header,
+                &block_body,
+                &block_execution_spec,
+            );
+
+            let cumulative_gas_used = processor
+                .execute_block_no_post_validation_while(|i, _| i < transaction_index)?
+                .last()
+                .map(|receipt| receipt.cumulative_gas_used)
+                .unwrap_or(0);
+
+            processor.set_tracer(tracer);
+            let receipt = processor.execute_transaction(&tx.message, tx.sender)?;
+
+            Ok(ExecutionResult {
+                success: receipt.success,
+                gas_used: receipt.cumulative_gas_used - cumulative_gas_used,
+                output: None,
+            })
+        })
+    }
+
+    fn start_call(
+        &self,
+        call_data: types::MessageCall,
+        block_number: types::BlockNumber,
+    ) -> anyhow::Result<StartSessionResponse> {
+        let db = self.db.clone();
+        let call_gas_limit = self.call_gas_limit;
+        let mut analysis_cache = self.analysis_cache.clone();
+
+        self.start_session(move |tracer| {
+            let txn = db.begin()?;
+
+            let (block_number, block_hash) = helpers::resolve_block_id(&txn, block_number)?
+                .ok_or_else(|| format_err!("failed to resolve block {block_number:?}"))?;
+
+            let chain_id = txn
+                .get(tables::Config, ())?
+                .ok_or_else(|| format_err!("chain spec not found"))?
+                .params
+                .chain_id;
+
+            let header = chain::header::read(&txn, block_number)?
+                .ok_or_else(|| format_err!("Header not found for #{block_number}/{block_hash}"))?;
+
+            let mut buffer = Buffer::new(&txn, Some(block_number));
+
+            let (sender, message) = helpers::convert_message_call(
+                &buffer,
+                chain_id,
+                call_data,
+                &header,
+                U256::ZERO,
+                Some(call_gas_limit),
+            )?;
+
+            let mut state = IntraBlockState::new(&mut buffer);
+
+            let chain_spec = chain::chain_config::read(&txn)?
+                .ok_or_else(|| format_err!("no chainspec found"))?;
+            let block_spec = chain_spec.collect_block_spec(block_number);
+
+            let beneficiary =
+                engine_factory(None, chain_spec, None, None)?.get_beneficiary(&header);
+            let res = evmglue::execute(
+                &mut state,
+                tracer,
+                &mut analysis_cache,
+                &header,
+                &block_spec,
+                &message,
+                sender,
+                beneficiary,
+                message.gas_limit(),
+            )?;
+
+            Ok(ExecutionResult {
+                success: res.status_code == StatusCode::Success,
+                gas_used: message.gas_limit() - res.gas_left as u64,
+                output: Some(res.output_data),
+            })
+        })
+    }
+
+    fn set_breakpoints(
+        &self,
+        id: U64,
+        breakpoints: Vec<BreakpointRequest>,
+    ) -> anyhow::Result<bool> {
+        let breakpoints = breakpoints
+            .into_iter()
+            .map(Breakpoint::try_from)
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let session = self.session(id)?;
+        let mut session = session.lock();
+        session.set_breakpoints(breakpoints)?;
+
+        Ok(true)
+    }
+
+    fn state(&self, id: U64) -> anyhow::Result<SessionStateResponse> {
+        let session = self.session(id)?;
+        let session = session.lock();
+        Ok(session.last_event().into())
+    }
+
+    fn stop(&self, id: U64) -> bool {
+        // Dropping the session lets execution run to completion and release the transaction.
+        self.sessions.lock().active.remove(&id.as_u64()).is_some()
+    }
+
+    /// Must be called within a Tokio runtime, which runs the reaper of idle sessions.
+    pub fn into_rpc(self) -> RpcModule<Self> {
+        tokio::spawn(reap_idle_sessions(
+            Arc::downgrade(&self.sessions),
+            self.timeout,
+        ));
+
+        let mut module = RpcModule::new(self);
+
+        module
+            .register_blocking_method("debug_sessionStartTransaction", |params, ctx| {
+                let hash = params.one::<H256>()?;
+                Ok::<_, RpcError>(ctx.start_transaction(hash)?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("debug_sessionStartCall", |params, ctx| {
+                let (call_data, block_number) =
+                    params.parse::<(types::MessageCall, types::BlockNumber)>()?;
+                Ok::<_, RpcError>(ctx.start_call(call_data, block_number)?)
+            })
+            .unwrap();
+
+        for (method, mode) in [
+            ("debug_sessionStepInto", StepMode::Into),
+            ("debug_sessionStepOver", StepMode::Over),
+            ("debug_sessionStepOut", StepMode::Out),
+            ("debug_sessionContinue", StepMode::Continue),
+        ] {
+            module
+                .register_blocking_method(method, move |params, ctx| {
+                    let id = params.one::<U64>()?;
+                    Ok::<_, RpcError>(ctx.resume(id, mode)?)
+                })
+                .unwrap();
+        }
+
+        module
+            .register_blocking_method("debug_sessionSetBreakpoints", |params, ctx| {
+                let (id, breakpoints) = params.parse::<(U64, Vec<BreakpointRequest>)>()?;
+                Ok::<_, RpcError>(ctx.set_breakpoints(id, breakpoints)?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("debug_sessionState", |params, ctx| {
+                let id = params.one::<U64>()?;
+                Ok::<_, RpcError>(ctx.state(id)?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("debug_sessionStop", |params, ctx| {
+                let id = params.one::<U64>()?;
+                Ok::<_, RpcError>(ctx.stop(id))
+            })
+            .unwrap();
+
+        module
+    }
+}
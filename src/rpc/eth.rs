@@ -26,6 +26,7 @@ where
 {
     pub db: Arc<MdbxWithDirHandle<SE>>,
     pub call_gas_limit: u64,
+    pub analysis_cache: AnalysisCache,
 }
 
 fn filter_log(
@@ -73,6 +74,7 @@ where
 
     async fn get_logs(&self, filter: LogFilter) -> RpcResult<Vec<TransactionLog>> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         let (logtx, mut logrx) = tokio::sync::mpsc::channel(1);
 
@@ -104,7 +106,7 @@ where
         });
 
         tokio::task::spawn_blocking(move || {
-            let f = {
+            let mut f = {
                 let logtx = logtx.clone();
                 move || {
                     let txn = db.begin()?;
@@ -171,8 +173,7 @@ where
                         let mut buffer = Buffer::new(&txn, Some(BlockNumber(block_number.0 - 1)));
 
                         let block_execution_spec = chain_spec.collect_block_spec(block_number);
-                        let mut engine = engine_factory(None, chain_spec, None)?;
-                        let mut analysis_cache = AnalysisCache::default();
+                        let mut engine = engine_factory(None, chain_spec, None, None)?;
                         let mut tracer = NoopTracer;
 
                         let mut processor = ExecutionProcessor::new(
@@ -243,6 +244,7 @@ where
     ) -> RpcResult<types::Bytes> {
         let db = self.db.clone();
         let call_gas_limit = self.call_gas_limit;
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
@@ -272,14 +274,14 @@ where
 
             let mut state = IntraBlockState::new(&mut buffer);
 
-            let mut analysis_cache = AnalysisCache::default();
             let chain_spec = chain::chain_config::read(&txn)?
                 .ok_or_else(|| format_err!("no chainspec found"))?;
             let block_spec = chain_spec.collect_block_spec(block_number);
 
             let mut tracer = NoopTracer;
 
-            let beneficiary = engine_factory(None, chain_spec, None)?.get_beneficiary(&header);
+            let beneficiary =
+                engine_factory(None, chain_spec, None, None)?.get_beneficiary(&header);
             Ok(evmglue::execute(
                 &mut state,
                 &mut tracer,
@@ -304,6 +306,7 @@ where
         block_number: types::BlockNumber,
     ) -> RpcResult<U64> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
@@ -329,20 +332,20 @@ where
 
             let mut state = IntraBlockState::new(&mut buffer);
 
-            let mut cache = AnalysisCache::default();
             let chain_spec = chain::chain_config::read(&txn)?
                 .ok_or_else(|| format_err!("no chainspec found"))?;
             let block_spec = chain_spec.collect_block_spec(block_number);
             let mut tracer = NoopTracer;
             let gas_limit = header.gas_limit;
 
-            let beneficiary = engine_factory(None, chain_spec, None)?.get_beneficiary(&header);
+            let beneficiary =
+                engine_factory(None, chain_spec, None, None)?.get_beneficiary(&header);
             Ok(U64::from(
                 gas_limit as i64
                     - evmglue::execute(
                         &mut state,
                         &mut tracer,
-                        &mut cache,
+                        &mut analysis_cache,
                         &header,
                         &block_spec,
                         &message,
@@ -683,6 +686,7 @@ where
         hash: H256,
     ) -> RpcResult<Option<types::TransactionReceipt>> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
@@ -704,8 +708,7 @@ where
                 let mut buffer = Buffer::new(&txn, Some(BlockNumber(block_number.0 - 1)));
 
                 let block_execution_spec = chain_spec.collect_block_spec(block_number);
-                let mut engine = engine_factory(None, chain_spec, None)?;
-                let mut analysis_cache = AnalysisCache::default();
+                let mut engine = engine_factory(None, chain_spec, None, None)?;
                 let mut tracer = NoopTracer;
 
                 let mut processor = ExecutionProcessor::new(
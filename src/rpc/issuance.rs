@@ -0,0 +1,84 @@
+use super::helpers;
+use crate::{
+    kv::{mdbx::*, tables, MdbxWithDirHandle},
+    models::*,
+};
+use ethereum_jsonrpc::types;
+use ethereum_types::U64;
+use jsonrpsee::core::{server::rpc_module::RpcModule, Error as RpcError};
+use serde::Serialize;
+use std::sync::Arc;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockIssuanceResponse {
+    pub block_number: U64,
+    pub block_reward: U256,
+    pub uncle_reward: U256,
+    /// Balances allocated by the chain specification at the block.
+    pub allocated: U256,
+    pub issuance: U256,
+    pub burnt: U256,
+    /// Issued up to and including the block, allocations included.
+    pub total_issued: U256,
+    pub total_burnt: U256,
+    pub total_supply: U256,
+}
+
+/// `issuance` namespace: block rewards, burnt fees and total supply, as recorded by the issuance
+/// index stage.
+pub struct IssuanceApiServerImpl<E>
+where
+    E: EnvironmentKind,
+{
+    pub db: Arc<MdbxWithDirHandle<E>>,
+}
+
+impl<E> IssuanceApiServerImpl<E>
+where
+    E: EnvironmentKind,
+{
+    fn block_issuance(
+        &self,
+        block_number: types::BlockNumber,
+    ) -> anyhow::Result<Option<BlockIssuanceResponse>> {
+        let txn = self.db.begin()?;
+        let block_number = helpers::resolve_block_number(&txn, block_number)?;
+
+        Ok(txn
+            .get(tables::Issuance, block_number)?
+            .map(|issuance| BlockIssuanceResponse {
+                block_number: block_number.0.into(),
+                block_reward: issuance.block_reward,
+                uncle_reward: issuance.uncle_reward,
+                allocated: issuance.allocated,
+                issuance: issuance.issuance(),
+                burnt: issuance.burnt_fees,
+                total_issued: issuance.total_issued,
+                total_burnt: issuance.total_burnt,
+                total_supply: issuance.total_supply(),
+            }))
+    }
+
+    pub fn into_rpc(self) -> RpcModule<Self> {
+        let mut module = RpcModule::new(self);
+
+        module
+            .register_blocking_method("issuance_getBlockIssuance", |params, ctx| {
+                let block_number = params.one::<types::BlockNumber>()?;
+                Ok::<_, RpcError>(ctx.block_issuance(block_number)?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("issuance_getTotalSupply", |params, ctx| {
+                let block_number = params.one::<types::BlockNumber>()?;
+                Ok::<_, RpcError>(
+                    ctx.block_issuance(block_number)?
+                        .map(|issuance| issuance.total_supply),
+                )
+            })
+            .unwrap();
+
+        module
+    }
+}
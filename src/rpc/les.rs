@@ -0,0 +1,563 @@
+use super::helpers;
+use crate::{
+    accessors::chain,
+    execution::analysis_cache::AnalysisCache,
+    kv::{mdbx::*, tables, MdbxWithDirHandle},
+    models::*,
+    stages::{FINISH, INTERMEDIATE_HASHES},
+    trie::{prove_account, unpack_nibbles, HashBuilder, TrieEncode},
+};
+use anyhow::{bail, format_err};
+use bytes::BytesMut;
+use ethereum_jsonrpc::types;
+use ethereum_types::U64;
+use fastrlp::{Encodable, RlpEncodable};
+use hashlink::LruCache;
+use hyper::{
+    header::{CONTENT_LENGTH, CONTENT_TYPE},
+    server::conn::AddrStream,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use jsonrpsee::core::{server::rpc_module::RpcModule, Error as RpcError};
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::*;
+
+/// Blocks per canonical hash trie section.
+pub const CHT_SECTION_SIZE: u64 = 32768;
+/// Sections are only built this many blocks behind the head, so that they are not reorged.
+pub const CHT_CONFIRMATIONS: u64 = 2048;
+const MAX_HEADERS: u64 = 192;
+const MAX_STORAGE_KEYS: usize = 64;
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+/// Sections kept with all of their nodes, a section takes a few megabytes.
+const MAX_CACHED_SECTIONS: usize = 8;
+
+/// Flow control: every client has a buffer of this many cost units, which recharges over time.
+pub const BUFFER_LIMIT: u64 = 3_000_000;
+pub const RECHARGE_PER_SEC: u64 = 50_000;
+const MAX_CLIENTS: usize = 16_384;
+
+const HEADER_COST: u64 = 100;
+/// Building a section hashes all of its headers.
+const CHT_COST: u64 = 50_000;
+const ACCOUNT_PROOF_COST: u64 = 5_000;
+const STORAGE_PROOF_COST: u64 = 1_000;
+/// Receipts are obtained by re-executing the block.
+const RECEIPTS_COST: u64 = 100_000;
+
+#[derive(Clone, Copy, Debug)]
+struct Buffer {
+    value: u64,
+    updated: Instant,
+}
+
+/// Per-client request cost accounting, in the spirit of LES flow control. Clients are told apart
+/// by their IP address.
+#[derive(Debug)]
+pub struct FlowControl {
+    clients: Mutex<LruCache<IpAddr, Buffer>>,
+}
+
+impl Default for FlowControl {
+    fn default() -> Self {
+        Self {
+            clients: Mutex::new(LruCache::new(MAX_CLIENTS)),
+        }
+    }
+}
+
+impl FlowControl {
+    fn recharged(buffer: Option<Buffer>, now: Instant) -> Buffer {
+        match buffer {
+            Some(Buffer { value, updated }) => {
+                let elapsed = now.saturating_duration_since(updated).as_millis() as u64;
+                Buffer {
+                    value: value
+                        .saturating_add(elapsed.saturating_mul(RECHARGE_PER_SEC) / 1000)
+                        .min(BUFFER_LIMIT),
+                    updated: now,
+                }
+            }
+            None => Buffer {
+                value: BUFFER_LIMIT,
+                updated: now,
+            },
+        }
+    }
+
+    /// Remaining buffer of the client.
+    pub fn buffer_value(&self, client: IpAddr, now: Instant) -> u64 {
+        let buffer = self.clients.lock().get(&client).copied();
+        Self::recharged(buffer, now).value
+    }
+
+    /// Deducts the request cost from the client's buffer, rejecting the request if it does not
+    /// have enough left.
+    pub fn charge(&self, client: IpAddr, cost: u64, now: Instant) -> anyhow::Result<u64> {
+        let mut clients = self.clients.lock();
+        let mut buffer = Self::recharged(clients.get(&client).copied(), now);
+        if buffer.value < cost {
+            let wait = Duration::from_millis(
+                (cost - buffer.value).saturating_mul(1000) / RECHARGE_PER_SEC,
+            );
+            bail!(
+                "request cost {cost} exceeds buffer value {}, retry in {}ms",
+                buffer.value,
+                wait.as_millis()
+            );
+        }
+        buffer.value -= cost;
+        clients.insert(client, buffer);
+        Ok(buffer.value)
+    }
+}
+
+#[derive(RlpEncodable)]
+struct ChtNode {
+    hash: H256,
+    td: U256,
+}
+
+fn cht_key(block_number: BlockNumber) -> Vec<u8> {
+    unpack_nibbles(&block_number.0.to_be_bytes())
+}
+
+/// Canonical hash trie of a section, with all of its nodes retained for proofs.
+struct ChtSection {
+    root: H256,
+    trie: HashBuilder<'static>,
+}
+
+/// Builds the canonical hash trie of the section.
+fn build_cht<K: TransactionKind, E: EnvironmentKind>(
+    txn: &MdbxTransaction<'_, K, E>,
+    section: u64,
+) -> anyhow::Result<ChtSection> {
+    let head = txn
+        .get(tables::SyncStage, FINISH)?
+        .unwrap_or(BlockNumber(0));
+    let first = section * CHT_SECTION_SIZE;
+    let last = first + CHT_SECTION_SIZE - 1;
+    if last + CHT_CONFIRMATIONS > head.0 {
+        bail!("CHT section {section} is not available yet");
+    }
+
+    let mut hb = HashBuilder::new(None)
+        .with_proof_targets((first..=last).map(|block_number| cht_key(BlockNumber(block_number))));
+    for entry in txn
+        .cursor(tables::CanonicalHeader)?
+        .walk(Some(BlockNumber(first)))
+        .take(CHT_SECTION_SIZE as usize)
+    {
+        let (block_number, hash) = entry?;
+        let td = txn
+            .get(tables::HeadersTotalDifficulty, block_number)?
+            .ok_or_else(|| format_err!("no total difficulty for block #{block_number}"))?;
+
+        let mut value = BytesMut::new();
+        ChtNode { hash, td }.encode(&mut value);
+        hb.add_leaf(cht_key(block_number), &value);
+    }
+
+    Ok(ChtSection {
+        root: hb.compute_root_hash(),
+        trie: hb,
+    })
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChtRoot {
+    pub section: U64,
+    pub section_size: U64,
+    pub root: H256,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeaderWithProof {
+    /// RLP encoded header.
+    pub header: types::Bytes,
+    pub hash: H256,
+    pub total_difficulty: U256,
+    pub section: U64,
+    pub cht_root: H256,
+    /// Proof of `rlp([hash, totalDifficulty])` under the big endian block number in the CHT.
+    pub proof: Vec<types::Bytes>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageProof {
+    pub key: H256,
+    pub value: U256,
+    pub proof: Vec<types::Bytes>,
+}
+
+/// EIP-1186 account proof, along with the block whose state root it is against.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountProof {
+    pub block_number: U64,
+    pub block_hash: H256,
+    pub state_root: H256,
+    pub address: Address,
+    pub balance: U256,
+    pub nonce: U64,
+    pub code_hash: H256,
+    pub storage_hash: H256,
+    pub account_proof: Vec<types::Bytes>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockReceipts {
+    pub block_hash: H256,
+    pub receipts_root: H256,
+    /// Consensus encoded receipts, which make up the receipts trie under their RLP encoded index.
+    pub receipts: Vec<types::Bytes>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientInfo {
+    pub buffer_value: U64,
+    pub buffer_limit: U64,
+    pub recharge_per_sec: U64,
+}
+
+fn to_bytes(bytes: bytes::Bytes) -> types::Bytes {
+    types::Bytes(bytes)
+}
+
+/// `les` namespace: headers with canonical hash trie proofs, state proofs and receipts which light
+/// clients can verify without trusting this node. Served by [`serve`], which charges every request
+/// to the client connected.
+pub struct LesApiServerImpl<E>
+where
+    E: EnvironmentKind,
+{
+    db: Arc<MdbxWithDirHandle<E>>,
+    analysis_cache: AnalysisCache,
+    flow_control: Arc<FlowControl>,
+    cht_roots: Arc<Mutex<HashMap<u64, H256>>>,
+    cht_sections: Arc<Mutex<LruCache<u64, Arc<ChtSection>>>>,
+    client: IpAddr,
+}
+
+impl<E> LesApiServerImpl<E>
+where
+    E: EnvironmentKind,
+{
+    pub fn new(db: Arc<MdbxWithDirHandle<E>>, analysis_cache: AnalysisCache) -> Self {
+        Self {
+            db,
+            analysis_cache,
+            flow_control: Default::default(),
+            cht_roots: Default::default(),
+            cht_sections: Arc::new(Mutex::new(LruCache::new(MAX_CACHED_SECTIONS))),
+            client: IpAddr::from([0, 0, 0, 0]),
+        }
+    }
+
+    /// Same API, charging requests to `client`.
+    fn for_client(&self, client: IpAddr) -> Self {
+        Self {
+            db: self.db.clone(),
+            analysis_cache: self.analysis_cache.clone(),
+            flow_control: self.flow_control.clone(),
+            cht_roots: self.cht_roots.clone(),
+            cht_sections: self.cht_sections.clone(),
+            client,
+        }
+    }
+
+    fn charge(&self, cost: u64) -> anyhow::Result<u64> {
+        self.flow_control.charge(self.client, cost, Instant::now())
+    }
+
+    fn cht_section(&self, section: u64) -> anyhow::Result<Arc<ChtSection>> {
+        if let Some(cht) = self.cht_sections.lock().get(&section) {
+            return Ok(cht.clone());
+        }
+
+        let cht = Arc::new(build_cht(&self.db.begin()?, section)?);
+        self.cht_roots.lock().insert(section, cht.root);
+        self.cht_sections.lock().insert(section, cht.clone());
+        Ok(cht)
+    }
+
+    fn cht_root(&self, section: u64) -> anyhow::Result<H256> {
+        if let Some(root) = self.cht_roots.lock().get(&section) {
+            return Ok(*root);
+        }
+
+        Ok(self.cht_section(section)?.root)
+    }
+
+    fn header_with_proof(&self, block_number: BlockNumber) -> anyhow::Result<HeaderWithProof> {
+        let section = block_number.0 / CHT_SECTION_SIZE;
+        let cht = self.cht_section(section)?;
+
+        let txn = self.db.begin()?;
+
+        let header = chain::header::read(&txn, block_number)?
+            .ok_or_else(|| format_err!("no header for block #{block_number}"))?;
+        let total_difficulty = txn
+            .get(tables::HeadersTotalDifficulty, block_number)?
+            .ok_or_else(|| format_err!("no total difficulty for block #{block_number}"))?;
+        let mut rlp = BytesMut::new();
+        header.encode(&mut rlp);
+
+        Ok(HeaderWithProof {
+            hash: header.hash(),
+            header: to_bytes(rlp.freeze()),
+            total_difficulty,
+            section: section.into(),
+            cht_root: cht.root,
+            proof: cht
+                .trie
+                .proof(&cht_key(block_number))
+                .into_iter()
+                .map(to_bytes)
+                .collect(),
+        })
+    }
+
+    fn headers(&self, from: BlockNumber, count: u64) -> anyhow::Result<Vec<types::Bytes>> {
+        let txn = self.db.begin()?;
+        let mut headers = vec![];
+        for block_number in from.0..from.0 + count {
+            match chain::header::read(&txn, BlockNumber(block_number))? {
+                Some(header) => {
+                    let mut rlp = BytesMut::new();
+                    header.encode(&mut rlp);
+                    headers.push(to_bytes(rlp.freeze()));
+                }
+                None => break,
+            }
+        }
+        Ok(headers)
+    }
+
+    fn account_proof(
+        &self,
+        address: Address,
+        storage_keys: Vec<H256>,
+    ) -> anyhow::Result<AccountProof> {
+        let txn = self.db.begin()?;
+        let block_number = txn
+            .get(tables::SyncStage, INTERMEDIATE_HASHES)?
+            .ok_or_else(|| format_err!("state is not available yet"))?;
+        let header = chain::header::read(&txn, block_number)?
+            .ok_or_else(|| format_err!("no header for block #{block_number}"))?;
+
+        let (state_root, proof) = prove_account(&txn, address, &storage_keys)?;
+        if state_root != header.state_root {
+            bail!("state is being updated, retry later");
+        }
+
+        let account = proof.account.unwrap_or_default();
+        Ok(AccountProof {
+            block_number: block_number.0.into(),
+            block_hash: header.hash(),
+            state_root,
+            address,
+            balance: account.balance,
+            nonce: account.nonce.into(),
+            code_hash: account.code_hash,
+            storage_hash: proof.storage_root,
+            account_proof: proof.proof.into_iter().map(to_bytes).collect(),
+            storage_proof: proof
+                .storage_proofs
+                .into_iter()
+                .map(|storage_proof| StorageProof {
+                    key: storage_proof.key,
+                    value: storage_proof.value,
+                    proof: storage_proof.proof.into_iter().map(to_bytes).collect(),
+                })
+                .collect(),
+        })
+    }
+
+    fn receipts(&self, block_number: BlockNumber) -> anyhow::Result<BlockReceipts> {
+        let txn = self.db.begin()?;
+        let header = chain::header::read(&txn, block_number)?
+            .ok_or_else(|| format_err!("no header for block #{block_number}"))?;
+        let (block_hash, _, receipts) =
+            helpers::execute_receipts(&txn, &mut self.analysis_cache.clone(), block_number)
+                .map_err(|e| format_err!("{e:?}"))?;
+
+        Ok(BlockReceipts {
+            block_hash,
+            receipts_root: header.receipts_root,
+            receipts: receipts
+                .iter()
+                .map(|receipt| {
+                    let mut buf = BytesMut::new();
+                    receipt.trie_encode(&mut buf);
+                    to_bytes(buf.freeze())
+                })
+                .collect(),
+        })
+    }
+
+    pub fn into_rpc(self) -> RpcModule<Self> {
+        let mut module = RpcModule::new(self);
+
+        module
+            .register_method("les_clientInfo", |_, ctx| {
+                Ok::<_, RpcError>(ClientInfo {
+                    buffer_value: ctx
+                        .flow_control
+                        .buffer_value(ctx.client, Instant::now())
+                        .into(),
+                    buffer_limit: BUFFER_LIMIT.into(),
+                    recharge_per_sec: RECHARGE_PER_SEC.into(),
+                })
+            })
+            .unwrap();
+        module
+            .register_blocking_method("les_getChtRoot", |params, ctx| {
+                let section = params.one::<U64>()?;
+                ctx.charge(CHT_COST)?;
+                Ok::<_, RpcError>(ChtRoot {
+                    section,
+                    section_size: CHT_SECTION_SIZE.into(),
+                    root: ctx.cht_root(section.as_u64())?,
+                })
+            })
+            .unwrap();
+        module
+            .register_blocking_method("les_getHeaderWithProof", |params, ctx| {
+                let block_number = params.one::<U64>()?;
+                ctx.charge(CHT_COST + HEADER_COST)?;
+                Ok::<_, RpcError>(ctx.header_with_proof(BlockNumber(block_number.as_u64()))?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("les_getHeaders", |params, ctx| {
+                let (from, count) = params.parse::<(U64, U64)>()?;
+                let count = count.as_u64().min(MAX_HEADERS);
+                ctx.charge(count * HEADER_COST)?;
+                Ok::<_, RpcError>(ctx.headers(BlockNumber(from.as_u64()), count)?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("les_getProof", |params, ctx| {
+                let (address, storage_keys) = params.parse::<(Address, Vec<H256>)>()?;
+                if storage_keys.len() > MAX_STORAGE_KEYS {
+                    return Err(format_err!("at most {MAX_STORAGE_KEYS} storage keys").into());
+                }
+                ctx.charge(ACCOUNT_PROOF_COST + storage_keys.len() as u64 * STORAGE_PROOF_COST)?;
+                Ok::<_, RpcError>(ctx.account_proof(address, storage_keys)?)
+            })
+            .unwrap();
+        module
+            .register_blocking_method("les_getReceipts", |params, ctx| {
+                let block_number = params.one::<U64>()?;
+                ctx.charge(RECEIPTS_COST)?;
+                Ok::<_, RpcError>(ctx.receipts(BlockNumber(block_number.as_u64()))?)
+            })
+            .unwrap();
+
+        module
+    }
+}
+
+async fn handle<C>(module: RpcModule<C>, req: Request<Body>) -> Result<Response<Body>, Infallible>
+where
+    C: Send + Sync + 'static,
+{
+    let error = |status: StatusCode| {
+        Ok(Response::builder()
+            .status(status)
+            .body(Body::empty())
+            .unwrap())
+    };
+
+    if req.method() != Method::POST {
+        return error(StatusCode::METHOD_NOT_ALLOWED);
+    }
+    let size = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|size| size.to_str().ok()?.parse::<u64>().ok());
+    if !matches!(size, Some(size) if size <= MAX_REQUEST_SIZE) {
+        return error(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    let body = match hyper::body::to_bytes(req.into_body()).await {
+        Ok(body) => body,
+        Err(_) => return error(StatusCode::BAD_REQUEST),
+    };
+    let request = match std::str::from_utf8(&body) {
+        Ok(request) => request,
+        Err(_) => return error(StatusCode::BAD_REQUEST),
+    };
+
+    match module.raw_json_request(request).await {
+        Ok((response, _)) => Ok(Response::builder()
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(response.result))
+            .unwrap()),
+        Err(_) => error(StatusCode::BAD_REQUEST),
+    }
+}
+
+/// Serves the `les` namespace as JSON-RPC over HTTP. Requests are charged to the IP address of
+/// the connection, which unlike anything in the request clients cannot pick freely.
+pub async fn serve<E>(listen_address: SocketAddr, api: LesApiServerImpl<E>) -> anyhow::Result<()>
+where
+    E: EnvironmentKind,
+{
+    let server =
+        Server::try_bind(&listen_address)?.serve(make_service_fn(move |conn: &AddrStream| {
+            let module = api.for_client(conn.remote_addr().ip()).into_rpc();
+            async move { Ok::<_, Infallible>(service_fn(move |req| handle(module.clone(), req))) }
+        }));
+    info!("LES server listening on {}", listen_address);
+
+    server.await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flow_control() {
+        let flow_control = FlowControl::default();
+        let now = Instant::now();
+        let a = IpAddr::from([10, 0, 0, 1]);
+        let b = IpAddr::from([10, 0, 0, 2]);
+
+        assert_eq!(flow_control.buffer_value(a, now), BUFFER_LIMIT);
+        assert_eq!(flow_control.charge(a, BUFFER_LIMIT - 10, now).unwrap(), 10);
+        assert!(flow_control.charge(a, 11, now).is_err());
+        assert_eq!(flow_control.buffer_value(b, now), BUFFER_LIMIT);
+
+        let later = now + Duration::from_millis(100);
+        assert_eq!(
+            flow_control.buffer_value(a, later),
+            10 + RECHARGE_PER_SEC / 10
+        );
+        assert!(flow_control.charge(a, 11, later).is_ok());
+        assert_eq!(
+            flow_control.buffer_value(a, now + Duration::from_secs(3600)),
+            BUFFER_LIMIT
+        );
+    }
+}
@@ -1,6 +1,10 @@
+pub mod admin;
 pub mod debug;
+pub mod debug_session;
 pub mod erigon;
 pub mod eth;
+pub mod issuance;
+pub mod les;
 pub mod net;
 pub mod otterscan;
 pub mod parity;
@@ -205,14 +209,14 @@ pub mod helpers {
         if let Some((block_number, block_hash)) = resolve_block_id(txn, block_id)? {
             if let Some((block_number, block_hash, header)) = {
                 if let Some(n) = uncle_index {
-                    txn.get(tables::BlockBody, block_number)?.and_then(|body| {
+                    chain::storage_body::read(txn, block_number)?.and_then(|body| {
                         body.ommers
                             .get(n.as_usize())
                             .cloned()
                             .map(|uncle| (uncle.number, uncle.hash(), uncle))
                     })
                 } else {
-                    txn.get(tables::Header, block_number)?
+                    chain::header::read(txn, block_number)?
                         .map(|header| (block_number, block_hash, header))
                 }
             } {
@@ -273,10 +277,12 @@ pub mod helpers {
         Ok(None)
     }
 
-    pub fn get_receipts<K: TransactionKind, E: EnvironmentKind>(
+    /// Re-executes the block to obtain its receipts.
+    pub fn execute_receipts<K: TransactionKind, E: EnvironmentKind>(
         txn: &MdbxTransaction<'_, K, E>,
+        analysis_cache: &mut AnalysisCache,
         block_number: BlockNumber,
-    ) -> Result<Vec<types::TransactionReceipt>, DuoError> {
+    ) -> Result<(H256, BlockBodyWithSenders, Vec<Receipt>), DuoError> {
         let block_hash = chain::canonical_hash::read(txn, block_number)?
             .ok_or_else(|| format_err!("no canonical header for block #{block_number:?}"))?;
         let header = chain::header::read(txn, block_number)?.ok_or_else(|| {
@@ -291,99 +297,105 @@ pub mod helpers {
         let mut buffer = Buffer::new(txn, Some(BlockNumber(block_number.0 - 1)));
 
         let block_execution_spec = chain_spec.collect_block_spec(block_number);
-        let mut engine = engine_factory(None, chain_spec, None)?;
-        let mut analysis_cache = AnalysisCache::default();
+        let mut engine = engine_factory(None, chain_spec, None, None)?;
         let mut tracer = NoopTracer;
 
         let mut processor = ExecutionProcessor::new(
             &mut buffer,
             &mut tracer,
-            &mut analysis_cache,
+            analysis_cache,
             &mut *engine,
             &header,
             &block_body,
             &block_execution_spec,
         );
 
-        processor
-            .execute_block_no_post_validation()
-            .map(|receipts| {
-                let mut last_cumul_gas_used = 0;
-                receipts
-                    .into_iter()
-                    .enumerate()
-                    .map(
-                        |(
-                            transaction_index,
-                            Receipt {
-                                success,
-                                cumulative_gas_used,
-                                bloom,
-                                logs,
-                                ..
-                            },
-                        )| {
-                            let transaction = &block_body.transactions[transaction_index];
-                            let transaction_hash = transaction.hash();
-                            let gas_used = (cumulative_gas_used - last_cumul_gas_used).into();
-                            last_cumul_gas_used = cumulative_gas_used;
-                            types::TransactionReceipt {
-                                transaction_hash,
-                                transaction_index: U64::from(transaction_index),
-                                block_hash,
-                                block_number: U64::from(block_number.0),
-                                from: transaction.sender,
-                                to: transaction.message.action().into_address(),
-                                cumulative_gas_used: cumulative_gas_used.into(),
-                                gas_used,
-                                contract_address: if let TransactionAction::Create =
-                                    transaction.message.action()
-                                {
-                                    Some(crate::execution::address::create_address(
-                                        transaction.sender,
-                                        transaction.message.nonce(),
-                                    ))
-                                } else {
-                                    None
-                                },
-                                logs: logs
-                                    .into_iter()
-                                    .enumerate()
-                                    .map(
-                                        |(
-                                            log_index,
-                                            Log {
-                                                address,
-                                                data,
-                                                topics,
-                                            },
-                                        )| {
-                                            types::TransactionLog {
-                                                log_index: Some(U64::from(log_index)),
-                                                transaction_index: Some(U64::from(
-                                                    transaction_index,
-                                                )),
-                                                transaction_hash: Some(transaction_hash),
-                                                block_hash: Some(block_hash),
-                                                block_number: Some(U64::from(block_number.0)),
-                                                address,
-                                                data: data.into(),
-                                                topics,
-                                            }
-                                        },
-                                    )
-                                    .collect::<Vec<_>>(),
-                                logs_bloom: bloom,
-                                status: if success {
-                                    U64::from(1_u16)
-                                } else {
-                                    U64::zero()
+        let receipts = processor.execute_block_no_post_validation()?;
+
+        Ok((block_hash, block_body, receipts))
+    }
+
+    pub fn get_receipts<K: TransactionKind, E: EnvironmentKind>(
+        txn: &MdbxTransaction<'_, K, E>,
+        analysis_cache: &mut AnalysisCache,
+        block_number: BlockNumber,
+    ) -> Result<Vec<types::TransactionReceipt>, DuoError> {
+        let (block_hash, block_body, receipts) =
+            execute_receipts(txn, analysis_cache, block_number)?;
+
+        let mut last_cumul_gas_used = 0;
+        Ok(receipts
+            .into_iter()
+            .enumerate()
+            .map(
+                |(
+                    transaction_index,
+                    Receipt {
+                        success,
+                        cumulative_gas_used,
+                        bloom,
+                        logs,
+                        ..
+                    },
+                )| {
+                    let transaction = &block_body.transactions[transaction_index];
+                    let transaction_hash = transaction.hash();
+                    let gas_used = (cumulative_gas_used - last_cumul_gas_used).into();
+                    last_cumul_gas_used = cumulative_gas_used;
+                    types::TransactionReceipt {
+                        transaction_hash,
+                        transaction_index: U64::from(transaction_index),
+                        block_hash,
+                        block_number: U64::from(block_number.0),
+                        from: transaction.sender,
+                        to: transaction.message.action().into_address(),
+                        cumulative_gas_used: cumulative_gas_used.into(),
+                        gas_used,
+                        contract_address: if let TransactionAction::Create =
+                            transaction.message.action()
+                        {
+                            Some(crate::execution::address::create_address(
+                                transaction.sender,
+                                transaction.message.nonce(),
+                            ))
+                        } else {
+                            None
+                        },
+                        logs: logs
+                            .into_iter()
+                            .enumerate()
+                            .map(
+                                |(
+                                    log_index,
+                                    Log {
+                                        address,
+                                        data,
+                                        topics,
+                                    },
+                                )| {
+                                    types::TransactionLog {
+                                        log_index: Some(U64::from(log_index)),
+                                        transaction_index: Some(U64::from(transaction_index)),
+                                        transaction_hash: Some(transaction_hash),
+                                        block_hash: Some(block_hash),
+                                        block_number: Some(U64::from(block_number.0)),
+                                        address,
+                                        data: data.into(),
+                                        topics,
+                                    }
                                 },
-                            }
+                            )
+                            .collect::<Vec<_>>(),
+                        logs_bloom: bloom,
+                        status: if success {
+                            U64::from(1_u16)
+                        } else {
+                            U64::zero()
                         },
-                    )
-                    .collect()
-            })
+                    }
+                },
+            )
+            .collect())
     }
 
     pub fn convert_message_call<S: StateReader>(
@@ -4,36 +4,31 @@ use crate::{
     consensus::{engine_factory, FinalizationChange},
     execution::{
         analysis_cache::AnalysisCache,
+        evm::{Output, StatusCode},
         processor::{execute_transaction, ExecutionProcessor},
-        tracer::{CallKind, MessageKind, NoopTracer, Tracer},
-    },
-    kv::{
-        mdbx::*,
-        tables::{self, BitmapKey},
-        traits::*,
-        MdbxWithDirHandle,
+        tracer::{CallKind, CodeKind, MessageKind, NoopTracer, Tracer},
     },
+    kv::{mdbx::*, tables, traits::*, MdbxWithDirHandle},
     models::*,
     Buffer, IntraBlockState,
 };
 use anyhow::format_err;
 use async_trait::async_trait;
 use bytes::Bytes;
-use croaring::Treemap;
 use ethereum_jsonrpc::{
     types, BlockData, BlockDetails, BlockTransactions, ContractCreatorData, InternalOperation,
-    Issuance, OperationType, OtterscanApiServer, ReceiptWithTimestamp, TraceEntry,
+    Issuance, OperationType, OtterscanApiServer, ReceiptWithTimestamp, TraceEntry, TraceEntryType,
     TransactionsWithReceipts,
 };
 use jsonrpsee::core::RpcResult;
 use std::{cmp::Ordering, sync::Arc};
-use tokio::pin;
 
 pub struct OtterscanApiServerImpl<SE>
 where
     SE: EnvironmentKind,
 {
     pub db: Arc<MdbxWithDirHandle<SE>>,
+    pub analysis_cache: AnalysisCache,
 }
 
 fn get_block_details_inner<K, E>(
@@ -48,29 +43,39 @@ where
     if let Some(block) = helpers::construct_block(tx, block_id, include_txs, None)? {
         let block_number = block.number.unwrap().as_u64().into();
 
-        let header = crate::accessors::chain::header::read(tx, block_number)?.unwrap();
-        let ommers = tx.get(tables::BlockBody, block_number)?.unwrap().ommers;
-
-        let chainspec = tx
-            .get(tables::Config, ())?
-            .ok_or_else(|| format_err!("no chainspec found"))?;
-        let finalization_changes =
-            engine_factory(None, chainspec, None)?.finalize(&header, &ommers)?;
-
-        let mut block_reward = U256::ZERO;
-        let mut uncle_reward = U256::ZERO;
-
-        for change in finalization_changes {
-            match change {
-                FinalizationChange::Reward { amount, ommer, .. } => {
-                    *if ommer {
-                        &mut uncle_reward
-                    } else {
-                        &mut block_reward
-                    } += amount;
+        let (block_reward, uncle_reward) =
+            if let Some(issuance) = tx.get(tables::Issuance, block_number)? {
+                (issuance.block_reward, issuance.uncle_reward)
+            } else {
+                // Issuance index has not reached the block yet.
+                let header = crate::accessors::chain::header::read(tx, block_number)?.unwrap();
+                let ommers = crate::accessors::chain::storage_body::read(tx, block_number)?
+                    .unwrap()
+                    .ommers;
+
+                let chainspec = tx
+                    .get(tables::Config, ())?
+                    .ok_or_else(|| format_err!("no chainspec found"))?;
+                let finalization_changes =
+                    engine_factory(None, chainspec, None, None)?.finalize(&header, &ommers)?;
+
+                let mut block_reward = U256::ZERO;
+                let mut uncle_reward = U256::ZERO;
+
+                for change in finalization_changes {
+                    match change {
+                        FinalizationChange::Reward { amount, ommer, .. } => {
+                            *if ommer {
+                                &mut uncle_reward
+                            } else {
+                                &mut block_reward
+                            } += amount;
+                        }
+                    }
                 }
-            }
-        }
+
+                (block_reward, uncle_reward)
+            };
         let issuance = block_reward + uncle_reward;
 
         let mut details = BlockDetails {
@@ -97,6 +102,7 @@ where
 
 fn search_trace_block<K, E>(
     txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
     addr: Address,
     chain_spec: &ChainSpec,
     block_number: BlockNumber,
@@ -146,7 +152,6 @@ where
     let mut state = IntraBlockState::new(&mut buffer);
 
     let block_spec = chain_spec.collect_block_spec(block_number);
-    let mut analysis_cache = AnalysisCache::default();
 
     let mut prev_cumulative_gas_used = 0;
     let mut cumulative_gas_used = 0;
@@ -171,7 +176,8 @@ where
         last_page: false,
     };
 
-    let beneficiary = engine_factory(None, chain_spec.clone(), None)?.get_beneficiary(&header);
+    let beneficiary =
+        engine_factory(None, chain_spec.clone(), None, None)?.get_beneficiary(&header);
 
     for (transaction_index, (transaction, sender)) in messages.into_iter().zip(senders).enumerate()
     {
@@ -181,7 +187,7 @@ where
             &block_spec,
             &header,
             &mut tracer,
-            &mut analysis_cache,
+            analysis_cache,
             &mut cumulative_gas_used,
             &transaction.message,
             sender,
@@ -263,6 +269,7 @@ where
 
 fn trace_blocks<K, E>(
     txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
     addr: Address,
     chain_config: &ChainSpec,
     page_size: usize,
@@ -282,7 +289,13 @@ where
 
     for _ in 0..est_blocks_to_trace {
         if let Some(block) = call_from_to_provider.next().transpose()? {
-            results.push(search_trace_block(txn, addr, chain_config, block)?);
+            results.push(search_trace_block(
+                txn,
+                analysis_cache,
+                addr,
+                chain_config,
+                block,
+            )?);
         } else {
             has_more = false;
         }
@@ -349,6 +362,79 @@ where
     })
 }
 
+/// Re-executes the block of transaction `hash` up to the transaction and then runs the
+/// transaction itself with `tracer`. Does nothing if the transaction is unknown.
+fn replay_transaction<K, E>(
+    txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
+    hash: H256,
+    tracer: &mut dyn Tracer,
+) -> anyhow::Result<()>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    let block_number = if let Some(block_number) = chain::tl::read(txn, hash)? {
+        block_number
+    } else {
+        return Ok(());
+    };
+
+    let block_hash = chain::canonical_hash::read(txn, block_number)?
+        .ok_or_else(|| format_err!("no canonical header for block #{block_number:?}"))?;
+    let header = chain::header::read(txn, block_number)?
+        .ok_or_else(|| format_err!("header not found for block #{block_number}/{block_hash}"))?;
+    let block_body = chain::block_body::read_with_senders(txn, block_number)?
+        .ok_or_else(|| format_err!("body not found for block #{block_number}/{block_hash}"))?;
+    let chain_spec = chain::chain_config::read(txn)?
+        .ok_or_else(|| format_err!("chain specification not found"))?;
+
+    // Prepare the execution context.
+    let mut buffer = Buffer::new(txn, Some(BlockNumber(block_number.0 - 1)));
+
+    let block_execution_spec = chain_spec.collect_block_spec(block_number);
+    let mut engine = engine_factory(None, chain_spec, None, None)?;
+    let mut noop_tracer = NoopTracer;
+
+    let mut processor = ExecutionProcessor::new(
+        &mut buffer,
+        &mut noop_tracer,
+        analysis_cache,
+        &mut *engine,
+        &header,
+        &block_body,
+        &block_execution_spec,
+    );
+
+    let transaction_index = chain::block_body::read_without_senders(txn, block_number)?
+        .ok_or_else(|| format_err!("where's block body"))?
+        .transactions
+        .into_iter()
+        .enumerate()
+        .find(|(_, tx)| tx.hash() == hash)
+        .ok_or_else(|| {
+            format_err!(
+                "transaction {hash} not found in block #{block_number}/{block_hash} despite lookup index"
+            )
+        })?
+        .0;
+
+    processor.execute_block_no_post_validation_while(|i, _| i < transaction_index)?;
+
+    let tx = block_body
+        .transactions
+        .get(transaction_index)
+        .ok_or_else(|| {
+            format_err!(
+                "block #{block_number}/{block_hash} too short: tx #{transaction_index} not in body"
+            )
+        })?;
+    processor.set_tracer(tracer);
+    processor.execute_transaction(&tx.message, tx.sender)?;
+
+    Ok(())
+}
+
 #[async_trait]
 impl<DB> OtterscanApiServer for OtterscanApiServerImpl<DB>
 where
@@ -419,58 +505,15 @@ where
         }
 
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
 
-            if let Some(block_number) = chain::tl::read(&txn, hash)? {
-                let block_hash = chain::canonical_hash::read(&txn, block_number)?
-                    .ok_or_else(|| format_err!("no canonical header for block #{block_number:?}"))?;
-                let header = chain::header::read(&txn, block_number)?.ok_or_else(|| {
-                    format_err!("header not found for block #{block_number}/{block_hash}")
-                })?;
-                let block_body = chain::block_body::read_with_senders(&txn, block_number)?
-                    .ok_or_else(|| {
-                        format_err!("body not found for block #{block_number}/{block_hash}")
-                    })?;
-                let chain_spec = chain::chain_config::read(&txn)?
-                    .ok_or_else(|| format_err!("chain specification not found"))?;
-
-                // Prepare the execution context.
-                let mut buffer = Buffer::new(&txn, Some(BlockNumber(block_number.0 - 1)));
-
-                let block_execution_spec = chain_spec.collect_block_spec(block_number);
-                let mut engine = engine_factory(None, chain_spec, None)?;
-                let mut analysis_cache = AnalysisCache::default();
-                let mut tracer = NoopTracer;
-
-                let mut processor = ExecutionProcessor::new(
-                    &mut buffer,
-                    &mut tracer,
-                    &mut analysis_cache,
-                    &mut *engine,
-                    &header,
-                    &block_body,
-                    &block_execution_spec,
-                );
-
-                let transaction_index = chain::block_body::read_without_senders(&txn, block_number)?.ok_or_else(|| format_err!("where's block body"))?.transactions
-                    .into_iter()
-                    .enumerate()
-                    .find(|(_, tx)| tx.hash() == hash)
-                    .ok_or_else(|| format_err!("transaction {hash} not found in block #{block_number}/{block_hash} despite lookup index"))?.0;
-
-                processor.execute_block_no_post_validation_while(|i, _| i < transaction_index)?;
-
-                let tx = block_body.transactions.get(transaction_index).ok_or_else(|| format_err!("block #{block_number}/{block_hash} too short: tx #{transaction_index} not in body"))?;
-                let mut operations_tracer = OperationsTracer::default();
-                processor.set_tracer(&mut operations_tracer);
-                processor.execute_transaction(&tx.message, tx.sender)?;
-
-                return Ok(operations_tracer.results);
-            }
+            let mut operations_tracer = OperationsTracer::default();
+            replay_transaction(&txn, &mut analysis_cache, hash, &mut operations_tracer)?;
 
-            Ok(vec![])
+            Ok(operations_tracer.results)
         })
         .await
         .unwrap_or_else(helpers::joinerror_to_result)
@@ -482,6 +525,7 @@ where
         page_size: usize,
     ) -> RpcResult<TransactionsWithReceipts> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let dbtx = db.begin()?;
@@ -520,6 +564,7 @@ where
                 let results;
                 (results, has_more) = trace_blocks(
                     &dbtx,
+                    &mut analysis_cache,
                     addr,
                     &chain_config,
                     page_size,
@@ -559,6 +604,7 @@ where
         page_size: usize,
     ) -> RpcResult<TransactionsWithReceipts> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let dbtx = db.begin()?;
@@ -597,6 +643,7 @@ where
                 let results;
                 (results, has_more) = trace_blocks(
                     &dbtx,
+                    &mut analysis_cache,
                     addr,
                     &chain_config,
                     page_size,
@@ -655,6 +702,7 @@ where
         page_size: usize,
     ) -> RpcResult<Option<BlockTransactions>> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
@@ -679,7 +727,7 @@ where
                     .unwrap_or_default();
 
                 return Ok(Some(BlockTransactions {
-                    receipts: helpers::get_receipts(&txn, number.into())?,
+                    receipts: helpers::get_receipts(&txn, &mut analysis_cache, number.into())?,
                     fullblock: block_details.block.inner,
                 }));
             }
@@ -709,12 +757,120 @@ where
         .unwrap_or_else(helpers::joinerror_to_result)
     }
     async fn trace_transaction(&self, hash: H256) -> RpcResult<Vec<TraceEntry>> {
-        let _ = hash;
-        Ok(vec![])
+        #[derive(Debug, Default)]
+        struct TransactionTracer {
+            /// Number of frames currently executing code.
+            depth: u16,
+            results: Vec<TraceEntry>,
+        }
+
+        impl Tracer for TransactionTracer {
+            fn capture_start(
+                &mut self,
+                depth: u16,
+                from: Address,
+                to: Address,
+                _: Address,
+                _: Address,
+                call_type: MessageKind,
+                input: Bytes,
+                _: u64,
+                value: U256,
+            ) {
+                // Only frames running code are matched by `capture_end`.
+                let runs_code = match &call_type {
+                    MessageKind::Create { .. } => true,
+                    MessageKind::Call { code_kind, .. } => {
+                        matches!(code_kind, CodeKind::Bytecode(Some(code)) if !code.is_empty())
+                    }
+                };
+                if runs_code {
+                    self.depth += 1;
+                }
+                self.results.push(TraceEntry {
+                    r#type: match call_type {
+                        MessageKind::Create { salt: None } => TraceEntryType::Create,
+                        MessageKind::Create { salt: Some(_) } => TraceEntryType::Create2,
+                        MessageKind::Call { call_kind, .. } => match call_kind {
+                            CallKind::Call => TraceEntryType::Call,
+                            CallKind::CallCode => TraceEntryType::CallCode,
+                            CallKind::DelegateCall => TraceEntryType::DelegateCall,
+                            CallKind::StaticCall => TraceEntryType::StaticCall,
+                        },
+                    },
+                    depth,
+                    from,
+                    to,
+                    value,
+                    input: input.into(),
+                });
+            }
+            fn capture_end(&mut self, depth: usize, _: u64, _: &Output) {
+                // Same as decrementing, but also recovers from a create that failed before
+                // running its init code.
+                self.depth = depth as u16;
+            }
+            fn capture_self_destruct(
+                &mut self,
+                caller: Address,
+                beneficiary: Address,
+                balance: U256,
+            ) {
+                // Reported as a child of the frame executing SELFDESTRUCT.
+                self.results.push(TraceEntry {
+                    r#type: TraceEntryType::SelfDestruct,
+                    depth: self.depth,
+                    from: caller,
+                    to: beneficiary,
+                    value: balance,
+                    input: Default::default(),
+                });
+            }
+        }
+
+        let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let txn = db.begin()?;
+
+            let mut tracer = TransactionTracer::default();
+            replay_transaction(&txn, &mut analysis_cache, hash, &mut tracer)?;
+
+            Ok(tracer.results)
+        })
+        .await
+        .unwrap_or_else(helpers::joinerror_to_result)
     }
     async fn get_transaction_error(&self, hash: H256) -> RpcResult<types::Bytes> {
-        let _ = hash;
-        Ok(types::Bytes::default())
+        /// Captures the output of the top-level frame if it did not succeed.
+        #[derive(Debug, Default)]
+        struct ErrorTracer {
+            output: Option<Bytes>,
+        }
+
+        impl Tracer for ErrorTracer {
+            fn capture_end(&mut self, depth: usize, _: u64, output: &Output) {
+                if depth == 0 && output.status_code != StatusCode::Success {
+                    self.output = Some(output.output_data.clone());
+                }
+            }
+        }
+
+        let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let txn = db.begin()?;
+
+            let mut tracer = ErrorTracer::default();
+            replay_transaction(&txn, &mut analysis_cache, hash, &mut tracer)?;
+
+            // Revert data is returned as is, Otterscan decodes the revert reason on its own.
+            Ok(tracer.output.unwrap_or_default().into())
+        })
+        .await
+        .unwrap_or_else(helpers::joinerror_to_result)
     }
     async fn get_transaction_by_sender_and_nonce(
         &self,
@@ -726,105 +882,36 @@ where
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
 
-            let acc_history_cursor = txn.cursor(tables::AccountHistory)?;
-            let mut acc_change_cursor = txn.cursor(tables::AccountChangeSet)?;
-
-            // Locate the chunk where the nonce happens
-            let mut max_bl_prev_chunk = BlockNumber(0);
-            let mut bitmap = Treemap::default();
-            let mut acc = None;
-
-            let walker = acc_history_cursor.walk(Some(BitmapKey {
-                inner: addr,
-                block_number: 0.into(),
-            }));
-            pin!(walker);
-
-            while let Some((BitmapKey { inner, .. }, b)) = walker.next().transpose()? {
-                if inner != addr {
-                    break;
-                }
-
-                bitmap = b;
+            Ok(txn.get(tables::SenderNonceIndex, (addr, nonce))?)
+        })
+        .await
+        .unwrap_or_else(helpers::joinerror_to_result)
+    }
+    async fn get_contract_creator(&self, addr: Address) -> RpcResult<Option<ContractCreatorData>> {
+        let db = self.db.clone();
 
-                // Inspect block changeset
-                let max_bl = bitmap.maximum().unwrap_or(0);
+        tokio::task::spawn_blocking(move || {
+            let txn = db.begin()?;
 
-                if let Some(a) = acc_change_cursor
-                    .find_account(max_bl.into(), addr)?
-                    .ok_or_else(|| format_err!("account not found"))?
+            // Creations are not recorded for blocks executed by older database versions.
+            if let Some(start) = txn.get(tables::ContractCreationStart, ())? {
+                if let Some(account) =
+                    crate::accessors::state::account::read(&txn, addr, Some(start - 1))?
                 {
-                    if a.nonce > nonce {
-                        acc = Some(a);
-                        break;
-                    }
-                }
-
-                max_bl_prev_chunk = BlockNumber(max_bl);
-            }
-
-            if acc.is_none() {
-                // Check plain state
-                if let Some(a) = txn.get(tables::Account, addr)? {
-                    // Nonce changed in plain state, so it means the last block of last chunk
-                    // contains the actual nonce change
-                    if a.nonce > nonce {
-                        acc = Some(a);
+                    if account.code_hash != EMPTY_HASH {
+                        return Err(format_err!(
+                            "{addr:?} was created before block {start}, creators are unknown"
+                        )
+                        .into());
                     }
                 }
             }
 
-            Ok(if acc.is_some() {
-                // Locate the exact block inside chunk when the nonce changed
-                let blocks = bitmap.iter().collect::<Vec<_>>();
-                let mut idx = 0;
-                for (i, block) in blocks.iter().enumerate() {
-                    // Locate the block changeset
-                    if let Some(acc) = acc_change_cursor
-                        .find_account((*block).into(), addr)?
-                        .ok_or_else(|| format_err!("account not found"))?
-                    {
-                        // Since the state contains the nonce BEFORE the block changes, we look for
-                        // the block when the nonce changed to be > the desired once, which means the
-                        // previous history block contains the actual change; it may contain multiple
-                        // nonce changes.
-                        if acc.nonce > nonce {
-                            idx = i;
-                            break;
-                        }
-                    }
-                }
-
-                // Since the changeset contains the state BEFORE the change, we inspect
-                // the block before the one we found; if it is the first block inside the chunk,
-                // we use the last block from prev chunk
-                let nonce_block = if idx > 0 {
-                    BlockNumber(blocks[idx - 1])
-                } else {
-                    max_bl_prev_chunk
-                };
-
-                let txs =
-                    crate::accessors::chain::block_body::read_without_senders(&txn, nonce_block)?
-                        .ok_or_else(|| format_err!("body not found for block {nonce_block}"))?
-                        .transactions;
-
-                Some({
-                    txs
-                        .into_iter()
-                        .find(|t| t.nonce() == nonce)
-                        .ok_or_else(|| format_err!("body for block #{nonce_block} does not contain tx for {addr}/#{nonce} despite indications for otherwise"))?
-                        .hash()
-                })
-            } else {
-                None
-            })
+            Ok(txn
+                .get(tables::ContractCreator, addr)?
+                .map(|(hash, creator)| ContractCreatorData { hash, creator }))
         })
         .await
         .unwrap_or_else(helpers::joinerror_to_result)
     }
-    async fn get_contract_creator(&self, addr: Address) -> RpcResult<Option<ContractCreatorData>> {
-        let _ = addr;
-        Ok(None)
-    }
 }
@@ -188,6 +188,7 @@ where
 {
     pub db: Arc<MdbxWithDirHandle<SE>>,
     pub call_gas_limit: u64,
+    pub analysis_cache: AnalysisCache,
 }
 
 #[derive(Debug)]
@@ -200,6 +201,7 @@ enum CallManyMode {
 
 fn do_call_many<K, E>(
     txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
     kind: CallManyMode,
     calls: Vec<(Address, Message, HashSet<types::TraceType>)>,
     ommers_for_finalization: Option<ArrayVec<BlockHeader, 2>>,
@@ -257,9 +259,8 @@ where
     let block_spec = chain_spec.collect_block_spec(block_number);
     let mut buffer = Buffer::new(txn, historical_block);
 
-    let engine = engine_factory(None, chain_spec.clone(), None)?;
+    let engine = engine_factory(None, chain_spec.clone(), None, None)?;
 
-    let mut analysis_cache = AnalysisCache::default();
     for (sender, message, trace_types) in calls {
         let (output, updates, trace) = {
             let mut buffer = LoggingBuffer::new(&mut buffer);
@@ -273,7 +274,7 @@ where
                 &block_spec,
                 &header,
                 &mut tracer,
-                &mut analysis_cache,
+                analysis_cache,
                 &mut gas_used,
                 &message,
                 sender,
@@ -389,7 +390,7 @@ where
 
     let mut rewards = vec![];
     if let Some(ommers) = ommers_for_finalization {
-        for change in engine_factory(None, chain_spec, None)?.finalize(&header, &ommers)? {
+        for change in engine_factory(None, chain_spec, None, None)?.finalize(&header, &ommers)? {
             match change {
                 crate::consensus::FinalizationChange::Reward {
                     address,
@@ -413,6 +414,7 @@ where
 
 fn replay_block_transactions<K, E>(
     txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
     block_id: types::BlockId,
     trace_types: HashSet<types::TraceType>,
     finalize: bool,
@@ -448,6 +450,7 @@ where
 
             let (traces, rewards) = do_call_many(
                 txn,
+                analysis_cache,
                 CallManyMode::Replay(block_id),
                 signed_messages
                     .into_iter()
@@ -482,63 +485,67 @@ where
 
 fn replay_block<K, E>(
     txn: &MdbxTransaction<'_, K, E>,
+    analysis_cache: &mut AnalysisCache,
     block_id: types::BlockId,
 ) -> RpcResult<Option<(BlockNumber, H256, Vec<TransactionTraceWithLocation>)>>
 where
     K: TransactionKind,
     E: EnvironmentKind,
 {
-    Ok(
-        replay_block_transactions(txn, block_id, hashset![types::TraceType::Trace], true)?.map(
-            |(block_number, block_hash, full_traces, rewards)| {
-                (
-                    block_number,
-                    block_hash,
-                    full_traces
+    Ok(replay_block_transactions(
+        txn,
+        analysis_cache,
+        block_id,
+        hashset![types::TraceType::Trace],
+        true,
+    )?
+    .map(|(block_number, block_hash, full_traces, rewards)| {
+        (
+            block_number,
+            block_hash,
+            full_traces
+                .into_iter()
+                .enumerate()
+                .flat_map(
+                    |(
+                        transaction_position,
+                        types::FullTraceWithTransactionHash {
+                            full_trace,
+                            transaction_hash,
+                        },
+                    )| {
+                        full_trace
+                            .trace
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(move |trace| TransactionTraceWithLocation {
+                                trace,
+                                transaction_position: Some(transaction_position),
+                                transaction_hash: Some(transaction_hash),
+                                block_number: block_number.0.into(),
+                                block_hash,
+                            })
+                    },
+                )
+                .chain(
+                    rewards
                         .into_iter()
-                        .enumerate()
-                        .flat_map(
-                            |(
-                                transaction_position,
-                                types::FullTraceWithTransactionHash {
-                                    full_trace,
-                                    transaction_hash,
-                                },
-                            )| {
-                                full_trace
-                                    .trace
-                                    .unwrap_or_default()
-                                    .into_iter()
-                                    .map(move |trace| TransactionTraceWithLocation {
-                                        trace,
-                                        transaction_position: Some(transaction_position),
-                                        transaction_hash: Some(transaction_hash),
-                                        block_number: block_number.0.into(),
-                                        block_hash,
-                                    })
+                        .map(|reward| TransactionTraceWithLocation {
+                            trace: types::TransactionTrace {
+                                trace_address: vec![],
+                                subtraces: 0,
+                                action: types::Action::Reward(reward),
+                                result: None,
                             },
-                        )
-                        .chain(
-                            rewards
-                                .into_iter()
-                                .map(|reward| TransactionTraceWithLocation {
-                                    trace: types::TransactionTrace {
-                                        trace_address: vec![],
-                                        subtraces: 0,
-                                        action: types::Action::Reward(reward),
-                                        result: None,
-                                    },
-                                    transaction_position: None,
-                                    transaction_hash: None,
-                                    block_number: block_number.0.into(),
-                                    block_hash,
-                                }),
-                        )
-                        .collect(),
+                            transaction_position: None,
+                            transaction_hash: None,
+                            block_number: block_number.0.into(),
+                            block_hash,
+                        }),
                 )
-            },
-        ),
-    )
+                .collect(),
+        )
+    }))
 }
 
 impl<DB> TraceApiServerImpl<DB>
@@ -562,11 +569,12 @@ where
         let to_block = to_block.unwrap_or(types::BlockId::Number(types::BlockNumber::Latest));
 
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         let (res_tx, rx) = tokio::sync::mpsc::channel(1);
 
         tokio::task::spawn_blocking(move || {
-            let f = {
+            let mut f = {
                 let res_tx = res_tx.clone();
                 move || {
                     let txn = db.begin()?;
@@ -647,9 +655,12 @@ where
                     for block in blocks_to_scan {
                         trace!("Tracing block {block}");
 
-                        let (_, _, traces) =
-                            replay_block(&txn, types::BlockNumber::Number(block.0.into()).into())?
-                                .unwrap();
+                        let (_, _, traces) = replay_block(
+                            &txn,
+                            &mut analysis_cache,
+                            types::BlockNumber::Number(block.0.into()).into(),
+                        )?
+                        .unwrap();
 
                         for trace in traces.into_iter().filter(|trace| {
                             if requested_from_addresses.is_empty()
@@ -738,6 +749,7 @@ where
     ) -> RpcResult<Vec<types::FullTrace>> {
         let db = self.db.clone();
         let call_gas_limit = self.call_gas_limit;
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
@@ -772,7 +784,14 @@ where
                 })
                 .collect::<anyhow::Result<_>>()?;
 
-            Ok(do_call_many(&txn, CallManyMode::Speculative(block_id), msgs, None)?.0)
+            Ok(do_call_many(
+                &txn,
+                &mut analysis_cache,
+                CallManyMode::Speculative(block_id),
+                msgs,
+                None,
+            )?
+            .0)
         })
         .await
         .unwrap_or_else(helpers::joinerror_to_result)
@@ -785,6 +804,7 @@ where
         block_id: Option<types::BlockId>,
     ) -> RpcResult<types::FullTrace> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let signed_message = <MessageWithSignature as fastrlp::Decodable>::decode(&mut &*rlp.0)
@@ -798,6 +818,7 @@ where
 
             Ok(do_call_many(
                 &txn,
+                &mut analysis_cache,
                 CallManyMode::Speculative(block_id),
                 vec![(sender, signed_message.message, trace_types)],
                 None,
@@ -815,12 +836,13 @@ where
         trace_types: HashSet<types::TraceType>,
     ) -> RpcResult<Option<Vec<types::FullTraceWithTransactionHash>>> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
 
             Ok(
-                replay_block_transactions(&txn, block_id, trace_types, false)?
+                replay_block_transactions(&txn, &mut analysis_cache, block_id, trace_types, false)?
                     .map(|(_, _, full_traces, _)| full_traces),
             )
         })
@@ -834,6 +856,7 @@ where
         trace_types: HashSet<types::TraceType>,
     ) -> RpcResult<types::FullTrace> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
@@ -863,6 +886,7 @@ where
 
                 return Ok(do_call_many(
                     &txn,
+                    &mut analysis_cache,
                     CallManyMode::Replay(types::BlockNumber::Number(block_number.0.into()).into()),
                     transactions
                         .into_iter()
@@ -886,54 +910,61 @@ where
         block_id: types::BlockId,
     ) -> RpcResult<Option<Vec<types::TransactionTraceWithLocation>>> {
         let db = self.db.clone();
+        let mut analysis_cache = self.analysis_cache.clone();
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
 
-            Ok(
-                replay_block_transactions(&txn, block_id, hashset![types::TraceType::Trace], true)?
-                    .map(|(block_number, block_hash, full_traces, rewards)| {
-                        full_traces
+            Ok(replay_block_transactions(
+                &txn,
+                &mut analysis_cache,
+                block_id,
+                hashset![types::TraceType::Trace],
+                true,
+            )?
+            .map(|(block_number, block_hash, full_traces, rewards)| {
+                full_traces
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(
+                        |(
+                            transaction_position,
+                            types::FullTraceWithTransactionHash {
+                                full_trace,
+                                transaction_hash,
+                            },
+                        )| {
+                            full_trace
+                                .trace
+                                .unwrap_or_default()
+                                .into_iter()
+                                .map(move |trace| TransactionTraceWithLocation {
+                                    trace,
+                                    transaction_position: Some(transaction_position),
+                                    transaction_hash: Some(transaction_hash),
+                                    block_number: block_number.0.into(),
+                                    block_hash,
+                                })
+                        },
+                    )
+                    .chain(
+                        rewards
                             .into_iter()
-                            .enumerate()
-                            .flat_map(
-                                |(
-                                    transaction_position,
-                                    types::FullTraceWithTransactionHash {
-                                        full_trace,
-                                        transaction_hash,
-                                    },
-                                )| {
-                                    full_trace.trace.unwrap_or_default().into_iter().map(
-                                        move |trace| TransactionTraceWithLocation {
-                                            trace,
-                                            transaction_position: Some(transaction_position),
-                                            transaction_hash: Some(transaction_hash),
-                                            block_number: block_number.0.into(),
-                                            block_hash,
-                                        },
-                                    )
+                            .map(|reward| TransactionTraceWithLocation {
+                                trace: types::TransactionTrace {
+                                    trace_address: vec![],
+                                    subtraces: 0,
+                                    action: types::Action::Reward(reward),
+                                    result: None,
                                 },
-                            )
-                            .chain(
-                                rewards
-                                    .into_iter()
-                                    .map(|reward| TransactionTraceWithLocation {
-                                        trace: types::TransactionTrace {
-                                            trace_address: vec![],
-                                            subtraces: 0,
-                                            action: types::Action::Reward(reward),
-                                            result: None,
-                                        },
-                                        transaction_position: None,
-                                        transaction_hash: None,
-                                        block_number: block_number.0.into(),
-                                        block_hash,
-                                    }),
-                            )
-                            .collect()
-                    }),
-            )
+                                transaction_position: None,
+                                transaction_hash: None,
+                                block_number: block_number.0.into(),
+                                block_hash,
+                            }),
+                    )
+                    .collect()
+            }))
         })
         .await
         .unwrap_or_else(helpers::joinerror_to_result)
@@ -1,4 +1,4 @@
-use crate::sentry::devp2p::{types::*, util::*};
+use crate::sentry::devp2p::{peer_db::PeerDb, types::*, util::*};
 use anyhow::{anyhow, bail};
 use arrayvec::ArrayString;
 use async_stream::{stream, try_stream};
@@ -8,6 +8,7 @@ use derive_more::{Deref, Display};
 use educe::Educe;
 use enr::{Enr, EnrKeyUnambiguous, EnrPublicKey};
 use maplit::hashset;
+use parking_lot::Mutex;
 use secp256k1::{PublicKey, SecretKey};
 use std::{
     collections::{HashMap, HashSet},
@@ -52,6 +53,7 @@ impl DnsDiscovery {
         discovery: Arc<Resolver<B, SecretKey>>,
         domain: String,
         public_key: Option<PublicKey>,
+        peer_db: Option<Arc<Mutex<PeerDb>>>,
     ) -> Self {
         let tasks = TaskGroup::default();
 
@@ -77,14 +79,16 @@ impl DnsDiscovery {
                         }
                         Ok(Some(Ok(v))) => {
                             if let Some(addr) = v.tcp4_socket() {
-                                if tx
-                                    .send(Ok(NodeRecord {
-                                        addr: addr.into(),
-                                        id: pk2id(&v.public_key()),
-                                    }))
-                                    .await
-                                    .is_err()
-                                {
+                                let node_record = NodeRecord {
+                                    addr: addr.into(),
+                                    id: pk2id(&v.public_key()),
+                                };
+                                if let Some(peer_db) = &peer_db {
+                                    peer_db
+                                        .lock()
+                                        .seen(node_record, v.udp4(), Some(v.to_base64()));
+                                }
+                                if tx.send(Ok(node_record)).await.is_err() {
                                     return;
                                 }
                             }
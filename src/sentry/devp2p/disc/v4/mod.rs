@@ -8,8 +8,10 @@ mod node;
 mod proto;
 mod util;
 
+use crate::sentry::devp2p::PeerDb;
 use educe::Educe;
 use ethereum_types::H512;
+use parking_lot::Mutex;
 use std::{
     pin::Pin,
     sync::{
@@ -35,6 +37,7 @@ pub struct Discv4Builder {
     #[educe(Default(20))]
     cache: usize,
     throttle: Arc<AtomicBool>,
+    peer_db: Option<Arc<Mutex<PeerDb>>>,
 }
 
 impl Discv4Builder {
@@ -53,8 +56,20 @@ impl Discv4Builder {
         self
     }
 
+    /// Record found nodes in peer database.
+    pub fn with_peer_db(mut self, peer_db: Arc<Mutex<PeerDb>>) -> Self {
+        self.peer_db = Some(peer_db);
+        self
+    }
+
     pub fn build(self, node: Arc<Node>) -> Discv4 {
-        Discv4::new(node, self.concurrent_lookups, self.throttle, self.cache)
+        Discv4::new(
+            node,
+            self.concurrent_lookups,
+            self.throttle,
+            self.cache,
+            self.peer_db,
+        )
     }
 }
 
@@ -71,6 +86,7 @@ impl Discv4 {
         concurrent_lookups: usize,
         throttled: Arc<AtomicBool>,
         cache: usize,
+        peer_db: Option<Arc<Mutex<PeerDb>>>,
     ) -> Self {
         let tasks = TaskGroup::default();
 
@@ -80,6 +96,7 @@ impl Discv4 {
             let node = node.clone();
             let tx = tx.clone();
             let throttled = throttled.clone();
+            let peer_db = peer_db.clone();
             tasks.spawn_with_name(format!("discv4 lookup #{}", i), {
                 async move {
                     loop {
@@ -88,12 +105,16 @@ impl Discv4 {
                             tokio::time::sleep(THROTTLE_INTERVAL).await;
                         } else {
                             for record in node.lookup(rand::random()).await {
-                                let _ = tx
-                                    .send(crate::sentry::devp2p::types::NodeRecord {
-                                        addr: record.tcp_addr(),
-                                        id: record.id,
-                                    })
-                                    .await;
+                                let node_record = crate::sentry::devp2p::types::NodeRecord {
+                                    addr: record.tcp_addr(),
+                                    id: record.id,
+                                };
+                                if let Some(peer_db) = &peer_db {
+                                    peer_db
+                                        .lock()
+                                        .seen(node_record, Some(record.udp_port), None);
+                                }
+                                let _ = tx.send(node_record).await;
                             }
                         }
                     }
@@ -3,7 +3,6 @@ use anyhow::{anyhow, bail, Context};
 use bytes::{BufMut, BytesMut};
 use fastrlp::*;
 use futures::future::join_all;
-use igd::aio::search_gateway;
 use num_traits::FromPrimitive;
 use parking_lot::{Mutex, RwLock};
 use primitive_types::H256;
@@ -27,6 +26,7 @@ use tokio::{
     sync::{
         mpsc::{channel, Sender},
         oneshot::{channel as oneshot, Sender as OneshotSender},
+        watch,
     },
     time::{sleep, timeout},
 };
@@ -37,7 +37,6 @@ pub type RequestId = u64;
 
 pub const MAX_PACKET_SIZE: usize = 1280;
 
-pub const UPNP_INTERVAL: Duration = Duration::from_secs(60);
 pub const PING_TIMEOUT: Duration = Duration::from_secs(5);
 pub const REFRESH_TIMEOUT: Duration = Duration::from_secs(60);
 pub const PING_INTERVAL: Duration = Duration::from_secs(10);
@@ -211,54 +210,32 @@ impl Node {
         addr: SocketAddr,
         secret_key: SecretKey,
         bootstrap_nodes: Vec<NodeRecord>,
-        public_address: Option<IpAddr>,
-        enable_upnp: bool,
+        known_nodes: Vec<NodeRecord>,
+        external_ip: watch::Receiver<Option<IpAddr>>,
         tcp_port: u16,
     ) -> anyhow::Result<Arc<Self>> {
         let node_endpoint = Arc::new(RwLock::new(Endpoint {
-            address: Ip(public_address.unwrap_or_else(|| addr.ip())),
+            address: Ip(external_ip.borrow().unwrap_or_else(|| addr.ip())),
             udp_port: addr.port(),
             tcp_port,
         }));
 
         let task_group = Arc::new(TaskGroup::new());
 
-        if enable_upnp {
-            task_group.spawn_with_name("discv4 - UPnP", {
-                let node_endpoint = node_endpoint.clone();
-                async move {
-                    loop {
-                        match async {
-                            Ok::<_, anyhow::Error>(
-                                search_gateway(Default::default())
-                                    .await?
-                                    .get_external_ip()
-                                    .await?,
-                            )
-                        }
-                        .await
-                        {
-                            Ok(v) => {
-                                debug!("Discovered public IP: {}", v);
-                                node_endpoint.write().address = Ip(v);
-                            }
-                            Err(e) => {
-                                debug!("Failed to get public IP via UPNP: {}", e);
-                                debug!("Trying with public_ip DNS/HTTP resolvers");
-                                if let Some(v) = public_ip::addr().await {
-                                    debug!("Discovered public IP: {}", v);
-                                    node_endpoint.write().address = Ip(v);
-                                } else {
-                                    debug!("Failed to get public IP via DNS/HTTP resolvers");
-                                }
-                            }
-                        }
-                        sleep(UPNP_INTERVAL).await;
+        task_group.spawn_with_name("discv4 - NAT", {
+            let node_endpoint = node_endpoint.clone();
+            let mut external_ip = external_ip.clone();
+            async move {
+                loop {
+                    if let Some(ip) = *external_ip.borrow_and_update() {
+                        node_endpoint.write().address = Ip(ip);
+                    }
+                    if external_ip.changed().await.is_err() {
+                        break;
                     }
                 }
-                .instrument(span!(Level::TRACE, "UPNP",))
-            });
-        }
+            }
+        });
 
         let id = pk2id(&PublicKey::from_secret_key(SECP256K1, &secret_key));
 
@@ -273,6 +250,9 @@ impl Node {
             debug!("Adding bootstrap node: {:?}", node);
             table.add_verified(node);
         }
+        for node in known_nodes {
+            table.add_seen(node);
+        }
 
         let connected = Arc::new(Mutex::new(table));
 
@@ -404,6 +384,7 @@ impl Node {
             let egress_requests_tx = egress_requests_tx.clone();
             let connected = connected.clone();
             let node_endpoint = node_endpoint.clone();
+            let external_ip = external_ip.clone();
             let expected_pings = expected_pings.clone();
             let inflight_find_node_requests = inflight_find_node_requests.clone();
             async move {
@@ -500,7 +481,8 @@ impl Node {
                                                 inflight_ping_requests.lock().remove(&message.echo)
                                             {
                                                 trace!("PONG - our endpoint is: {:?}", message.to);
-                                                {
+                                                // Trust the NAT configuration over what peers see
+                                                if external_ip.borrow().is_none() {
                                                     let mut node_endpoint = node_endpoint.write();
                                                     node_endpoint.address = message.to.address;
                                                     node_endpoint.udp_port = message.to.udp_port;
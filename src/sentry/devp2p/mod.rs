@@ -12,16 +12,22 @@ pub mod disc;
 pub mod ecies;
 mod errors;
 mod mac;
+pub mod nat;
 mod node_filter;
 mod peer;
+mod peer_db;
+mod reputation;
 mod rlpx;
 pub mod transport;
 mod types;
 pub mod util;
 
 pub use disc::*;
+pub use node_filter::{MemoryNodeFilter, NodeFilter, PersistentNodeFilter};
 pub use peer::{DisconnectReason, PeerStream};
-pub use rlpx::{ListenOptions, Swarm, SwarmBuilder};
+pub use peer_db::{PeerDb, PeerDbEntry};
+pub use reputation::{PeerAction, Reputation, DISCONNECT_THRESHOLD, RESPONSE_REWARD};
+pub use rlpx::{ListenOptions, PeerConnectionInfo, Swarm, SwarmBuilder};
 pub use types::{
     CapabilityId, CapabilityInfo, CapabilityName, CapabilityServer, CapabilityVersion,
     InboundEvent, Message, NodeRecord, OutboundEvent, PeerId,
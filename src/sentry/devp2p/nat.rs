@@ -0,0 +1,295 @@
+//! Port mapping and external address discovery for nodes behind NAT.
+
+use anyhow::{bail, format_err, Context};
+use igd::{aio::search_gateway, PortMappingProtocol};
+use std::{
+    fmt::{self, Display},
+    net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    net::UdpSocket,
+    sync::watch,
+    time::{sleep, timeout},
+};
+use tracing::*;
+
+/// Lifetime requested for port mappings.
+pub const MAPPING_LIFETIME: Duration = Duration::from_secs(20 * 60);
+/// Mappings are renewed well before they expire.
+pub const MAPPING_RENEW_INTERVAL: Duration = Duration::from_secs(10 * 60);
+const RETRY_INTERVAL: Duration = Duration::from_secs(60);
+const MAPPING_DESCRIPTION: &str = "hana";
+
+const NATPMP_PORT: u16 = 5351;
+const NATPMP_TIMEOUT: Duration = Duration::from_secs(1);
+const NATPMP_ATTEMPTS: usize = 3;
+const NATPMP_OP_EXTERNAL_ADDRESS: u8 = 0;
+const NATPMP_OP_MAP_UDP: u8 = 1;
+const NATPMP_OP_MAP_TCP: u8 = 2;
+
+/// How the node makes itself reachable from outside of its network.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Nat {
+    /// No port mapping, external address is learned from discovery pongs.
+    None,
+    Upnp,
+    /// NAT-PMP with the given gateway, or the default one.
+    Pmp(Option<Ipv4Addr>),
+    /// Ports are forwarded manually, the external address is known.
+    ExtIp(IpAddr),
+}
+
+impl FromStr for Nat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.split_once(':') {
+            None if s == "none" => Self::None,
+            None if s == "upnp" => Self::Upnp,
+            None if s == "pmp" => Self::Pmp(None),
+            Some(("pmp", gateway)) => Self::Pmp(Some(
+                gateway
+                    .parse()
+                    .with_context(|| format!("invalid NAT-PMP gateway {gateway}"))?,
+            )),
+            Some(("extip", ip)) => Self::ExtIp(
+                ip.parse()
+                    .with_context(|| format!("invalid external IP {ip}"))?,
+            ),
+            _ => {
+                bail!("invalid NAT option {s}, expected none, upnp, pmp[:<gateway>] or extip:<ip>")
+            }
+        })
+    }
+}
+
+impl Display for Nat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "none"),
+            Self::Upnp => write!(f, "upnp"),
+            Self::Pmp(None) => write!(f, "pmp"),
+            Self::Pmp(Some(gateway)) => write!(f, "pmp:{gateway}"),
+            Self::ExtIp(ip) => write!(f, "extip:{ip}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ports {
+    /// RLPx listener port.
+    pub tcp: u16,
+    /// Discovery port.
+    pub udp: u16,
+}
+
+/// Keeps ports mapped on the gateway and publishes the external IP address.
+pub async fn run(nat: Nat, ports: Ports, external_ip: watch::Sender<Option<IpAddr>>) {
+    match nat {
+        Nat::None => {}
+        Nat::ExtIp(ip) => {
+            info!("External IP address: {ip}");
+            external_ip.send_replace(Some(ip));
+        }
+        Nat::Upnp | Nat::Pmp(_) => loop {
+            let mapped = if let Nat::Pmp(gateway) = nat {
+                map_pmp(gateway, ports).await
+            } else {
+                map_upnp(ports).await
+            };
+            let retry_in = match mapped {
+                Ok(ip) => {
+                    if *external_ip.borrow() != Some(ip) {
+                        info!(
+                            "Mapped TCP port {} and UDP port {} via {nat}, external IP {ip}",
+                            ports.tcp, ports.udp
+                        );
+                    }
+                    external_ip.send_replace(Some(ip));
+                    MAPPING_RENEW_INTERVAL
+                }
+                Err(e) => {
+                    warn!("Failed to map ports via {nat}: {e}");
+                    if external_ip.borrow().is_none() {
+                        if let Some(ip) = public_ip::addr().await {
+                            info!("External IP address from DNS/HTTP resolvers: {ip}");
+                            external_ip.send_replace(Some(ip));
+                        }
+                    }
+                    RETRY_INTERVAL
+                }
+            };
+            sleep(retry_in).await;
+        },
+    }
+}
+
+/// Local address used to reach the gateway.
+async fn local_ipv4(gateway: SocketAddr) -> anyhow::Result<Ipv4Addr> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect(gateway).await?;
+    match socket.local_addr()?.ip() {
+        IpAddr::V4(ip) => Ok(ip),
+        ip => bail!("unexpected local address {ip}"),
+    }
+}
+
+async fn map_upnp(ports: Ports) -> anyhow::Result<IpAddr> {
+    let gateway = search_gateway(Default::default()).await?;
+    let local_ip = local_ipv4(gateway.addr.into()).await?;
+
+    for (protocol, port) in [
+        (PortMappingProtocol::TCP, ports.tcp),
+        (PortMappingProtocol::UDP, ports.udp),
+    ] {
+        gateway
+            .add_port(
+                protocol,
+                port,
+                SocketAddrV4::new(local_ip, port).into(),
+                MAPPING_LIFETIME.as_secs() as u32,
+                MAPPING_DESCRIPTION,
+            )
+            .await
+            .with_context(|| format!("failed to map {protocol:?} port {port}"))?;
+    }
+
+    Ok(gateway.get_external_ip().await?.into())
+}
+
+/// Sends a NAT-PMP (RFC 6886) request, retrying on timeout.
+async fn natpmp_request(
+    socket: &UdpSocket,
+    request: &[u8],
+    response_len: usize,
+) -> anyhow::Result<[u8; 16]> {
+    let mut buf = [0; 16];
+    for _ in 0..NATPMP_ATTEMPTS {
+        socket.send(request).await?;
+        if let Ok(len) = timeout(NATPMP_TIMEOUT, socket.recv(&mut buf)).await {
+            if len? < response_len || buf[0] != 0 || buf[1] != request[1] | 0x80 {
+                bail!("malformed NAT-PMP response");
+            }
+            let result = u16::from_be_bytes([buf[2], buf[3]]);
+            if result != 0 {
+                bail!("NAT-PMP request failed with result code {result}");
+            }
+            return Ok(buf);
+        }
+    }
+
+    bail!("no response from NAT-PMP gateway")
+}
+
+async fn map_pmp(gateway: Option<Ipv4Addr>, ports: Ports) -> anyhow::Result<IpAddr> {
+    let gateway = match gateway {
+        Some(gateway) => gateway,
+        None => default_gateway()?,
+    };
+
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    socket.connect((gateway, NATPMP_PORT)).await?;
+
+    let response = natpmp_request(&socket, &[0, NATPMP_OP_EXTERNAL_ADDRESS], 12).await?;
+    let external_ip = Ipv4Addr::new(response[8], response[9], response[10], response[11]);
+
+    for (opcode, port) in [
+        (NATPMP_OP_MAP_TCP, ports.tcp),
+        (NATPMP_OP_MAP_UDP, ports.udp),
+    ] {
+        let mut request = vec![0, opcode, 0, 0];
+        // Internal port, then suggested external port
+        request.extend_from_slice(&port.to_be_bytes());
+        request.extend_from_slice(&port.to_be_bytes());
+        request.extend_from_slice(&(MAPPING_LIFETIME.as_secs() as u32).to_be_bytes());
+
+        let response = natpmp_request(&socket, &request, 16)
+            .await
+            .with_context(|| format!("failed to map port {port}"))?;
+        let mapped_port = u16::from_be_bytes([response[10], response[11]]);
+        if mapped_port != port {
+            warn!(
+                "Gateway mapped port {port} to external port {mapped_port}, peers will not be able \
+                 to reach us"
+            );
+        }
+    }
+
+    Ok(external_ip.into())
+}
+
+fn default_gateway() -> anyhow::Result<Ipv4Addr> {
+    let routes = std::fs::read_to_string("/proc/net/route")
+        .context("cannot determine default gateway, specify it as pmp:<gateway>")?;
+    parse_default_gateway(&routes)
+        .ok_or_else(|| format_err!("no default gateway, specify it as pmp:<gateway>"))
+}
+
+/// Parses the default IPv4 gateway out of `/proc/net/route`.
+fn parse_default_gateway(routes: &str) -> Option<Ipv4Addr> {
+    routes.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace().skip(1);
+        if fields.next()? != "00000000" {
+            return None;
+        }
+        // Network byte order, printed as a host (little endian) integer
+        let gateway = u32::from_str_radix(fields.next()?, 16).ok()?;
+        Some(Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_nat() {
+        for (s, nat) in [
+            ("none", Nat::None),
+            ("upnp", Nat::Upnp),
+            ("pmp", Nat::Pmp(None)),
+            (
+                "pmp:192.168.1.1",
+                Nat::Pmp(Some(Ipv4Addr::new(192, 168, 1, 1))),
+            ),
+            ("extip:1.2.3.4", Nat::ExtIp(IpAddr::from([1, 2, 3, 4]))),
+            (
+                "extip:::1",
+                Nat::ExtIp(IpAddr::from(std::net::Ipv6Addr::LOCALHOST)),
+            ),
+        ] {
+            assert_eq!(s.parse::<Nat>().unwrap(), nat);
+            assert_eq!(nat.to_string(), s);
+        }
+
+        for s in ["", "upnp:1.2.3.4", "extip", "extip:localhost", "pmp:::1"] {
+            assert!(s.parse::<Nat>().is_err(), "{s}");
+        }
+    }
+
+    #[test]
+    fn parse_routes() {
+        let routes =
+            "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT
+eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\t0\t0\t0
+eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\t0\t0\t0
+";
+        assert_eq!(
+            parse_default_gateway(routes),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+        assert_eq!(
+            parse_default_gateway(
+                routes
+                    .lines()
+                    .take(2)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+                    .as_str()
+            ),
+            None
+        );
+    }
+}
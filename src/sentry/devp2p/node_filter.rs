@@ -1,26 +1,49 @@
-use super::types::PeerId;
+use super::{
+    reputation::{PeerAction, Reputation, REPUTATION_BAN_DURATION},
+    types::{NodeRecord, PeerId},
+};
+use anyhow::Context;
+use lru::LruCache;
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashSet,
+    collections::{BTreeMap, HashMap},
     fmt::Debug,
+    num::NonZeroUsize,
+    path::PathBuf,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
+use tracing::*;
+
+const REPUTATION_CACHE_SIZE: usize = 10_000;
 
 pub trait NodeFilter: Debug + Send + 'static {
     fn max_peers(&self) -> usize;
     fn is_banned(&self, id: PeerId) -> bool;
+    /// Trusted peers are never banned or disconnected and bypass the peer limit.
+    fn is_trusted(&self, id: PeerId) -> bool;
     fn is_allowed(&self, pool_size: usize, id: PeerId) -> bool {
-        pool_size < self.max_peers() && !self.is_banned(id)
+        self.is_trusted(id) || (pool_size < self.max_peers() && !self.is_banned(id))
     }
-    fn ban(&mut self, id: PeerId);
+    fn ban(&mut self, id: PeerId, duration: Duration);
+    /// Changes reputation of the peer, returning what should be done with it.
+    fn report(&mut self, id: PeerId, change: i32) -> PeerAction;
+    fn trusted_peers(&self) -> Vec<NodeRecord>;
+    /// Returns `false` if the peer was already trusted.
+    fn add_trusted(&mut self, node: NodeRecord) -> bool;
+    /// Returns `false` if the peer was not trusted.
+    fn remove_trusted(&mut self, id: PeerId) -> bool;
 }
 
 #[derive(Debug)]
 pub struct MemoryNodeFilter {
     peer_limiter: Arc<AtomicUsize>,
-    ban_list: HashSet<PeerId>,
+    ban_list: HashMap<PeerId, SystemTime>,
+    reputations: LruCache<PeerId, Reputation>,
+    trusted: HashMap<PeerId, NodeRecord>,
 }
 
 impl MemoryNodeFilter {
@@ -28,6 +51,8 @@ impl MemoryNodeFilter {
         Self {
             peer_limiter,
             ban_list: Default::default(),
+            reputations: LruCache::new(NonZeroUsize::new(REPUTATION_CACHE_SIZE).unwrap()),
+            trusted: Default::default(),
         }
     }
 }
@@ -38,10 +63,238 @@ impl NodeFilter for MemoryNodeFilter {
     }
 
     fn is_banned(&self, id: PeerId) -> bool {
-        self.ban_list.contains(&id)
+        self.ban_list
+            .get(&id)
+            .map_or(false, |&until| until > SystemTime::now())
+    }
+
+    fn is_trusted(&self, id: PeerId) -> bool {
+        self.trusted.contains_key(&id)
+    }
+
+    fn ban(&mut self, id: PeerId, duration: Duration) {
+        let now = SystemTime::now();
+        self.ban_list.retain(|_, until| *until > now);
+        self.ban_list.insert(id, now + duration);
+    }
+
+    fn report(&mut self, id: PeerId, change: i32) -> PeerAction {
+        let now = Instant::now();
+        let score = match self.reputations.get_mut(&id) {
+            Some(reputation) => reputation.change(change, now),
+            None => {
+                let mut reputation = Reputation::new(now);
+                let score = reputation.change(change, now);
+                self.reputations.put(id, reputation);
+                score
+            }
+        };
+
+        if self.is_trusted(id) {
+            return PeerAction::None;
+        }
+
+        let action = PeerAction::from_score(score);
+        if action == PeerAction::Ban {
+            debug!("Banning peer {id} with reputation {score}");
+            self.ban(id, REPUTATION_BAN_DURATION);
+        }
+        action
+    }
+
+    fn trusted_peers(&self) -> Vec<NodeRecord> {
+        self.trusted.values().copied().collect()
+    }
+
+    fn add_trusted(&mut self, node: NodeRecord) -> bool {
+        self.ban_list.remove(&node.id);
+        self.trusted.insert(node.id, node).is_none()
+    }
+
+    fn remove_trusted(&mut self, id: PeerId) -> bool {
+        self.trusted.remove(&id).is_some()
+    }
+}
+
+/// Bans and trusted peers as stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PeerFile {
+    /// Peer ID to ban expiry in seconds since Unix epoch.
+    bans: BTreeMap<String, u64>,
+    /// Enode URLs.
+    trusted: Vec<String>,
+}
+
+/// Node filter keeping bans and trusted peers in a file, so that they survive restarts.
+#[derive(Debug)]
+pub struct PersistentNodeFilter {
+    inner: MemoryNodeFilter,
+    path: PathBuf,
+}
+
+impl PersistentNodeFilter {
+    pub fn open(path: PathBuf, peer_limiter: Arc<AtomicUsize>) -> anyhow::Result<Self> {
+        let mut inner = MemoryNodeFilter::new(peer_limiter);
+
+        match std::fs::read(&path) {
+            Ok(data) => {
+                let file = serde_json::from_slice::<PeerFile>(&data)
+                    .with_context(|| format!("failed to parse {}", path.display()))?;
+
+                let now = SystemTime::now();
+                for (id, until) in file.bans {
+                    let until = UNIX_EPOCH + Duration::from_secs(until);
+                    if until > now {
+                        let id = id
+                            .parse()
+                            .with_context(|| format!("invalid banned peer ID {id}"))?;
+                        inner.ban_list.insert(id, until);
+                    }
+                }
+                for enode in file.trusted {
+                    let node = enode
+                        .parse::<NodeRecord>()
+                        .map_err(|e| anyhow::format_err!("invalid trusted peer {enode}: {e}"))?;
+                    inner.trusted.insert(node.id, node);
+                }
+
+                info!(
+                    "Loaded {} banned and {} trusted peers",
+                    inner.ban_list.len(),
+                    inner.trusted.len()
+                );
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Self { inner, path })
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let now = SystemTime::now();
+        let file = PeerFile {
+            bans: self
+                .inner
+                .ban_list
+                .iter()
+                .filter(|(_, &until)| until > now)
+                .map(|(id, until)| {
+                    (
+                        hex::encode(id),
+                        until
+                            .duration_since(UNIX_EPOCH)
+                            .unwrap_or_default()
+                            .as_secs(),
+                    )
+                })
+                .collect(),
+            trusted: self
+                .inner
+                .trusted
+                .values()
+                .map(|node| node.to_string())
+                .collect(),
+        };
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec_pretty(&file)?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+
+    fn save_or_warn(&self) {
+        if let Err(e) = self.save() {
+            warn!("Failed to save peers to {}: {e}", self.path.display());
+        }
+    }
+}
+
+impl NodeFilter for PersistentNodeFilter {
+    fn max_peers(&self) -> usize {
+        self.inner.max_peers()
+    }
+
+    fn is_banned(&self, id: PeerId) -> bool {
+        self.inner.is_banned(id)
+    }
+
+    fn is_trusted(&self, id: PeerId) -> bool {
+        self.inner.is_trusted(id)
+    }
+
+    fn ban(&mut self, id: PeerId, duration: Duration) {
+        self.inner.ban(id, duration);
+        self.save_or_warn();
     }
 
-    fn ban(&mut self, id: PeerId) {
-        self.ban_list.insert(id);
+    fn report(&mut self, id: PeerId, change: i32) -> PeerAction {
+        let action = self.inner.report(id, change);
+        if action == PeerAction::Ban {
+            self.save_or_warn();
+        }
+        action
+    }
+
+    fn trusted_peers(&self) -> Vec<NodeRecord> {
+        self.inner.trusted_peers()
+    }
+
+    fn add_trusted(&mut self, node: NodeRecord) -> bool {
+        let added = self.inner.add_trusted(node);
+        if added {
+            self.save_or_warn();
+        }
+        added
+    }
+
+    fn remove_trusted(&mut self, id: PeerId) -> bool {
+        let removed = self.inner.remove_trusted(id);
+        if removed {
+            self.save_or_warn();
+        }
+        removed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sentry::devp2p::reputation::BAN_THRESHOLD;
+
+    #[test]
+    fn bans_and_trusted_persist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("peers.json");
+        let peer_limiter = Arc::new(AtomicUsize::new(1));
+
+        let bad = PeerId::repeat_byte(1);
+        let trusted = NodeRecord {
+            id: PeerId::repeat_byte(2),
+            addr: "127.0.0.1:30303".parse().unwrap(),
+        };
+
+        let mut filter = PersistentNodeFilter::open(path.clone(), peer_limiter.clone()).unwrap();
+        assert_eq!(
+            filter.report(bad, BAN_THRESHOLD / 2),
+            PeerAction::Disconnect
+        );
+        assert!(!filter.is_banned(bad));
+        assert_eq!(filter.report(bad, BAN_THRESHOLD), PeerAction::Ban);
+        assert!(filter.add_trusted(trusted));
+        assert_eq!(filter.report(trusted.id, BAN_THRESHOLD), PeerAction::None);
+
+        let filter = PersistentNodeFilter::open(path, peer_limiter).unwrap();
+        assert!(filter.is_banned(bad));
+        assert!(!filter.is_allowed(0, bad));
+        assert!(!filter.is_banned(trusted.id));
+        assert!(filter.is_allowed(100, trusted.id));
+        assert!(!filter.is_allowed(1, PeerId::repeat_byte(3)));
+        assert_eq!(filter.trusted_peers().len(), 1);
     }
 }
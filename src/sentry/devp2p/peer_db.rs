@@ -0,0 +1,223 @@
+use super::{disc::v4, types::*};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tracing::*;
+
+/// Nodes not seen for this long are forgotten.
+pub const NODE_EXPIRY: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+/// Nodes failing this many dials in a row are forgotten.
+pub const MAX_DIAL_FAILURES: u32 = 5;
+/// Maximum number of nodes kept, the least useful are forgotten first.
+pub const MAX_NODES: usize = 10_000;
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerDbEntry {
+    pub id: PeerId,
+    pub ip: IpAddr,
+    pub tcp_port: u16,
+    /// Discovery port, if the node was found via discv4 or ENR.
+    pub udp_port: Option<u16>,
+    /// Base64 ENR, if the node was found via DNS discovery.
+    pub enr: Option<String>,
+    /// Seconds since Unix epoch.
+    pub last_seen: u64,
+    /// Seconds since Unix epoch.
+    pub last_success: Option<u64>,
+    /// Failed dials since the last successful one.
+    pub failures: u32,
+}
+
+impl PeerDbEntry {
+    pub fn node_record(&self) -> NodeRecord {
+        NodeRecord {
+            id: self.id,
+            addr: SocketAddr::new(self.ip, self.tcp_port),
+        }
+    }
+
+    pub fn discv4_record(&self) -> Option<v4::NodeRecord> {
+        Some(v4::NodeRecord {
+            address: self.ip.into(),
+            tcp_port: self.tcp_port,
+            udp_port: self.udp_port?,
+            id: self.id,
+        })
+    }
+}
+
+/// Nodes seen by discovery and the outcome of dialing them, kept across restarts to seed discovery
+/// and the dialer.
+#[derive(Debug)]
+pub struct PeerDb {
+    path: PathBuf,
+    entries: HashMap<PeerId, PeerDbEntry>,
+}
+
+impl PeerDb {
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
+        let entries = match std::fs::read(&path) {
+            Ok(data) => serde_json::from_slice::<Vec<PeerDbEntry>>(&data)
+                .with_context(|| format!("failed to parse {}", path.display()))?
+                .into_iter()
+                .map(|entry| (entry.id, entry))
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut db = Self { path, entries };
+        db.expire(unix_now());
+
+        info!("Loaded {} known nodes", db.len());
+
+        Ok(db)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn get(&self, id: PeerId) -> Option<&PeerDbEntry> {
+        self.entries.get(&id)
+    }
+
+    /// Records that discovery has found the node.
+    pub fn seen(&mut self, node: NodeRecord, udp_port: Option<u16>, enr: Option<String>) {
+        let now = unix_now();
+        let entry = self.entries.entry(node.id).or_insert_with(|| PeerDbEntry {
+            id: node.id,
+            ip: node.addr.ip(),
+            tcp_port: node.addr.port(),
+            udp_port: None,
+            enr: None,
+            last_seen: now,
+            last_success: None,
+            failures: 0,
+        });
+
+        entry.ip = node.addr.ip();
+        entry.tcp_port = node.addr.port();
+        entry.udp_port = udp_port.or(entry.udp_port);
+        entry.enr = enr.or(entry.enr.take());
+        entry.last_seen = now;
+    }
+
+    pub fn dial_succeeded(&mut self, node: NodeRecord) {
+        self.seen(node, None, None);
+        let entry = self.entries.get_mut(&node.id).unwrap();
+        entry.last_success = Some(entry.last_seen);
+        entry.failures = 0;
+    }
+
+    pub fn dial_failed(&mut self, id: PeerId) {
+        if let Some(entry) = self.entries.get_mut(&id) {
+            entry.failures += 1;
+        }
+    }
+
+    /// Known nodes, most recently successfully dialed first.
+    pub fn nodes(&self) -> Vec<PeerDbEntry> {
+        let mut nodes = self.entries.values().cloned().collect::<Vec<_>>();
+        nodes.sort_by(|a, b| {
+            b.last_success
+                .cmp(&a.last_success)
+                .then(a.failures.cmp(&b.failures))
+                .then(b.last_seen.cmp(&a.last_seen))
+        });
+        nodes
+    }
+
+    /// Forgets nodes that were not seen recently or keep failing, and the least useful ones
+    /// above `MAX_NODES`.
+    pub fn expire(&mut self, now: u64) {
+        let before = self.entries.len();
+
+        self.entries.retain(|_, entry| {
+            entry.last_seen + NODE_EXPIRY.as_secs() > now && entry.failures < MAX_DIAL_FAILURES
+        });
+        if self.entries.len() > MAX_NODES {
+            for entry in self.nodes().into_iter().skip(MAX_NODES) {
+                self.entries.remove(&entry.id);
+            }
+        }
+
+        let expired = before - self.entries.len();
+        if expired > 0 {
+            debug!("Expired {expired} known nodes");
+        }
+    }
+
+    pub fn save(&mut self) -> anyhow::Result<()> {
+        self.expire(unix_now());
+
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let tmp_path = self.path.with_extension("tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&self.nodes())?)?;
+        std::fs::rename(&tmp_path, &self.path)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_db() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nodes.json");
+
+        let node = |b| NodeRecord {
+            id: PeerId::repeat_byte(b),
+            addr: SocketAddr::new([127, 0, 0, b].into(), 30303),
+        };
+
+        let mut db = PeerDb::open(path.clone()).unwrap();
+        assert!(db.is_empty());
+
+        db.seen(node(1), Some(30301), None);
+        db.seen(node(2), None, Some("enr:-test".into()));
+        db.dial_succeeded(node(2));
+        db.seen(node(3), None, None);
+        for _ in 0..MAX_DIAL_FAILURES {
+            db.dial_failed(node(3).id);
+        }
+        db.save().unwrap();
+
+        let db = PeerDb::open(path).unwrap();
+        assert_eq!(db.len(), 2);
+        let nodes = db.nodes();
+        assert_eq!(nodes[0].node_record().id, node(2).id);
+        assert_eq!(nodes[0].enr.as_deref(), Some("enr:-test"));
+        assert!(nodes[0].discv4_record().is_none());
+        assert_eq!(
+            nodes[1].discv4_record().unwrap().udp_addr(),
+            SocketAddr::new([127, 0, 0, 1].into(), 30301)
+        );
+
+        let mut db = db;
+        db.expire(unix_now() + NODE_EXPIRY.as_secs());
+        assert!(db.is_empty());
+    }
+}
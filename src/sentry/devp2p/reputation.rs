@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+
+/// Highest reputation a peer can earn with useful responses.
+pub const REPUTATION_MAX: i32 = 500;
+/// Peers at or below this reputation are disconnected.
+pub const DISCONNECT_THRESHOLD: i32 = -500;
+/// Peers at or below this reputation are banned.
+pub const BAN_THRESHOLD: i32 = -1000;
+/// Lowest tracked reputation, so that a banned peer can recover once the ban expires.
+const REPUTATION_MIN: i32 = 2 * BAN_THRESHOLD;
+/// Time it takes for reputation to decay halfway to neutral.
+pub const REPUTATION_HALF_LIFE: Duration = Duration::from_secs(600);
+/// How long peers reaching `BAN_THRESHOLD` stay banned.
+pub const REPUTATION_BAN_DURATION: Duration = Duration::from_secs(12 * 60 * 60);
+
+/// Reputation reward for a response to our request.
+pub const RESPONSE_REWARD: i32 = 5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerAction {
+    None,
+    Disconnect,
+    Ban,
+}
+
+impl PeerAction {
+    pub fn from_score(score: i32) -> Self {
+        if score <= BAN_THRESHOLD {
+            Self::Ban
+        } else if score <= DISCONNECT_THRESHOLD {
+            Self::Disconnect
+        } else {
+            Self::None
+        }
+    }
+}
+
+/// Reputation score of a peer, decaying towards neutral over time.
+#[derive(Clone, Copy, Debug)]
+pub struct Reputation {
+    score: f64,
+    updated_at: Instant,
+}
+
+impl Reputation {
+    pub fn new(now: Instant) -> Self {
+        Self {
+            score: 0.0,
+            updated_at: now,
+        }
+    }
+
+    fn decayed(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+        self.score * 0.5_f64.powf(elapsed.as_secs_f64() / REPUTATION_HALF_LIFE.as_secs_f64())
+    }
+
+    pub fn score(&self, now: Instant) -> i32 {
+        self.decayed(now).round() as i32
+    }
+
+    /// Applies `change` on top of decayed score, returning the new score.
+    pub fn change(&mut self, change: i32, now: Instant) -> i32 {
+        self.score =
+            (self.decayed(now) + change as f64).clamp(REPUTATION_MIN as f64, REPUTATION_MAX as f64);
+        self.updated_at = now;
+        self.score(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decay_and_thresholds() {
+        let now = Instant::now();
+        let mut reputation = Reputation::new(now);
+
+        assert_eq!(reputation.change(-600, now), -600);
+        assert_eq!(PeerAction::from_score(-600), PeerAction::Disconnect);
+        assert_eq!(reputation.score(now + REPUTATION_HALF_LIFE), -300);
+        assert_eq!(
+            PeerAction::from_score(reputation.change(-700, now + REPUTATION_HALF_LIFE)),
+            PeerAction::Ban
+        );
+
+        for _ in 0..1000 {
+            reputation.change(RESPONSE_REWARD, now + REPUTATION_HALF_LIFE);
+        }
+        assert_eq!(reputation.score(now + REPUTATION_HALF_LIFE), REPUTATION_MAX);
+        assert_eq!(PeerAction::from_score(REPUTATION_MAX), PeerAction::None);
+    }
+}
@@ -4,8 +4,10 @@ use super::{
     disc::Discovery,
     node_filter::{MemoryNodeFilter, NodeFilter},
     peer::*,
+    peer_db::PeerDb,
     transport::{TcpServer, TokioCidrListener, Transport},
     types::*,
+    util::pk2id,
 };
 use anyhow::{anyhow, bail, Context};
 use cidr::IpCidr;
@@ -13,7 +15,7 @@ use educe::Educe;
 use futures::sink::SinkExt;
 use lru::LruCache;
 use parking_lot::Mutex;
-use secp256k1::SecretKey;
+use secp256k1::{PublicKey, SecretKey, SECP256K1};
 use std::{
     collections::{hash_map::Entry, BTreeMap, HashMap},
     fmt::Debug,
@@ -48,6 +50,7 @@ const MAX_FAILED_PINGS: usize = 3;
 const BAN_DURATION: Duration = Duration::from_secs(300);
 const BAN_BACKOFF: Duration = Duration::from_millis(100);
 const DIAL_SLEEP: Duration = Duration::from_millis(2000);
+const TRUSTED_PEER_REDIAL_INTERVAL: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Copy, Debug)]
 enum DisconnectInitiator {
@@ -62,9 +65,18 @@ struct DisconnectSignal {
     reason: DisconnectReason,
 }
 
+/// Information about established connection with a peer.
+#[derive(Clone, Debug)]
+pub struct PeerConnectionInfo {
+    pub remote_addr: Option<SocketAddr>,
+    pub inbound: bool,
+    pub capabilities: Vec<CapabilityInfo>,
+}
+
 #[derive(Debug)]
 struct ConnectedPeerState {
     _tasks: TaskGroup,
+    info: PeerConnectionInfo,
 }
 
 #[derive(Debug)]
@@ -82,7 +94,8 @@ impl PeerConnectionState {
 #[derive(Debug)]
 struct PeerState {
     connection_state: PeerConnectionState,
-    sem_permit: OwnedSemaphorePermit,
+    /// Trusted peers do not take up a slot.
+    sem_permit: Option<OwnedSemaphorePermit>,
 }
 
 #[derive(Debug)]
@@ -158,11 +171,18 @@ fn setup_peer_state<C, Io>(
     capability_server: Arc<C>,
     remote_id: PeerId,
     peer: PeerStream<Io>,
+    remote_addr: Option<SocketAddr>,
+    inbound: bool,
 ) -> ConnectedPeerState
 where
     C: CapabilityServer,
     Io: Transport,
 {
+    let info = PeerConnectionInfo {
+        remote_addr,
+        inbound,
+        capabilities: peer.capabilities().to_vec(),
+    };
     let capability_set = peer
         .capabilities()
         .iter()
@@ -372,7 +392,10 @@ where
             }
         }
     });
-    ConnectedPeerState { _tasks: tasks }
+    ConnectedPeerState {
+        _tasks: tasks,
+        info,
+    }
 }
 
 /// Establishes the connection with peer and adds them to internal state.
@@ -392,6 +415,7 @@ async fn handle_incoming_request<C, Io>(
         capability_server,
         port,
     } = handshake_data;
+    let remote_addr = stream.remote_addr();
     // Do handshake and convert incoming connection into stream.
     let peer_res = tokio::time::timeout(
         HANDSHAKE_TIMEOUT,
@@ -428,21 +452,36 @@ async fn handle_incoming_request<C, Io>(
                     );
                 }
                 Entry::Vacant(entry) => {
-                    if let Ok(sem_permit) = semaphore.clone().try_acquire_owned() {
-                        if node_filter.lock().is_allowed(total_connections, remote_id) {
-                            debug!("New incoming peer connected: {}", remote_id);
-                            entry.insert(PeerState {
-                                connection_state: PeerConnectionState::Connected(setup_peer_state(
-                                    Arc::downgrade(&streams),
-                                    capability_server,
-                                    remote_id,
-                                    peer,
-                                )),
-                                sem_permit,
-                            });
-                        } else {
-                            trace!("Node filter rejected peer {}, disconnecting", remote_id);
-                        }
+                    let (trusted, allowed) = {
+                        let node_filter = node_filter.lock();
+                        (
+                            node_filter.is_trusted(remote_id),
+                            node_filter.is_allowed(total_connections, remote_id),
+                        )
+                    };
+                    let sem_permit = if trusted {
+                        None
+                    } else if let Ok(sem_permit) = semaphore.clone().try_acquire_owned() {
+                        Some(sem_permit)
+                    } else {
+                        return;
+                    };
+
+                    if allowed {
+                        debug!("New incoming peer connected: {}", remote_id);
+                        entry.insert(PeerState {
+                            connection_state: PeerConnectionState::Connected(setup_peer_state(
+                                Arc::downgrade(&streams),
+                                capability_server,
+                                remote_id,
+                                peer,
+                                remote_addr,
+                                true,
+                            )),
+                            sem_permit,
+                        });
+                    } else {
+                        trace!("Node filter rejected peer {}, disconnecting", remote_id);
                     }
                 }
             }
@@ -518,6 +557,8 @@ pub struct Swarm<C: CapabilityServer> {
 pub struct SwarmBuilder {
     task_group: Option<Arc<TaskGroup>>,
     listen_options: Option<ListenOptions>,
+    node_filter: Option<Arc<Mutex<dyn NodeFilter>>>,
+    peer_db: Option<Arc<Mutex<PeerDb>>>,
     client_version: String,
 }
 
@@ -532,6 +573,18 @@ impl SwarmBuilder {
         self
     }
 
+    /// Use the given node filter instead of an in-memory one.
+    pub fn with_node_filter(mut self, node_filter: Arc<Mutex<dyn NodeFilter>>) -> Self {
+        self.node_filter = Some(node_filter);
+        self
+    }
+
+    /// Record discovered nodes and outcomes of dialing them.
+    pub fn with_peer_db(mut self, peer_db: Arc<Mutex<PeerDb>>) -> Self {
+        self.peer_db = Some(peer_db);
+        self
+    }
+
     pub fn with_client_version(mut self, version: String) -> Self {
         self.client_version = version;
         self
@@ -551,6 +604,8 @@ impl SwarmBuilder {
             capability_mask.into(),
             capability_server,
             self.listen_options,
+            self.node_filter,
+            self.peer_db,
         )
         .await
     }
@@ -593,6 +648,8 @@ impl Swarm<()> {
         SwarmBuilder {
             task_group: None,
             listen_options: None,
+            node_filter: None,
+            peer_db: None,
             client_version: format!("rust-devp2p/{}", env!("CARGO_PKG_VERSION")),
         }
     }
@@ -617,6 +674,8 @@ impl<C: CapabilityServer> Swarm<C> {
         capabilities: CapabilitySet,
         capability_server: Arc<C>,
         listen_options: Option<ListenOptions>,
+        node_filter: Option<Arc<Mutex<dyn NodeFilter>>>,
+        peer_db: Option<Arc<Mutex<PeerDb>>>,
     ) -> anyhow::Result<Arc<Self>> {
         let tasks = task_group.unwrap_or_default();
 
@@ -628,9 +687,11 @@ impl<C: CapabilityServer> Swarm<C> {
             .as_ref()
             .map_or(usize::MAX, |options| options.max_peers.get());
         let streams = Arc::new(Mutex::new(PeerStreams::new(max_peers)));
-        let node_filter = Arc::new(Mutex::new(MemoryNodeFilter::new(Arc::new(
-            max_peers.into(),
-        ))));
+        let node_filter = node_filter.unwrap_or_else(|| {
+            Arc::new(Mutex::new(MemoryNodeFilter::new(Arc::new(
+                max_peers.into(),
+            ))))
+        });
 
         let capabilities = Arc::new(capabilities);
 
@@ -671,6 +732,11 @@ impl<C: CapabilityServer> Swarm<C> {
             port,
         });
 
+        let trusted_peers = server.node_filter.lock().trusted_peers();
+        for node_record in trusted_peers {
+            server.spawn_trusted_peer_dialer(node_record);
+        }
+
         if let Some(options) = listen_options {
             for (disc_id, mut discovery) in options.discovery_tasks {
                 let task_id = format!("dialer ({disc_id})");
@@ -681,6 +747,7 @@ impl<C: CapabilityServer> Swarm<C> {
 
                     let server = server.clone();
                     let no_new_peers = options.no_new_peers.clone();
+                    let peer_db = peer_db.clone();
 
                     async move {
                         while let Some(num_peers) = server.upgrade().map(|server| server.num_peers()) {
@@ -714,16 +781,25 @@ impl<C: CapabilityServer> Swarm<C> {
 
                                         if let Some(server) = server.upgrade() {
                                             let banlist = banlist.clone();
+                                            let peer_db = peer_db.clone();
                                             let tasks = server.tasks.clone();
                                             let disc_id = disc_id.clone();
                                             tasks.spawn(async move {
                                                 debug!("Dialing peer {id:?}@{addr} ({disc_id})");
-                                                if server
-                                                    .add_peer_inner(addr, id, true)
-                                                    .await
-                                                    .is_err()
-                                                {
-                                                    banlist.lock().put(id, Instant::now());
+                                                match server.add_peer_inner(addr, id, true).await {
+                                                    Ok(true) => {
+                                                        if let Some(peer_db) = &peer_db {
+                                                            let record = NodeRecord { id, addr };
+                                                            peer_db.lock().dial_succeeded(record);
+                                                        }
+                                                    }
+                                                    Ok(false) => {}
+                                                    Err(_) => {
+                                                        banlist.lock().put(id, Instant::now());
+                                                        if let Some(peer_db) = &peer_db {
+                                                            peer_db.lock().dial_failed(id);
+                                                        }
+                                                    }
                                                 }
                                             });
                                         } else {
@@ -808,13 +884,18 @@ impl<C: CapabilityServer> Swarm<C> {
             let mut inserted = false;
 
             {
-                let semaphore = streams.lock().semaphore.clone();
-                trace!("Awaiting semaphore permit");
-                let sem_permit = match semaphore.acquire_owned().await {
-                    Ok(v) => v,
-                    Err(_) => return Ok(false),
+                let sem_permit = if node_filter.lock().is_trusted(remote_id) {
+                    None
+                } else {
+                    let semaphore = streams.lock().semaphore.clone();
+                    trace!("Awaiting semaphore permit");
+                    let sem_permit = match semaphore.acquire_owned().await {
+                        Ok(v) => v,
+                        Err(_) => return Ok(false),
+                    };
+                    trace!("Semaphore permit acquired");
+                    Some(sem_permit)
                 };
-                trace!("Semaphore permit acquired");
 
                 currently_connecting.fetch_add(1, Ordering::SeqCst);
 
@@ -888,6 +969,8 @@ impl<C: CapabilityServer> Swarm<C> {
                                     capability_server,
                                     remote_id,
                                     peer,
+                                    Some(addr),
+                                    false,
                                 ));
 
                             let _ = tx.send(());
@@ -926,6 +1009,81 @@ impl<C: CapabilityServer> Swarm<C> {
     pub fn num_peers(&self) -> usize {
         self.streams.lock().mapping.len()
     }
+
+    /// Node ID of this RLPx node
+    pub fn local_id(&self) -> PeerId {
+        pk2id(&PublicKey::from_secret_key(SECP256K1, &self.secret_key))
+    }
+
+    pub fn client_version(&self) -> &str {
+        &self.client_version
+    }
+
+    /// Port of RLPx TCP server, 0 if not listening
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Peers with established connection
+    pub fn connected_peers_info(&self) -> Vec<(PeerId, PeerConnectionInfo)> {
+        self.streams
+            .lock()
+            .mapping
+            .iter()
+            .filter_map(|(&id, state)| match &state.connection_state {
+                PeerConnectionState::Connected(connected) => Some((id, connected.info.clone())),
+                PeerConnectionState::Connecting { .. } => None,
+            })
+            .collect()
+    }
+
+    pub fn is_trusted(&self, id: PeerId) -> bool {
+        self.node_filter.lock().is_trusted(id)
+    }
+
+    /// Add a trusted peer. Trusted peers bypass bans and the peer limit and are redialed while disconnected.
+    /// Returns `false` if the peer was already trusted.
+    pub fn add_trusted_peer(self: &Arc<Self>, node_record: NodeRecord) -> bool {
+        if !self.node_filter.lock().add_trusted(node_record) {
+            return false;
+        }
+
+        self.spawn_trusted_peer_dialer(node_record);
+
+        true
+    }
+
+    fn spawn_trusted_peer_dialer(self: &Arc<Self>, node_record: NodeRecord) {
+        let server = Arc::downgrade(self);
+        self.tasks.spawn_with_name(
+            format!("trusted peer {} dialer", node_record.id),
+            async move {
+                while let Some(server) = server.upgrade() {
+                    if !server.is_trusted(node_record.id) {
+                        break;
+                    }
+
+                    if !server.streams.lock().mapping.contains_key(&node_record.id) {
+                        let dial = server.add_peer(node_record);
+                        drop(server);
+                        if let Err(e) = dial.await {
+                            debug!("Failed to dial trusted peer {}: {}", node_record.id, e);
+                        }
+                    } else {
+                        drop(server);
+                    }
+
+                    sleep(TRUSTED_PEER_REDIAL_INTERVAL).await;
+                }
+            },
+        );
+    }
+
+    /// Remove a trusted peer, keeping the connection if there is one.
+    /// Returns `false` if the peer was not trusted.
+    pub fn remove_trusted_peer(&self, id: PeerId) -> bool {
+        self.node_filter.lock().remove_trusted(id)
+    }
 }
 
 impl<C: CapabilityServer> Deref for Swarm<C> {
@@ -24,10 +24,7 @@ impl FromStr for NodeRecord {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         const PREFIX: &str = "enode://";
 
-        let (prefix, data) = s.split_at(PREFIX.len());
-        if prefix != PREFIX {
-            return Err("Not an enode".into());
-        }
+        let data = s.strip_prefix(PREFIX).ok_or("Not an enode")?;
 
         let mut parts = data.split('@');
         let id = parts.next().ok_or("Failed to read remote ID")?.parse()?;
@@ -37,6 +34,12 @@ impl FromStr for NodeRecord {
     }
 }
 
+impl std::fmt::Display for NodeRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "enode://{}@{}", hex::encode(self.id), self.addr)
+    }
+}
+
 #[derive(Clone, Copy, Debug, Display, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct CapabilityName(pub ArrayString<4>);
 
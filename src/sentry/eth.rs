@@ -98,6 +98,32 @@ pub enum EthMessageId {
     Receipts = 16,
 }
 
+impl EthMessageId {
+    /// Message answering this request.
+    pub fn response(self) -> Option<Self> {
+        Some(match self {
+            Self::GetBlockHeaders => Self::BlockHeaders,
+            Self::GetBlockBodies => Self::BlockBodies,
+            Self::GetPooledTransactions => Self::PooledTransactions,
+            Self::GetNodeData => Self::NodeData,
+            Self::GetReceipts => Self::Receipts,
+            _ => return None,
+        })
+    }
+}
+
+/// Request ID of an eth/66 request or response, and whether its payload list is non-empty.
+pub fn request_id(mut data: &[u8]) -> Option<(u64, bool)> {
+    let buf = &mut data;
+    if !Header::decode(buf).ok()?.list {
+        return None;
+    }
+    let request_id = u64::decode(buf).ok()?;
+    let payload = Header::decode(buf).ok()?;
+
+    Some((request_id, payload.payload_length > 0))
+}
+
 #[derive(Clone, Copy, Debug, Primitive)]
 pub enum EthProtocolVersion {
     Eth65 = 65,
@@ -18,7 +18,7 @@ use fastrlp::Decodable;
 use futures::stream::BoxStream;
 use maplit::btreemap;
 use num_traits::{FromPrimitive, ToPrimitive};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use secp256k1::{PublicKey, SecretKey, SECP256K1};
 use std::{
     self,
@@ -27,7 +27,7 @@ use std::{
     net::{IpAddr, SocketAddr},
     num::NonZeroUsize,
     sync::{
-        atomic::{AtomicBool, Ordering},
+        atomic::{AtomicBool, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
@@ -59,6 +59,9 @@ pub const MAX_INITIAL_WINDOW_SIZE: u32 = (1 << 31) - 1;
 /// MAX_FRAME_SIZE upper bound
 pub const MAX_FRAME_SIZE: u32 = (1 << 24) - 1;
 const THROTTLE_INTERVAL: Duration = Duration::from_secs(5);
+const PEER_DB_SAVE_INTERVAL: Duration = Duration::from_secs(300);
+/// Requests per peer remembered for rewarding their responses.
+const MAX_OUTSTANDING_REQUESTS: usize = 256;
 
 #[derive(Clone, Debug, FromStr)]
 pub struct NR(pub NodeRecord);
@@ -137,24 +140,34 @@ pub struct CapabilityServerImpl {
     status_message: Arc<RwLock<Option<FullStatusData>>>,
     protocol_version: EthProtocolVersion,
     valid_peers: Arc<RwLock<HashSet<PeerId>>>,
+    /// Expected response message by request ID, for requests sent to each peer.
+    outstanding_requests: Arc<Mutex<HashMap<PeerId, HashMap<u64, usize>>>>,
 
     data_sender: BroadcastSender<InboundMessage>,
     peers_status_sender: BroadcastSender<PeerEvent>,
 
     no_new_peers: Arc<AtomicBool>,
+
+    node_filter: Arc<Mutex<dyn NodeFilter>>,
 }
 
 impl CapabilityServerImpl {
-    pub fn new(protocol_version: EthProtocolVersion, max_peers: NonZeroUsize) -> Self {
+    pub fn new(
+        protocol_version: EthProtocolVersion,
+        max_peers: NonZeroUsize,
+        node_filter: Arc<Mutex<dyn NodeFilter>>,
+    ) -> Self {
         Self {
             peer_pipes: Default::default(),
             block_tracker: Default::default(),
             status_message: Default::default(),
             protocol_version,
             valid_peers: Default::default(),
+            outstanding_requests: Default::default(),
             data_sender: broadcast_channel(max_peers.get() * BUFFERING_FACTOR).0,
             peers_status_sender: broadcast_channel(max_peers.get()).0,
             no_new_peers: Arc::new(AtomicBool::new(true)),
+            node_filter,
         }
     }
 
@@ -170,6 +183,21 @@ impl CapabilityServerImpl {
         block_tracker.set_block_number(peer, 0, true);
     }
 
+    /// Changes reputation of the peer, disconnecting it if reputation falls too low.
+    pub async fn report_peer(&self, peer: PeerId, change: i32) {
+        let action = self.node_filter.lock().report(peer, change);
+        if action != PeerAction::None {
+            debug!("Disconnecting peer {peer} ({action:?})");
+            self.disconnect_peer(peer, DisconnectReason::UselessPeer).await;
+        }
+    }
+
+    pub async fn disconnect_peer(&self, peer: PeerId, reason: DisconnectReason) {
+        if let Some(sender) = self.sender(peer) {
+            let _ = sender.send(OutboundEvent::Disconnect { reason }).await;
+        }
+    }
+
     fn get_pipes(&self, peer: PeerId) -> Option<Pipes> {
         self.peer_pipes.read().get(&peer).cloned()
     }
@@ -197,6 +225,7 @@ impl CapabilityServerImpl {
         pipes.remove(&peer);
         block_tracker.remove_peer(peer);
         valid_peers.remove(&peer);
+        self.outstanding_requests.lock().remove(&peer);
 
         let send_status_result =
             self.peers_status_sender
@@ -218,6 +247,40 @@ impl CapabilityServerImpl {
         self.valid_peers.read().len()
     }
 
+    fn track_request(&self, peer: PeerId, message: &Message) {
+        let response = match EthMessageId::from_usize(message.id).and_then(EthMessageId::response) {
+            Some(response) => response,
+            None => return,
+        };
+        if let Some((request_id, _)) = request_id(&message.data) {
+            let mut outstanding_requests = self.outstanding_requests.lock();
+            let requests = outstanding_requests.entry(peer).or_default();
+            if requests.len() < MAX_OUTSTANDING_REQUESTS {
+                requests.insert(request_id, response as usize);
+            }
+        }
+    }
+
+    /// Whether the message is a non-empty response to a request we sent to the peer.
+    fn is_useful_response(&self, peer: PeerId, message: &Message) -> bool {
+        match request_id(&message.data) {
+            Some((request_id, non_empty)) => {
+                let mut outstanding_requests = self.outstanding_requests.lock();
+                let answered = outstanding_requests.get_mut(&peer).map_or(false, |requests| {
+                    match requests.get(&request_id) {
+                        Some(&response) if response == message.id => {
+                            requests.remove(&request_id);
+                            true
+                        }
+                        _ => false,
+                    }
+                });
+                answered && non_empty
+            }
+            None => false,
+        }
+    }
+
     pub fn set_status(&self, message: FullStatusData) {
         *self.status_message.write() = Some(message);
         self.no_new_peers.store(false, Ordering::SeqCst);
@@ -230,10 +293,9 @@ impl CapabilityServerImpl {
                 debug!("Peer disconnect (reason: {:?}), tearing down peer.", reason);
                 self.teardown_peer(peer);
             }
-            InboundEvent::Message {
-                message: Message { id, data },
-                ..
-            } => {
+            InboundEvent::Message { message, .. } => {
+                let useful_response = self.is_useful_response(peer, &message);
+                let Message { id, data } = message;
                 let valid_peer = self.valid_peers.read().contains(&peer);
                 let message_id = EthMessageId::from_usize(id);
                 match message_id {
@@ -270,6 +332,10 @@ impl CapabilityServerImpl {
                         }
                     }
                     Some(inbound_id) if valid_peer => {
+                        if useful_response {
+                            self.node_filter.lock().report(peer, RESPONSE_REWARD);
+                        }
+
                         let _ = self.data_sender.send(InboundMessage {
                             id: sentry::MessageId::from(inbound_id) as i32,
                             data,
@@ -352,7 +418,8 @@ impl CapabilityServer for CapabilityServerImpl {
         }
     }
     async fn next(&self, peer: PeerId) -> OutboundEvent {
-        self.receiver(peer)
+        let event = self
+            .receiver(peer)
             .unwrap()
             .lock()
             .await
@@ -360,7 +427,11 @@ impl CapabilityServer for CapabilityServerImpl {
             .await
             .unwrap_or(OutboundEvent::Disconnect {
                 reason: DisconnectReason::DisconnectRequested,
-            })
+            });
+        if let OutboundEvent::Message { message, .. } = &event {
+            self.track_request(peer, message);
+        }
+        event
     }
 }
 
@@ -392,6 +463,9 @@ pub struct Opts {
     pub static_peers: Vec<NR>,
     #[clap(long, default_value = "5000")]
     pub static_peers_interval: u64,
+    /// Peers to always stay connected to, regardless of bans and peer limit. Persisted in data directory.
+    #[clap(long)]
+    pub trusted_peers: Vec<NR>,
     #[clap(long, default_value = "100")]
     pub max_peers: NonZeroUsize,
     /// Minimum number of peers, below which we will search for peers more aggressively.
@@ -403,6 +477,9 @@ pub struct Opts {
     /// Disable DNS discovery
     #[clap(long, num_args = 0)]
     pub no_dns_discovery: bool,
+    /// How to make the node reachable from outside: none, upnp, pmp[:<gateway>] or extip:<ip>.
+    #[clap(long, default_value = "upnp")]
+    pub nat: nat::Nat,
 }
 
 pub async fn run(
@@ -473,6 +550,26 @@ pub async fn run(
 
     let discv4_throttle = Arc::new(AtomicBool::new(false));
 
+    let tasks = Arc::new(TaskGroup::new());
+
+    let (external_ip_tx, external_ip) = tokio::sync::watch::channel(None);
+    tasks.spawn_with_name(
+        "NAT",
+        nat::run(
+            opts.nat,
+            nat::Ports {
+                tcp: opts.listen_port,
+                udp: opts.discv4_port,
+            },
+            external_ip_tx,
+        ),
+    );
+
+    let peer_db = Arc::new(Mutex::new(
+        PeerDb::open(db_path.sentry_db().join("nodes.json"))
+            .context("Failed to load known nodes")?,
+    ));
+
     if !opts.no_discovery {
         if !opts.no_dns_discovery {
             if let Some(dns_addr) = dns_addr {
@@ -483,7 +580,12 @@ pub async fn run(
                         .map_err(|err| format_err!("Failed to start DNS resolver: {err}"))?,
                 ));
 
-                let task = DnsDiscovery::new(Arc::new(dns_resolver), dns_addr, None);
+                let task = DnsDiscovery::new(
+                    Arc::new(dns_resolver),
+                    dns_addr,
+                    None,
+                    Some(peer_db.clone()),
+                );
 
                 discovery_tasks.insert("dnsdisc".to_string(), Box::pin(task));
             }
@@ -496,12 +598,17 @@ pub async fn run(
             .map(|Discv4NR(nr)| nr)
             .collect::<Vec<_>>();
 
+        let known_nodes = peer_db.lock().nodes();
+
         let node = disc::v4::Node::new(
             format!("0.0.0.0:{}", opts.discv4_port).parse().unwrap(),
             secret_key,
             bootstrap_nodes,
-            None,
-            true,
+            known_nodes
+                .iter()
+                .filter_map(PeerDbEntry::discv4_record)
+                .collect(),
+            external_ip,
             opts.listen_port,
         )
         .await?;
@@ -510,9 +617,24 @@ pub async fn run(
             .with_cache(opts.discv4_cache)
             .with_concurrent_lookups(opts.discv4_concurrent_lookups)
             .with_throttle(discv4_throttle.clone())
+            .with_peer_db(peer_db.clone())
             .build(node);
 
         discovery_tasks.insert("discv4".to_string(), Box::pin(task));
+
+        if !known_nodes.is_empty() {
+            info!("Dialing {} nodes known from previous runs", known_nodes.len());
+
+            let task = futures::stream::iter(
+                known_nodes
+                    .iter()
+                    .map(|entry| Ok::<_, anyhow::Error>(entry.node_record()))
+                    .collect::<Vec<_>>(),
+            )
+            .chain(futures::stream::pending());
+
+            discovery_tasks.insert("known nodes".to_string(), Box::pin(task));
+        }
     }
 
     if !opts.static_peers.is_empty() {
@@ -533,11 +655,21 @@ pub async fn run(
         warn!("All discovery methods are disabled, sentry will not search for peers.");
     }
 
-    let tasks = Arc::new(TaskGroup::new());
-
     let protocol_version = EthProtocolVersion::Eth66;
 
-    let capability_server = Arc::new(CapabilityServerImpl::new(protocol_version, opts.max_peers));
+    let node_filter: Arc<Mutex<dyn NodeFilter>> = Arc::new(Mutex::new(
+        PersistentNodeFilter::open(
+            db_path.sentry_db().join("peers.json"),
+            Arc::new(AtomicUsize::new(opts.max_peers.get())),
+        )
+        .context("Failed to load peers")?,
+    ));
+
+    let capability_server = Arc::new(CapabilityServerImpl::new(
+        protocol_version,
+        opts.max_peers,
+        node_filter.clone(),
+    ));
 
     let no_new_peers = capability_server.no_new_peers_handle();
 
@@ -551,6 +683,8 @@ pub async fn run(
             opts.cidr,
             no_new_peers,
         ))
+        .with_node_filter(node_filter)
+        .with_peer_db(peer_db.clone())
         .with_client_version(version_string())
         .build(
             btreemap! {
@@ -572,14 +706,35 @@ pub async fn run(
         });
     }
 
+    tasks.spawn_with_name("peer db saver", async move {
+        loop {
+            tokio::time::sleep(PEER_DB_SAVE_INTERVAL).await;
+            if let Err(e) = peer_db.lock().save() {
+                warn!("Failed to save known nodes: {e}");
+            }
+        }
+    });
+
+    for NR(node_record) in opts.trusted_peers {
+        if swarm.add_trusted_peer(node_record) {
+            info!("Added trusted peer {}", node_record);
+        }
+    }
+
     info!("RLPx node listening at {}", listen_addr);
 
     tasks.spawn(async move {
         let svc = SentryServer::new(SentryService::new(capability_server));
 
+        let (mut health_reporter, health_svc) = tonic_health::server::health_reporter();
+        health_reporter
+            .set_serving::<SentryServer<SentryService>>()
+            .await;
+
         info!("Sentry gRPC server starting on {}", opts.sentry_addr);
 
         Server::builder()
+            .add_service(health_svc)
             .add_service(svc)
             .serve(opts.sentry_addr)
             .await
@@ -8,7 +8,7 @@ use ethereum_interfaces::{
     sentry::{
         sentry_server::Sentry, HandShakeReply, InboundMessage, MessageId as ProtoMessageId,
         OutboundMessageData, PeerByIdReply, PeerEvent, PeerEventsRequest, PeerMinBlockRequest,
-        SentPeers, SetStatusReply,
+        PenaltyKind, SentPeers, SetStatusReply,
     },
     types::NodeInfoReply,
 };
@@ -116,18 +116,20 @@ impl Sentry for SentryService {
         &self,
         request: tonic::Request<ethereum_interfaces::sentry::PenalizePeerRequest>,
     ) -> Result<Response<()>, tonic::Status> {
-        let peer = request
-            .into_inner()
-            .peer_id
+        let ethereum_interfaces::sentry::PenalizePeerRequest { peer_id, penalty } =
+            request.into_inner();
+        let peer = peer_id
             .ok_or_else(|| tonic::Status::invalid_argument("no peer id"))?
             .into();
-        if let Some(sender) = self.capability_server.sender(peer) {
-            let _ = sender
-                .send(OutboundEvent::Disconnect {
-                    reason: DisconnectReason::DisconnectRequested,
-                })
-                .await;
+
+        // Kick is the only penalty there is. It costs the peer enough reputation to be
+        // disconnected, so that peers kicked again soon after get banned.
+        if penalty != PenaltyKind::Kick as i32 {
+            return Err(tonic::Status::invalid_argument("unknown penalty"));
         }
+        self.capability_server
+            .report_peer(peer, DISCONNECT_THRESHOLD)
+            .await;
 
         Ok(Response::new(()))
     }
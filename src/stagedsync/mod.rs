@@ -2,7 +2,7 @@ pub mod stage;
 pub mod util;
 
 use self::stage::{Stage, StageInput, UnwindInput};
-use crate::{kv::mdbx::*, models::*, stagedsync::stage::*, StageId};
+use crate::{kv::mdbx::*, metrics, models::*, stagedsync::stage::*, StageId};
 use futures::future::BoxFuture;
 use std::time::{Duration, Instant};
 use tokio::sync::watch::{Receiver as WatchReceiver, Sender as WatchSender};
@@ -50,6 +50,8 @@ where
     stages: Vec<QueuedStage<'db, E>>,
     current_stage_sender: WatchSender<Option<StageId>>,
     current_stage_receiver: WatchReceiver<Option<StageId>>,
+    reached_tip_sender: WatchSender<bool>,
+    reached_tip_receiver: WatchReceiver<bool>,
     min_progress_to_commit_after_stage: u64,
     max_block: Option<BlockNumber>,
     start_with_unwind: Option<BlockNumber>,
@@ -74,10 +76,13 @@ where
 {
     pub fn new() -> Self {
         let (current_stage_sender, current_stage_receiver) = tokio::sync::watch::channel(None);
+        let (reached_tip_sender, reached_tip_receiver) = tokio::sync::watch::channel(false);
         Self {
             stages: Vec::new(),
             current_stage_sender,
             current_stage_receiver,
+            reached_tip_sender,
+            reached_tip_receiver,
             min_progress_to_commit_after_stage: 0,
             max_block: None,
             start_with_unwind: None,
@@ -150,6 +155,11 @@ where
         self.current_stage_receiver.clone()
     }
 
+    /// Whether the last completed cycle has reached the tip of the chain.
+    pub fn reached_tip(&self) -> WatchReceiver<bool> {
+        self.reached_tip_receiver.clone()
+    }
+
     /// Run staged sync loop.
     /// Invokes each loaded stage, and does unwinds if necessary.
     ///
@@ -436,11 +446,13 @@ where
                             }
                         }
                     };
-                    receipts.push(StageExecutionReceipt {
+                    let receipt = StageExecutionReceipt {
                         stage_id,
                         progress: done_progress,
                         duration: Instant::now() - start_time,
-                    });
+                    };
+                    metrics::record_stage(&receipt);
+                    receipts.push(receipt);
 
                     previous_stage = Some((stage_id, done_progress))
                 }
@@ -460,6 +472,8 @@ where
 
                 tx.commit()?;
 
+                self.reached_tip_sender.send(reached_tip_flag).unwrap();
+
                 if let Some(cb) = &self.post_cycle_callback {
                     (cb)(StagedSyncStatus {
                         maximum_progress,
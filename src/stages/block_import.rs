@@ -0,0 +1,192 @@
+use crate::{
+    accessors,
+    consensus::Consensus,
+    kv::{mdbx::*, tables},
+    models::*,
+    stagedsync::{stage::*, util::unwind_by_block_key},
+    stages::{BODIES, HEADERS},
+    StageId,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use std::{convert::identity, sync::Arc};
+use tracing::*;
+
+pub const BLOCK_IMPORT: StageId = StageId("BlockImport");
+
+/// Inserts blocks of a chain file in place of [`HeaderDownload`](super::HeaderDownload) and
+/// [`BodyDownload`](super::BodyDownload), validating them the same way. Blocks must extend the
+/// canonical chain in database.
+#[derive(Debug)]
+pub struct BlockImport {
+    blocks: Vec<Block>,
+    consensus: Arc<dyn Consensus>,
+    bad_block: Option<BlockNumber>,
+}
+
+impl BlockImport {
+    pub fn new(blocks: Vec<Block>, consensus: Arc<dyn Consensus>) -> Self {
+        Self {
+            blocks,
+            consensus,
+            bad_block: None,
+        }
+    }
+
+    /// Number of the last block to be imported.
+    pub fn last_block(&self) -> Option<BlockNumber> {
+        self.blocks.last().map(|block| block.header.number)
+    }
+}
+
+#[async_trait]
+impl<'db, E> Stage<'db, E> for BlockImport
+where
+    E: EnvironmentKind,
+{
+    fn id(&self) -> StageId {
+        BLOCK_IMPORT
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: StageInput,
+    ) -> Result<ExecOutput, StageError>
+    where
+        'db: 'tx,
+    {
+        let prev_progress = input.stage_progress.unwrap_or_default();
+
+        let mut parent = accessors::chain::header::read(tx, prev_progress)?
+            .ok_or_else(|| format_err!("no header for block #{prev_progress}"))?;
+        let mut parent_hash = parent.hash();
+        let mut td = accessors::chain::td::read(tx, prev_progress)?
+            .ok_or_else(|| format_err!("no total difficulty for block #{prev_progress}"))?;
+
+        let mut cursor_header_number = tx.cursor(tables::HeaderNumber)?;
+        let mut cursor_header = tx.cursor(tables::Header)?;
+        let mut cursor_canonical = tx.cursor(tables::CanonicalHeader)?;
+        let mut cursor_td = tx.cursor(tables::HeadersTotalDifficulty)?;
+        let mut cursor_body = tx.cursor(tables::BlockBody)?;
+        let mut cursor_block_tx = tx.cursor(tables::BlockTransaction)?;
+        let mut base_tx_id = accessors::chain::storage_body::last(tx)?
+            .map(|(_, body)| *body.base_tx_id + body.tx_amount)
+            .unwrap_or_default();
+
+        let mut stage_progress = prev_progress;
+        for block in &self.blocks {
+            let block_number = block.header.number;
+            if block_number <= prev_progress {
+                continue;
+            }
+
+            let hash = block.header.hash();
+            if self.bad_block == Some(block_number) {
+                return Err(
+                    format_err!("block #{block_number} ({hash:?}) failed execution").into(),
+                );
+            }
+            if block_number != parent.number + 1 || block.header.parent_hash != parent_hash {
+                return Err(format_err!(
+                    "block #{block_number} ({hash:?}) does not attach to the chain in database"
+                )
+                .into());
+            }
+
+            let invalid = |e| format_err!("block #{block_number} ({hash:?}) is invalid: {e}");
+
+            self.consensus
+                .validate_block_header(&block.header, &parent, false)
+                .map_err(invalid)?;
+
+            td += block.header.difficulty;
+            cursor_header_number.put(hash, block_number)?;
+            cursor_header.append(block_number, block.header.clone())?;
+            cursor_canonical.append(block_number, hash)?;
+            cursor_td.append(block_number, td)?;
+
+            self.consensus
+                .pre_validate_block(block, tx)
+                .map_err(invalid)?;
+
+            cursor_body.append(
+                block_number,
+                BodyForStorage {
+                    base_tx_id: TxIndex(base_tx_id),
+                    tx_amount: block.transactions.len() as u64,
+                    ommers: block.ommers.clone(),
+                },
+            )?;
+            for transaction in &block.transactions {
+                cursor_block_tx.append(TxIndex(base_tx_id), transaction.clone())?;
+                base_tx_id += 1;
+            }
+
+            parent = block.header.clone();
+            parent_hash = hash;
+            stage_progress = block_number;
+        }
+
+        if stage_progress > prev_progress {
+            info!("Imported blocks {} to {stage_progress}", prev_progress + 1);
+        }
+
+        HEADERS.save_progress(tx, stage_progress)?;
+        BODIES.save_progress(tx, stage_progress)?;
+
+        Ok(ExecOutput::Progress {
+            stage_progress,
+            done: true,
+            reached_tip: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        if let Some(bad_block) = input.bad_block {
+            self.bad_block = Some(bad_block);
+        }
+
+        {
+            let mut block_body_cur = tx.cursor(tables::BlockBody)?;
+            let mut block_tx_cur = tx.cursor(tables::BlockTransaction)?;
+            while let Some((number, body)) = block_body_cur.last()? {
+                if number <= input.unwind_to {
+                    break;
+                }
+
+                block_body_cur.delete_current()?;
+                for i in 0..body.tx_amount {
+                    if block_tx_cur.seek_exact(body.base_tx_id + i)?.is_some() {
+                        block_tx_cur.delete_current()?;
+                    }
+                }
+            }
+
+            let mut walker = tx
+                .cursor(tables::CanonicalHeader)?
+                .walk(Some(input.unwind_to + 1));
+            while let Some((_, hash)) = walker.next().transpose()? {
+                tx.del(tables::HeaderNumber, hash, None)?;
+            }
+        }
+
+        unwind_by_block_key(tx, tables::Header, input, identity)?;
+        unwind_by_block_key(tx, tables::CanonicalHeader, input, identity)?;
+        unwind_by_block_key(tx, tables::HeadersTotalDifficulty, input, identity)?;
+
+        HEADERS.save_progress(tx, input.unwind_to)?;
+        BODIES.save_progress(tx, input.unwind_to)?;
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
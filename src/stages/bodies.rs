@@ -1,39 +1,43 @@
 use crate::{
+    accessors,
     consensus::{Consensus, DuoError},
     kv::{mdbx::MdbxTransaction, tables, traits::ttw},
     models::*,
     p2p::{
-        node::{Node, NodeStream},
-        types::{BlockBodies, Message},
+        node::{Node, NodeStream, RequestId, RequestKind},
+        types::{BlockBodies, GetBlockBodies, Message},
     },
     stagedsync::stage::*,
     StageId, TaskGuard,
 };
 use anyhow::format_err;
 use async_trait::async_trait;
-use futures::stream::FuturesUnordered;
 use hashbrown::HashMap;
 use mdbx::{EnvironmentKind, RW};
-use parking_lot::{Mutex, RwLock};
+use parking_lot::RwLock;
 use rand::prelude::*;
 use rayon::iter::{ParallelDrainRange, ParallelIterator};
 use std::{
     collections::{HashSet, VecDeque},
     sync::{
-        atomic::{AtomicBool, AtomicUsize, Ordering},
+        atomic::{AtomicBool, Ordering},
         Arc,
     },
     time::{Duration, Instant},
 };
-use tokio::{select, sync::watch};
+use tokio::select;
 use tokio_stream::StreamExt;
 use tracing::*;
 
 const STAGE_UPPER_BOUND: usize = 90_000;
 const REQUEST_INTERVAL: Duration = Duration::from_secs(2);
-const INTERVAL_TWEAK_STEP: Duration = Duration::from_secs(2);
-const MIN_SEND_INTERVAL: Duration = Duration::from_secs(2);
-const MAX_SEND_INTERVAL: Duration = Duration::from_secs(60);
+const SCHEDULE_INTERVAL: Duration = Duration::from_millis(100);
+/// Give up on the session if no bodies arrive for this long.
+const NO_PROGRESS_TIMEOUT: Duration = Duration::from_secs(60);
+/// Block bodies per request.
+const REQUEST_CHUNK: usize = 16;
+/// Requests sent per interval while there are no peers known to the scheduler.
+const UNSCHEDULED_REQUESTS: usize = 32;
 
 pub const BODIES: StageId = StageId("Bodies");
 
@@ -150,58 +154,9 @@ where
     }
 }
 
-#[derive(Debug)]
-pub struct PendingResponses {
-    inner: HashSet<u64>,
-    watch_sender: watch::Sender<usize>,
-}
-
-impl PendingResponses {
-    pub fn new() -> (Self, watch::Receiver<usize>) {
-        let (watch_sender, receiver) = watch::channel(0);
-        (
-            Self {
-                inner: Default::default(),
-                watch_sender,
-            },
-            receiver,
-        )
-    }
-
-    pub fn notify(&mut self) {
-        let _ = self.watch_sender.send(self.inner.len());
-    }
-
-    pub fn get_id(&mut self) -> u64 {
-        loop {
-            let id = rand::thread_rng().gen::<u64>();
-
-            if self.inner.insert(id) {
-                self.notify();
-                return id;
-            }
-        }
-    }
-
-    pub fn remove(&mut self, request_id: u64) {
-        self.inner.remove(&request_id);
-        self.notify();
-    }
-
-    pub fn count(&mut self) -> usize {
-        self.inner.len()
-    }
-
-    pub fn clear(&mut self) {
-        self.inner.clear();
-        self.notify();
-    }
-}
-
 struct DownloadSession {
     handler: Arc<Node>,
     requests: RwLock<HashMap<(H256, H256), (BlockNumber, H256)>>,
-    pending_responses: Mutex<PendingResponses>,
     exit_early: AtomicBool,
 }
 
@@ -214,11 +169,9 @@ impl BodyDownload {
         target: BlockNumber,
         will_reach_tip: bool,
     ) -> Result<(), DownloadError> {
-        let (pending_responses, mut pending_responses_watch) = PendingResponses::new();
         let session = Arc::new(DownloadSession {
             handler: self.node.clone(),
             requests: RwLock::new(Self::prepare_requests(txn, starting_block, target)?),
-            pending_responses: Mutex::new(pending_responses),
             exit_early: AtomicBool::new(false),
         });
 
@@ -227,11 +180,13 @@ impl BodyDownload {
                 let session = session.clone();
 
                 async move {
-                    let mut send_interval = REQUEST_INTERVAL;
-                    let mut cycles_without_progress = 0;
-                    let mut last_left_requests = session.requests.read().len();
+                    let scheduler = &session.handler.scheduler;
+                    // Request currently in flight for each block
+                    let mut in_flight = HashMap::<BlockNumber, RequestId>::new();
+                    let mut last_left_requests = usize::MAX;
+                    let mut last_progress = Instant::now();
                     loop {
-                        let left_requests = session
+                        let mut left_requests = session
                             .requests
                             .read()
                             .values()
@@ -242,108 +197,77 @@ impl BodyDownload {
                             break;
                         }
 
-                        // Apply additional delay on no progress to prevent busy loop
-                        cycles_without_progress = if left_requests.len() == last_left_requests {
-                            if cycles_without_progress % 3 == 0 && cycles_without_progress > 0 {
-                                tokio::time::sleep(send_interval).await;
-                            }
-
-                            if cycles_without_progress > 9 {
-                                session.exit_early.store(true, Ordering::SeqCst);
-                                break;
-                            }
-
-                            cycles_without_progress + 1
-                        } else {
-                            0
-                        };
-
-                        last_left_requests = left_requests.len();
-                        let chunk = 16;
-
-                        let mut will_reach_tip_this_cycle = false;
+                        let now = Instant::now();
+                        if left_requests.len() < last_left_requests {
+                            last_left_requests = left_requests.len();
+                            last_progress = now;
+                        } else if now.duration_since(last_progress) > NO_PROGRESS_TIMEOUT {
+                            session.exit_early.store(true, Ordering::SeqCst);
+                            break;
+                        }
 
-                        let mut total_chunks_this_cycle = 32 * session.handler.total_peers().await;
-                        let left_chunks = (left_requests.len() + chunk - 1) / chunk;
-                        if left_chunks < total_chunks_this_cycle {
-                            total_chunks_this_cycle = left_chunks;
-                            will_reach_tip_this_cycle = true;
+                        let expired = scheduler.expire(now);
+                        if !expired.is_empty() {
+                            debug!("{} block requests timed out, rescheduling", expired.len());
                         }
 
-                        info!(
-                            "Sending {total_chunks_this_cycle} requests for {} out of {} block bodies, will receive for {send_interval:?}",
-                            std::cmp::min(total_chunks_this_cycle * chunk, left_requests.len()),
-                            left_requests.len()
-                        );
-
-                        let total_sent = Arc::new(AtomicUsize::new(total_chunks_this_cycle));
-                        left_requests
-                            .chunks(chunk)
-                            .take(total_chunks_this_cycle)
-                            .map(|chunk| {
-                                let session = session.clone();
-                                let total_sent = total_sent.clone();
-
-                                async move {
-                                    let _ = tokio::time::timeout(send_interval, async move {
-                                        let request_id = session.pending_responses.lock().get_id();
-                                        if session
-                                            .handler
-                                            .send_block_request(
-                                                request_id,
-                                                chunk,
-                                                will_reach_tip_this_cycle,
-                                            )
-                                            .await
-                                            .is_none()
-                                        {
-                                            session.pending_responses.lock().remove(request_id);
-                                            total_sent.fetch_sub(1, Ordering::SeqCst);
-                                        } else {
-                                            debug!("Sent block request with id {request_id}");
-                                        }
-                                    })
+                        if scheduler.num_peers() == 0 {
+                            // No peers to schedule on yet, ask random ones
+                            for chunk in left_requests
+                                .chunks(REQUEST_CHUNK)
+                                .take(UNSCHEDULED_REQUESTS)
+                            {
+                                let request_id = rand::thread_rng().gen::<u64>();
+                                session
+                                    .handler
+                                    .send_block_request(request_id, chunk, will_reach_tip)
                                     .await;
-                                }
+                            }
+                            tokio::time::sleep(REQUEST_INTERVAL).await;
+                            continue;
+                        }
+
+                        // Lowest blocks first, they unblock saving
+                        left_requests.sort_unstable_by_key(|(block_number, _)| *block_number);
+                        let unassigned = left_requests
+                            .into_iter()
+                            .filter(|(block_number, _)| {
+                                !in_flight
+                                    .get(block_number)
+                                    .map_or(false, |&request_id| scheduler.is_pending(request_id))
                             })
-                            .collect::<FuturesUnordered<_>>()
-                            .map(|_| ())
-                            .collect::<()>()
-                            .await;
-
-                        let mut send_cycle_successful = false;
-
-                        let timeout = tokio::time::sleep(send_interval);
-                        tokio::pin!(timeout);
-                        loop {
-                            tokio::select! {
-                                _ = pending_responses_watch.changed() => {
-                                    let next_cycle_threshold = total_sent.load(Ordering::SeqCst) / 5;
-                                    if *pending_responses_watch.borrow() < next_cycle_threshold {
-                                        send_cycle_successful = true;
-                                    }
-                                }
-                                _ = &mut timeout => {
-                                    break;
-                                }
+                            .collect::<Vec<_>>();
+
+                        let mut sent = 0;
+                        for chunk in unassigned.chunks(REQUEST_CHUNK) {
+                            let request_id = rand::thread_rng().gen::<u64>();
+                            let msg = Message::GetBlockBodies(GetBlockBodies {
+                                request_id,
+                                hashes: chunk.iter().map(|(_, hash)| *hash).collect(),
+                            });
+                            if session
+                                .handler
+                                .send_scheduled(request_id, RequestKind::Bodies, msg)
+                                .await
+                                .is_none()
+                            {
+                                // All peers are busy
+                                break;
+                            }
+
+                            for (block_number, _) in chunk {
+                                in_flight.insert(*block_number, request_id);
                             }
+                            sent += 1;
                         }
 
-                        if send_cycle_successful {
-                            send_interval = std::cmp::max(
-                                send_interval.saturating_sub(INTERVAL_TWEAK_STEP),
-                                MIN_SEND_INTERVAL,
-                            );
-                            debug!("Request cycle interval lowered to {send_interval:?}");
-                        } else {
-                            send_interval = std::cmp::min(
-                                send_interval.saturating_add(INTERVAL_TWEAK_STEP),
-                                MAX_SEND_INTERVAL,
+                        if sent > 0 {
+                            debug!(
+                                "Sent {sent} block body requests, {last_left_requests} block bodies left"
                             );
-                            debug!("Request cycle interval increased to {send_interval:?}");
                         }
 
-                        session.pending_responses.lock().clear();
+                        tokio::time::sleep(SCHEDULE_INTERVAL).await;
                     }
                 }
             }));
@@ -365,7 +289,7 @@ impl BodyDownload {
                 let mut pending_bodies = Vec::with_capacity(batch_size);
 
                 let s = stream.filter_map(|msg| match msg.msg {
-                    Message::BlockBodies(msg) => Some(msg),
+                    Message::BlockBodies(bodies) => Some(((msg.sentry_id, msg.peer_id), bodies)),
                     _ => None,
                 });
                 tokio::pin!(s);
@@ -379,13 +303,17 @@ impl BodyDownload {
                 loop {
                     select! {
                         res = s.next() => {
-                            if let Some(BlockBodies { request_id, bodies }) = res {
-                                let mut pending_responses = session.pending_responses.lock();
-                                pending_responses.remove(request_id);
+                            if let Some((peer, BlockBodies { request_id, bodies })) = res {
+                                session.handler.scheduler.received(
+                                    request_id,
+                                    peer,
+                                    bodies.len(),
+                                    Instant::now(),
+                                );
                                 debug!("Accepted block bodies with id {request_id}");
                                 pending_bodies.push(bodies);
 
-                                if pending_responses.count() == 0 {
+                                if pending_bodies.len() >= batch_size {
                                     break;
                                 }
                             } else {
@@ -479,10 +407,8 @@ impl BodyDownload {
             .collect::<HashSet<_>>();
 
         let mut cursor = txn.cursor(tables::BlockBody)?;
-        let mut header_cur = txn.cursor(tables::Header)?;
         let mut block_tx_cursor = txn.cursor(tables::BlockTransaction)?;
-        let mut base_tx_id = cursor
-            .last()?
+        let mut base_tx_id = accessors::chain::storage_body::last(txn)?
             .map(|(_, body)| *body.base_tx_id + body.tx_amount)
             .unwrap();
 
@@ -499,7 +425,8 @@ impl BodyDownload {
                 .unwrap_or_default();
 
             let block = Block {
-                header: header_cur.seek_exact(block_number).unwrap().unwrap().1,
+                header: accessors::chain::header::read(txn, block_number)?
+                    .ok_or_else(|| format_err!("no header for block #{block_number}"))?,
                 transactions: body.transactions,
                 ommers: body.ommers,
             };
@@ -543,9 +470,7 @@ impl BodyDownload {
         };
         let mut map = HashMap::with_capacity(cap);
 
-        let mut header_cursor = txn
-            .cursor(tables::Header)?
-            .walk(Some(starting_block))
+        let mut header_cursor = accessors::chain::header::walk(txn, starting_block)
             .take_while(ttw(|&(block_number, _)| block_number <= target));
 
         while let Some(Ok((block_number, header))) = header_cursor.next() {
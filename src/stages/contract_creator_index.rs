@@ -0,0 +1,360 @@
+use crate::{
+    accessors,
+    kv::{
+        mdbx::*,
+        tables::{self, ContractCreationEntry},
+    },
+    models::*,
+    stagedsync::stage::*,
+    StageId,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use std::time::{Duration, Instant};
+use tokio::pin;
+use tracing::*;
+
+pub const CONTRACT_CREATORS: StageId = StageId("ContractCreators");
+
+/// Generation of contract address => (creating transaction hash, creator) mapping
+#[derive(Debug)]
+pub struct ContractCreatorIndex;
+
+fn transaction_hash<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    block_number: BlockNumber,
+    transaction_index: u64,
+) -> anyhow::Result<H256> {
+    let body = accessors::chain::storage_body::read(tx, block_number)?
+        .ok_or_else(|| format_err!("Block body not found: {block_number}"))?;
+
+    accessors::chain::tx::read(tx, body.base_tx_id + transaction_index, 1)?
+        .pop()
+        .map(|transaction| transaction.hash())
+        .ok_or_else(|| {
+            format_err!("Transaction {transaction_index} not found in block {block_number}")
+        })
+}
+
+#[async_trait]
+impl<'db, E> Stage<'db, E> for ContractCreatorIndex
+where
+    E: EnvironmentKind,
+{
+    fn id(&self) -> StageId {
+        CONTRACT_CREATORS
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: StageInput,
+    ) -> Result<ExecOutput, StageError>
+    where
+        'db: 'tx,
+    {
+        let starting_block = input.stage_progress.unwrap_or(BlockNumber(0));
+        let max_block = input
+            .previous_stage
+            .ok_or_else(|| {
+                format_err!("Contract creator index generation cannot be the first stage")
+            })?
+            .1;
+
+        let walker = tx
+            .cursor(tables::ContractCreationSet)?
+            .walk(Some(starting_block + 1));
+        pin!(walker);
+
+        let mut creators = tx.cursor(tables::ContractCreator)?;
+
+        let mut last_log = Instant::now();
+        while let Some((
+            block_number,
+            ContractCreationEntry {
+                address,
+                transaction_index,
+                creator,
+            },
+        )) = walker.next().transpose()?
+        {
+            if block_number > max_block {
+                break;
+            }
+
+            // Address may be reused after self-destruct, the first creation is kept.
+            if creators.seek_exact(address)?.is_none() {
+                creators.put(
+                    address,
+                    (
+                        transaction_hash(tx, block_number, transaction_index)?,
+                        creator,
+                    ),
+                )?;
+            }
+
+            let now = Instant::now();
+            if now - last_log > Duration::from_secs(30) {
+                info!("Current block: {}", block_number);
+                last_log = now;
+            }
+        }
+
+        Ok(ExecOutput::Progress {
+            stage_progress: max_block,
+            done: true,
+            reached_tip: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        let walker = tx
+            .cursor(tables::ContractCreationSet)?
+            .walk(Some(input.unwind_to + 1));
+        pin!(walker);
+
+        let mut creators = tx.cursor(tables::ContractCreator)?;
+
+        while let Some((block_number, entry)) = walker.next().transpose()? {
+            if let Some((_, (transaction_hash_indexed, _))) = creators.seek_exact(entry.address)? {
+                if transaction_hash_indexed
+                    == transaction_hash(tx, block_number, entry.transaction_index)?
+                {
+                    creators.delete_current()?;
+                }
+            }
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accessors::chain, kv::new_mem_chaindata, stages};
+    use bytes::Bytes;
+    use hex_literal::hex;
+
+    fn create(nonce: u64) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: Some(ChainId(1)),
+                nonce,
+                gas_price: 1_000_000.as_u256(),
+                gas_limit: 100_000,
+                action: TransactionAction::Create,
+                value: U256::ZERO,
+                input: Bytes::from_static(&hex!("600080fd")),
+            },
+            signature: MessageSignature::new(
+                false,
+                H256::from(hex!(
+                    "11d244ae19e3bb96d1bb864aa761d48e957984a154329f0de757cd105f9c7ac4"
+                )),
+                H256::from(hex!(
+                    "0e3828d13eed24036941eb5f7fd65de57aad1184342f2244130d2941554342ba"
+                )),
+            )
+            .unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn contract_creators() {
+        let db = new_mem_chaindata().unwrap();
+        let mut tx = db.begin_mutable().unwrap();
+
+        let creator = Address::from(hex!("f4148309cc30f2dd4ba117122cad6be1e3ba0e2b"));
+        let contract1 = Address::from(hex!("d7fa8303df7073290f66ced1add5fe89dac0c462"));
+        let contract2 = Address::from(hex!("5a0b54d5dc17e0aadc383d2db43b0a0d3e029c4c"));
+
+        let txs = [create(0), create(1), create(2)];
+        let hashes = txs.iter().map(|t| t.hash()).collect::<Vec<_>>();
+
+        chain::storage_body::write(
+            &tx,
+            1,
+            &BodyForStorage {
+                base_tx_id: 1.into(),
+                tx_amount: 1,
+                ommers: Default::default(),
+            },
+        )
+        .unwrap();
+        chain::storage_body::write(
+            &tx,
+            2,
+            &BodyForStorage {
+                base_tx_id: 2.into(),
+                tx_amount: 2,
+                ommers: Default::default(),
+            },
+        )
+        .unwrap();
+        chain::tx::write(&tx, TxIndex(1), &txs).unwrap();
+
+        for (block_number, address, transaction_index) in
+            [(1, contract1, 0), (2, contract1, 0), (2, contract2, 1)]
+        {
+            tx.set(
+                tables::ContractCreationSet,
+                BlockNumber(block_number),
+                ContractCreationEntry {
+                    address,
+                    transaction_index,
+                    creator,
+                },
+            )
+            .unwrap();
+        }
+
+        ContractCreatorIndex
+            .execute(
+                &mut tx,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (Instant::now(), Some(BlockNumber(0))),
+                    previous_stage: Some((stages::EXECUTION, BlockNumber(2))),
+                    stage_progress: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tx.get(tables::ContractCreator, contract1).unwrap(),
+            Some((hashes[0], creator))
+        );
+        assert_eq!(
+            tx.get(tables::ContractCreator, contract2).unwrap(),
+            Some((hashes[2], creator))
+        );
+
+        ContractCreatorIndex
+            .unwind(
+                &mut tx,
+                UnwindInput {
+                    stage_progress: BlockNumber(2),
+                    unwind_to: BlockNumber(1),
+                    bad_block: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tx.get(tables::ContractCreator, contract1).unwrap(),
+            Some((hashes[0], creator))
+        );
+        assert_eq!(tx.get(tables::ContractCreator, contract2).unwrap(), None);
+    }
+
+    fn created_at<E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        address: Address,
+        creator: Address,
+        block_number: u64,
+    ) {
+        tx.set(
+            tables::ContractCreationSet,
+            BlockNumber(block_number),
+            ContractCreationEntry {
+                address,
+                transaction_index: 0,
+                creator,
+            },
+        )
+        .unwrap();
+    }
+
+    async fn execute<E: EnvironmentKind>(tx: &mut MdbxTransaction<'_, RW, E>, from: u64) {
+        ContractCreatorIndex
+            .execute(
+                tx,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (Instant::now(), Some(BlockNumber(from))),
+                    previous_stage: Some((stages::EXECUTION, BlockNumber(2))),
+                    stage_progress: Some(BlockNumber(from)),
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    async fn unwind<E: EnvironmentKind>(tx: &mut MdbxTransaction<'_, RW, E>, from: u64, to: u64) {
+        ContractCreatorIndex
+            .unwind(
+                tx,
+                UnwindInput {
+                    stage_progress: BlockNumber(from),
+                    unwind_to: BlockNumber(to),
+                    bad_block: None,
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn recreated_contract() {
+        let db = new_mem_chaindata().unwrap();
+        let mut tx = db.begin_mutable().unwrap();
+
+        let creator = Address::from(hex!("f4148309cc30f2dd4ba117122cad6be1e3ba0e2b"));
+        let contract = Address::from(hex!("d7fa8303df7073290f66ced1add5fe89dac0c462"));
+
+        let txs = [create(0), create(1)];
+        let hashes = txs.iter().map(|t| t.hash()).collect::<Vec<_>>();
+        for block_number in [1_u64, 2] {
+            chain::storage_body::write(
+                &tx,
+                block_number,
+                &BodyForStorage {
+                    base_tx_id: block_number.into(),
+                    tx_amount: 1,
+                    ommers: Default::default(),
+                },
+            )
+            .unwrap();
+        }
+        chain::tx::write(&tx, TxIndex(1), &txs).unwrap();
+
+        // Created in block 1, self-destructed and created again in block 2
+        created_at(&tx, contract, creator, 1);
+        created_at(&tx, contract, creator, 2);
+        execute(&mut tx, 0).await;
+        assert_eq!(
+            tx.get(tables::ContractCreator, contract).unwrap(),
+            Some((hashes[0], creator))
+        );
+
+        // Unwinding the recreation keeps the original creation
+        unwind(&mut tx, 2, 1).await;
+        assert_eq!(
+            tx.get(tables::ContractCreator, contract).unwrap(),
+            Some((hashes[0], creator))
+        );
+
+        // Unwinding the original creation as well, new chain only creates it in block 2
+        unwind(&mut tx, 1, 0).await;
+        assert_eq!(tx.get(tables::ContractCreator, contract).unwrap(), None);
+        tx.del(tables::ContractCreationSet, BlockNumber(1), None)
+            .unwrap();
+        execute(&mut tx, 0).await;
+        assert_eq!(
+            tx.get(tables::ContractCreator, contract).unwrap(),
+            Some((hashes[1], creator))
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    accessors,
+    consensus::{Consensus, DuoError},
+    era1::{self, Era1, EPOCH_SIZE},
+    kv::{mdbx::*, tables},
+    models::*,
+    stagedsync::stage::*,
+    stages::{BODIES, HEADERS},
+    StageId,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use rayon::prelude::*;
+use std::{path::PathBuf, sync::Arc};
+use tracing::*;
+
+pub const ERA1_IMPORT: StageId = StageId("Era1Import");
+
+/// Imports pre-merge history from local era1 files. Must be the first stage, headers and bodies
+/// it imports are handed over to [`HeaderDownload`](super::HeaderDownload) and
+/// [`BodyDownload`](super::BodyDownload) by advancing their progress.
+#[derive(Debug)]
+pub struct Era1Import {
+    /// Directory holding era1 files.
+    pub dir: PathBuf,
+    /// Accumulator roots of all epochs. Every imported file must match the root of its epoch.
+    /// `None` trusts any self-consistent file and must only be set on explicit user request.
+    pub trusted_accumulators: Option<Vec<H256>>,
+    /// Engine the seals of imported headers are checked with.
+    pub consensus: Arc<dyn Consensus>,
+}
+
+#[async_trait]
+impl<'db, E> Stage<'db, E> for Era1Import
+where
+    E: EnvironmentKind,
+{
+    fn id(&self) -> StageId {
+        ERA1_IMPORT
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: StageInput,
+    ) -> Result<ExecOutput, StageError>
+    where
+        'db: 'tx,
+    {
+        let prev_progress = input.stage_progress.unwrap_or_default();
+
+        // Once history comes from the network there is nothing left to import.
+        if HEADERS.get_progress(tx)?.unwrap_or_default() > prev_progress
+            || BODIES.get_progress(tx)?.unwrap_or_default() > prev_progress
+        {
+            return Ok(ExecOutput::Progress {
+                stage_progress: prev_progress,
+                done: true,
+                reached_tip: true,
+            });
+        }
+
+        let files = era1::list_files(&self.dir)?;
+        let starting_block = prev_progress + 1;
+        let epoch = starting_block.0 / EPOCH_SIZE;
+        let path = match files.get(&epoch) {
+            Some(path) => path,
+            None => {
+                return Ok(ExecOutput::Progress {
+                    stage_progress: prev_progress,
+                    done: true,
+                    reached_tip: true,
+                })
+            }
+        };
+
+        info!("Importing epoch {epoch} from {}", path.display());
+
+        let file = Era1::read_file(path)
+            .and_then(|file| file.verify().map(|_| file))
+            .map_err(|e| format_err!("invalid era1 file {}: {e}", path.display()))?;
+
+        if file.epoch() != Some(epoch) {
+            return Err(format_err!("{} does not hold epoch {epoch}", path.display()).into());
+        }
+
+        if let Some(trusted_accumulators) = &self.trusted_accumulators {
+            let trusted = trusted_accumulators
+                .get(epoch as usize)
+                .ok_or_else(|| format_err!("no trusted accumulator root for epoch {epoch}"))?;
+            if *trusted != file.accumulator {
+                return Err(format_err!(
+                    "accumulator root of epoch {epoch} is {:?}, expected {trusted:?}",
+                    file.accumulator
+                )
+                .into());
+            }
+        }
+
+        if let Some((block, error)) = file
+            .blocks
+            .par_iter()
+            .filter(|block| block.header.number > prev_progress)
+            .find_map_first(|block| {
+                self.consensus
+                    .validate_header_parallel(&block.header)
+                    .err()
+                    .map(|error| (block.header.number, error))
+            })
+        {
+            return Err(match error {
+                DuoError::Validation(error) => StageError::Validation { block, error },
+                DuoError::Internal(e) => StageError::Internal(e),
+            });
+        }
+
+        let mut cursor_header_number = tx.cursor(tables::HeaderNumber)?;
+        let mut cursor_header = tx.cursor(tables::Header)?;
+        let mut cursor_canonical = tx.cursor(tables::CanonicalHeader)?;
+        let mut cursor_td = tx.cursor(tables::HeadersTotalDifficulty)?;
+        let mut cursor_body = tx.cursor(tables::BlockBody)?;
+        let mut cursor_block_tx = tx.cursor(tables::BlockTransaction)?;
+
+        let mut parent_hash = tx
+            .get(tables::CanonicalHeader, prev_progress)?
+            .ok_or_else(|| format_err!("no canonical hash for block #{prev_progress}"))?;
+        let mut td = tx
+            .get(tables::HeadersTotalDifficulty, prev_progress)?
+            .ok_or_else(|| format_err!("no total difficulty for block #{prev_progress}"))?;
+        let mut base_tx_id = accessors::chain::storage_body::last(tx)?
+            .map(|(_, body)| *body.base_tx_id + body.tx_amount)
+            .unwrap_or_default();
+
+        let mut stage_progress = prev_progress;
+        for block in file.blocks {
+            let block_number = block.header.number;
+            if block_number <= prev_progress {
+                continue;
+            }
+
+            td += block.header.difficulty;
+            if block.header.parent_hash != parent_hash || block.total_difficulty != td {
+                return Err(format_err!(
+                    "block #{block_number} from {} does not attach to the chain in database",
+                    path.display()
+                )
+                .into());
+            }
+
+            let hash = block.header.hash();
+            cursor_header_number.put(hash, block_number)?;
+            cursor_header.append(block_number, block.header)?;
+            cursor_canonical.append(block_number, hash)?;
+            cursor_td.append(block_number, td)?;
+
+            cursor_body.append(
+                block_number,
+                BodyForStorage {
+                    base_tx_id: TxIndex(base_tx_id),
+                    tx_amount: block.body.transactions.len() as u64,
+                    ommers: block.body.ommers,
+                },
+            )?;
+            for transaction in block.body.transactions {
+                cursor_block_tx.append(TxIndex(base_tx_id), transaction)?;
+                base_tx_id += 1;
+            }
+
+            parent_hash = hash;
+            stage_progress = block_number;
+        }
+
+        HEADERS.save_progress(tx, stage_progress)?;
+        BODIES.save_progress(tx, stage_progress)?;
+
+        Ok(ExecOutput::Progress {
+            stage_progress,
+            done: stage_progress == prev_progress
+                || !files.contains_key(&((stage_progress.0 + 1) / EPOCH_SIZE)),
+            reached_tip: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        _: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        // Imported data is removed by header and body stages.
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
@@ -4,20 +4,24 @@ use crate::{
     execution::{
         analysis_cache::AnalysisCache,
         processor::ExecutionProcessor,
-        tracer::{CallTracer, CallTracerFlags},
+        tracer::{CallTracer, CallTracerFlags, ContractCreation, NoopTracer},
     },
     h256_to_u256,
     kv::{
         mdbx::*,
-        tables::{self, CallTraceSetEntry},
+        tables::{self, CallTraceSetEntry, ContractCreationEntry},
     },
+    metrics,
     models::*,
     stagedsync::{format_duration, stage::*, util::*},
-    upsert_storage_value, Buffer, StageId,
+    upsert_storage_value, AccountChanges, Buffer, StageId, StateReader,
 };
 use anyhow::format_err;
 use async_trait::async_trait;
-use std::time::{Duration, Instant};
+use std::{
+    collections::BTreeMap,
+    time::{Duration, Instant},
+};
 use tracing::*;
 
 pub const EXECUTION: StageId = StageId("Execution");
@@ -31,6 +35,43 @@ pub struct Execution {
     pub exit_after_batch: bool,
     pub batch_until: Option<BlockNumber>,
     pub commit_every: Option<Duration>,
+    /// Execute transactions within a block speculatively in parallel.
+    pub parallel: bool,
+    /// Code analysis cache shared with the rest of the node.
+    pub analysis_cache: AnalysisCache,
+}
+
+/// Contract creations of the block that left code at their address by the end of it.
+fn successful_creations<'c>(
+    creations: &'c [ContractCreation],
+    account_changes: Option<&AccountChanges>,
+    state: &impl StateReader,
+) -> anyhow::Result<Vec<&'c ContractCreation>> {
+    // Only the last attempt to create a contract at a given address can have succeeded.
+    let creations = creations
+        .iter()
+        .map(|creation| (creation.address, creation))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut out = Vec::new();
+    for (address, creation) in creations {
+        match account_changes.and_then(|changes| changes.get(&address)) {
+            // Account untouched by the block, creation failed.
+            None => continue,
+            // Contract existed before the block.
+            Some(Some(account)) if account.code_hash != EMPTY_HASH => continue,
+            _ => {}
+        }
+
+        // Created contract may have self-destructed within the block.
+        if let Some(account) = state.read_account(address)? {
+            if account.code_hash != EMPTY_HASH {
+                out.push(creation);
+            }
+        }
+    }
+
+    Ok(out)
 }
 
 #[allow(clippy::too_many_arguments)]
@@ -42,18 +83,20 @@ fn execute_batch_of_blocks<E: EnvironmentKind>(
     history_batch_size: u64,
     batch_until: Option<BlockNumber>,
     commit_every: Option<Duration>,
+    parallel: bool,
+    analysis_cache: &mut AnalysisCache,
     starting_block: BlockNumber,
     first_started_at: (Instant, Option<BlockNumber>),
 ) -> Result<BlockNumber, StageError> {
-    let mut consensus_engine = engine_factory(None, chain_config.clone(), None)?;
+    let mut consensus_engine = engine_factory(None, chain_config.clone(), None, None)?;
     consensus_engine.set_state(ConsensusState::recover(tx, &chain_config, starting_block)?);
 
     let mut buffer = Buffer::new(tx, None);
-    let mut analysis_cache = AnalysisCache::default();
 
     let mut block_number = starting_block;
     let mut gas_since_start = 0;
     let mut gas_since_last_message = 0;
+    let mut txs_since_last_message = 0;
     let mut gas_since_history_commit = 0;
     let batch_started_at = Instant::now();
     let first_started_at_gas = tx
@@ -80,16 +123,29 @@ fn execute_batch_of_blocks<E: EnvironmentKind>(
         }
 
         let mut call_tracer = CallTracer::default();
-        let receipts = ExecutionProcessor::new(
-            &mut buffer,
-            &mut call_tracer,
-            &mut analysis_cache,
-            &mut *consensus_engine,
-            &header,
-            &block,
-            &block_spec,
-        )
-        .execute_and_write_block()
+        let receipts = if parallel {
+            ExecutionProcessor::new(
+                &mut buffer,
+                &mut NoopTracer,
+                analysis_cache,
+                &mut *consensus_engine,
+                &header,
+                &block,
+                &block_spec,
+            )
+            .execute_and_write_block_parallel(&mut call_tracer)
+        } else {
+            ExecutionProcessor::new(
+                &mut buffer,
+                &mut call_tracer,
+                analysis_cache,
+                &mut *consensus_engine,
+                &header,
+                &block,
+                &block_spec,
+            )
+            .execute_and_write_block()
+        }
         .map_err(|e| match e {
             DuoError::Validation(error) => StageError::Validation {
                 block: block_number,
@@ -110,8 +166,31 @@ fn execute_batch_of_blocks<E: EnvironmentKind>(
             }
         }
 
+        {
+            let mut c = tx.cursor(tables::ContractCreationSet)?;
+            for creation in successful_creations(
+                call_tracer.contract_creations(),
+                buffer.account_changes().get(&block_number),
+                &buffer,
+            )? {
+                c.append_dup(
+                    header.number,
+                    ContractCreationEntry {
+                        address: creation.address,
+                        transaction_index: creation.transaction_index as u64,
+                        creator: creation.creator,
+                    },
+                )?;
+            }
+        }
+
+        metrics::EXECUTION_BLOCKS.inc();
+        metrics::EXECUTION_TRANSACTIONS.inc_by(block.transactions.len() as u64);
+        metrics::EXECUTION_GAS.inc_by(header.gas_used);
+
         gas_since_start += header.gas_used;
         gas_since_last_message += header.gas_used;
+        txs_since_last_message += block.transactions.len();
         gas_since_history_commit += header.gas_used;
 
         if gas_since_history_commit >= history_batch_size {
@@ -138,6 +217,9 @@ fn execute_batch_of_blocks<E: EnvironmentKind>(
             let mgas_sec = gas_since_last_message as f64
                 / (elapsed.as_secs() as f64 + (elapsed.subsec_millis() as f64 / 1000_f64))
                 / 1_000_000f64;
+            metrics::EXECUTION_GAS_PER_SECOND.set(mgas_sec * 1_000_000f64);
+            metrics::EXECUTION_TRANSACTIONS_PER_SECOND
+                .set(txs_since_last_message as f64 / elapsed.as_secs_f64());
             info!(
                 "Executed block {}, Mgas/sec: {:.2}{}",
                 block_number,
@@ -171,6 +253,7 @@ fn execute_batch_of_blocks<E: EnvironmentKind>(
             printed_at_least_once = true;
             last_message = now;
             gas_since_last_message = 0;
+            txs_since_last_message = 0;
         }
 
         if end_of_batch {
@@ -181,6 +264,7 @@ fn execute_batch_of_blocks<E: EnvironmentKind>(
     }
 
     buffer.write_to_db()?;
+    analysis_cache.save(tx)?;
 
     Ok(block_number)
 }
@@ -220,6 +304,8 @@ where
                 self.history_batch_size,
                 self.batch_until,
                 self.commit_every,
+                self.parallel,
+                &mut self.analysis_cache,
                 starting_block,
                 input.first_started_at,
             );
@@ -333,8 +419,205 @@ where
         info!("Unwinding call trace sets");
         unwind_by_block_key_duplicates(tx, tables::CallTraceSet, input, std::convert::identity)?;
 
+        info!("Unwinding contract creation sets");
+        unwind_by_block_key_duplicates(
+            tx,
+            tables::ContractCreationSet,
+            input,
+            std::convert::identity,
+        )?;
+
         Ok(UnwindOutput {
             stage_progress: input.unwind_to,
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        accessors::chain, kv::new_mem_chaindata, res::chainspec::MAINNET, stages, InMemoryState,
+        StateWriter,
+    };
+    use bytes::Bytes;
+
+    #[test]
+    fn contract_creations() {
+        let creator = Address::repeat_byte(0xc0);
+        let deployed = Address::repeat_byte(1);
+        let self_destructed = Address::repeat_byte(2);
+        let existing = Address::repeat_byte(3);
+        let contract = Account {
+            code_hash: H256::repeat_byte(0xaa),
+            ..Default::default()
+        };
+
+        let mut state = InMemoryState::new();
+        state.update_account(deployed, None, Some(contract));
+        state.update_account(existing, None, Some(contract));
+
+        let account_changes = AccountChanges::from([
+            (creator, Some(Account::default())),
+            (deployed, None),
+            // Created and self-destructed by the same transaction
+            (self_destructed, None),
+            (existing, Some(contract)),
+        ]);
+        let creations = [deployed, self_destructed, existing]
+            .into_iter()
+            .enumerate()
+            .map(|(transaction_index, address)| ContractCreation {
+                transaction_index,
+                address,
+                creator,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            successful_creations(&creations, Some(&account_changes), &state).unwrap(),
+            vec![&creations[0]]
+        );
+        assert_eq!(
+            successful_creations(&creations, None, &state).unwrap(),
+            Vec::<&ContractCreation>::new()
+        );
+    }
+
+    type Executed = (
+        Vec<(Address, Account)>,
+        Vec<(BlockNumber, tables::AccountChange)>,
+    );
+
+    async fn execute_transfers(parallel: bool) -> Executed {
+        let db = new_mem_chaindata().unwrap();
+        let mut tx = db.begin_mutable().unwrap();
+
+        tx.set(tables::Config, (), MAINNET.clone()).unwrap();
+
+        let alice = Address::repeat_byte(0xa);
+        let bob = Address::repeat_byte(0xb);
+        let carol = Address::repeat_byte(0xc);
+        let dave = Address::repeat_byte(0xd);
+        let eve = Address::repeat_byte(0xe);
+        for address in [alice, carol] {
+            tx.set(
+                tables::Account,
+                address,
+                Account {
+                    balance: (10 * ETHER).as_u256(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        }
+
+        let transfer = |to, nonce, value: u128| MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: None,
+                nonce,
+                gas_price: U256::ZERO,
+                gas_limit: 21_000,
+                action: TransactionAction::Call(to),
+                value: value.as_u256(),
+                input: Bytes::new(),
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                .unwrap(),
+        };
+        let txs = [
+            transfer(bob, 0, ETHER),
+            // Spends funds received from the previous transaction
+            transfer(dave, 0, ETHER / 2),
+            // Depends on the first transaction through the sender's nonce
+            transfer(dave, 1, ETHER),
+            // Independent transfer
+            transfer(eve, 0, 2 * ETHER),
+        ];
+
+        let header = BlockHeader::new(
+            PartialHeader {
+                number: BlockNumber(1),
+                beneficiary: Address::repeat_byte(0xf),
+                gas_limit: 1_000_000,
+                gas_used: 84_000,
+                ..PartialHeader::empty()
+            },
+            EMPTY_LIST_HASH,
+            EMPTY_ROOT,
+        );
+        tx.set(tables::CanonicalHeader, header.number, header.hash())
+            .unwrap();
+        tx.set(tables::Header, header.number, header).unwrap();
+        tx.set(tables::TotalGas, BlockNumber(0), 0).unwrap();
+        tx.set(tables::TotalGas, BlockNumber(1), 84_000).unwrap();
+        chain::storage_body::write(
+            &tx,
+            1,
+            &BodyForStorage {
+                base_tx_id: 0.into(),
+                tx_amount: txs.len() as u64,
+                ommers: Default::default(),
+            },
+        )
+        .unwrap();
+        chain::tx::write(&tx, TxIndex(0), &txs).unwrap();
+        chain::tx_sender::write(&tx, 1, vec![alice, bob, alice, carol]).unwrap();
+
+        Execution {
+            max_block: None,
+            batch_size: u64::MAX,
+            history_batch_size: u64::MAX,
+            exit_after_batch: false,
+            batch_until: None,
+            commit_every: None,
+            parallel,
+            analysis_cache: AnalysisCache::default(),
+        }
+        .execute(
+            &mut tx,
+            StageInput {
+                restarted: false,
+                first_started_at: (Instant::now(), None),
+                previous_stage: Some((stages::SENDERS, BlockNumber(1))),
+                stage_progress: Some(BlockNumber(0)),
+            },
+        )
+        .await
+        .unwrap();
+
+        (
+            tx.cursor(tables::Account)
+                .unwrap()
+                .walk(None)
+                .collect::<anyhow::Result<_>>()
+                .unwrap(),
+            tx.cursor(tables::AccountChangeSet)
+                .unwrap()
+                .walk(None)
+                .collect::<anyhow::Result<_>>()
+                .unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn parallel_execution_over_database() {
+        let (accounts, changes) = execute_transfers(true).await;
+
+        assert_eq!((accounts.clone(), changes), execute_transfers(false).await);
+
+        let balance = |address| {
+            accounts
+                .iter()
+                .find(|(a, _)| *a == Address::repeat_byte(address))
+                .unwrap()
+                .1
+                .balance
+        };
+        assert_eq!(balance(0xa), (8 * ETHER).as_u256());
+        assert_eq!(balance(0xb), (ETHER / 2).as_u256());
+        assert_eq!(balance(0xc), (8 * ETHER).as_u256());
+        assert_eq!(balance(0xd), (3 * ETHER / 2).as_u256());
+        assert_eq!(balance(0xe), (2 * ETHER).as_u256());
+    }
+}
@@ -0,0 +1,250 @@
+use crate::{
+    kv::{
+        freezer::{Freezer, SEGMENT_SIZE},
+        mdbx::*,
+        tables,
+    },
+    models::*,
+    stagedsync::stage::*,
+    StageId,
+};
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
+use tracing::*;
+
+pub const FREEZE: StageId = StageId("Freeze");
+
+/// Moves headers, bodies and transactions of blocks deeper than `threshold` from MDBX into the
+/// [`Freezer`] attached to the database. Frozen blocks cannot be unwound.
+#[derive(Debug)]
+pub struct Freeze {
+    /// Number of most recent blocks that are always kept in MDBX.
+    pub threshold: u64,
+}
+
+fn freeze_segment<E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, RW, E>,
+    freezer: &Freezer,
+) -> anyhow::Result<()> {
+    let first_block = BlockNumber(freezer.frozen_blocks());
+    let segment = *first_block / SEGMENT_SIZE;
+
+    let mut headers = Vec::with_capacity(SEGMENT_SIZE as usize);
+    let mut walker = tx.cursor(tables::Header)?.walk(Some(first_block));
+    while let Some((number, header)) = walker.next().transpose()? {
+        if headers.len() as u64 == SEGMENT_SIZE {
+            break;
+        }
+        if number != first_block + headers.len() as u64 {
+            bail!(
+                "missing header for block #{}",
+                first_block + headers.len() as u64
+            );
+        }
+        headers.push(header);
+    }
+
+    let mut bodies = Vec::with_capacity(SEGMENT_SIZE as usize);
+    let mut walker = tx.cursor(tables::BlockBody)?.walk(Some(first_block));
+    while let Some((number, body)) = walker.next().transpose()? {
+        if bodies.len() as u64 == SEGMENT_SIZE {
+            break;
+        }
+        if number != first_block + bodies.len() as u64 {
+            bail!(
+                "missing body for block #{}",
+                first_block + bodies.len() as u64
+            );
+        }
+        bodies.push(body);
+    }
+
+    let transactions = match (bodies.first(), bodies.last()) {
+        (Some(first), Some(last)) => {
+            let amount = *last.base_tx_id + last.tx_amount - *first.base_tx_id;
+            tx.cursor(tables::BlockTransaction)?
+                .walk(Some(first.base_tx_id))
+                .take(amount as usize)
+                .map(|res| res.map(|(_, v)| v))
+                .collect::<anyhow::Result<Vec<_>>>()?
+        }
+        _ => vec![],
+    };
+
+    freezer.freeze(segment, headers, bodies, transactions)?;
+
+    info!(
+        "Froze blocks {first_block}..={}",
+        first_block + SEGMENT_SIZE - 1
+    );
+
+    Ok(())
+}
+
+/// Drops freezer segments whose pruning from MDBX was never committed.
+///
+/// Segments are written before the transaction pruning their blocks commits, and blocks are
+/// pruned in that same transaction. Blocks that are still in MDBX were therefore never committed
+/// as frozen, and segments holding them may come from a transaction that was rolled back.
+pub fn recover_freezer<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+) -> anyhow::Result<()> {
+    if let Some(freezer) = tx.freezer() {
+        if let Some((first, _)) = tx.cursor(tables::Header)?.first()? {
+            if first.0 < freezer.frozen_blocks() {
+                freezer.truncate(first.0)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes everything that is already in the freezer from MDBX.
+fn prune<E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, RW, E>,
+    freezer: &Freezer,
+) -> anyhow::Result<()> {
+    let frozen_blocks = BlockNumber(freezer.frozen_blocks());
+    let frozen_transactions = TxIndex(freezer.frozen_transactions());
+
+    let mut cursor = tx.cursor(tables::Header)?;
+    while let Some((number, _)) = cursor.first()? {
+        if number >= frozen_blocks {
+            break;
+        }
+        cursor.delete_current()?;
+    }
+
+    let mut cursor = tx.cursor(tables::BlockBody)?;
+    while let Some((number, _)) = cursor.first()? {
+        if number >= frozen_blocks {
+            break;
+        }
+        cursor.delete_current()?;
+    }
+
+    let mut cursor = tx.cursor(tables::BlockTransaction)?;
+    while let Some((id, _)) = cursor.first()? {
+        if id >= frozen_transactions {
+            break;
+        }
+        cursor.delete_current()?;
+    }
+
+    Ok(())
+}
+
+#[async_trait]
+impl<'db, E> Stage<'db, E> for Freeze
+where
+    E: EnvironmentKind,
+{
+    fn id(&self) -> StageId {
+        FREEZE
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: StageInput,
+    ) -> Result<ExecOutput, StageError>
+    where
+        'db: 'tx,
+    {
+        let freezer = tx
+            .freezer()
+            .ok_or_else(|| format_err!("no freezer attached to database"))?;
+        recover_freezer(tx)?;
+
+        let prev_stage_progress = input
+            .previous_stage
+            .map(|(_, b)| b)
+            .unwrap_or(BlockNumber(0));
+        let finalized = prev_stage_progress.0.saturating_sub(self.threshold);
+
+        // The latest block always stays in MDBX, for stages appending after it.
+        while freezer.frozen_blocks() + SEGMENT_SIZE <= finalized {
+            freeze_segment(tx, freezer)?;
+        }
+        prune(tx, freezer)?;
+
+        Ok(ExecOutput::Progress {
+            stage_progress: prev_stage_progress,
+            done: true,
+            reached_tip: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        recover_freezer(tx)?;
+
+        if let Some(freezer) = tx.freezer() {
+            let frozen_blocks = freezer.frozen_blocks();
+            if input.unwind_to.0 + 1 < frozen_blocks {
+                bail!(
+                    "cannot unwind to block #{}, blocks below #{frozen_blocks} are frozen",
+                    input.unwind_to
+                );
+            }
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_chaindata;
+    use std::sync::Arc;
+
+    #[test]
+    fn drops_uncommitted_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let freezer = Arc::new(Freezer::open(dir.path()).unwrap());
+        let db = new_mem_chaindata().unwrap().with_freezer(freezer.clone());
+
+        let headers = (0..SEGMENT_SIZE)
+            .map(|number| BlockHeader {
+                number: BlockNumber(number),
+                ..BlockHeader::empty()
+            })
+            .collect::<Vec<_>>();
+        let bodies = (0..SEGMENT_SIZE)
+            .map(|_| BodyForStorage {
+                base_tx_id: TxIndex(0),
+                tx_amount: 0,
+                ommers: Default::default(),
+            })
+            .collect::<Vec<_>>();
+
+        let tx = db.begin_mutable().unwrap();
+        for (number, header) in headers.iter().enumerate().take(2) {
+            tx.set(tables::Header, BlockNumber(number as u64), header.clone())
+                .unwrap();
+        }
+        tx.commit().unwrap();
+
+        // Segment written, but the transaction pruning its blocks never committed.
+        freezer.freeze(0, headers, bodies, vec![]).unwrap();
+        assert_eq!(freezer.frozen_blocks(), SEGMENT_SIZE);
+
+        recover_freezer(&db.begin().unwrap()).unwrap();
+        assert_eq!(freezer.frozen_blocks(), 0);
+        assert_eq!(
+            Freezer::open(dir.path()).unwrap().frozen_blocks(),
+            0,
+            "segment files must be removed"
+        );
+    }
+}
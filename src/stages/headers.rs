@@ -6,8 +6,11 @@ use crate::{
     kv::{mdbx::*, tables},
     models::{BlockHeader, BlockNumber, H256},
     p2p::{
-        node::{Node, NodeStream},
-        types::{BlockHeaders, BlockId, HeaderRequest, Message, Status},
+        node::{Node, NodeStream, RequestId, RequestKind},
+        types::{
+            BlockHeaders, BlockId, GetBlockHeaders, HeaderRequest, Message, Penalty, PenaltyKind,
+            Status,
+        },
     },
     stagedsync::{stage::*, util::unwind_by_block_key},
     StageId, TaskGuard,
@@ -20,7 +23,7 @@ use parking_lot::Mutex;
 use rand::prelude::*;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet},
     convert::identity,
     sync::{
         atomic::{AtomicUsize, Ordering},
@@ -89,12 +92,10 @@ where
                 ForkChoiceMode::External(mut chain_tip_watch) => {
                     // Reverse download mode
 
-                    let prev_progress_block =
-                        txn.get(tables::Header, prev_progress)?.ok_or_else(|| {
-                            StageError::Internal(format_err!(
-                                "no header for block #{prev_progress}"
-                            ))
-                        })?;
+                    let prev_progress_block = accessors::chain::header::read(txn, prev_progress)?
+                        .ok_or_else(|| {
+                        StageError::Internal(format_err!("no header for block #{prev_progress}"))
+                    })?;
 
                     info!("Awaiting chain tip from external consensus engine...");
 
@@ -308,6 +309,7 @@ enum LinearDownloadResult {
 
 impl HeaderDownload {
     const BACK_OFF: Duration = Duration::from_secs(5);
+    const SCHEDULE_INTERVAL: Duration = Duration::from_millis(100);
 
     async fn reverse_download_linear(
         &self,
@@ -339,21 +341,36 @@ impl HeaderDownload {
 
             let mut success = true;
             let sent_request_id = rand::thread_rng().gen();
-            if let Ok(sent) = tokio::time::timeout(
-                Duration::from_secs(5),
-                self.node.send_header_request(
-                    Some(sent_request_id),
-                    HeaderRequest {
-                        start: BlockId::Hash(request_start),
-                        limit,
-                        reverse: true,
-                        ..Default::default()
-                    },
-                    request_start_block,
-                ),
-            )
-            .await
+            let request = HeaderRequest {
+                start: BlockId::Hash(request_start),
+                limit,
+                reverse: true,
+                ..Default::default()
+            };
+            let msg = Message::GetBlockHeaders(GetBlockHeaders {
+                request_id: sent_request_id,
+                params: request.into(),
+            });
+            // Prefer the best known peer, fall back to asking everyone
+            let sent = match self
+                .node
+                .send_scheduled(sent_request_id, RequestKind::Headers, msg)
+                .await
             {
+                Some(peer) => Ok(HashSet::from([peer])),
+                None => {
+                    tokio::time::timeout(
+                        Duration::from_secs(5),
+                        self.node.send_header_request(
+                            Some(sent_request_id),
+                            request,
+                            request_start_block,
+                        ),
+                    )
+                    .await
+                }
+            };
+            if let Ok(sent) = sent {
                 let timeout = tokio::time::sleep(Duration::from_secs(5));
                 tokio::pin!(timeout);
 
@@ -363,6 +380,12 @@ impl HeaderDownload {
                             if let Some(msg) = msg {
                                 if sent.contains(&(msg.sentry_id, msg.peer_id)) {
                                     if let Message::BlockHeaders(BlockHeaders { request_id, headers }) = msg.msg.clone() {
+                                        self.node.scheduler.received(
+                                            request_id,
+                                            (msg.sentry_id, msg.peer_id),
+                                            headers.len(),
+                                            std::time::Instant::now(),
+                                        );
                                         if sent_request_id == request_id && !headers.is_empty() {
                                             info!("Received {} headers from peer {}/{}", headers.len(), msg.sentry_id, msg.peer_id);
 
@@ -374,6 +397,7 @@ impl HeaderDownload {
                             }
                         }
                         _ = &mut timeout => {
+                            self.node.scheduler.timed_out(sent_request_id);
                             break None;
                         }
                     }
@@ -485,13 +509,52 @@ impl HeaderDownload {
                 let requests = requests.clone();
 
                 async move {
+                    // Request currently in flight for each segment
+                    let mut in_flight = HashMap::<BlockNumber, RequestId>::new();
                     loop {
-                        let reqs = requests
+                        node.scheduler.expire(std::time::Instant::now());
+
+                        let mut reqs = requests
                             .iter()
-                            .map(|entry_ref| *entry_ref.value())
+                            .map(|entry_ref| (*entry_ref.key(), *entry_ref.value()))
                             .collect::<Vec<_>>();
-                        node.clone().send_many_header_requests(reqs).await;
-                        tokio::time::sleep(Self::BACK_OFF).await;
+
+                        if node.scheduler.num_peers() == 0 {
+                            // No peers to schedule on yet, ask everyone
+                            node.clone()
+                                .send_many_header_requests(reqs.into_iter().map(|(_, req)| req))
+                                .await;
+                            tokio::time::sleep(Self::BACK_OFF).await;
+                            continue;
+                        }
+
+                        // Lowest segments first, they unblock the chain
+                        reqs.sort_unstable_by_key(|(start, _)| *start);
+                        for (start, request) in reqs {
+                            if in_flight
+                                .get(&start)
+                                .map_or(false, |&request_id| node.scheduler.is_pending(request_id))
+                            {
+                                continue;
+                            }
+
+                            let request_id = rand::thread_rng().gen::<u64>();
+                            let msg = Message::GetBlockHeaders(GetBlockHeaders {
+                                request_id,
+                                params: request.into(),
+                            });
+                            if node
+                                .send_scheduled(request_id, RequestKind::Headers, msg)
+                                .await
+                                .is_none()
+                            {
+                                // All peers are busy
+                                break;
+                            }
+                            in_flight.insert(start, request_id);
+                        }
+
+                        tokio::time::sleep(Self::SCHEDULE_INTERVAL).await;
                     }
                 }
             }));
@@ -499,8 +562,16 @@ impl HeaderDownload {
             while !requests.is_empty() {
                 if let Some(msg) = stream.next().await {
                     let peer_id = msg.peer_id;
+                    let sentry_id = msg.sentry_id;
 
                     if let Message::BlockHeaders(inner) = msg.msg {
+                        self.node.scheduler.received(
+                            inner.request_id,
+                            (sentry_id, peer_id),
+                            inner.headers.len(),
+                            std::time::Instant::now(),
+                        );
+
                         if inner.headers.is_empty() {
                             continue;
                         }
@@ -563,7 +634,12 @@ impl HeaderDownload {
             headers.truncate(last_valid);
 
             if let Some(peer_id) = peer_map.get(&invalid_hash).map(|e| *e) {
-                self.node.penalize_peer(peer_id).await;
+                self.node
+                    .penalize_peer(Penalty {
+                        peer_id,
+                        kind: PenaltyKind::BadBlock,
+                    })
+                    .await;
             }
         }
 
@@ -572,7 +648,12 @@ impl HeaderDownload {
                 headers.truncate(last_valid);
 
                 if let Some(peer_id) = peer_map.get(&invalid_hash).map(|e| *e) {
-                    self.node.penalize_peer(peer_id).await;
+                    self.node
+                        .penalize_peer(Penalty {
+                            peer_id,
+                            kind: PenaltyKind::BadBlock,
+                        })
+                        .await;
                 }
             }
         }
@@ -631,7 +712,11 @@ impl HeaderDownload {
             }
             Err(()) => {
                 warn!("Rejected discontiguous header segment from {peer_id}");
-                node.penalize_peer(peer_id).await
+                node.penalize_peer(Penalty {
+                    peer_id,
+                    kind: PenaltyKind::WrongChildBlockHeight,
+                })
+                .await
             }
         }
     }
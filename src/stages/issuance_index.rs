@@ -0,0 +1,291 @@
+use crate::{
+    accessors,
+    consensus::{engine_factory, FinalizationChange},
+    kv::{
+        mdbx::*,
+        tables::{self, BlockIssuance},
+    },
+    models::*,
+    stagedsync::stage::*,
+    StageId,
+};
+use anyhow::format_err;
+use async_trait::async_trait;
+use tracing::*;
+
+pub const ISSUANCE_INDEX: StageId = StageId("IssuanceIndex");
+
+/// Per-block and cumulative accounting of block rewards and burnt fees
+#[derive(Debug)]
+pub struct IssuanceIndex;
+
+#[async_trait]
+impl<'db, E> Stage<'db, E> for IssuanceIndex
+where
+    E: EnvironmentKind,
+{
+    fn id(&self) -> StageId {
+        ISSUANCE_INDEX
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: StageInput,
+    ) -> Result<ExecOutput, StageError>
+    where
+        'db: 'tx,
+    {
+        let prev_progress = input.stage_progress.unwrap_or_default();
+
+        let chain_spec = tx
+            .get(tables::Config, ())?
+            .ok_or_else(|| format_err!("No chain specification set"))?;
+
+        let mut issuance_cur = tx.cursor(tables::Issuance)?;
+
+        let starting_block = prev_progress + 1;
+        let max_block = input
+            .previous_stage
+            .map(|(_, v)| v)
+            .ok_or_else(|| format_err!("Cannot be the first stage"))?;
+
+        // Allocated balances are counted in full, as allocations are assumed to go to accounts
+        // that held nothing before.
+        let allocated = |block_num| {
+            chain_spec
+                .balances
+                .get(&block_num)
+                .into_iter()
+                .flat_map(|balances| balances.values())
+                .fold(U256::ZERO, |total, &balance| total + balance)
+        };
+
+        if max_block >= starting_block {
+            let mut last = match issuance_cur.seek_exact(prev_progress)? {
+                Some((_, issuance)) => issuance,
+                None if prev_progress == 0 => {
+                    let allocated = allocated(BlockNumber(0));
+                    let genesis = BlockIssuance {
+                        allocated,
+                        total_issued: allocated,
+                        ..Default::default()
+                    };
+                    issuance_cur.append(BlockNumber(0), genesis)?;
+                    genesis
+                }
+                None => {
+                    return Err(format_err!("No issuance for block #{prev_progress}").into());
+                }
+            };
+
+            let engine = engine_factory(None, chain_spec.clone(), None, None)?;
+
+            for block_num in starting_block..=max_block {
+                if block_num.0 % 500_000 == 0 {
+                    info!("Building issuance index for block {block_num}");
+                }
+
+                let header = accessors::chain::header::read(tx, block_num)?
+                    .ok_or_else(|| format_err!("No header for block #{block_num}"))?;
+                let ommers = accessors::chain::storage_body::read(tx, block_num)?
+                    .ok_or_else(|| format_err!("No body for block #{block_num}"))?
+                    .ommers;
+
+                let mut issuance = BlockIssuance {
+                    allocated: allocated(block_num),
+                    ..Default::default()
+                };
+                for change in engine.finalize(&header, &ommers)? {
+                    match change {
+                        FinalizationChange::Reward { amount, ommer, .. } => {
+                            if ommer {
+                                issuance.uncle_reward += amount;
+                            } else {
+                                issuance.block_reward += amount;
+                            }
+                        }
+                    }
+                }
+                if let Some(base_fee_per_gas) = header.base_fee_per_gas {
+                    issuance.burnt_fees = base_fee_per_gas * U256::from(header.gas_used);
+                }
+                issuance.total_issued = last.total_issued + issuance.issuance();
+                issuance.total_burnt = last.total_burnt + issuance.burnt_fees;
+
+                issuance_cur.append(block_num, issuance)?;
+                last = issuance;
+            }
+        }
+
+        Ok(ExecOutput::Progress {
+            stage_progress: max_block,
+            done: true,
+            reached_tip: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        let mut issuance_cur = tx.cursor(tables::Issuance)?;
+
+        while let Some((block_num, _)) = issuance_cur.last()? {
+            if block_num > input.unwind_to {
+                issuance_cur.delete_current()?;
+            } else {
+                break;
+            }
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accessors::chain, kv::new_mem_chaindata, res::chainspec::MAINNET, stages};
+    use std::time::Instant;
+
+    #[tokio::test]
+    async fn issuance_index() {
+        let db = new_mem_chaindata().unwrap();
+        let mut tx = db.begin_mutable().unwrap();
+
+        let mut chain_spec = MAINNET.clone();
+        chain_spec.balances = [
+            (
+                BlockNumber(0),
+                [(Address::repeat_byte(0xaa), ETHER.as_u256())]
+                    .into_iter()
+                    .collect(),
+            ),
+            (
+                BlockNumber(1),
+                [(Address::repeat_byte(0xbb), (3 * ETHER).as_u256())]
+                    .into_iter()
+                    .collect(),
+            ),
+        ]
+        .into_iter()
+        .collect();
+        tx.set(tables::Config, (), chain_spec).unwrap();
+
+        let london = MAINNET.upgrades.london.unwrap();
+        for block_number in [BlockNumber(1), london] {
+            tx.set(
+                tables::Header,
+                block_number,
+                BlockHeader {
+                    number: block_number,
+                    gas_used: 100,
+                    base_fee_per_gas: (block_number == london).then_some(7.as_u256()),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            chain::storage_body::write(
+                &tx,
+                block_number,
+                &BodyForStorage {
+                    base_tx_id: TxIndex(0),
+                    tx_amount: 0,
+                    ommers: Default::default(),
+                },
+            )
+            .unwrap();
+        }
+
+        let stage_input = |previous_stage, stage_progress| StageInput {
+            restarted: false,
+            first_started_at: (Instant::now(), None),
+            previous_stage: Some((stages::TOTAL_TX_INDEX, previous_stage)),
+            stage_progress: Some(stage_progress),
+        };
+
+        IssuanceIndex
+            .execute(&mut tx, stage_input(BlockNumber(1), BlockNumber(0)))
+            .await
+            .unwrap();
+
+        let block_reward = (5 * ETHER).as_u256();
+        let block_1 = BlockIssuance {
+            block_reward,
+            uncle_reward: U256::ZERO,
+            allocated: (3 * ETHER).as_u256(),
+            burnt_fees: U256::ZERO,
+            total_issued: ETHER.as_u256() + block_reward + (3 * ETHER).as_u256(),
+            total_burnt: U256::ZERO,
+        };
+        assert_eq!(
+            tx.get(tables::Issuance, BlockNumber(0))
+                .unwrap()
+                .unwrap()
+                .allocated,
+            ETHER.as_u256()
+        );
+        assert_eq!(
+            tx.get(tables::Issuance, BlockNumber(1)).unwrap().unwrap(),
+            block_1
+        );
+
+        let unwind = |unwind_to| UnwindInput {
+            stage_progress: BlockNumber(1),
+            unwind_to,
+            bad_block: None,
+        };
+
+        // Allocation is dropped with its block and counted again when it is re-indexed.
+        IssuanceIndex
+            .unwind(&mut tx, unwind(BlockNumber(0)))
+            .await
+            .unwrap();
+        assert_eq!(tx.get(tables::Issuance, BlockNumber(1)).unwrap(), None);
+        IssuanceIndex
+            .execute(&mut tx, stage_input(BlockNumber(1), BlockNumber(0)))
+            .await
+            .unwrap();
+        assert_eq!(
+            tx.get(tables::Issuance, BlockNumber(1)).unwrap().unwrap(),
+            block_1
+        );
+
+        // Skip to London without the blocks in between.
+        IssuanceIndex
+            .unwind(&mut tx, unwind(BlockNumber(0)))
+            .await
+            .unwrap();
+
+        tx.set(
+            tables::Issuance,
+            BlockNumber(london.0 - 1),
+            BlockIssuance {
+                total_issued: 1000.as_u256(),
+                total_burnt: 10.as_u256(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        IssuanceIndex
+            .execute(&mut tx, stage_input(london, BlockNumber(london.0 - 1)))
+            .await
+            .unwrap();
+
+        let issuance = tx.get(tables::Issuance, london).unwrap().unwrap();
+        assert_eq!(issuance.block_reward, (2 * ETHER).as_u256());
+        assert_eq!(issuance.burnt_fees, 700.as_u256());
+        assert_eq!(issuance.total_burnt, 710.as_u256());
+        assert_eq!(
+            issuance.total_supply(),
+            1000.as_u256() + (2 * ETHER).as_u256() - 710.as_u256()
+        );
+    }
+}
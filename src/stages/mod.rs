@@ -1,14 +1,20 @@
 mod block_hashes;
+mod block_import;
 mod bodies;
 mod call_trace_index;
+mod contract_creator_index;
+mod era1_import;
 mod execution;
 mod finish;
+mod freeze;
 mod hashstate;
 mod headers;
 mod history_index;
 mod interhashes;
+mod issuance_index;
 mod log_address_index;
 mod log_topic_index;
+mod sender_nonce_index;
 mod sender_recovery;
 pub mod stage_util;
 mod total_gas_index;
@@ -16,16 +22,22 @@ mod total_tx_index;
 mod tx_lookup;
 
 pub use block_hashes::*;
+pub use block_import::*;
 pub use bodies::*;
 pub use call_trace_index::*;
+pub use contract_creator_index::*;
+pub use era1_import::*;
 pub use execution::*;
 pub use finish::*;
+pub use freeze::*;
 pub use hashstate::*;
 pub use headers::*;
 pub use history_index::*;
 pub use interhashes::*;
+pub use issuance_index::*;
 pub use log_address_index::*;
 pub use log_topic_index::*;
+pub use sender_nonce_index::*;
 pub use sender_recovery::*;
 pub use total_gas_index::*;
 pub use total_tx_index::*;
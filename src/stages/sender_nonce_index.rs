@@ -0,0 +1,240 @@
+use crate::{
+    accessors,
+    etl::collector::*,
+    kv::{mdbx::*, tables},
+    models::*,
+    stagedsync::stage::*,
+    StageId,
+};
+use anyhow::{ensure, format_err};
+use async_trait::async_trait;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tempfile::TempDir;
+use tracing::*;
+
+pub const SENDER_NONCE_INDEX: StageId = StageId("SenderNonceIndex");
+
+/// Generation of (Sender, Nonce) => TransactionHash mapping
+#[derive(Debug)]
+pub struct SenderNonceIndex {
+    pub temp_dir: Arc<TempDir>,
+}
+
+fn block_transactions<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    block_number: BlockNumber,
+) -> anyhow::Result<impl Iterator<Item = (Address, MessageWithSignature)>> {
+    let body = accessors::chain::storage_body::read(tx, block_number)?
+        .ok_or_else(|| format_err!("Block body not found: {block_number}"))?;
+    let transactions = accessors::chain::tx::read(tx, body.base_tx_id, body.tx_amount.try_into()?)?;
+    let senders = accessors::chain::tx_sender::read(tx, block_number)?;
+    ensure!(
+        senders.len() == transactions.len(),
+        "Senders for block {block_number} not recovered"
+    );
+
+    Ok(senders.into_iter().zip(transactions))
+}
+
+#[async_trait]
+impl<'db, E> Stage<'db, E> for SenderNonceIndex
+where
+    E: EnvironmentKind,
+{
+    fn id(&self) -> StageId {
+        SENDER_NONCE_INDEX
+    }
+
+    async fn execute<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: StageInput,
+    ) -> Result<ExecOutput, StageError>
+    where
+        'db: 'tx,
+    {
+        let start_block = input.stage_progress.unwrap_or(BlockNumber(0)) + 1;
+        let max_block = input
+            .previous_stage
+            .ok_or_else(|| format_err!("Sender nonce index generation cannot be the first stage"))?
+            .1;
+
+        let mut collector = TableCollector::<tables::SenderNonceIndex>::new(
+            &self.temp_dir,
+            OPTIMAL_BUFFER_CAPACITY,
+        );
+
+        let mut last_printed = Instant::now();
+        let mut block_number = start_block;
+        while block_number <= max_block {
+            for (sender, transaction) in block_transactions(tx, block_number)? {
+                collector.push((sender, transaction.message.nonce()), transaction.hash());
+            }
+
+            let now = Instant::now();
+            if now.duration_since(last_printed) > Duration::from_secs(30) {
+                info!("Block #{block_number}");
+                last_printed = now;
+            }
+
+            block_number.0 += 1;
+        }
+
+        collector.load(&mut tx.cursor(tables::SenderNonceIndex.erased())?)?;
+
+        Ok(ExecOutput::Progress {
+            stage_progress: max_block,
+            done: true,
+            reached_tip: true,
+        })
+    }
+
+    async fn unwind<'tx>(
+        &mut self,
+        tx: &'tx mut MdbxTransaction<'db, RW, E>,
+        input: UnwindInput,
+    ) -> anyhow::Result<UnwindOutput>
+    where
+        'db: 'tx,
+    {
+        let mut block_number = input.unwind_to + 1;
+        while block_number <= input.stage_progress {
+            for (sender, transaction) in block_transactions(tx, block_number)? {
+                tx.del(
+                    tables::SenderNonceIndex,
+                    (sender, transaction.message.nonce()),
+                    None,
+                )?;
+            }
+
+            block_number.0 += 1;
+        }
+
+        Ok(UnwindOutput {
+            stage_progress: input.unwind_to,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{accessors::chain, kv::new_mem_chaindata, stages};
+    use bytes::Bytes;
+    use hex_literal::hex;
+
+    fn transfer(nonce: u64, value: u64) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: Some(ChainId(1)),
+                nonce,
+                gas_price: 1_000_000.as_u256(),
+                gas_limit: 21_000,
+                action: TransactionAction::Call(Address::from(hex!(
+                    "f4148309cc30f2dd4ba117122cad6be1e3ba0e2b"
+                ))),
+                value: value.as_u256(),
+                input: Bytes::new(),
+            },
+            signature: MessageSignature::new(
+                false,
+                H256::from(hex!(
+                    "11d244ae19e3bb96d1bb864aa761d48e957984a154329f0de757cd105f9c7ac4"
+                )),
+                H256::from(hex!(
+                    "0e3828d13eed24036941eb5f7fd65de57aad1184342f2244130d2941554342ba"
+                )),
+            )
+            .unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn sender_nonce_index() {
+        let db = new_mem_chaindata().unwrap();
+        let mut tx = db.begin_mutable().unwrap();
+
+        let sender1 = Address::from(hex!("d7fa8303df7073290f66ced1add5fe89dac0c462"));
+        let sender2 = Address::from(hex!("5a0b54d5dc17e0aadc383d2db43b0a0d3e029c4c"));
+
+        let txs = [transfer(0, 1), transfer(0, 2), transfer(1, 3)];
+        let hashes = txs.iter().map(|t| t.hash()).collect::<Vec<_>>();
+
+        for (block_number, base_tx_id, tx_amount, senders) in
+            [(1, 1, 2, vec![sender1, sender2]), (2, 3, 1, vec![sender1])]
+        {
+            chain::storage_body::write(
+                &tx,
+                BlockNumber(block_number),
+                &BodyForStorage {
+                    base_tx_id: TxIndex(base_tx_id),
+                    tx_amount,
+                    ommers: Default::default(),
+                },
+            )
+            .unwrap();
+            chain::tx_sender::write(&tx, BlockNumber(block_number), senders).unwrap();
+        }
+        chain::tx::write(&tx, TxIndex(1), &txs).unwrap();
+
+        let mut stage = SenderNonceIndex {
+            temp_dir: Arc::new(TempDir::new().unwrap()),
+        };
+
+        assert_eq!(
+            stage
+                .execute(
+                    &mut tx,
+                    StageInput {
+                        restarted: false,
+                        first_started_at: (Instant::now(), Some(BlockNumber(0))),
+                        previous_stage: Some((stages::EXECUTION, BlockNumber(2))),
+                        stage_progress: None,
+                    },
+                )
+                .await
+                .unwrap(),
+            ExecOutput::Progress {
+                stage_progress: BlockNumber(2),
+                done: true,
+                reached_tip: true,
+            }
+        );
+
+        for (sender, nonce, hash) in [
+            (sender1, 0, Some(hashes[0])),
+            (sender2, 0, Some(hashes[1])),
+            (sender1, 1, Some(hashes[2])),
+            (sender2, 1, None),
+        ] {
+            assert_eq!(
+                tx.get(tables::SenderNonceIndex, (sender, nonce)).unwrap(),
+                hash
+            );
+        }
+
+        stage
+            .unwind(
+                &mut tx,
+                UnwindInput {
+                    stage_progress: BlockNumber(2),
+                    unwind_to: BlockNumber(1),
+                    bad_block: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            tx.get(tables::SenderNonceIndex, (sender1, 0)).unwrap(),
+            Some(hashes[0])
+        );
+        assert_eq!(
+            tx.get(tables::SenderNonceIndex, (sender1, 1)).unwrap(),
+            None
+        );
+    }
+}
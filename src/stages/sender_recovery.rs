@@ -1,4 +1,5 @@
 use crate::{
+    accessors,
     kv::{
         mdbx::*,
         tables::{self, ErasedTable},
@@ -45,9 +46,7 @@ where
         let mut senders_cur = tx.cursor(tables::TxSender.erased())?;
         senders_cur.last()?;
 
-        let walker = tx
-            .cursor(tables::BlockBody)?
-            .walk(Some(BlockNumber(highest_block.0 + 1)));
+        let walker = accessors::chain::storage_body::walk(tx, highest_block + 1);
         pin!(walker);
         let mut batch = Vec::with_capacity(self.batch_size);
         let started_at = Instant::now();
@@ -60,12 +59,11 @@ where
             let mut batch_txs = 0;
             debug!("Reading bodies");
             while let Some((block_number, body)) = walker.next().transpose()? {
-                let txs = tx
-                    .cursor(tables::BlockTransaction.erased())?
-                    .walk(Some(body.base_tx_id.encode().to_vec()))
-                    .take(body.tx_amount.try_into().unwrap())
-                    .map(|res| res.map(|(_, tx)| tx))
-                    .collect::<anyhow::Result<Vec<_>>>()?;
+                let txs = accessors::chain::tx::read(
+                    tx,
+                    body.base_tx_id,
+                    body.tx_amount.try_into().unwrap(),
+                )?;
                 batch_txs += txs.len();
                 batch.push((block_number, txs));
 
@@ -84,13 +82,7 @@ where
                     if !txs.is_empty() {
                         let senders = txs
                             .into_iter()
-                            .map(|encoded_tx| {
-                                let tx = ErasedTable::<tables::BlockTransaction>::decode_value(
-                                    &encoded_tx,
-                                )?;
-                                let sender = tx.recover_sender()?;
-                                Ok::<_, anyhow::Error>(sender)
-                            })
+                            .map(|tx| tx.recover_sender())
                             .collect::<anyhow::Result<Vec<Address>>>();
 
                         Some(senders.map(|senders| {
@@ -1,4 +1,5 @@
 use crate::{
+    accessors,
     kv::{mdbx::*, tables},
     stagedsync::stage::*,
     StageId,
@@ -52,8 +53,7 @@ where
                     info!("Building total tx index for block {block_num}");
                 }
 
-                let body = tx
-                    .get(tables::BlockBody, block_num)?
+                let body = accessors::chain::storage_body::read(tx, block_num)?
                     .ok_or_else(|| format_err!("Body not found for block #{block_num}"))?;
 
                 tx_num += body.tx_amount;
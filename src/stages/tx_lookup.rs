@@ -1,4 +1,5 @@
 use crate::{
+    accessors,
     etl::collector::*,
     kv::{mdbx::*, tables},
     models::*,
@@ -46,35 +47,28 @@ where
         let mut highest_block = input.stage_progress.unwrap_or(BlockNumber(0));
         let start_block = highest_block + 1;
 
-        let walker_block_body = tx.cursor(tables::BlockBody)?.walk(Some(start_block));
+        let walker_block_body = accessors::chain::storage_body::walk(tx, start_block);
         pin!(walker_block_body);
 
-        let mut block_tx_cursor = tx.cursor(tables::BlockTransaction)?;
-
         let mut last_printed = Instant::now();
 
         while let Some((
             block_number,
             BodyForStorage {
                 base_tx_id,
-                mut tx_amount,
+                tx_amount,
                 ..
             },
         )) = walker_block_body.next().transpose()?
         {
-            let mut first = true;
-            while tx_amount > 0 {
-                let (_, tx) = if first {
-                    first = false;
-                    block_tx_cursor.seek_exact(base_tx_id)?
-                } else {
-                    block_tx_cursor.next()?
-                }
-                .ok_or_else(|| format_err!("unexpected end of block tx table"))?;
-
-                tx_amount -= 1;
+            let transactions =
+                accessors::chain::tx::read(tx, base_tx_id, tx_amount.try_into().unwrap())?;
+            if transactions.len() as u64 != tx_amount {
+                return Err(format_err!("unexpected end of block tx table").into());
+            }
 
-                collector.push(tx.hash(), tables::TruncateStart(block_number));
+            for transaction in transactions {
+                collector.push(transaction.hash(), tables::TruncateStart(block_number));
             }
 
             let now = Instant::now();
@@ -106,7 +100,6 @@ where
     where
         'db: 'tx,
     {
-        let bodies_cursor = tx.cursor(tables::BlockBody)?;
         let mut tx_hash_cursor = tx.cursor(tables::BlockTransactionLookup)?;
 
         let start_block_number = input.unwind_to + 1;
@@ -116,7 +109,7 @@ where
             input.stage_progress, input.unwind_to
         );
 
-        let walker_block_body = bodies_cursor.walk(Some(start_block_number));
+        let walker_block_body = accessors::chain::storage_body::walk(tx, start_block_number);
         pin!(walker_block_body);
 
         while let Some((
@@ -128,20 +121,10 @@ where
             },
         )) = walker_block_body.next().transpose()?
         {
-            let walker_block_txs = tx.cursor(tables::BlockTransaction)?.walk(Some(base_tx_id));
-            pin!(walker_block_txs);
-
-            let mut num_txs = 1;
-
-            while let Some((_, tx_value)) = walker_block_txs.next().transpose()? {
-                if num_txs > tx_amount {
-                    break;
-                }
-
+            for tx_value in accessors::chain::tx::read(tx, base_tx_id, tx_amount.try_into()?)? {
                 if tx_hash_cursor.seek(tx_value.hash())?.is_some() {
                     tx_hash_cursor.delete_current()?;
                 }
-                num_txs += 1;
             }
         }
         Ok(UnwindOutput {
@@ -92,6 +92,16 @@ where
             ),
         );
     }
+
+    /// Account changes not written yet, by block.
+    pub fn account_changes(&self) -> &BTreeMap<BlockNumber, AccountChanges> {
+        &self.account_changes
+    }
+
+    /// Storage changes not written yet, by block.
+    pub fn storage_changes(&self) -> &BTreeMap<BlockNumber, StorageChanges> {
+        &self.storage_changes
+    }
 }
 
 impl<'db, 'tx, K, E> HeaderReader for MdbxTransaction<'db, K, E>
@@ -1,9 +1,15 @@
-use crate::kv::{mdbx::*, tables, MdbxWithDirHandle};
+use crate::{
+    kv::{mdbx::*, tables, traits::Table, CustomTable, MdbxWithDirHandle},
+    stages::EXECUTION,
+};
 use anyhow::format_err;
 use std::collections::BTreeMap;
 use thiserror::Error;
 
-const DATABASE_VERSION: u64 = 3;
+pub const DATABASE_VERSION: u64 = 5;
+
+/// Entries compressed per transaction when migrating to compressed tables.
+const COMPRESSION_BATCH_SIZE: usize = 100_000;
 
 type Migration<'db, E> = fn(&mut MdbxTransaction<'db, RW, E>) -> anyhow::Result<u64>;
 
@@ -13,6 +19,12 @@ pub enum MigrationError {
     DbTooOld { current: u64 },
     #[error("Database version {current} too high. Newest version {newest}")]
     DbTooNew { current: u64, newest: u64 },
+    #[error("Table {table} is compressed with dictionary {stored}, but this build has {embedded}")]
+    DictionaryMismatch {
+        table: String,
+        stored: u64,
+        embedded: u64,
+    },
     #[error("Migration error")]
     MigrationError(#[from] anyhow::Error),
 }
@@ -24,6 +36,114 @@ where
     Ok(DATABASE_VERSION)
 }
 
+/// Rewrites values of a table stored uncompressed by previous database versions, committing every
+/// `batch_size` entries. The last key rewritten is saved with each batch, so an interrupted
+/// migration resumes after it.
+fn compress_table<T, E>(
+    db: &MdbxWithDirHandle<E>,
+    table: T,
+    batch_size: usize,
+) -> anyhow::Result<()>
+where
+    T: Table,
+    E: EnvironmentKind,
+{
+    let compression = T::compression()
+        .ok_or_else(|| format_err!("table {} is not compressed", table.db_name()))?;
+    let name = table.db_name().as_bytes().to_vec();
+
+    loop {
+        let txn = db.begin_mutable()?;
+        let mut cursor = txn.cursor(CustomTable(table.db_name()))?;
+        let mut entry = match txn.get(tables::CompressionProgress, name.clone())? {
+            Some(last_key) => match cursor.seek(last_key.clone())? {
+                Some((key, _)) if key == last_key => cursor.next()?,
+                entry => entry,
+            },
+            None => cursor.first()?,
+        };
+
+        let mut last_key = None;
+        for _ in 0..batch_size {
+            let (key, value) = match entry {
+                Some(entry) => entry,
+                None => break,
+            };
+            cursor.upsert(key.clone(), compression.compress(&value)?)?;
+            last_key = Some(key);
+            entry = cursor.next()?;
+        }
+
+        let done = entry.is_none();
+        drop(cursor);
+        if let Some(last_key) = last_key {
+            txn.set(tables::CompressionProgress, name.clone(), last_key)?;
+        }
+        txn.commit()?;
+
+        if done {
+            return Ok(());
+        }
+    }
+}
+
+fn compress_tables<E>(db: &MdbxWithDirHandle<E>, batch_size: usize) -> anyhow::Result<()>
+where
+    E: EnvironmentKind,
+{
+    compress_table(db, tables::Code, batch_size)?;
+    compress_table(db, tables::BlockTransaction, batch_size)?;
+
+    let txn = db.begin_mutable()?;
+    txn.clear_table(tables::CompressionProgress)?;
+    set_database_version(&txn, 4)?;
+    txn.commit()?;
+
+    Ok(())
+}
+
+/// Records the dictionary IDs of compressed tables in a new database, and checks them against the
+/// dictionaries embedded into this build otherwise.
+fn check_dictionaries<E>(txn: &MdbxTransaction<'_, RW, E>) -> Result<(), MigrationError>
+where
+    E: EnvironmentKind,
+{
+    fn check<T: Table, E: EnvironmentKind>(
+        txn: &MdbxTransaction<'_, RW, E>,
+        table: T,
+    ) -> Result<(), MigrationError> {
+        let name = table.db_name().as_bytes().to_vec();
+        let embedded = T::compression().map_or(0, |c| c.dictionary_id()) as u64;
+        match txn.get(tables::CompressionDictionary, name.clone())? {
+            Some(stored) if stored != embedded => Err(MigrationError::DictionaryMismatch {
+                table: table.db_name().to_string(),
+                stored,
+                embedded,
+            }),
+            Some(_) => Ok(()),
+            None => Ok(txn.set(tables::CompressionDictionary, name, embedded)?),
+        }
+    }
+
+    check(txn, tables::Code)?;
+    check(txn, tables::BlockTransaction)?;
+
+    Ok(())
+}
+
+/// Contract creation sets are only written for blocks executed from now on, record the first
+/// of them.
+fn start_contract_creations<E>(txn: &mut MdbxTransaction<'_, RW, E>) -> anyhow::Result<u64>
+where
+    E: EnvironmentKind,
+{
+    if let Some(executed) = EXECUTION.get_progress(txn)? {
+        txn.set(tables::ContractCreationStart, (), executed + 1)?;
+    }
+
+    Ok(5)
+}
+
 fn set_database_version<E>(txn: &MdbxTransaction<'_, RW, E>, version: u64) -> anyhow::Result<()>
 where
     E: EnvironmentKind,
@@ -32,7 +152,7 @@ where
     Ok(())
 }
 
-fn get_database_version<K, E>(txn: &MdbxTransaction<'_, K, E>) -> anyhow::Result<u64>
+pub fn get_database_version<K, E>(txn: &MdbxTransaction<'_, K, E>) -> anyhow::Result<u64>
 where
     K: TransactionKind,
     E: EnvironmentKind,
@@ -70,8 +190,7 @@ pub fn migrate_database<E>(db: &MdbxWithDirHandle<E>) -> Result<(), MigrationErr
 where
     E: EnvironmentKind,
 {
-    let mut tx = db.begin_mutable()?;
-    let current_version = get_database_version(&tx).unwrap_or(0);
+    let mut current_version = get_database_version(&db.begin()?).unwrap_or(0);
 
     if current_version > DATABASE_VERSION {
         return Err(MigrationError::DbTooNew {
@@ -80,10 +199,21 @@ where
         });
     }
 
-    let migrations: BTreeMap<u64, Migration<E>> =
-        BTreeMap::from([(0, init_database_version as Migration<E>); 1]);
+    // Too large for a single transaction.
+    if current_version == 3 {
+        compress_tables(db, COMPRESSION_BATCH_SIZE)
+            .map_err(|e| format_err!("Failed database migration: {e}"))?;
+        current_version = 4;
+    }
+
+    let mut tx = db.begin_mutable()?;
+    let migrations: BTreeMap<u64, Migration<E>> = BTreeMap::from([
+        (0, init_database_version as Migration<E>),
+        (4, start_contract_creations as Migration<E>),
+    ]);
 
     apply_migrations(&mut tx, current_version, migrations)?;
+    check_dictionaries(&tx)?;
     tx.commit()?;
 
     Ok(())
@@ -92,7 +222,11 @@ where
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::kv::new_mem_chaindata;
+    use crate::{
+        kv::new_mem_chaindata,
+        models::{BlockNumber, H256},
+    };
+    use bytes::Bytes;
     use std::assert_matches::assert_matches;
 
     #[test]
@@ -120,6 +254,114 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_compress_tables() {
+        let db = new_mem_chaindata().unwrap();
+        let code = |i: u8| Bytes::from(vec![0x60 + i; 1024]);
+        {
+            let txn = db.begin_mutable().unwrap();
+            // An interrupted migration compressed the first two entries.
+            for i in 1..=2 {
+                txn.set(tables::Code, H256::repeat_byte(i), code(i))
+                    .unwrap();
+            }
+            txn.set(
+                tables::CompressionProgress,
+                tables::Code.db_name().as_bytes().to_vec(),
+                H256::repeat_byte(2).0.to_vec(),
+            )
+            .unwrap();
+            for i in 3..=7 {
+                txn.set(
+                    CustomTable(tables::Code.db_name()),
+                    H256::repeat_byte(i).0.to_vec(),
+                    code(i).to_vec(),
+                )
+                .unwrap();
+            }
+            set_database_version(&txn, 3).unwrap();
+            txn.commit().unwrap();
+        }
+
+        compress_tables(&db, 2).unwrap();
+        migrate_database(&db).unwrap();
+
+        let txn = db.begin().unwrap();
+        assert_eq!(get_database_version(&txn).unwrap(), DATABASE_VERSION);
+        for i in 1..=7 {
+            assert_eq!(
+                txn.get(tables::Code, H256::repeat_byte(i)).unwrap(),
+                Some(code(i))
+            );
+            assert!(
+                txn.get(
+                    CustomTable(tables::Code.db_name()),
+                    H256::repeat_byte(i).0.to_vec()
+                )
+                .unwrap()
+                .unwrap()
+                .len()
+                    < 1024
+            );
+        }
+        assert_eq!(
+            txn.cursor(tables::CompressionProgress)
+                .unwrap()
+                .first()
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_dictionary_mismatch() {
+        let db = new_mem_chaindata().unwrap();
+        migrate_database(&db).unwrap();
+        {
+            let txn = db.begin_mutable().unwrap();
+            // No dictionaries are embedded yet.
+            assert_eq!(
+                txn.get(
+                    tables::CompressionDictionary,
+                    tables::Code.db_name().as_bytes().to_vec()
+                )
+                .unwrap(),
+                Some(0)
+            );
+            txn.set(
+                tables::CompressionDictionary,
+                tables::Code.db_name().as_bytes().to_vec(),
+                1,
+            )
+            .unwrap();
+            txn.commit().unwrap();
+        }
+
+        assert_matches!(
+            migrate_database(&db),
+            Err(MigrationError::DictionaryMismatch { stored: 1, .. })
+        );
+    }
+
+    #[test]
+    fn test_start_contract_creations() {
+        let db = new_mem_chaindata().unwrap();
+        {
+            let txn = db.begin_mutable().unwrap();
+            EXECUTION.save_progress(&txn, BlockNumber(100)).unwrap();
+            set_database_version(&txn, 4).unwrap();
+            txn.commit().unwrap();
+        }
+
+        migrate_database(&db).unwrap();
+
+        let txn = db.begin().unwrap();
+        assert_eq!(
+            txn.get(tables::ContractCreationStart, ()).unwrap(),
+            Some(BlockNumber(101))
+        );
+    }
+
     #[test]
     fn test_apply_migrations() {
         let db = new_mem_chaindata().unwrap();
@@ -11,6 +11,15 @@ pub struct Snapshot {
     refund: u64,
 }
 
+/// Changes accumulated by an [IntraBlockState], detached from the reader they were made against.
+#[derive(Debug, Default)]
+pub(crate) struct WriteSet {
+    objects: HashMap<Address, Object>,
+    storage: HashMap<Address, Storage>,
+    incarnations: HashMap<Address, u64>,
+    new_code: HashMap<H256, Bytes>,
+}
+
 #[derive(Debug)]
 pub struct IntraBlockState<'db, S>
 where
@@ -506,6 +515,57 @@ impl<'r, S: StateReader> IntraBlockState<'r, S> {
     pub fn get_refund(&self) -> u64 {
         self.refund
     }
+
+    pub(crate) fn get_account(&mut self, address: Address) -> anyhow::Result<Option<Account>> {
+        Ok(get_object(self.state, &mut self.objects, address)?.and_then(|object| object.current))
+    }
+
+    pub(crate) fn into_write_set(self) -> WriteSet {
+        WriteSet {
+            objects: self.objects,
+            storage: self.storage,
+            incarnations: self.incarnations,
+            new_code: self.new_code,
+        }
+    }
+
+    /// Replays changes of a finalized transaction that was executed on top of the state this
+    /// `IntraBlockState` currently observes.
+    pub(crate) fn apply_write_set(&mut self, write_set: WriteSet) -> anyhow::Result<()> {
+        for (address, incarnation) in write_set.incarnations {
+            if incarnation > 0 {
+                *self.incarnations.entry(address).or_default() += 1;
+                self.storage.remove(&address);
+            }
+        }
+
+        for (address, object) in write_set.objects {
+            if let Some(existing) = get_object(self.state, &mut self.objects, address)? {
+                existing.current = object.current;
+            } else {
+                self.objects.insert(address, object);
+            }
+        }
+
+        for (address, storage) in write_set.storage {
+            let committed = &mut self.storage.entry(address).or_default().committed;
+            for (key, value) in storage.committed {
+                committed
+                    .entry(key)
+                    .or_insert(CommittedValue {
+                        initial: value.initial,
+                        original: value.initial,
+                    })
+                    .original = value.original;
+            }
+        }
+
+        for (code_hash, code) in write_set.new_code {
+            self.new_code.entry(code_hash).or_insert(code);
+        }
+
+        Ok(())
+    }
 }
 
 impl<'r, S> IntraBlockState<'r, S>
@@ -9,7 +9,7 @@ use crate::{
 use bytes::{BufMut, Bytes, BytesMut};
 use ethereum_types::H256;
 use fastrlp::{Encodable, RlpEncodable, EMPTY_STRING_CODE};
-use std::{boxed::Box, cmp};
+use std::{boxed::Box, cmp, collections::BTreeMap};
 
 const RLP_EMPTY_STRING_CODE: u8 = 0x80;
 
@@ -99,6 +99,8 @@ pub struct HashBuilder<'nc> {
     tree_masks: Vec<u16>,
     hash_masks: Vec<u16>,
     stack: Vec<Vec<u8>>,
+    proof_targets: Vec<Vec<u8>>,
+    proof_nodes: BTreeMap<Vec<u8>, Bytes>,
 }
 
 impl<'nc> HashBuilder<'nc> {
@@ -112,13 +114,51 @@ impl<'nc> HashBuilder<'nc> {
             tree_masks: vec![],
             hash_masks: vec![],
             stack: vec![],
+            proof_targets: vec![],
+            proof_nodes: BTreeMap::new(),
         }
     }
 
+    /// Retains the nodes on the paths to the given unpacked keys, so that proofs for them can be
+    /// taken after computing the root hash. The paths must not be skipped with branch nodes.
+    pub fn with_proof_targets(mut self, targets: impl IntoIterator<Item = Vec<u8>>) -> Self {
+        self.proof_targets = targets.into_iter().collect();
+        self.proof_targets.sort_unstable();
+        self
+    }
+
+    /// Merkle proof for the unpacked key: RLP of the nodes on its path, starting from the root.
+    /// Nodes short enough to be embedded in their parents are omitted.
+    pub fn proof(&self, key: &[u8]) -> Vec<Bytes> {
+        (0..=key.len())
+            .filter_map(|len| {
+                self.proof_nodes
+                    .get(&key[..len])
+                    .filter(|rlp| len == 0 || rlp.len() >= KECCAK_LENGTH)
+            })
+            .cloned()
+            .collect()
+    }
+
     fn collects_nodes(&self) -> bool {
         self.node_collector.is_some()
     }
 
+    fn retain_proof_node(&mut self, path: &[u8], rlp: &[u8]) {
+        // Targets are sorted, so the first one not below the path is the one it may prefix.
+        let i = self
+            .proof_targets
+            .partition_point(|target| target.as_slice() < path);
+        if self
+            .proof_targets
+            .get(i)
+            .map_or(false, |target| target.starts_with(path))
+        {
+            self.proof_nodes
+                .insert(path.to_vec(), Bytes::copy_from_slice(rlp));
+        }
+    }
+
     pub fn add_leaf(&mut self, key: Vec<u8>, value: &[u8]) {
         assert!(key > self.key);
         if !self.key.is_empty() {
@@ -199,9 +239,9 @@ impl<'nc> HashBuilder<'nc> {
                 let value = self.value.clone();
                 match &value {
                     HashBuilderValue::Bytes(leaf_value) => {
-                        let node_ref =
-                            node_ref(leaf_node_rlp(short_node_key.as_slice(), leaf_value).as_ref());
-                        self.stack.push(node_ref);
+                        let rlp = leaf_node_rlp(short_node_key.as_slice(), leaf_value);
+                        self.retain_proof_node(&current[..len_from], &rlp);
+                        self.stack.push(node_ref(&rlp));
                     }
                     HashBuilderValue::Hash(hash) => {
                         self.stack.push(wrap_hash(hash));
@@ -229,10 +269,9 @@ impl<'nc> HashBuilder<'nc> {
                 }
 
                 let stack_last = self.stack.pop().unwrap();
-                let new_stack_last = node_ref(
-                    extension_node_rlp(short_node_key.as_slice(), stack_last.as_slice()).as_ref(),
-                );
-                self.stack.push(new_stack_last);
+                let rlp = extension_node_rlp(short_node_key.as_slice(), stack_last.as_slice());
+                self.retain_proof_node(&current[..len_from], &rlp);
+                self.stack.push(node_ref(&rlp));
 
                 self.hash_masks.resize(len_from, 0u16);
                 self.tree_masks.resize(len_from, 0u16);
@@ -243,7 +282,8 @@ impl<'nc> HashBuilder<'nc> {
             }
 
             if !succeeding.is_empty() || preceding_exists {
-                let child_hashes = self.branch_ref(self.groups[len], self.hash_masks[len]);
+                let child_hashes =
+                    self.branch_ref(&current[..len], self.groups[len], self.hash_masks[len]);
 
                 if self.collects_nodes() {
                     if len > 0 {
@@ -295,7 +335,7 @@ impl<'nc> HashBuilder<'nc> {
         }
     }
 
-    fn branch_ref(&mut self, state_mask: u16, hash_mask: u16) -> Vec<Vec<u8>> {
+    fn branch_ref(&mut self, path: &[u8], state_mask: u16, hash_mask: u16) -> Vec<Vec<u8>> {
         assert_subset(hash_mask, state_mask);
         let mut child_hashes = Vec::<Vec<u8>>::with_capacity(hash_mask.count_ones() as usize);
         let first_child_idx = self.stack.len() - state_mask.count_ones() as usize;
@@ -336,6 +376,7 @@ impl<'nc> HashBuilder<'nc> {
         // branch nodes with values are not supported
         rlp_buffer.put_u8(EMPTY_STRING_CODE);
 
+        self.retain_proof_node(path, &rlp_buffer);
         self.stack.resize(first_child_idx, vec![]);
         self.stack.push(node_ref(&rlp_buffer));
 
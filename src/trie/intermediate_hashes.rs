@@ -94,29 +94,56 @@ fn increment_key(unpacked: &[u8]) -> Option<Vec<u8>> {
     None
 }
 
-struct Cursor<'cu, 'tx, 'ps, T>
+/// Visited trie nodes are deleted when regenerating intermediate hashes, as they are written
+/// anew, while read-only walks leave them in place.
+pub(super) trait ConsumeNode {
+    fn consume_current(&mut self) -> Result<()>;
+}
+
+impl<'tx, T> ConsumeNode for MdbxCursor<'tx, RW, T>
+where
+    T: Table,
+{
+    fn consume_current(&mut self) -> Result<()> {
+        self.delete_current()
+    }
+}
+
+impl<'tx, T> ConsumeNode for MdbxCursor<'tx, RO, T>
+where
+    T: Table,
+{
+    fn consume_current(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+pub(super) struct Cursor<'cu, 'tx, 'ps, K, T>
 where
+    K: TransactionKind,
     T: Table<Key = Vec<u8>, SeekKey = Vec<u8>, Value = Vec<u8>>,
     'tx: 'cu,
 {
-    cursor: Mutex<&'cu mut MdbxCursor<'tx, RW, T>>,
+    cursor: Mutex<&'cu mut MdbxCursor<'tx, K, T>>,
     changed: &'ps mut PrefixSet,
     prefix: Vec<u8>,
     stack: Vec<CursorSubNode>,
-    can_skip_state: bool,
+    pub(super) can_skip_state: bool,
     _marker: PhantomData<&'tx T>,
 }
 
-impl<'cu, 'tx, 'ps, T> Cursor<'cu, 'tx, 'ps, T>
+impl<'cu, 'tx, 'ps, K, T> Cursor<'cu, 'tx, 'ps, K, T>
 where
+    K: TransactionKind,
     T: Table<Key = Vec<u8>, SeekKey = Vec<u8>, Value = Vec<u8>>,
     'tx: 'cu,
+    MdbxCursor<'tx, K, T>: ConsumeNode,
 {
-    fn new(
-        cursor: &'cu mut MdbxCursor<'tx, RW, T>,
+    pub(super) fn new(
+        cursor: &'cu mut MdbxCursor<'tx, K, T>,
         changed: &'ps mut PrefixSet,
         prefix: &[u8],
-    ) -> Result<Cursor<'cu, 'tx, 'ps, T>> {
+    ) -> Result<Cursor<'cu, 'tx, 'ps, K, T>> {
         let mut new_cursor = Self {
             cursor: Mutex::new(cursor),
             changed,
@@ -129,7 +156,7 @@ where
         Ok(new_cursor)
     }
 
-    fn next(&mut self) -> Result<()> {
+    pub(super) fn next(&mut self) -> Result<()> {
         if let Some(last) = self.stack.last() {
             if !self.can_skip_state && self.children_are_in_trie() {
                 match last.nibble {
@@ -145,19 +172,19 @@ where
         Ok(())
     }
 
-    fn key(&self) -> Option<Vec<u8>> {
+    pub(super) fn key(&self) -> Option<Vec<u8>> {
         self.stack.last().map(|n| n.full_key())
     }
 
-    fn hash(&self) -> Option<H256> {
+    pub(super) fn hash(&self) -> Option<H256> {
         self.stack.last().and_then(|n| n.hash())
     }
 
-    fn children_are_in_trie(&self) -> bool {
+    pub(super) fn children_are_in_trie(&self) -> bool {
         self.stack.last().map_or(false, |n| n.tree_flag())
     }
 
-    fn first_uncovered_prefix(&self) -> Option<Vec<u8>> {
+    pub(super) fn first_uncovered_prefix(&self) -> Option<Vec<u8>> {
         match &self.key() {
             Some(key) => {
                 if self.can_skip_state {
@@ -214,7 +241,7 @@ where
         self.update_skip_state();
 
         if entry.is_some() && (!self.can_skip_state || nibble != -1) {
-            self.cursor.lock().delete_current()?;
+            self.cursor.lock().consume_current()?;
         }
 
         Ok(())
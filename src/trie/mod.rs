@@ -2,6 +2,7 @@ mod hash_builder;
 mod intermediate_hashes;
 mod node;
 mod prefix_set;
+mod proof;
 mod util;
 mod vector_root;
 
@@ -11,4 +12,5 @@ pub use intermediate_hashes::{
     regenerate_intermediate_hashes, unwind_intermediate_hashes, DbTrieLoader,
 };
 pub use prefix_set::PrefixSet;
+pub use proof::{prove_account, verify_proof, AccountProof, StorageProof};
 pub use vector_root::{root_hash, TrieEncode};
@@ -0,0 +1,408 @@
+use crate::{
+    crypto::keccak256,
+    kv::{mdbx::*, tables},
+    models::*,
+    trie::{
+        hash_builder::{unpack_nibbles, HashBuilder},
+        intermediate_hashes::Cursor,
+        prefix_set::PrefixSet,
+    },
+};
+use anyhow::{bail, format_err, Result};
+use bytes::Bytes;
+use fastrlp::{Header, EMPTY_STRING_CODE};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StorageProof {
+    pub key: H256,
+    pub value: U256,
+    pub proof: Vec<Bytes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AccountProof {
+    pub address: Address,
+    /// `None` if the account does not exist, the proof then shows its absence.
+    pub account: Option<Account>,
+    pub storage_root: H256,
+    pub proof: Vec<Bytes>,
+    pub storage_proofs: Vec<StorageProof>,
+}
+
+/// Proves the account and its storage slots against the current hashed state. Only the nodes on
+/// the paths to the proven keys are recomputed, the rest of the trie comes from intermediate
+/// hashes. Returns the state root along with the proof.
+pub fn prove_account<E>(
+    txn: &MdbxTransaction<'_, RO, E>,
+    address: Address,
+    storage_keys: &[H256],
+) -> Result<(H256, AccountProof)>
+where
+    E: EnvironmentKind,
+{
+    let hashed_address = keccak256(address);
+    let target = unpack_nibbles(hashed_address.as_bytes());
+
+    let mut changed = PrefixSet::new();
+    changed.insert(&target);
+
+    let mut hb = HashBuilder::new(None).with_proof_targets([target.clone()]);
+    let mut state = txn.cursor(tables::HashedAccount)?;
+    let mut trie_db_cursor = txn.cursor(tables::TrieAccount)?;
+    let mut trie = Cursor::new(&mut trie_db_cursor, &mut changed, &[])?;
+
+    let mut proven = None;
+    while let Some(key) = trie.key() {
+        if trie.can_skip_state {
+            hb.add_branch_node(
+                key,
+                trie.hash().as_ref().unwrap(),
+                trie.children_are_in_trie(),
+            );
+        }
+
+        let seek_key = match trie.first_uncovered_prefix() {
+            Some(mut uncovered) => {
+                uncovered.resize(32, 0);
+                uncovered
+            }
+            None => break,
+        };
+
+        trie.next()?;
+
+        let mut acc = state.seek(H256::from_slice(seek_key.as_slice()))?;
+        let trie_key = trie.key();
+
+        while let Some((hashed, account)) = acc {
+            let unpacked_key = unpack_nibbles(hashed.as_bytes());
+            if let Some(ref key) = trie_key {
+                if key < &unpacked_key {
+                    break;
+                }
+            }
+
+            let storage_root = if hashed == hashed_address {
+                let (storage_root, storage_proofs) = prove_storage(txn, hashed, storage_keys)?;
+                proven = Some((account, storage_root, storage_proofs));
+                storage_root
+            } else {
+                prove_storage(txn, hashed, &[])?.0
+            };
+
+            hb.add_leaf(
+                unpacked_key,
+                &fastrlp::encode_fixed_size(&account.to_rlp(storage_root)),
+            );
+
+            acc = state.next()?;
+        }
+    }
+
+    let root = hb.compute_root_hash();
+    let (account, storage_root, storage_proofs) = match proven {
+        Some((account, storage_root, storage_proofs)) => {
+            (Some(account), storage_root, storage_proofs)
+        }
+        None => (
+            None,
+            EMPTY_ROOT,
+            storage_keys
+                .iter()
+                .map(|&key| StorageProof {
+                    key,
+                    value: U256::ZERO,
+                    proof: vec![],
+                })
+                .collect(),
+        ),
+    };
+
+    Ok((
+        root,
+        AccountProof {
+            address,
+            account,
+            storage_root,
+            proof: hb.proof(&target),
+            storage_proofs,
+        },
+    ))
+}
+
+fn prove_storage<E>(
+    txn: &MdbxTransaction<'_, RO, E>,
+    hashed_address: H256,
+    keys: &[H256],
+) -> Result<(H256, Vec<StorageProof>)>
+where
+    E: EnvironmentKind,
+{
+    let account_key = hashed_address.as_bytes();
+    let targets = keys
+        .iter()
+        .map(|key| unpack_nibbles(keccak256(key).as_bytes()))
+        .collect::<Vec<_>>();
+
+    let mut changed = PrefixSet::new();
+    for target in &targets {
+        changed.insert(&[account_key, target].concat());
+    }
+
+    let mut hb = HashBuilder::new(None).with_proof_targets(targets.clone());
+    let mut state = txn.cursor(tables::HashedStorage)?;
+    let mut trie_db_cursor = txn.cursor(tables::TrieStorage)?;
+    let mut trie = Cursor::new(&mut trie_db_cursor, &mut changed, account_key)?;
+
+    let mut values = HashMap::new();
+    while let Some(key) = trie.key() {
+        if trie.can_skip_state {
+            if state.seek_exact(hashed_address)?.is_none() {
+                break;
+            }
+            hb.add_branch_node(
+                key,
+                trie.hash().as_ref().unwrap(),
+                trie.children_are_in_trie(),
+            );
+        }
+
+        let seek_key = match trie.first_uncovered_prefix() {
+            Some(mut uncovered) => {
+                uncovered.resize(32, 0);
+                uncovered
+            }
+            None => break,
+        };
+
+        trie.next()?;
+
+        let mut storage =
+            state.seek_both_range(hashed_address, H256::from_slice(seek_key.as_slice()))?;
+        let trie_key = trie.key();
+
+        while let Some((location, value)) = storage {
+            let unpacked_loc = unpack_nibbles(location.as_bytes());
+            if let Some(ref key) = trie_key {
+                if key < &unpacked_loc {
+                    break;
+                }
+            }
+            if targets.contains(&unpacked_loc) {
+                values.insert(location, value);
+            }
+            hb.add_leaf(unpacked_loc, fastrlp::encode_fixed_size(&value).as_ref());
+            storage = state.next_dup()?.map(|(_, v)| v);
+        }
+    }
+
+    let root = hb.compute_root_hash();
+    let proofs = keys
+        .iter()
+        .zip(&targets)
+        .map(|(&key, target)| StorageProof {
+            key,
+            value: values.get(&keccak256(key)).copied().unwrap_or(U256::ZERO),
+            proof: hb.proof(target),
+        })
+        .collect();
+
+    Ok((root, proofs))
+}
+
+/// Splits an RLP list into its raw items.
+fn rlp_list_items(mut buf: &[u8]) -> Result<Vec<&[u8]>> {
+    let header = Header::decode(&mut buf)?;
+    if !header.list || header.payload_length > buf.len() {
+        bail!("invalid trie node");
+    }
+
+    let mut payload = &buf[..header.payload_length];
+    let mut items = vec![];
+    while !payload.is_empty() {
+        let mut rest = payload;
+        let item_header = Header::decode(&mut rest)?;
+        let len = payload.len() - rest.len() + item_header.payload_length;
+        if len > payload.len() {
+            bail!("invalid trie node");
+        }
+        let (item, remaining) = payload.split_at(len);
+        items.push(item);
+        payload = remaining;
+    }
+
+    Ok(items)
+}
+
+fn rlp_string(mut item: &[u8]) -> Result<&[u8]> {
+    let header = Header::decode(&mut item)?;
+    if header.list || header.payload_length > item.len() {
+        bail!("expected string in trie node");
+    }
+    Ok(&item[..header.payload_length])
+}
+
+/// Decodes a hex-prefix encoded path into nibbles and the leaf flag.
+fn decode_path(encoded: &[u8]) -> Result<(Vec<u8>, bool)> {
+    let first = *encoded
+        .first()
+        .ok_or_else(|| format_err!("empty node path"))?;
+    let mut nibbles = unpack_nibbles(&encoded[1..]);
+    if first & 0x10 != 0 {
+        nibbles.insert(0, first & 0x0f);
+    }
+    Ok((nibbles, first & 0x20 != 0))
+}
+
+/// Checks a Merkle proof of the (already hashed, for secure tries) key against the root. Returns
+/// the value stored under the key, or `None` if the proof shows there is none.
+pub fn verify_proof(root: H256, key: &[u8], proof: &[Bytes]) -> Result<Option<Bytes>> {
+    let key = unpack_nibbles(key);
+    let mut path = key.as_slice();
+    let mut proof = proof.iter();
+
+    let mut node = match proof.next() {
+        None if root == EMPTY_ROOT => return Ok(None),
+        None => bail!("empty proof for non-empty trie"),
+        Some(rlp) if keccak256(rlp) != root => bail!("proof does not match root {root}"),
+        Some(rlp) => rlp.to_vec(),
+    };
+
+    loop {
+        let items = rlp_list_items(&node)?;
+        let child = match items.len() {
+            17 => {
+                let (&nibble, rest) = path
+                    .split_first()
+                    .ok_or_else(|| format_err!("branch values are not supported"))?;
+                path = rest;
+                items[nibble as usize]
+            }
+            2 => {
+                let (node_path, leaf) = decode_path(rlp_string(items[0])?)?;
+                if leaf {
+                    if node_path != path {
+                        return Ok(None);
+                    }
+                    return Ok(Some(Bytes::copy_from_slice(rlp_string(items[1])?)));
+                }
+                match path.strip_prefix(node_path.as_slice()) {
+                    Some(rest) => path = rest,
+                    None => return Ok(None),
+                }
+                items[1]
+            }
+            n => bail!("invalid trie node with {n} items"),
+        };
+
+        node = if child == [EMPTY_STRING_CODE] {
+            return Ok(None);
+        } else if child.len() == KECCAK_LENGTH + 1 {
+            let hash = H256::from_slice(rlp_string(child)?);
+            let next = proof
+                .next()
+                .ok_or_else(|| format_err!("proof is missing node {hash}"))?;
+            if keccak256(next) != hash {
+                bail!("proof node does not match hash {hash}");
+            }
+            next.to_vec()
+        } else {
+            // Embedded node
+            child.to_vec()
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::new_mem_chaindata, trie::regenerate_intermediate_hashes, upsert_hashed_storage_value,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn account_and_storage_proofs() {
+        let temp_dir = TempDir::new().unwrap();
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let address = |i: u64| Address::from_low_u64_be(i + 1);
+        let account = |i: u64| Account {
+            nonce: i,
+            balance: (i + 1).as_u256() * ETHER,
+            ..Default::default()
+        };
+
+        let slots = (0..200).map(H256::from_low_u64_be).collect::<Vec<_>>();
+        {
+            let mut hashed_accounts = txn.cursor(tables::HashedAccount).unwrap();
+            for i in 0..1000 {
+                hashed_accounts
+                    .upsert(keccak256(address(i)), account(i))
+                    .unwrap();
+            }
+
+            let mut hashed_storage = txn.cursor(tables::HashedStorage).unwrap();
+            for (i, slot) in slots.iter().enumerate() {
+                upsert_hashed_storage_value(
+                    &mut hashed_storage,
+                    keccak256(address(7)),
+                    keccak256(slot),
+                    (i as u64 + 1).as_u256(),
+                )
+                .unwrap();
+            }
+        }
+
+        let expected_root = regenerate_intermediate_hashes(&txn, &temp_dir, None).unwrap();
+        txn.commit().unwrap();
+
+        let txn = db.begin().unwrap();
+        let missing_slot = H256::from_low_u64_be(1000);
+        let (root, proof) = prove_account(&txn, address(7), &[slots[3], missing_slot]).unwrap();
+        assert_eq!(root, expected_root);
+        assert_eq!(proof.account, Some(account(7)));
+
+        let value = verify_proof(root, keccak256(address(7)).as_bytes(), &proof.proof)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            value,
+            fastrlp::encode_fixed_size(&account(7).to_rlp(proof.storage_root)).as_ref()
+        );
+
+        let [present, missing] = <[StorageProof; 2]>::try_from(proof.storage_proofs).unwrap();
+        assert_eq!(present.value, 4.as_u256());
+        assert_eq!(
+            verify_proof(
+                proof.storage_root,
+                keccak256(slots[3]).as_bytes(),
+                &present.proof
+            )
+            .unwrap()
+            .unwrap(),
+            fastrlp::encode_fixed_size(&present.value).as_ref()
+        );
+        assert_eq!(missing.value, U256::ZERO);
+        assert_eq!(
+            verify_proof(
+                proof.storage_root,
+                keccak256(missing_slot).as_bytes(),
+                &missing.proof
+            )
+            .unwrap(),
+            None
+        );
+
+        let (root, absent) = prove_account(&txn, address(5000), &[]).unwrap();
+        assert_eq!(root, expected_root);
+        assert_eq!(absent.account, None);
+        assert_eq!(
+            verify_proof(root, keccak256(address(5000)).as_bytes(), &absent.proof).unwrap(),
+            None
+        );
+        assert!(verify_proof(root, keccak256(address(7)).as_bytes(), &absent.proof).is_err());
+    }
+}